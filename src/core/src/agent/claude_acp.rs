@@ -7,14 +7,15 @@
 //! It delegates all CLI communication to `claude_sdk::ClaudeSdk` and translates
 //! `SdkEvent`s into ACP `SessionNotification`s.
 
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use agent_client_protocol as acp;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
-use super::claude_sdk::{ClaudeSdk, ContentBlock, SdkEvent};
+use super::claude_sdk::{ClaudeInterrupt, ClaudeSdk, ContentBlock, SdkEvent, UserImage};
 
 /// Spawn a Claude ACP agent on a dedicated thread (required because `ClaudeSdk` uses `spawn_local`).
 /// Returns the client-side halves of a duplex pipe for `ClientSideConnection`.
@@ -71,8 +72,10 @@ async fn run_acp_bridge(
 
     // Notification channel: event translator → ACP connection
     let (notif_tx, mut notif_rx) = mpsc::channel::<acp::SessionNotification>(256);
+    // Permission channel: bridge → ACP client `session/request_permission`
+    let (perm_tx, mut perm_rx) = mpsc::channel::<PermissionAsk>(8);
 
-    let agent_impl = ClaudeAcpBridge::new(cwd.clone(), notif_tx, system_prompt, real_session_id_tx);
+    let agent_impl = ClaudeAcpBridge::new(cwd.clone(), notif_tx, perm_tx, system_prompt, real_session_id_tx);
 
     let (conn, handle_io) = acp::AgentSideConnection::new(
         agent_impl,
@@ -94,6 +97,24 @@ async fn run_acp_bridge(
         }
     });
 
+    // Forward permission asks → ACP client; each ask is awaited on its own task
+    let conn_for_perm = conn.clone();
+    tokio::task::spawn_local(async move {
+        while let Some((req, reply_tx)) = perm_rx.recv().await {
+            let conn = conn_for_perm.clone();
+            tokio::task::spawn_local(async move {
+                let outcome = match conn.request_permission(req).await {
+                    Ok(resp) => resp.outcome,
+                    Err(e) => {
+                        eprintln!("[claude-acp] request_permission failed: {}", e);
+                        acp::RequestPermissionOutcome::Cancelled
+                    }
+                };
+                let _ = reply_tx.send(outcome);
+            });
+        }
+    });
+
     handle_io.await.map_err(|e| format!("ACP IO error: {}", e))
 }
 
@@ -101,50 +122,133 @@ async fn run_acp_bridge(
 // ClaudeAcpBridge — ACP Agent that delegates to ClaudeSdk
 // ---------------------------------------------------------------------------
 
+/// A permission request for the ACP client plus the channel for its outcome.
+type PermissionAsk = (acp::RequestPermissionRequest, oneshot::Sender<acp::RequestPermissionOutcome>);
+
+const PERMISSION_ALLOW: &str = "allow";
+const PERMISSION_DENY: &str = "deny";
+
+const RESUME_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 struct ClaudeAcpBridge {
     cwd: PathBuf,
     notif_tx: mpsc::Sender<acp::SessionNotification>,
+    perm_tx: mpsc::Sender<PermissionAsk>,
     system_prompt: Option<String>,
     /// The underlying SDK handle, created on first `initialize`.
     sdk: tokio::sync::Mutex<Option<ClaudeSdk>>,
+    /// Interrupts the running turn; usable while `sdk` is locked by `prompt`.
+    interrupt: RefCell<Option<ClaudeInterrupt>>,
+    /// Set by `cancel` so the running `prompt` reports `StopReason::Cancelled`.
+    cancelled: Cell<bool>,
     /// Stable ACP session id used by the in-process bridge.
     acp_session_id: String,
     /// Real Claude CLI session ids observed from SDK events.
     real_session_id_tx: tokio::sync::mpsc::UnboundedSender<String>,
+    /// `--mcp-config` file for the session's MCP servers. Kept under the data dir, never the
+    /// workspace, because the URLs carry the MCP token; removed when the bridge goes away.
+    mcp_config_path: PathBuf,
+}
+
+impl Drop for ClaudeAcpBridge {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.mcp_config_path);
+    }
 }
 
 impl ClaudeAcpBridge {
     fn new(
         cwd: PathBuf,
         notif_tx: mpsc::Sender<acp::SessionNotification>,
+        perm_tx: mpsc::Sender<PermissionAsk>,
         system_prompt: Option<String>,
         real_session_id_tx: tokio::sync::mpsc::UnboundedSender<String>,
     ) -> Self {
         static NEXT_ACP_SESSION_ID: AtomicU64 = AtomicU64::new(1);
         let acp_session_id = format!("claude-acp-{}", NEXT_ACP_SESSION_ID.fetch_add(1, Ordering::Relaxed));
+        let mcp_config_path = crate::config::data_dir()
+            .join("mcp")
+            .join(format!("{}.json", acp_session_id));
         Self {
             cwd,
             notif_tx,
+            perm_tx,
             system_prompt,
             sdk: tokio::sync::Mutex::new(None),
+            interrupt: RefCell::new(None),
+            cancelled: Cell::new(false),
             acp_session_id,
             real_session_id_tx,
+            mcp_config_path,
         }
     }
 
-    async fn ensure_sdk(&self) -> Result<(), acp::Error> {
+    /// Spawn the SDK unless it is running. With `resume_session_id`, the CLI is started with
+    /// `--resume` and must complete its handshake, or the spawn fails and nothing is kept.
+    async fn ensure_sdk(
+        &self,
+        resume_session_id: Option<&str>,
+        mcp_servers: &[acp::McpServer],
+    ) -> Result<(), acp::Error> {
         let mut lock = self.sdk.lock().await;
         if lock.is_some() {
             return Ok(());
         }
-        let sdk = ClaudeSdk::spawn(&self.cwd, self.system_prompt.as_deref(), None).await
-            .map_err(|e| acp::Error::new(-32603, e))?;
+        let mcp_config = self.write_mcp_config(mcp_servers).map_err(|e| acp::Error::new(-32603, e))?;
+        let interactive = crate::config::ensure_loaded().permissions.mode == crate::config::PermissionMode::Ask;
+        let sdk = ClaudeSdk::spawn(
+            &self.cwd,
+            self.system_prompt.as_deref(),
+            resume_session_id,
+            interactive,
+            mcp_config,
+        )
+        .await
+        .map_err(|e| acp::Error::new(-32603, e))?;
+        if resume_session_id.is_some() {
+            let handshake = tokio::time::timeout(RESUME_HANDSHAKE_TIMEOUT, sdk.wait_initialized())
+                .await
+                .unwrap_or_else(|_| Err("timed out waiting for claude".to_string()));
+            if let Err(e) = handshake {
+                sdk.shutdown().await;
+                return Err(acp::Error::new(-32603, format!("Failed to resume Claude session: {}", e)));
+            }
+        }
+        *self.interrupt.borrow_mut() = Some(sdk.interrupt_handle());
         *lock = Some(sdk);
         Ok(())
     }
 
-    /// Translate SDK events into ACP notifications until a TurnResult arrives.
-    async fn drain_until_turn_result(&self, session_id: &str) -> Result<(bool, Option<String>), acp::Error> {
+    /// Write the session's HTTP MCP servers in Claude's `--mcp-config` format.
+    /// Returns `None` when there are none.
+    fn write_mcp_config(&self, mcp_servers: &[acp::McpServer]) -> Result<Option<&Path>, String> {
+        let servers: serde_json::Map<String, serde_json::Value> = mcp_servers
+            .iter()
+            .filter_map(|server| match server {
+                acp::McpServer::Http(http) => {
+                    Some((http.name.clone(), serde_json::json!({ "type": "http", "url": http.url })))
+                }
+                _ => None,
+            })
+            .collect();
+        if servers.is_empty() {
+            return Ok(None);
+        }
+        let content = serde_json::json!({ "mcpServers": servers }).to_string();
+        if let Some(parent) = self.mcp_config_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        crate::config::write_private_file(&self.mcp_config_path, content.as_bytes())
+            .map_err(|e| format!("Failed to write MCP config {:?}: {}", self.mcp_config_path, e))?;
+        Ok(Some(&self.mcp_config_path))
+    }
+
+    /// Translate SDK events into ACP notifications until a TurnResult arrives; returns its error
+    /// state and token usage.
+    async fn drain_until_turn_result(
+        &self,
+        session_id: &str,
+    ) -> Result<(bool, Option<String>, Option<acp::Usage>), acp::Error> {
         let lock = self.sdk.lock().await;
         let sdk = lock.as_ref().ok_or_else(|| acp::Error::new(-32603, "SDK not running"))?;
 
@@ -159,11 +263,16 @@ impl ClaudeAcpBridge {
                         let _ = self.notif_tx.send(notif).await;
                     }
                 }
-                SdkEvent::TurnResult { session_id, is_error, error_text } => {
+                SdkEvent::TurnResult { session_id, is_error, error_text, usage } => {
                     if let Some(real_session_id) = session_id {
                         let _ = self.real_session_id_tx.send(real_session_id);
                     }
-                    return Ok((is_error, error_text));
+                    let usage = usage.map(|u| {
+                        acp::Usage::new(u.input_tokens + u.output_tokens, u.input_tokens, u.output_tokens)
+                            .cached_read_tokens(u.cached_read_tokens)
+                            .cached_write_tokens(u.cached_write_tokens)
+                    });
+                    return Ok((is_error, error_text, usage));
                 }
                 SdkEvent::SystemInit { session_id } => {
                     if let Some(real_session_id) = session_id {
@@ -171,31 +280,67 @@ impl ClaudeAcpBridge {
                     }
                     // informational, no ACP notification needed
                 }
-                SdkEvent::ControlHandled { .. } => {
+                SdkEvent::ControlHandled { .. } | SdkEvent::Initialized => {
                     // informational, no ACP notification needed
                 }
+                SdkEvent::PermissionRequest { request_id, tool_use_id, tool_name, input } => {
+                    let allow = self.ask_permission(session_id, tool_use_id.as_deref().unwrap_or(&request_id), &tool_name, &input).await;
+                    sdk.respond_permission(&request_id, allow, input).await
+                        .map_err(|e| acp::Error::new(-32603, e))?;
+                }
             }
         }
     }
+
+    /// Relay a `can_use_tool` check to the ACP client; true if the user allowed it.
+    async fn ask_permission(&self, session_id: &str, tool_call_id: &str, tool_name: &str, input: &serde_json::Value) -> bool {
+        let fields = acp::ToolCallUpdateFields::new()
+            .title(tool_name.to_string())
+            .raw_input(input.clone());
+        let req = acp::RequestPermissionRequest::new(
+            session_id.to_string(),
+            acp::ToolCallUpdate::new(tool_call_id.to_string(), fields),
+            vec![
+                acp::PermissionOption::new(PERMISSION_ALLOW, "Allow", acp::PermissionOptionKind::AllowOnce),
+                acp::PermissionOption::new(PERMISSION_DENY, "Deny", acp::PermissionOptionKind::RejectOnce),
+            ],
+        );
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.perm_tx.send((req, reply_tx)).await.is_err() {
+            return false;
+        }
+        matches!(
+            reply_rx.await,
+            Ok(acp::RequestPermissionOutcome::Selected(sel)) if &*sel.option_id.0 == PERMISSION_ALLOW
+        )
+    }
 }
 
 #[async_trait::async_trait(?Send)]
 impl acp::Agent for ClaudeAcpBridge {
     async fn initialize(&self, _args: acp::InitializeRequest) -> acp::Result<acp::InitializeResponse> {
-        self.ensure_sdk().await?;
-        Ok(acp::InitializeResponse::new(acp::ProtocolVersion::V1))
+        // The SDK is spawned by `new_session` or `load_session`, which decides whether to `--resume`.
+        Ok(acp::InitializeResponse::new(acp::ProtocolVersion::V1).agent_capabilities(
+            acp::AgentCapabilities::new()
+                .load_session(true)
+                .prompt_capabilities(acp::PromptCapabilities::new().image(true))
+                .mcp_capabilities(acp::McpCapabilities::new().http(true)),
+        ))
     }
 
     async fn authenticate(&self, _args: acp::AuthenticateRequest) -> acp::Result<acp::AuthenticateResponse> {
         Ok(acp::AuthenticateResponse::default())
     }
 
-    async fn new_session(&self, _args: acp::NewSessionRequest) -> acp::Result<acp::NewSessionResponse> {
+    async fn new_session(&self, args: acp::NewSessionRequest) -> acp::Result<acp::NewSessionResponse> {
+        self.ensure_sdk(None, &args.mcp_servers).await?;
         Ok(acp::NewSessionResponse::new(self.acp_session_id.clone()))
     }
 
-    async fn load_session(&self, _args: acp::LoadSessionRequest) -> acp::Result<acp::LoadSessionResponse> {
-        Err(acp::Error::method_not_found())
+    /// Resume a Claude CLI session; `args.session_id` is the real CLI session id.
+    async fn load_session(&self, args: acp::LoadSessionRequest) -> acp::Result<acp::LoadSessionResponse> {
+        self.ensure_sdk(Some(&args.session_id.to_string()), &args.mcp_servers).await?;
+        Ok(acp::LoadSessionResponse::default())
     }
 
     async fn set_session_mode(&self, _args: acp::SetSessionModeRequest) -> acp::Result<acp::SetSessionModeResponse> {
@@ -203,19 +348,38 @@ impl acp::Agent for ClaudeAcpBridge {
     }
 
     async fn prompt(&self, args: acp::PromptRequest) -> acp::Result<acp::PromptResponse> {
-        self.ensure_sdk().await?;
+        self.ensure_sdk(None, &[]).await?;
+
+        // Extract text and images from ACP content blocks; linked files are referenced by path
+        let mut parts = Vec::new();
+        let mut images = Vec::new();
+        for block in &args.prompt {
+            match block {
+                acp::ContentBlock::Text(t) => parts.push(t.text.clone()),
+                acp::ContentBlock::Image(img) => images.push(UserImage {
+                    media_type: img.mime_type.clone(),
+                    data: img.data.clone(),
+                }),
+                acp::ContentBlock::ResourceLink(link) => {
+                    let location = url::Url::parse(&link.uri)
+                        .ok()
+                        .and_then(|u| u.to_file_path().ok())
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_else(|| link.uri.clone());
+                    parts.push(format!("[Attached file: {}]", location));
+                }
+                _ => {}
+            }
+        }
+        let text = parts.join("\n");
 
-        // Extract text from ACP content blocks
-        let text = args.prompt.iter().filter_map(|block| match block {
-            acp::ContentBlock::Text(t) => Some(t.text.as_str()),
-            _ => None,
-        }).collect::<Vec<_>>().join("\n");
+        self.cancelled.set(false);
 
         // Send to Claude SDK
         {
             let lock = self.sdk.lock().await;
             let sdk = lock.as_ref().ok_or_else(|| acp::Error::new(-32603, "SDK not running"))?;
-            sdk.send_user_message(&text).await
+            sdk.send_user_message(&text, &images).await
                 .map_err(|e| acp::Error::new(-32603, e))?;
         }
 
@@ -223,16 +387,25 @@ impl acp::Agent for ClaudeAcpBridge {
         let sid = self.acp_session_id.clone();
 
         // Drain events until turn completes
-        let (is_error, error_text) = self.drain_until_turn_result(&sid).await?;
+        let (is_error, error_text, usage) = self.drain_until_turn_result(&sid).await?;
 
+        // An interrupted turn ends with an error `result`; report it as a cancellation.
+        if self.cancelled.get() {
+            return Ok(acp::PromptResponse::new(acp::StopReason::Cancelled));
+        }
         if is_error {
             return Err(acp::Error::new(-32603, error_text.unwrap_or_else(|| "Unknown error".into())));
         }
 
-        Ok(acp::PromptResponse::new(acp::StopReason::EndTurn))
+        Ok(acp::PromptResponse::new(acp::StopReason::EndTurn).usage(usage))
     }
 
     async fn cancel(&self, _args: acp::CancelNotification) -> acp::Result<()> {
+        let interrupt = self.interrupt.borrow().clone();
+        if let Some(interrupt) = interrupt {
+            self.cancelled.set(true);
+            interrupt.interrupt().await.map_err(|e| acp::Error::new(-32603, e))?;
+        }
         Ok(())
     }
 
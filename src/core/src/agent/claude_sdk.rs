@@ -3,7 +3,7 @@
 //! Spawns `claude --input-format stream-json --output-format stream-json` and provides:
 //! - Process lifecycle management (spawn, shutdown)
 //! - Bidirectional message I/O (send user messages, receive events)
-//! - Control protocol handling (initialize, can_use_tool auto-allow, hook_callback)
+//! - Control protocol handling (initialize, interrupt, can_use_tool auto-allow or relay, hook_callback)
 //!
 //! This module knows NOTHING about ACP. It only speaks the Claude CLI private protocol.
 //! The ACP translation layer lives in `claude_acp.rs`.
@@ -39,17 +39,61 @@ pub enum SdkEvent {
         session_id: Option<String>,
         is_error: bool,
         error_text: Option<String>,
+        /// The `result` message's `usage`, when it has input and output counts.
+        usage: Option<super::TokenUsage>,
     },
     /// System init message received.
     SystemInit { session_id: Option<String> },
+    /// The CLI answered our `initialize` control request.
+    Initialized,
     /// Control request that was auto-handled (logged for debugging).
     ControlHandled { subtype: String },
+    /// `can_use_tool` control request awaiting a decision (interactive permission mode only).
+    /// Answer with `ClaudeSdk::respond_permission`.
+    PermissionRequest {
+        request_id: String,
+        tool_use_id: Option<String>,
+        tool_name: String,
+        input: serde_json::Value,
+    },
+}
+
+/// A base64 image attached to a user message.
+#[derive(Debug, Clone)]
+pub struct UserImage {
+    pub media_type: String,
+    pub data: String,
 }
 
 // ---------------------------------------------------------------------------
 // ClaudeSdk — the main handle
 // ---------------------------------------------------------------------------
 
+const INIT_REQUEST_ID: &str = "req_init_1";
+
+/// Cloneable handle that interrupts the running turn without borrowing the `ClaudeSdk`
+/// (which is held for the whole turn while events are drained).
+#[derive(Clone)]
+pub struct ClaudeInterrupt {
+    write_tx: mpsc::Sender<String>,
+}
+
+impl ClaudeInterrupt {
+    /// Send the CLI's `interrupt` control request. The turn then ends with a `result` message.
+    pub async fn interrupt(&self) -> Result<(), String> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT_INTERRUPT_ID: AtomicU64 = AtomicU64::new(1);
+
+        let msg = serde_json::json!({
+            "type": "control_request",
+            "request_id": format!("req_interrupt_{}", NEXT_INTERRUPT_ID.fetch_add(1, Ordering::Relaxed)),
+            "request": { "subtype": "interrupt" }
+        });
+        self.write_tx.send(msg.to_string()).await
+            .map_err(|e| format!("Failed to send interrupt: {}", e))
+    }
+}
+
 /// A running Claude CLI subprocess with bidirectional communication.
 pub struct ClaudeSdk {
     /// Send raw JSON lines to claude's stdin.
@@ -67,17 +111,36 @@ impl ClaudeSdk {
     ///
     /// The subprocess is initialized (control handshake sent) before returning.
     /// Events start flowing immediately into the internal channel.
-    pub async fn spawn(cwd: &Path, system_prompt: Option<&str>, resume_session_id: Option<&str>) -> Result<Self, String> {
+    ///
+    /// With `interactive_permissions`, tool permission checks are routed to us over the
+    /// control protocol and surface as `SdkEvent::PermissionRequest` instead of being skipped.
+    /// `mcp_config` is passed as `--mcp-config`.
+    pub async fn spawn(
+        cwd: &Path,
+        system_prompt: Option<&str>,
+        resume_session_id: Option<&str>,
+        interactive_permissions: bool,
+        mcp_config: Option<&Path>,
+    ) -> Result<Self, String> {
         let mut args = vec![
             "--input-format".to_string(), "stream-json".to_string(),
             "--output-format".to_string(), "stream-json".to_string(),
             "--verbose".to_string(),
-            "--dangerously-skip-permissions".to_string(),
         ];
+        if interactive_permissions {
+            args.push("--permission-prompt-tool".to_string());
+            args.push("stdio".to_string());
+        } else {
+            args.push("--dangerously-skip-permissions".to_string());
+        }
         if let Some(id) = resume_session_id {
             args.push("--resume".to_string());
             args.push(id.to_string());
         }
+        if let Some(path) = mcp_config {
+            args.push("--mcp-config".to_string());
+            args.push(path.display().to_string());
+        }
         if let Some(prompt) = system_prompt {
             args.push("--system-prompt".to_string());
             args.push(prompt.to_string());
@@ -112,7 +175,7 @@ impl ClaudeSdk {
         // Send initialize control request
         let init_msg = serde_json::json!({
             "type": "control_request",
-            "request_id": "req_init_1",
+            "request_id": INIT_REQUEST_ID,
             "request": { "subtype": "initialize", "hooks": null, "agents": null }
         });
         write_tx.send(init_msg.to_string()).await
@@ -146,7 +209,7 @@ impl ClaudeSdk {
                     }
 
                     "control_request" => {
-                        handle_control_request(&msg, &write_tx_for_reader, &event_tx).await;
+                        handle_control_request(&msg, &write_tx_for_reader, &event_tx, interactive_permissions).await;
                     }
 
                     "result" => {
@@ -160,10 +223,21 @@ impl ClaudeSdk {
                         } else {
                             None
                         };
+                        let count = |key: &str| msg.get("usage").and_then(|u| u.get(key)).and_then(|v| v.as_u64());
+                        let usage = count("input_tokens").zip(count("output_tokens")).map(|(input, output)| {
+                            super::TokenUsage {
+                                input_tokens: input,
+                                output_tokens: output,
+                                thought_tokens: None,
+                                cached_read_tokens: count("cache_read_input_tokens"),
+                                cached_write_tokens: count("cache_creation_input_tokens"),
+                            }
+                        });
                         let _ = event_tx.send(SdkEvent::TurnResult {
                             session_id: new_sid,
                             is_error,
                             error_text,
+                            usage,
                         }).await;
                     }
 
@@ -175,7 +249,13 @@ impl ClaudeSdk {
                         let _ = event_tx.send(SdkEvent::SystemInit { session_id: sid }).await;
                     }
 
-                    // control_response, user — internal, no event needed
+                    "control_response"
+                        if msg.pointer("/response/request_id").and_then(|v| v.as_str()) == Some(INIT_REQUEST_ID) =>
+                    {
+                        let _ = event_tx.send(SdkEvent::Initialized).await;
+                    }
+
+                    // user — internal, no event needed
                     _ => {}
                 }
             }
@@ -192,12 +272,27 @@ impl ClaudeSdk {
         })
     }
 
-    /// Send a user message to the Claude CLI.
-    pub async fn send_user_message(&self, text: &str) -> Result<(), String> {
+    /// Send a user message to the Claude CLI. Images are sent as `image` content blocks.
+    pub async fn send_user_message(&self, text: &str, images: &[UserImage]) -> Result<(), String> {
         let session_id = self.session_id.lock().await.clone();
+        let content = if images.is_empty() {
+            serde_json::Value::String(text.to_string())
+        } else {
+            let mut blocks: Vec<serde_json::Value> = images
+                .iter()
+                .map(|img| {
+                    serde_json::json!({
+                        "type": "image",
+                        "source": { "type": "base64", "media_type": img.media_type, "data": img.data }
+                    })
+                })
+                .collect();
+            blocks.push(serde_json::json!({ "type": "text", "text": text }));
+            serde_json::Value::Array(blocks)
+        };
         let mut user_msg = serde_json::json!({
             "type": "user",
-            "message": { "role": "user", "content": text },
+            "message": { "role": "user", "content": content },
             "parent_tool_use_id": null
         });
         if let Some(session_id) = session_id {
@@ -207,6 +302,42 @@ impl ClaudeSdk {
             .map_err(|e| format!("Failed to send user message: {}", e))
     }
 
+    /// Answer a pending `can_use_tool` request. `input` is echoed back as the (unchanged) tool input.
+    pub async fn respond_permission(&self, request_id: &str, allow: bool, input: serde_json::Value) -> Result<(), String> {
+        let decision = if allow {
+            serde_json::json!({ "behavior": "allow", "updatedInput": input })
+        } else {
+            serde_json::json!({ "behavior": "deny", "message": "The user denied this tool call." })
+        };
+        let response = serde_json::json!({
+            "type": "control_response",
+            "response": {
+                "subtype": "success",
+                "request_id": request_id,
+                "response": decision
+            }
+        });
+        self.write_tx.send(response.to_string()).await
+            .map_err(|e| format!("Failed to send permission response: {}", e))
+    }
+
+    /// Handle for interrupting the current turn from another task.
+    pub fn interrupt_handle(&self) -> ClaudeInterrupt {
+        ClaudeInterrupt { write_tx: self.write_tx.clone() }
+    }
+
+    /// Wait until the CLI has answered the `initialize` handshake.
+    /// Fails if the process exits first (e.g. `--resume` with an unknown session id).
+    pub async fn wait_initialized(&self) -> Result<(), String> {
+        loop {
+            match self.recv_event().await {
+                Some(SdkEvent::Initialized) => return Ok(()),
+                Some(_) => {}
+                None => return Err("claude exited during startup".to_string()),
+            }
+        }
+    }
+
     /// Receive the next SDK event. Returns `None` if the reader task has ended.
     pub async fn recv_event(&self) -> Option<SdkEvent> {
         self.event_rx.lock().await.recv().await
@@ -268,15 +399,27 @@ fn parse_content_blocks(msg: &serde_json::Value) -> Vec<ContentBlock> {
     blocks
 }
 
-/// Handle a control_request from the Claude CLI (auto-allow tools, ack hooks).
+/// Handle a control_request from the Claude CLI (auto-allow or relay tools, ack hooks).
 async fn handle_control_request(
     msg: &serde_json::Value,
     write_tx: &mpsc::Sender<String>,
     event_tx: &mpsc::Sender<SdkEvent>,
+    interactive_permissions: bool,
 ) {
     let request_id = msg.get("request_id").and_then(|v| v.as_str()).unwrap_or("");
     let subtype = msg.pointer("/request/subtype").and_then(|v| v.as_str()).unwrap_or("");
 
+    if subtype == "can_use_tool" && interactive_permissions {
+        let tool_name = msg.pointer("/request/tool_name").and_then(|v| v.as_str()).unwrap_or("unknown");
+        let _ = event_tx.send(SdkEvent::PermissionRequest {
+            request_id: request_id.to_string(),
+            tool_use_id: msg.pointer("/request/tool_use_id").and_then(|v| v.as_str()).map(String::from),
+            tool_name: tool_name.to_string(),
+            input: msg.pointer("/request/input").cloned().unwrap_or(serde_json::Value::Null),
+        }).await;
+        return;
+    }
+
     let response = match subtype {
         "can_use_tool" => serde_json::json!({
             "type": "control_response",
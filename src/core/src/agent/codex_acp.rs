@@ -6,16 +6,21 @@
 
 use std::path::Path;
 
+use super::AgentLaunch;
+
 /// Spawn `npx @zed-industries/codex-acp` and return (stdout_as_read, stdin_as_write) streams.
 /// npx auto-downloads the package on first run; no global install needed.
 pub fn spawn_codex_process(
     cwd: &Path,
+    launch: &AgentLaunch,
 ) -> Result<(tokio::io::DuplexStream, tokio::io::DuplexStream), String> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     eprintln!("[codex-acp] spawning npx @zed-industries/codex-acp in {:?}", cwd);
     let mut child = tokio::process::Command::new("npx")
         .args(["@zed-industries/codex-acp"])
+        .args(&launch.args)
+        .envs(launch.env.iter().map(|(k, v)| (*k, v)))
         .current_dir(cwd)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
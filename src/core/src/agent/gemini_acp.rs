@@ -3,6 +3,8 @@
 
 use std::path::Path;
 
+use super::AgentLaunch;
+
 /// Spawn `gemini --experimental-acp` and return (stdout_as_read, stdin_as_write) streams
 /// wrapped as `DuplexStream`-compatible types.
 ///
@@ -11,21 +13,20 @@ use std::path::Path;
 /// a bridging task.
 pub fn spawn_gemini_process(
     cwd: &Path,
-    system_md_path: Option<&Path>,
+    launch: &AgentLaunch,
 ) -> Result<(tokio::io::DuplexStream, tokio::io::DuplexStream), String> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     eprintln!("[gemini-acp] spawning gemini --experimental-acp in {:?}", cwd);
     let mut cmd = tokio::process::Command::new("gemini");
     cmd.arg("--experimental-acp")
+        .args(&launch.args)
+        .envs(launch.env.iter().map(|(k, v)| (*k, v)))
         .current_dir(cwd)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::inherit())
         .kill_on_drop(true);
-    if let Some(path) = system_md_path {
-        cmd.env("GEMINI_SYSTEM_MD", path);
-    }
     let mut child = cmd
         .spawn()
         .map_err(|e| format!("Failed to spawn gemini: {}", e))?;
@@ -139,65 +139,21 @@ const DEFAULT_RULES: &str = r#"# Rules
 - Keep memory.md concise — summarize rather than log everything."#;
 
 // ---------------------------------------------------------------------------
-// MCP config file generation (per-kind, written to workspace before spawn)
+// MCP server URL (attached per ACP session; nothing is written to the workspace)
 // ---------------------------------------------------------------------------
 
-use crate::agent::AgentKind;
-use std::path::Path;
-
-/// Ensure the MCP config file for the given agent kind exists in the workspace.
-/// Called by `registry::spawn_agent()` before starting the backend.
-/// Only writes if the file doesn't already exist.
-pub fn ensure_mcp_config(kind: AgentKind, workspace: &Path, port: u16) {
-    let url = format!("http://127.0.0.1:{}/mcp", port);
-
-    let (rel_path, content) = match kind {
-        AgentKind::Claude => (
-            ".mcp.json",
-            format!(
-                r#"{{"mcpServers":{{"vibearound":{{"type":"http","url":"{}"}}}}}}"#,
-                url
-            ),
+/// URL of the VibeAround MCP server for an agent, attached to its session at spawn.
+/// `caller` (the agent key) is passed as `?caller=` so `dispatch_task` knows which chat to stream into;
+/// `?token=` is the scoped MCP token the server requires on /mcp.
+pub fn mcp_url(port: u16, caller: Option<&str>) -> String {
+    let token = config::mcp_token();
+    match caller {
+        Some(c) => format!(
+            "http://127.0.0.1:{}/mcp?token={}&caller={}",
+            port,
+            token,
+            urlencoding::encode(c)
         ),
-        AgentKind::Gemini => (
-            ".gemini/settings.json",
-            format!(
-                r#"{{"mcpServers":{{"vibearound":{{"httpUrl":"{}"}}}}}}"#,
-                url
-            ),
-        ),
-        AgentKind::OpenCode => (
-            "opencode.json",
-            format!(
-                r#"{{"mcp":{{"vibearound":{{"type":"remote","url":"{}","enabled":true}}}}}}"#,
-                url
-            ),
-        ),
-        AgentKind::Codex => (
-            ".codex/config.toml",
-            format!(
-                "[mcp_servers.vibearound]\nurl = \"{}\"\n",
-                url
-            ),
-        ),
-    };
-
-    let path = workspace.join(rel_path);
-    if path.exists() {
-        return;
-    }
-    if let Some(parent) = path.parent() {
-        let _ = std::fs::create_dir_all(parent);
-    }
-    if let Err(e) = std::fs::write(&path, &content) {
-        eprintln!(
-            "[VibeAround] Failed to write MCP config {:?}: {}",
-            path, e
-        );
-    } else {
-        eprintln!(
-            "[VibeAround] Wrote MCP config for {} at {:?}",
-            kind, path
-        );
+        None => format!("http://127.0.0.1:{}/mcp?token={}", port, token),
     }
 }
@@ -14,6 +14,7 @@ pub mod manager_prompt;
 pub mod opencode_acp;
 pub mod opencode_jsonl;
 
+use std::ffi::OsString;
 use std::fmt;
 use std::path::Path;
 
@@ -94,9 +95,106 @@ pub enum AgentEvent {
     TurnComplete {
         session_id: Option<String>,
         cost_usd: Option<f64>,
+        /// Token counts, when the agent reports them.
+        usage: Option<TokenUsage>,
+        stop_reason: StopReason,
     },
     /// An error occurred.
     Error(String),
+    /// The agent asks for permission to run a tool and is waiting for a choice.
+    /// Answer via `AgentBackend::resolve_permission`.
+    PermissionRequest {
+        request_id: String,
+        tool: String,
+        input: Option<String>,
+        options: Vec<PermissionChoice>,
+    },
+}
+
+/// Why an agent turn ended (mirrors ACP `StopReason`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StopReason {
+    #[default]
+    EndTurn,
+    MaxTokens,
+    MaxTurnRequests,
+    Refusal,
+    /// The turn was stopped via `AgentBackend::cancel`.
+    Cancelled,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::EndTurn => "end_turn",
+            StopReason::MaxTokens => "max_tokens",
+            StopReason::MaxTurnRequests => "max_turn_requests",
+            StopReason::Refusal => "refusal",
+            StopReason::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl From<agent_client_protocol::StopReason> for StopReason {
+    fn from(reason: agent_client_protocol::StopReason) -> Self {
+        use agent_client_protocol::StopReason as Acp;
+        match reason {
+            Acp::MaxTokens => StopReason::MaxTokens,
+            Acp::MaxTurnRequests => StopReason::MaxTurnRequests,
+            Acp::Refusal => StopReason::Refusal,
+            Acp::Cancelled => StopReason::Cancelled,
+            _ => StopReason::EndTurn,
+        }
+    }
+}
+
+/// Token counts of a turn (mirrors ACP `Usage`). Serialized in camelCase for clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thought_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_read_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_write_tokens: Option<u64>,
+}
+
+impl From<agent_client_protocol::Usage> for TokenUsage {
+    fn from(usage: agent_client_protocol::Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            thought_tokens: usage.thought_tokens,
+            cached_read_tokens: usage.cached_read_tokens,
+            cached_write_tokens: usage.cached_write_tokens,
+        }
+    }
+}
+
+/// One selectable answer to a permission request (mirrors an ACP `PermissionOption`).
+#[derive(Debug, Clone)]
+pub struct PermissionChoice {
+    pub id: String,
+    pub label: String,
+    /// "allow_once", "allow_always", "reject_once" or "reject_always".
+    pub kind: String,
+}
+
+/// A file sent to the agent along with a prompt (e.g. an IM attachment saved in the workspace).
+#[derive(Debug, Clone)]
+pub struct PromptFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub mime_type: String,
+}
+
+impl PromptFile {
+    fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
 }
 
 /// Unified interface for agent backends (Claude, Gemini, etc.).
@@ -105,26 +203,55 @@ pub enum AgentEvent {
 pub trait AgentBackend: Send + Sync {
     /// Spawn the agent subprocess, establish ACP connection, create session.
     /// `system_prompt` is injected for Manager agents only (None for Workers).
-    /// Returns the initial CLI session id if it is known at startup time.
-    async fn start(&mut self, cwd: &Path, system_prompt: Option<&str>) -> Result<Option<String>, String>;
+    /// With `resume_session_id`, the previous CLI session is loaded instead (ACP `session/load`);
+    /// if that fails the agent falls back to a new session.
+    /// Returns the initial CLI session id if it is known at startup time; after a successful
+    /// resume this is `resume_session_id`.
+    /// `mcp_url` is the VibeAround MCP server to attach to the session, if any.
+    /// `key` is the agent key; files the CLI reads at launch go to `agent_files_dir(key)`.
+    async fn start(
+        &mut self,
+        key: &str,
+        cwd: &Path,
+        system_prompt: Option<&str>,
+        resume_session_id: Option<&str>,
+        mcp_url: Option<&str>,
+    ) -> Result<Option<String>, String>;
 
     /// Send a user message via ACP `prompt()`. Blocks until the turn completes.
+    /// `files` are attached as image or resource-link content blocks.
     /// Events are delivered via `subscribe()`.
-    async fn send_message(&self, text: &str) -> Result<(), String>;
+    async fn send_message(&self, text: &str, files: &[PromptFile]) -> Result<(), String>;
 
     /// Fire a prompt without waiting for the turn to complete.
     /// Returns immediately after the command is queued.
     /// Use `subscribe()` to consume events; the turn ends with `AgentEvent::TurnComplete`.
-    async fn send_message_fire(&self, text: &str) -> Result<(), String>;
+    async fn send_message_fire(&self, text: &str, files: &[PromptFile]) -> Result<(), String>;
 
     /// Subscribe to the agent's event stream.
     fn subscribe(&self) -> tokio::sync::broadcast::Receiver<AgentEvent>;
 
+    /// Stop the running turn (ACP `session/cancel`) while keeping the agent process alive.
+    /// The turn still ends with `AgentEvent::TurnComplete`, with `StopReason::Cancelled`.
+    async fn cancel(&self) -> Result<(), String>;
+
     /// Gracefully shut down the agent subprocess.
-    async fn shutdown(&mut self);
+    async fn shutdown(&self);
 
     /// Which agent kind this backend represents.
     fn kind(&self) -> AgentKind;
+
+    /// Answer a pending `AgentEvent::PermissionRequest`. `None` cancels the request.
+    /// Returns false if no such request is pending (already answered or timed out).
+    fn resolve_permission(&self, _request_id: &str, _option_id: Option<&str>) -> bool {
+        false
+    }
+
+    /// Whether the MCP server passed to `start` is reachable by the agent, either attached
+    /// to the ACP session or configured when the CLI was launched.
+    fn tools_available(&self) -> bool {
+        false
+    }
 }
 
 /// Create a new (unstarted) agent backend for the given kind.
@@ -137,19 +264,153 @@ pub fn create_backend(kind: AgentKind) -> Box<dyn AgentBackend> {
     Box::new(AcpBackend::new(kind))
 }
 
+/// Name of the VibeAround MCP server in agent configs and ACP sessions.
+const MCP_SERVER_NAME: &str = "vibearound";
+
+/// Directory for the files VibeAround writes for one agent (system prompt, MCP config), keyed
+/// by its agent key. It lives under `data_dir()` so that nothing is written into the workspace,
+/// which may be a user's project shared by several chats.
+pub fn agent_files_dir(key: &str) -> PathBuf {
+    crate::config::data_dir().join("agent_files").join(crate::workspace::path_component(key))
+}
+
+/// Environment variable that carries the MCP token to Codex, which reads it as a bearer token.
+const MCP_TOKEN_ENV: &str = "VIBEAROUND_MCP_TOKEN";
+
+/// Split the `token` query parameter off an MCP URL, so the token can be passed outside argv.
+fn take_url_token(url: &str) -> (String, Option<String>) {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return (url.to_string(), None);
+    };
+    let mut token = None;
+    let rest: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter_map(|(k, v)| {
+            if k == "token" {
+                token = Some(v.into_owned());
+                None
+            } else {
+                Some((k.into_owned(), v.into_owned()))
+            }
+        })
+        .collect();
+    if rest.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(&rest);
+    }
+    (parsed.to_string(), token)
+}
+
+/// Extra launch settings that point a native ACP CLI (Gemini, OpenCode, Codex) at the files
+/// in `agent_files_dir`.
+#[derive(Debug, Default)]
+pub struct AgentLaunch {
+    pub env: Vec<(&'static str, OsString)>,
+    pub args: Vec<String>,
+    /// The MCP server is configured at launch, so the agent has it even when it does not
+    /// accept HTTP MCP servers over ACP.
+    pub mcp_configured: bool,
+}
+
+impl AgentLaunch {
+    /// Write the system prompt and MCP config for `kind` into `dir`.
+    /// Claude gets both through the in-process bridge instead.
+    fn prepare(
+        kind: AgentKind,
+        dir: &Path,
+        system_prompt: Option<&str>,
+        mcp_url: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut launch = Self::default();
+        if matches!(kind, AgentKind::Claude) || (system_prompt.is_none() && mcp_url.is_none()) {
+            return Ok(launch);
+        }
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        let write = |name: &str, contents: &str| -> Result<OsString, String> {
+            let path = dir.join(name);
+            // The MCP config embeds the MCP token, so these files are private to the user.
+            crate::config::write_private_file(&path, contents.as_bytes())
+                .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+            Ok(path.into_os_string())
+        };
+        match kind {
+            AgentKind::Gemini => {
+                if let Some(prompt) = system_prompt {
+                    launch.env.push(("GEMINI_SYSTEM_MD", write("system.md", prompt)?));
+                }
+                if let Some(url) = mcp_url {
+                    // Stands in for the system-wide settings file; user and project settings still apply.
+                    let settings = serde_json::json!({ "mcpServers": { MCP_SERVER_NAME: { "httpUrl": url } } });
+                    launch.env.push(("GEMINI_CLI_SYSTEM_SETTINGS_PATH", write("settings.json", &settings.to_string())?));
+                    launch.mcp_configured = true;
+                }
+            }
+            AgentKind::OpenCode => {
+                // Merged on top of the global and project configs.
+                let mut config = serde_json::json!({});
+                if let Some(prompt) = system_prompt {
+                    config["instructions"] = serde_json::json!([write("AGENTS.md", prompt)?]);
+                }
+                if let Some(url) = mcp_url {
+                    config["mcp"] = serde_json::json!({ MCP_SERVER_NAME: { "type": "remote", "url": url, "enabled": true } });
+                    launch.mcp_configured = true;
+                }
+                launch.env.push(("OPENCODE_CONFIG", write("opencode.json", &config.to_string())?));
+            }
+            AgentKind::Codex => {
+                if let Some(prompt) = system_prompt {
+                    let path = write("instructions.md", prompt)?;
+                    launch.codex_override("experimental_instructions_file", &path.to_string_lossy());
+                }
+                if let Some(url) = mcp_url {
+                    // Overrides end up on argv, readable by any local user; the token goes in the
+                    // environment instead.
+                    let (url, token) = take_url_token(url);
+                    launch.codex_override(&format!("mcp_servers.{}.url", MCP_SERVER_NAME), &url);
+                    if let Some(token) = token {
+                        launch.codex_override(
+                            &format!("mcp_servers.{}.bearer_token_env_var", MCP_SERVER_NAME),
+                            MCP_TOKEN_ENV,
+                        );
+                        launch.env.push((MCP_TOKEN_ENV, token.into()));
+                    }
+                    launch.mcp_configured = true;
+                }
+            }
+            AgentKind::Claude => {}
+        }
+        Ok(launch)
+    }
+
+    /// Add a `-c key=value` config override. Values are TOML; a JSON-quoted string is valid TOML.
+    fn codex_override(&mut self, key: &str, value: &str) {
+        self.args.push("-c".to_string());
+        self.args.push(format!("{}={}", key, serde_json::Value::from(value)));
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Unified ACP backend — wraps ClientSideConnection for both Claude and Gemini
 // ---------------------------------------------------------------------------
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use dashmap::DashMap;
 use tokio::sync::{broadcast, mpsc, oneshot};
 
+/// Permission requests awaiting a user decision, keyed by request id.
+type PendingPermissions = Arc<DashMap<String, oneshot::Sender<Option<String>>>>;
+
 /// Commands sent from the main (Send) world to the ACP thread.
 enum AcpCmd {
     Prompt {
         text: String,
+        files: Vec<PromptFile>,
         done_tx: oneshot::Sender<Result<(), String>>,
     },
+    Cancel,
     Shutdown,
 }
 
@@ -158,8 +419,10 @@ enum AcpCmd {
 pub struct AcpBackend {
     agent_kind: AgentKind,
     event_tx: broadcast::Sender<AgentEvent>,
+    permissions: PendingPermissions,
+    tools_available: Arc<AtomicBool>,
     cmd_tx: Option<mpsc::Sender<AcpCmd>>,
-    thread_handle: Option<std::thread::JoinHandle<()>>,
+    thread_handle: std::sync::Mutex<Option<std::thread::JoinHandle<()>>>,
 }
 
 impl AcpBackend {
@@ -168,43 +431,70 @@ impl AcpBackend {
         Self {
             agent_kind,
             event_tx,
+            permissions: Arc::new(DashMap::new()),
+            tools_available: Arc::new(AtomicBool::new(false)),
             cmd_tx: None,
-            thread_handle: None,
+            thread_handle: std::sync::Mutex::new(None),
         }
     }
 }
 
 #[async_trait::async_trait]
 impl AgentBackend for AcpBackend {
-    async fn start(&mut self, cwd: &Path, system_prompt: Option<&str>) -> Result<Option<String>, String> {
+    async fn start(
+        &mut self,
+        key: &str,
+        cwd: &Path,
+        system_prompt: Option<&str>,
+        resume_session_id: Option<&str>,
+        mcp_url: Option<&str>,
+    ) -> Result<Option<String>, String> {
         let cwd = cwd.to_path_buf();
+        let files_dir = agent_files_dir(key);
         let event_tx = self.event_tx.clone();
+        let permissions = Arc::clone(&self.permissions);
+        let tools_available = Arc::clone(&self.tools_available);
         let agent_kind = self.agent_kind;
         let system_prompt_owned = system_prompt.map(|s| s.to_string());
+        let resume_owned = resume_session_id.map(|s| s.to_string());
+        let mcp_url_owned = mcp_url.map(|s| s.to_string());
         let (cmd_tx, cmd_rx) = mpsc::channel::<AcpCmd>(32);
         let (ready_tx, ready_rx) = oneshot::channel::<Result<Option<String>, String>>();
 
         let handle = std::thread::Builder::new()
             .name(format!("{}-acp", agent_kind))
             .spawn(move || {
-                run_acp_thread(agent_kind, cwd, event_tx, cmd_rx, ready_tx, system_prompt_owned);
+                run_acp_thread(
+                    agent_kind,
+                    cwd,
+                    files_dir,
+                    event_tx,
+                    permissions,
+                    tools_available,
+                    cmd_rx,
+                    ready_tx,
+                    system_prompt_owned,
+                    resume_owned,
+                    mcp_url_owned,
+                );
             })
             .map_err(|e| format!("Failed to spawn ACP thread: {}", e))?;
 
         self.cmd_tx = Some(cmd_tx);
-        self.thread_handle = Some(handle);
+        *self.thread_handle.lock().unwrap() = Some(handle);
 
         ready_rx
             .await
             .map_err(|_| "ACP thread died during init".to_string())?
     }
 
-    async fn send_message(&self, text: &str) -> Result<(), String> {
+    async fn send_message(&self, text: &str, files: &[PromptFile]) -> Result<(), String> {
         let cmd_tx = self.cmd_tx.as_ref().ok_or("Agent not started")?;
         let (done_tx, done_rx) = oneshot::channel();
         cmd_tx
             .send(AcpCmd::Prompt {
                 text: text.to_string(),
+                files: files.to_vec(),
                 done_tx,
             })
             .await
@@ -212,12 +502,13 @@ impl AgentBackend for AcpBackend {
         done_rx.await.map_err(|_| "ACP thread gone".to_string())?
     }
 
-    async fn send_message_fire(&self, text: &str) -> Result<(), String> {
+    async fn send_message_fire(&self, text: &str, files: &[PromptFile]) -> Result<(), String> {
         let cmd_tx = self.cmd_tx.as_ref().ok_or("Agent not started")?;
         let (done_tx, _done_rx) = oneshot::channel();
         cmd_tx
             .send(AcpCmd::Prompt {
                 text: text.to_string(),
+                files: files.to_vec(),
                 done_tx,
             })
             .await
@@ -230,11 +521,20 @@ impl AgentBackend for AcpBackend {
         self.event_tx.subscribe()
     }
 
-    async fn shutdown(&mut self) {
-        if let Some(tx) = self.cmd_tx.take() {
+    async fn cancel(&self) -> Result<(), String> {
+        let cmd_tx = self.cmd_tx.as_ref().ok_or("Agent not started")?;
+        cmd_tx
+            .send(AcpCmd::Cancel)
+            .await
+            .map_err(|_| "ACP thread gone".to_string())
+    }
+
+    async fn shutdown(&self) {
+        if let Some(tx) = &self.cmd_tx {
             let _ = tx.send(AcpCmd::Shutdown).await;
         }
-        if let Some(h) = self.thread_handle.take() {
+        let handle = self.thread_handle.lock().unwrap().take();
+        if let Some(h) = handle {
             let _ = h.join();
         }
         eprintln!("[{}-acp] shutdown", self.agent_kind);
@@ -243,16 +543,33 @@ impl AgentBackend for AcpBackend {
     fn kind(&self) -> AgentKind {
         self.agent_kind
     }
+
+    fn resolve_permission(&self, request_id: &str, option_id: Option<&str>) -> bool {
+        match self.permissions.remove(request_id) {
+            Some((_, tx)) => tx.send(option_id.map(|s| s.to_string())).is_ok(),
+            None => false,
+        }
+    }
+
+    fn tools_available(&self) -> bool {
+        self.tools_available.load(Ordering::Relaxed)
+    }
 }
 
 /// Runs on a dedicated thread with a single-threaded tokio runtime + LocalSet.
+#[allow(clippy::too_many_arguments)]
 fn run_acp_thread(
     agent_kind: AgentKind,
     cwd: PathBuf,
+    files_dir: PathBuf,
     event_tx: broadcast::Sender<AgentEvent>,
+    permissions: PendingPermissions,
+    tools_available: Arc<AtomicBool>,
     cmd_rx: mpsc::Receiver<AcpCmd>,
     ready_tx: oneshot::Sender<Result<Option<String>, String>>,
     system_prompt: Option<String>,
+    resume_session_id: Option<String>,
+    mcp_url: Option<String>,
 ) {
     let rt = match tokio::runtime::Builder::new_current_thread()
         .enable_all()
@@ -269,7 +586,21 @@ fn run_acp_thread(
         let local = tokio::task::LocalSet::new();
         local
             .run_until(async move {
-                match acp_session_loop(agent_kind, cwd, event_tx, cmd_rx, ready_tx, system_prompt).await {
+                match acp_session_loop(
+                    agent_kind,
+                    cwd,
+                    files_dir,
+                    event_tx,
+                    permissions,
+                    tools_available,
+                    cmd_rx,
+                    ready_tx,
+                    system_prompt,
+                    resume_session_id,
+                    mcp_url,
+                )
+                .await
+                {
                     Ok(()) => {}
                     Err(e) => eprintln!("[{}-acp] session loop error: {}", agent_kind, e),
                 }
@@ -280,42 +611,26 @@ fn run_acp_thread(
 
 /// The actual ACP session lifecycle, running inside LocalSet.
 /// Handles both Claude (via in-process duplex pipe) and Gemini (via subprocess stdio).
+#[allow(clippy::too_many_arguments)]
 async fn acp_session_loop(
     agent_kind: AgentKind,
     cwd: PathBuf,
+    files_dir: PathBuf,
     event_tx: broadcast::Sender<AgentEvent>,
+    permissions: PendingPermissions,
+    tools_available: Arc<AtomicBool>,
     mut cmd_rx: mpsc::Receiver<AcpCmd>,
     ready_tx: oneshot::Sender<Result<Option<String>, String>>,
     system_prompt: Option<String>,
+    resume_session_id: Option<String>,
+    mcp_url: Option<String>,
 ) -> Result<(), String> {
     use agent_client_protocol as acp;
     use acp::Agent as _;
     use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
-    // --- Write system prompt file for non-Claude agents (they read from files, not CLI flags) ---
-    if let Some(ref prompt) = system_prompt {
-        match agent_kind {
-            AgentKind::Gemini => {
-                // Gemini reads system prompt from GEMINI_SYSTEM_MD env var pointing to a file
-                let prompt_path = cwd.join(".gemini").join("system.md");
-                if let Some(parent) = prompt_path.parent() {
-                    let _ = std::fs::create_dir_all(parent);
-                }
-                let _ = std::fs::write(&prompt_path, prompt);
-            }
-            AgentKind::OpenCode => {
-                // OpenCode reads AGENTS.md from workspace root
-                let _ = std::fs::write(cwd.join("AGENTS.md"), prompt);
-            }
-            AgentKind::Codex => {
-                // Codex reads .codex/instructions.md from workspace
-                let dir = cwd.join(".codex");
-                let _ = std::fs::create_dir_all(&dir);
-                let _ = std::fs::write(dir.join("instructions.md"), prompt);
-            }
-            AgentKind::Claude => {} // handled via --system-prompt flag
-        }
-    }
+    // --- Write the system prompt and MCP config for non-Claude agents (they read files, not flags) ---
+    let launch = AgentLaunch::prepare(agent_kind, &files_dir, system_prompt.as_deref(), mcp_url.as_deref())?;
 
     // --- Obtain the read/write streams depending on agent kind ---
     let (read_stream, write_stream, _claude_thread, mut claude_real_session_id_rx): (
@@ -329,16 +644,15 @@ async fn acp_session_loop(
             (r, w, Some(h), Some(sid_rx))
         }
         AgentKind::Gemini => {
-            let system_md = system_prompt.as_ref().map(|_| cwd.join(".gemini").join("system.md"));
-            let (r, w) = gemini_acp::spawn_gemini_process(&cwd, system_md.as_deref())?;
+            let (r, w) = gemini_acp::spawn_gemini_process(&cwd, &launch)?;
             (r, w, None, None)
         }
         AgentKind::OpenCode => {
-            let (r, w) = opencode_acp::spawn_opencode_process(&cwd)?;
+            let (r, w) = opencode_acp::spawn_opencode_process(&cwd, &launch)?;
             (r, w, None, None)
         }
         AgentKind::Codex => {
-            let (r, w) = codex_acp::spawn_codex_process(&cwd)?;
+            let (r, w) = codex_acp::spawn_codex_process(&cwd, &launch)?;
             (r, w, None, None)
         }
     };
@@ -346,6 +660,7 @@ async fn acp_session_loop(
     // --- Create ACP ClientSideConnection ---
     let client_handler = SharedAcpClientHandler {
         event_tx: event_tx.clone(),
+        permissions: Arc::clone(&permissions),
     };
     let (conn, handle_io) = acp::ClientSideConnection::new(
         client_handler,
@@ -359,7 +674,7 @@ async fn acp_session_loop(
 
     // --- Initialize ---
     eprintln!("[{}-acp] sending initialize...", agent_kind);
-    let _init_resp = conn
+    let init_resp = conn
         .initialize(
             acp::InitializeRequest::new(acp::ProtocolVersion::V1)
                 .client_info(acp::Implementation::new("vibearound", "0.1.0").title("VibeAround")),
@@ -367,70 +682,178 @@ async fn acp_session_loop(
         .await
         .map_err(|e| format!("ACP initialize failed: {}", e))?;
     eprintln!("[{}-acp] initialize ok", agent_kind);
+    let accepts_images = init_resp.agent_capabilities.prompt_capabilities.image;
+    // The session's server replaces the launch-time one of the same name, if any.
+    let mcp_servers = match mcp_url {
+        Some(url) if init_resp.agent_capabilities.mcp_capabilities.http => {
+            tools_available.store(true, Ordering::Relaxed);
+            vec![acp::McpServer::Http(acp::McpServerHttp::new(MCP_SERVER_NAME, url))]
+        }
+        Some(_) if launch.mcp_configured => {
+            eprintln!("[{}-acp] agent does not accept HTTP MCP servers; using the launch config", agent_kind);
+            tools_available.store(true, Ordering::Relaxed);
+            Vec::new()
+        }
+        Some(_) => {
+            eprintln!("[{}-acp] agent does not accept HTTP MCP servers; VibeAround tools unavailable", agent_kind);
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
 
-    // --- Create session ---
-    eprintln!("[{}-acp] creating session in {:?}...", agent_kind, &cwd);
-    let session_resp = conn
-        .new_session(acp::NewSessionRequest::new(cwd))
-        .await
-        .map_err(|e| format!("ACP new_session failed: {}", e))?;
+    // --- Resume the previous session if asked and supported ---
+    let resumed = match resume_session_id {
+        Some(id) if init_resp.agent_capabilities.load_session => {
+            eprintln!("[{}-acp] loading session {}...", agent_kind, id);
+            match conn
+                .load_session(acp::LoadSessionRequest::new(id.clone(), cwd.clone()).mcp_servers(mcp_servers.clone()))
+                .await {
+                Ok(_) => {
+                    eprintln!("[{}-acp] session resumed: {}", agent_kind, id);
+                    Some(id)
+                }
+                Err(e) => {
+                    eprintln!("[{}-acp] load_session failed, starting a new session: {}", agent_kind, e);
+                    None
+                }
+            }
+        }
+        Some(_) => {
+            eprintln!("[{}-acp] agent cannot load sessions, starting a new session", agent_kind);
+            None
+        }
+        None => None,
+    };
 
-    let session_id = session_resp.session_id;
-    eprintln!("[{}-acp] session created: {:?}", agent_kind, session_id);
+    // --- Create session ---
+    let session_id: acp::SessionId = match &resumed {
+        Some(id) => id.clone().into(),
+        None => {
+            eprintln!("[{}-acp] creating session in {:?}...", agent_kind, &cwd);
+            let session_resp = conn
+                .new_session(acp::NewSessionRequest::new(cwd).mcp_servers(mcp_servers))
+                .await
+                .map_err(|e| format!("ACP new_session failed: {}", e))?;
+            eprintln!("[{}-acp] session created: {:?}", agent_kind, session_resp.session_id);
+            session_resp.session_id
+        }
+    };
 
+    // Claude's real CLI session id is only known after the first turn, unless it was resumed.
     let startup_session_id = if matches!(agent_kind, AgentKind::Claude) {
-        None
+        resumed.clone()
     } else {
         Some(session_id.to_string())
     };
     let _ = ready_tx.send(Ok(startup_session_id));
 
-    let mut real_cli_session_id: Option<String> = None;
+    let mut real_cli_session_id: Option<String> = resumed;
 
     // --- Command loop ---
+    // Prompts that arrive while a turn is running are queued; Cancel applies to the running turn.
+    type QueuedPrompt = (String, Vec<PromptFile>, oneshot::Sender<Result<(), String>>);
+    let mut queued: std::collections::VecDeque<QueuedPrompt> = std::collections::VecDeque::new();
     loop {
-        let cmd = match cmd_rx.recv().await {
-            Some(c) => c,
-            None => break,
+        let (text, files, done_tx) = match queued.pop_front() {
+            Some(prompt) => prompt,
+            None => match cmd_rx.recv().await {
+                Some(AcpCmd::Prompt { text, files, done_tx }) => (text, files, done_tx),
+                Some(AcpCmd::Cancel) => continue, // nothing running
+                Some(AcpCmd::Shutdown) | None => break,
+            },
         };
-        match cmd {
-            AcpCmd::Prompt { text, done_tx } => {
-                eprintln!("[{}-acp] sending prompt: {}", agent_kind, &text);
-                let text_content = acp::ContentBlock::Text(acp::TextContent::new(text));
-                let result = conn
-                    .prompt(acp::PromptRequest::new(
-                        session_id.clone(),
-                        vec![text_content],
-                    ))
-                    .await;
-                eprintln!("[{}-acp] prompt returned: {:?}", agent_kind, result.is_ok());
-                if let Some(session_id_rx) = claude_real_session_id_rx.as_mut() {
-                    while let Ok(discovered_session_id) = session_id_rx.try_recv() {
-                        real_cli_session_id = Some(discovered_session_id);
-                    }
-                }
-                match result {
-                    Ok(_) => {
-                        let _ = event_tx.send(AgentEvent::TurnComplete {
-                            session_id: real_cli_session_id.clone(),
-                            cost_usd: None,
-                        });
-                        let _ = done_tx.send(Ok(()));
-                    }
-                    Err(e) => {
-                        let err = format!("ACP prompt error: {}", e);
-                        let _ = event_tx.send(AgentEvent::Error(err.clone()));
-                        let _ = done_tx.send(Err(err));
+
+        eprintln!("[{}-acp] sending prompt: {} (+{} files)", agent_kind, &text, files.len());
+        let mut blocks = vec![acp::ContentBlock::Text(acp::TextContent::new(text))];
+        blocks.extend(files.iter().map(|f| prompt_file_block(f, accepts_images)));
+        let prompt = conn.prompt(acp::PromptRequest::new(session_id.clone(), blocks));
+        tokio::pin!(prompt);
+
+        let mut cancelled = false;
+        let result = loop {
+            tokio::select! {
+                result = &mut prompt => break result,
+                cmd = cmd_rx.recv() => match cmd {
+                    Some(AcpCmd::Prompt { text, files, done_tx }) => queued.push_back((text, files, done_tx)),
+                    Some(AcpCmd::Cancel) => {
+                        eprintln!("[{}-acp] cancelling turn", agent_kind);
+                        cancelled = true;
+                        // ACP: the client must answer outstanding permission requests with Cancelled.
+                        let pending: Vec<String> = permissions.iter().map(|e| e.key().clone()).collect();
+                        for request_id in pending {
+                            if let Some((_, tx)) = permissions.remove(&request_id) {
+                                let _ = tx.send(None);
+                            }
+                        }
+                        if let Err(e) = conn.cancel(acp::CancelNotification::new(session_id.clone())).await {
+                            eprintln!("[{}-acp] session/cancel failed: {}", agent_kind, e);
+                        }
                     }
-                }
+                    Some(AcpCmd::Shutdown) | None => return Ok(()),
+                },
+            }
+        };
+
+        eprintln!("[{}-acp] prompt returned: {:?}", agent_kind, result.is_ok());
+        if let Some(session_id_rx) = claude_real_session_id_rx.as_mut() {
+            while let Ok(discovered_session_id) = session_id_rx.try_recv() {
+                real_cli_session_id = Some(discovered_session_id);
+            }
+        }
+        match result {
+            Ok(resp) => {
+                let stop_reason = if cancelled { StopReason::Cancelled } else { resp.stop_reason.into() };
+                let _ = event_tx.send(AgentEvent::TurnComplete {
+                    session_id: real_cli_session_id.clone(),
+                    cost_usd: None,
+                    usage: resp.usage.map(TokenUsage::from),
+                    stop_reason,
+                });
+                let _ = done_tx.send(Ok(()));
+            }
+            // Some agents fail the prompt instead of returning `cancelled`; the user asked for it, so it is not an error.
+            Err(_) if cancelled => {
+                let _ = event_tx.send(AgentEvent::TurnComplete {
+                    session_id: real_cli_session_id.clone(),
+                    cost_usd: None,
+                    usage: None,
+                    stop_reason: StopReason::Cancelled,
+                });
+                let _ = done_tx.send(Ok(()));
+            }
+            Err(e) => {
+                let err = format!("ACP prompt error: {}", e);
+                let _ = event_tx.send(AgentEvent::Error(err.clone()));
+                let _ = done_tx.send(Err(err));
             }
-            AcpCmd::Shutdown => break,
         }
     }
 
     Ok(())
 }
 
+/// Images go inline when the agent accepts them; everything else is a `file://` resource link.
+fn prompt_file_block(file: &PromptFile, accepts_images: bool) -> agent_client_protocol::ContentBlock {
+    use agent_client_protocol as acp;
+    use base64::Engine as _;
+
+    if accepts_images && file.is_image() {
+        match std::fs::read(&file.path) {
+            Ok(bytes) => {
+                let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+                return acp::ContentBlock::Image(acp::ImageContent::new(data, file.mime_type.clone()));
+            }
+            Err(e) => eprintln!("[acp] failed to read image {}: {}", file.path.display(), e),
+        }
+    }
+    let uri = url::Url::from_file_path(&file.path)
+        .map(|u| u.to_string())
+        .unwrap_or_else(|_| file.path.display().to_string());
+    acp::ContentBlock::ResourceLink(
+        acp::ResourceLink::new(file.name.clone(), uri).mime_type(file.mime_type.clone()),
+    )
+}
+
 // ---------------------------------------------------------------------------
 // Shared ACP Client handler — receives notifications from any ACP agent
 // ---------------------------------------------------------------------------
@@ -439,6 +862,39 @@ async fn acp_session_loop(
 /// Used by both Claude (via adapter) and Gemini (native ACP).
 struct SharedAcpClientHandler {
     event_tx: broadcast::Sender<AgentEvent>,
+    permissions: PendingPermissions,
+}
+
+impl SharedAcpClientHandler {
+    /// Pick the option matching the configured default decision (timeout / auto mode).
+    fn default_outcome(
+        options: &[agent_client_protocol::PermissionOption],
+        allow: bool,
+    ) -> agent_client_protocol::RequestPermissionOutcome {
+        use agent_client_protocol::PermissionOptionKind as Kind;
+        let wanted: &[Kind] = if allow {
+            &[Kind::AllowOnce, Kind::AllowAlways]
+        } else {
+            &[Kind::RejectOnce, Kind::RejectAlways]
+        };
+        match options.iter().find(|o| wanted.contains(&o.kind)) {
+            Some(o) => agent_client_protocol::RequestPermissionOutcome::Selected(
+                agent_client_protocol::SelectedPermissionOutcome::new(o.option_id.clone()),
+            ),
+            None => agent_client_protocol::RequestPermissionOutcome::Cancelled,
+        }
+    }
+}
+
+fn permission_kind_str(kind: agent_client_protocol::PermissionOptionKind) -> &'static str {
+    use agent_client_protocol::PermissionOptionKind as Kind;
+    match kind {
+        Kind::AllowOnce => "allow_once",
+        Kind::AllowAlways => "allow_always",
+        Kind::RejectOnce => "reject_once",
+        Kind::RejectAlways => "reject_always",
+        _ => "other",
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -447,17 +903,69 @@ impl agent_client_protocol::Client for SharedAcpClientHandler {
         &self,
         args: agent_client_protocol::RequestPermissionRequest,
     ) -> agent_client_protocol::Result<agent_client_protocol::RequestPermissionResponse> {
-        // Auto-allow: pick the first option
-        let option_id = args
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT_PERMISSION_ID: AtomicU64 = AtomicU64::new(1);
+
+        let cfg = crate::config::ensure_loaded().permissions.clone();
+        if cfg.mode == crate::config::PermissionMode::Auto {
+            // Auto-allow: pick the first option
+            let option_id = args
+                .options
+                .first()
+                .map(|o| o.option_id.clone())
+                .unwrap_or_else(|| "allow".into());
+            return Ok(agent_client_protocol::RequestPermissionResponse::new(
+                agent_client_protocol::RequestPermissionOutcome::Selected(
+                    agent_client_protocol::SelectedPermissionOutcome::new(option_id),
+                ),
+            ));
+        }
+
+        // Short ids: IM button payloads are size-limited (Telegram allows 64 bytes).
+        let request_id = format!("p{}", NEXT_PERMISSION_ID.fetch_add(1, Ordering::Relaxed));
+        let (tx, rx) = oneshot::channel();
+        self.permissions.insert(request_id.clone(), tx);
+
+        let tool = args.tool_call.fields.title.clone().unwrap_or_else(|| "unknown".into());
+        let input = args.tool_call.fields.raw_input.as_ref().map(|v| {
+            if let Some(s) = v.as_str() { s.to_string() } else { v.to_string() }
+        });
+        let options = args
             .options
-            .first()
-            .map(|o| o.option_id.clone())
-            .unwrap_or_else(|| "allow".into());
-        Ok(agent_client_protocol::RequestPermissionResponse::new(
-            agent_client_protocol::RequestPermissionOutcome::Selected(
-                agent_client_protocol::SelectedPermissionOutcome::new(option_id),
-            ),
-        ))
+            .iter()
+            .map(|o| PermissionChoice {
+                id: o.option_id.to_string(),
+                label: o.name.clone(),
+                kind: permission_kind_str(o.kind).to_string(),
+            })
+            .collect();
+        let _ = self.event_tx.send(AgentEvent::PermissionRequest {
+            request_id: request_id.clone(),
+            tool,
+            input,
+            options,
+        });
+
+        let timeout = std::time::Duration::from_secs(cfg.timeout_secs);
+        let outcome = match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(Some(option_id))) if args.options.iter().any(|o| *o.option_id.0 == *option_id) => {
+                agent_client_protocol::RequestPermissionOutcome::Selected(
+                    agent_client_protocol::SelectedPermissionOutcome::new(option_id),
+                )
+            }
+            Ok(Ok(_)) | Ok(Err(_)) => agent_client_protocol::RequestPermissionOutcome::Cancelled,
+            Err(_) => {
+                self.permissions.remove(&request_id);
+                eprintln!(
+                    "[acp] permission request {} timed out after {}s, default={}",
+                    request_id,
+                    cfg.timeout_secs,
+                    if cfg.default_allow { "allow" } else { "deny" }
+                );
+                Self::default_outcome(&args.options, cfg.default_allow)
+            }
+        };
+        Ok(agent_client_protocol::RequestPermissionResponse::new(outcome))
     }
 
     async fn session_notification(
@@ -596,19 +1104,122 @@ pub struct JsonlBackend {
     agent_kind: AgentKind,
     event_tx: broadcast::Sender<AgentEvent>,
     cwd: Option<PathBuf>,
+    /// Kills the running turn's subprocess, if any.
+    cancel_tx: Arc<std::sync::Mutex<Option<oneshot::Sender<()>>>>,
 }
 
 impl JsonlBackend {
     pub fn new(agent_kind: AgentKind) -> Self {
         let (event_tx, _) = broadcast::channel(256);
-        Self { agent_kind, event_tx, cwd: None }
+        Self {
+            agent_kind,
+            event_tx,
+            cwd: None,
+            cancel_tx: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Register a new running turn and return the receiver that fires on `cancel()`.
+    fn arm_cancel(&self) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        *self.cancel_tx.lock().unwrap() = Some(tx);
+        rx
     }
 }
 
+/// Run one prompt as a subprocess, forwarding its output as events, then emit `TurnComplete`.
+async fn run_jsonl_turn(
+    agent_kind: AgentKind,
+    cwd: &Path,
+    text: String,
+    event_tx: &broadcast::Sender<AgentEvent>,
+    mut cancel_rx: oneshot::Receiver<()>,
+) -> Result<(), String> {
+    let (cmd, args): (&str, Vec<String>) = match agent_kind {
+        AgentKind::OpenCode => ("opencode", vec![
+            "run".into(), "--format".into(), "json".into(), "--".into(), text,
+        ]),
+        AgentKind::Codex => ("codex", vec![
+            "exec".into(), "--json".into(), "--full-auto".into(), text,
+        ]),
+        _ => unreachable!(),
+    };
+
+    eprintln!("[{}-jsonl] spawning: {} {:?}", agent_kind, cmd, &args[..args.len().min(3)]);
+
+    let mut child = tokio::process::Command::new(cmd)
+        .args(&args)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to spawn {}: {}", cmd, e))?;
+
+    let stdout = child.stdout.take().ok_or("No stdout")?;
+
+    // Read JSONL lines from stdout, parse into AgentEvents
+    let reader = BufReader::new(stdout);
+    let mut lines = reader.lines();
+    let mut stop_reason = StopReason::EndTurn;
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => line,
+                _ => break,
+            },
+            Ok(()) = &mut cancel_rx => {
+                eprintln!("[{}-jsonl] cancelling turn", agent_kind);
+                let _ = child.kill().await;
+                stop_reason = StopReason::Cancelled;
+                break;
+            }
+        };
+        if line.trim().is_empty() { continue; }
+        match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(msg) => match agent_kind {
+                AgentKind::OpenCode => opencode_jsonl::parse_event(&msg, event_tx),
+                AgentKind::Codex => codex_jsonl::parse_event(&msg, event_tx),
+                _ => {}
+            },
+            // Not JSON — treat as plain text output
+            Err(_) => {
+                let _ = event_tx.send(AgentEvent::Text(line));
+            }
+        }
+    }
+
+    // Wait for process to exit
+    let status = child.wait().await.map_err(|e| format!("{} wait: {}", cmd, e))?;
+    eprintln!("[{}-jsonl] process exited: {}", agent_kind, status);
+
+    // Emit TurnComplete so the worker's event loop knows we're done
+    let _ = event_tx.send(AgentEvent::TurnComplete { session_id: None, cost_usd: None, usage: None, stop_reason });
+
+    Ok(())
+}
+
+/// CLI-argument prompts cannot carry content blocks, so files are referenced by path.
+fn text_with_file_paths(text: &str, files: &[PromptFile]) -> String {
+    let mut out = text.to_string();
+    for file in files {
+        out.push_str(&format!("\n[Attached file: {}]", file.path.display()));
+    }
+    out
+}
+
 #[async_trait::async_trait]
 impl AgentBackend for JsonlBackend {
-    async fn start(&mut self, cwd: &Path, _system_prompt: Option<&str>) -> Result<Option<String>, String> {
-        // Just verify the CLI exists
+    async fn start(
+        &mut self,
+        _key: &str,
+        cwd: &Path,
+        _system_prompt: Option<&str>,
+        _resume_session_id: Option<&str>,
+        _mcp_url: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        // Just verify the CLI exists (each prompt is a fresh process, so there is nothing to resume)
         let cmd = match self.agent_kind {
             AgentKind::OpenCode => "opencode",
             AgentKind::Codex => "codex",
@@ -627,125 +1238,29 @@ impl AgentBackend for JsonlBackend {
         Ok(None)
     }
 
-    async fn send_message(&self, text: &str) -> Result<(), String> {
+    async fn send_message(&self, text: &str, files: &[PromptFile]) -> Result<(), String> {
         let cwd = self.cwd.as_ref().ok_or("Agent not started")?;
-        let event_tx = self.event_tx.clone();
-        let agent_kind = self.agent_kind;
-
-        let (cmd, args): (&str, Vec<String>) = match agent_kind {
-            AgentKind::OpenCode => ("opencode", vec![
-                "run".into(), "--format".into(), "json".into(), "--".into(), text.to_string(),
-            ]),
-            AgentKind::Codex => ("codex", vec![
-                "exec".into(), "--json".into(), "--full-auto".into(), text.to_string(),
-            ]),
-            _ => unreachable!(),
-        };
-
-        eprintln!("[{}-jsonl] spawning: {} {:?}", agent_kind, cmd, &args[..args.len().min(3)]);
-
-        let mut child = tokio::process::Command::new(cmd)
-            .args(&args)
-            .current_dir(cwd)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .kill_on_drop(true)
-            .spawn()
-            .map_err(|e| format!("Failed to spawn {}: {}", cmd, e))?;
-
-        let stdout = child.stdout.take().ok_or("No stdout")?;
-
-        // Read JSONL lines from stdout, parse into AgentEvents
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            if line.trim().is_empty() { continue; }
-            let msg: serde_json::Value = match serde_json::from_str(&line) {
-                Ok(v) => v,
-                Err(_) => {
-                    // Not JSON — treat as plain text output
-                    if !line.trim().is_empty() {
-                        let _ = event_tx.send(AgentEvent::Text(line));
-                    }
-                    continue;
-                }
-            };
-            match agent_kind {
-                AgentKind::OpenCode => opencode_jsonl::parse_event(&msg, &event_tx),
-                AgentKind::Codex => codex_jsonl::parse_event(&msg, &event_tx),
-                _ => {}
-            }
-        }
-
-        // Wait for process to exit
-        let status = child.wait().await.map_err(|e| format!("{} wait: {}", cmd, e))?;
-        eprintln!("[{}-jsonl] process exited: {}", agent_kind, status);
-
-        // Emit TurnComplete so the worker's event loop knows we're done
-        let _ = event_tx.send(AgentEvent::TurnComplete { session_id: None, cost_usd: None });
-
-        Ok(())
+        let cancel_rx = self.arm_cancel();
+        run_jsonl_turn(self.agent_kind, cwd, text_with_file_paths(text, files), &self.event_tx, cancel_rx).await
     }
 
-    async fn send_message_fire(&self, text: &str) -> Result<(), String> {
+    async fn send_message_fire(&self, text: &str, files: &[PromptFile]) -> Result<(), String> {
         let cwd = self.cwd.as_ref().ok_or("Agent not started")?.clone();
         let event_tx = self.event_tx.clone();
         let agent_kind = self.agent_kind;
-        let text = text.to_string();
+        let text = text_with_file_paths(text, files);
+        let cancel_rx = self.arm_cancel();
 
         tokio::spawn(async move {
-            let (cmd, args): (&str, Vec<String>) = match agent_kind {
-                AgentKind::OpenCode => ("opencode", vec![
-                    "run".into(), "--format".into(), "json".into(), "--".into(), text,
-                ]),
-                AgentKind::Codex => ("codex", vec![
-                    "exec".into(), "--json".into(), "--full-auto".into(), text,
-                ]),
-                _ => unreachable!(),
-            };
-
-            eprintln!("[{}-jsonl] spawning (fire): {} {:?}", agent_kind, cmd, &args[..args.len().min(3)]);
-
-            let mut child = match tokio::process::Command::new(cmd)
-                .args(&args)
-                .current_dir(&cwd)
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::inherit())
-                .kill_on_drop(true)
-                .spawn()
-            {
-                Ok(c) => c,
-                Err(e) => {
-                    let _ = event_tx.send(AgentEvent::Error(format!("Failed to spawn {}: {}", cmd, e)));
-                    let _ = event_tx.send(AgentEvent::TurnComplete { session_id: None, cost_usd: None });
-                    return;
-                }
-            };
-
-            if let Some(stdout) = child.stdout.take() {
-                let reader = BufReader::new(stdout);
-                let mut lines = reader.lines();
-                while let Ok(Some(line)) = lines.next_line().await {
-                    if line.trim().is_empty() { continue; }
-                    match serde_json::from_str::<serde_json::Value>(&line) {
-                        Ok(msg) => match agent_kind {
-                            AgentKind::OpenCode => opencode_jsonl::parse_event(&msg, &event_tx),
-                            AgentKind::Codex => codex_jsonl::parse_event(&msg, &event_tx),
-                            _ => {}
-                        },
-                        Err(_) => {
-                            if !line.trim().is_empty() {
-                                let _ = event_tx.send(AgentEvent::Text(line));
-                            }
-                        }
-                    }
-                }
+            if let Err(e) = run_jsonl_turn(agent_kind, &cwd, text, &event_tx, cancel_rx).await {
+                let _ = event_tx.send(AgentEvent::Error(e));
+                let _ = event_tx.send(AgentEvent::TurnComplete {
+                    session_id: None,
+                    cost_usd: None,
+                    usage: None,
+                    stop_reason: StopReason::EndTurn,
+                });
             }
-
-            let _ = child.wait().await;
-            let _ = event_tx.send(AgentEvent::TurnComplete { session_id: None, cost_usd: None });
         });
 
         Ok(())
@@ -755,8 +1270,17 @@ impl AgentBackend for JsonlBackend {
         self.event_tx.subscribe()
     }
 
-    async fn shutdown(&mut self) {
-        self.cwd = None;
+    async fn cancel(&self) -> Result<(), String> {
+        match self.cancel_tx.lock().unwrap().take() {
+            Some(tx) => {
+                let _ = tx.send(());
+                Ok(())
+            }
+            None => Err("No turn is running".to_string()),
+        }
+    }
+
+    async fn shutdown(&self) {
         eprintln!("[{}-jsonl] shutdown", self.agent_kind);
     }
 
@@ -764,3 +1288,48 @@ impl AgentBackend for JsonlBackend {
         self.agent_kind
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_url_token_moves_the_token_out_of_the_url() {
+        let (url, token) = take_url_token("http://127.0.0.1:5182/mcp?token=abc&caller=tg%3A1");
+        assert_eq!(url, "http://127.0.0.1:5182/mcp?caller=tg%3A1");
+        assert_eq!(token.as_deref(), Some("abc"));
+        let (url, token) = take_url_token("http://127.0.0.1:5182/mcp?token=abc");
+        assert_eq!(url, "http://127.0.0.1:5182/mcp");
+        assert_eq!(token.as_deref(), Some("abc"));
+        assert_eq!(take_url_token("http://127.0.0.1:5182/mcp").1, None);
+    }
+
+    #[test]
+    fn codex_gets_the_mcp_token_through_the_environment() {
+        let dir = std::env::temp_dir().join(format!("vibearound-launch-test-{}", std::process::id()));
+        let launch =
+            AgentLaunch::prepare(AgentKind::Codex, &dir, None, Some("http://127.0.0.1:1/mcp?token=secret")).unwrap();
+        assert!(launch.mcp_configured);
+        assert!(launch.args.iter().all(|a| !a.contains("secret")), "{:?}", launch.args);
+        assert!(launch.args.iter().any(|a| a == "mcp_servers.vibearound.bearer_token_env_var=\"VIBEAROUND_MCP_TOKEN\""));
+        assert_eq!(launch.env, [(MCP_TOKEN_ENV, OsString::from("secret"))]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("vibearound-launch-files-{}", std::process::id()));
+        let launch =
+            AgentLaunch::prepare(AgentKind::Gemini, &dir, Some("prompt"), Some("http://127.0.0.1:1/mcp?token=secret"))
+                .unwrap();
+        assert_eq!(launch.env.len(), 2);
+        for (_, path) in &launch.env {
+            let mode = std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{:?}", path);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
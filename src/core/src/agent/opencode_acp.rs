@@ -5,18 +5,23 @@
 
 use std::path::Path;
 
+use super::AgentLaunch;
+
 /// Spawn `opencode acp` and return (stdout_as_read, stdin_as_write) streams
 /// wrapped as `DuplexStream` via bridging tasks.
 ///
 /// `opencode acp` speaks ACP natively over stdin/stdout, same pattern as Gemini.
 pub fn spawn_opencode_process(
     cwd: &Path,
+    launch: &AgentLaunch,
 ) -> Result<(tokio::io::DuplexStream, tokio::io::DuplexStream), String> {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     eprintln!("[opencode-acp] spawning opencode acp in {:?}", cwd);
     let mut child = tokio::process::Command::new("opencode")
         .arg("acp")
+        .args(&launch.args)
+        .envs(launch.env.iter().map(|(k, v)| (*k, v)))
         .current_dir(cwd)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
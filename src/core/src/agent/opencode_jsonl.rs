@@ -6,10 +6,11 @@
 //!   {"type":"text", "part":{"text":"..."}}
 //!   {"type":"tool_start", "part":{"tool":"...", "input":{...}}}
 //!   {"type":"tool_finish", "part":{"output":"..."}}
-//!   {"type":"step_finish", "part":{"reason":"stop", "cost":0.0, "tokens":{...}}}
+//!   {"type":"step_finish", "part":{"reason":"stop", "cost":0.0,
+//!     "tokens":{"input":0, "output":0, "reasoning":0, "cache":{"read":0, "write":0}}}}
 
 use tokio::sync::broadcast;
-use super::AgentEvent;
+use super::{AgentEvent, StopReason, TokenUsage};
 
 pub fn parse_event(msg: &serde_json::Value, event_tx: &broadcast::Sender<AgentEvent>) {
     let msg_type = msg.get("type").and_then(|v| v.as_str()).unwrap_or("");
@@ -40,7 +41,13 @@ pub fn parse_event(msg: &serde_json::Value, event_tx: &broadcast::Sender<AgentEv
         }
         "step_finish" => {
             let cost = part.and_then(|p| p.get("cost")).and_then(|v| v.as_f64());
-            let _ = event_tx.send(AgentEvent::TurnComplete { session_id: None, cost_usd: cost });
+            let usage = part.and_then(|p| p.get("tokens")).and_then(token_usage);
+            let _ = event_tx.send(AgentEvent::TurnComplete {
+                session_id: None,
+                cost_usd: cost,
+                usage,
+                stop_reason: StopReason::EndTurn,
+            });
         }
         "error" => {
             let text = part.and_then(|p| p.get("message").or(p.get("text")))
@@ -50,3 +57,15 @@ pub fn parse_event(msg: &serde_json::Value, event_tx: &broadcast::Sender<AgentEv
         _ => {}
     }
 }
+
+/// Token counts from a `step_finish` part's `tokens`; None without input and output counts.
+fn token_usage(tokens: &serde_json::Value) -> Option<TokenUsage> {
+    let count = |pointer: &str| tokens.pointer(pointer).and_then(|v| v.as_u64());
+    Some(TokenUsage {
+        input_tokens: count("/input")?,
+        output_tokens: count("/output")?,
+        thought_tokens: count("/reasoning"),
+        cached_read_tokens: count("/cache/read"),
+        cached_write_tokens: count("/cache/write"),
+    })
+}
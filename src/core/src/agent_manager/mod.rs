@@ -5,29 +5,68 @@
 //! - Maintain its own agent process table (keyed by channel:chat:profile:cli)
 //! - Load agent profiles from ~/.vibearound/agents/<profile>/profile/
 //! - Forward messages to agents and stream replies back to SessionHub
+//! - Run worker agents for the Manager's `dispatch_task` MCP tool
+//! - Record every conversation as a JSONL transcript (see `session_store`)
+//! - Resume a chat's last CLI session when its agent is respawned (e.g. after a restart)
 //! - Kill agents on session reset
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
-use tokio::sync::{broadcast, OnceCell};
+use tokio::sync::{broadcast, Mutex, OnceCell};
 
-use crate::agent::{self, AgentBackend, AgentEvent, AgentKind};
+use crate::agent::{self, AgentBackend, AgentEvent, AgentKind, PromptFile, StopReason};
 use crate::config::{self, ImVerboseConfig};
 use crate::session_hub::types::*;
 use crate::session_hub::SessionHub;
+use crate::session_store::{self, SessionOrigin, SessionWriter};
 
 struct AgentProcess {
-    backend: Box<dyn AgentBackend>,
+    backend: Arc<dyn AgentBackend>,
     cli_session_id: Option<String>,
+    /// Chat this agent currently reports to. Workers take the route of whoever dispatched to them.
+    route: Option<TurnRoute>,
+}
+
+/// Where a running turn's output goes.
+#[derive(Debug, Clone)]
+struct TurnRoute {
+    channel_kind: ChannelKind,
+    chat_id: ChatId,
+    message_id: MessageId,
+}
+
+/// How long a dispatched worker task may run before the worker is stopped.
+const DISPATCH_TASK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Which optional parts of a turn are relayed to a chat. The web chat always gets tool calls and
+/// thinking: protocol 2 clients render them as typed events, and protocol 1 connections apply
+/// `channels.web.verbose` themselves. Other channels follow their `verbose` settings.
+fn relayed_events(cfg: &config::Config, channel_kind: &str) -> ImVerboseConfig {
+    if channel_kind == "web" {
+        ImVerboseConfig { show_thinking: true, show_tool_use: true }
+    } else {
+        cfg.channel_verbose(channel_kind)
+    }
 }
 
 fn agent_key(channel_kind: &str, chat_id: &str, profile: &str, cli_kind: &str) -> String {
     format!("{}:{}:{}:{}", channel_kind, chat_id, profile, cli_kind)
 }
 
+fn worker_key(workspace: &Path, kind: AgentKind) -> String {
+    format!("worker:{}:{}", workspace.display(), kind)
+}
+
 pub struct AgentManager {
     agents: DashMap<String, AgentProcess>,
+    /// One lock per worker key so concurrent dispatches to the same worker run one at a time.
+    worker_locks: DashMap<String, Arc<Mutex<()>>>,
+    /// Open transcripts, keyed like `agents`. Closed when the agent is killed.
+    transcripts: DashMap<String, SessionWriter>,
     session_hub: OnceCell<Arc<SessionHub>>,
     hub_tx: broadcast::Sender<HubEvent>,
 }
@@ -37,6 +76,8 @@ impl AgentManager {
         let (hub_tx, _) = broadcast::channel(64);
         Self {
             agents: DashMap::new(),
+            worker_locks: DashMap::new(),
+            transcripts: DashMap::new(),
             session_hub: OnceCell::new(),
             hub_tx,
         }
@@ -78,7 +119,7 @@ impl AgentManager {
                 chat_id,
                 message,
             } => {
-                let verbose = config::ensure_loaded().channel_verbose(&channel_kind);
+                let verbose = relayed_events(&config::ensure_loaded(), &channel_kind);
                 self.dispatch(
                     message,
                     verbose,
@@ -86,11 +127,30 @@ impl AgentManager {
                     self.get_session_profile(&channel_kind, &chat_id).await,
                 );
             }
-            crate::session_hub::types::AgentEvent::OnStopRuntime { channel_kind, chat_id }
-            | crate::session_hub::types::AgentEvent::OnCloseRuntime { channel_kind, chat_id, .. } => {
+            crate::session_hub::types::AgentEvent::OnStopRuntime { channel_kind, chat_id } => {
                 self.kill_chat_agents(&channel_kind, &chat_id).await;
             }
+            crate::session_hub::types::AgentEvent::OnCloseRuntime { channel_kind, chat_id, reason } => {
+                self.close_chat_agents(&channel_kind, &chat_id, reason.as_deref()).await;
+            }
+            crate::session_hub::types::AgentEvent::OnCancelTurn { channel_kind, chat_id } => {
+                self.cancel_chat_turn(&channel_kind, &chat_id).await;
+            }
             crate::session_hub::types::AgentEvent::OnStartRuntime { .. } => {}
+            crate::session_hub::types::AgentEvent::OnPermissionResponse {
+                channel_kind,
+                chat_id,
+                request_id,
+                option_id,
+            } => {
+                if !self.resolve_chat_permission(&channel_kind, &chat_id, &request_id, option_id.as_deref()) {
+                    self.session_hub().agent_system_text(
+                        &channel_kind,
+                        &chat_id,
+                        "That permission request is no longer pending.".to_string(),
+                    );
+                }
+            }
         }
     }
 
@@ -120,11 +180,34 @@ impl AgentManager {
         let profile_owned = profile.unwrap_or_else(|| "default".to_string());
         let key = agent_key(&msg.channel_kind, &msg.chat_id, &profile_owned, &cli_kind_owned);
         let pfx = format!("[AgentManager][{}]", key);
+        let origin = SessionOrigin {
+            channel_kind: msg.channel_kind.clone(),
+            chat_id: msg.chat_id.clone(),
+            profile: profile_owned.clone(),
+        };
 
-        let startup_session_id = match self.ensure_agent(&key, kind, &profile_owned).await {
+        let binding = self.session_hub().get_session_workspace(&msg.channel_kind, &msg.chat_id).await;
+        let workspace = crate::workspace::chat_workspace(&msg.channel_kind, &msg.chat_id, binding.as_deref());
+
+        // Ensure first: a resumed agent continues its previous transcript.
+        let ensured = self.ensure_agent(&key, kind, &origin, &workspace).await;
+
+        self.open_transcript(
+            &key,
+            kind,
+            &session_store::manager_sessions_dir(),
+            "manager",
+            &workspace,
+            Some(origin),
+            &msg.text,
+        );
+        self.record(&key, |t| t.append_user_message(&msg.text));
+
+        let startup_session_id = match ensured {
             Ok(session_id) => session_id,
             Err(e) => {
                 eprintln!("{} failed to ensure agent: {}", pfx, e);
+                self.record(&key, |t| t.append_agent_event(&key, &AgentEvent::Error(e.clone())));
                 self.session_hub()
                     .agent_acp_event(AgentReply {
                         channel_kind: msg.channel_kind,
@@ -142,36 +225,44 @@ impl AgentManager {
 
         eprintln!("{} → text={}", pfx, truncate(&msg.text, 80));
 
-        let mut rx = {
-            let entry = match self.agents.get(&key) {
-                Some(e) => e,
-                None => {
-                    eprintln!("{} agent not found after ensure", pfx);
-                    return;
-                }
-            };
-            let rx = entry.backend.subscribe();
-            if let Err(e) = entry.backend.send_message_fire(&msg.text).await {
-                eprintln!("{} send_message_fire failed: {}", pfx, e);
-                self.session_hub()
-                    .agent_acp_event(AgentReply {
-                        channel_kind: msg.channel_kind,
-                        chat_id: msg.chat_id,
-                        message_id: msg.message_id,
-                        session_id: String::new(),
-                        event: AgentReplyEvent::Error { error: e },
-                    })
-                    .await;
+        // Clone the backend out so no map guard is held across the send.
+        let backend = match self.agents.get_mut(&key) {
+            Some(mut entry) => {
+                entry.route = Some(TurnRoute {
+                    channel_kind: msg.channel_kind.clone(),
+                    chat_id: msg.chat_id.clone(),
+                    message_id: msg.message_id.clone(),
+                });
+                Arc::clone(&entry.backend)
+            }
+            None => {
+                eprintln!("{} agent not found after ensure", pfx);
                 return;
             }
-            rx
         };
 
+        let mut rx = backend.subscribe();
+        let files = import_attachments(&workspace, &msg.attachments);
+        if let Err(e) = backend.send_message_fire(&msg.text, &files).await {
+            eprintln!("{} send_message_fire failed: {}", pfx, e);
+            self.session_hub()
+                .agent_acp_event(AgentReply {
+                    channel_kind: msg.channel_kind,
+                    chat_id: msg.chat_id,
+                    message_id: msg.message_id,
+                    session_id: String::new(),
+                    event: AgentReplyEvent::Error { error: e },
+                })
+                .await;
+            return;
+        }
+
         let channel_kind = msg.channel_kind.clone();
         let chat_id = msg.chat_id.clone();
         let message_id = msg.message_id.clone();
 
         if let Some(session_id) = startup_session_id {
+            self.record_cli_session_id(&key, &session_id);
             self.session_hub()
                 .agent_session_id_ready(AgentReady {
                     channel_kind: channel_kind.clone(),
@@ -196,15 +287,20 @@ impl AgentManager {
             .await;
 
         let key_clone = key.clone();
+        // Tool results only carry the call id; remember names to label them.
+        let mut tool_names: HashMap<String, String> = HashMap::new();
 
         loop {
             match rx.recv().await {
                 Ok(event) => {
+                    self.record(&key_clone, |t| t.append_agent_event(&key_clone, &event));
+
                     if let AgentEvent::TurnComplete {
                         session_id: Some(real_session_id),
                         ..
                     } = &event
                     {
+                        self.record_cli_session_id(&key_clone, real_session_id);
                         let should_notify_ready = if let Some(mut entry) = self.agents.get_mut(&key_clone)
                         {
                             if entry.cli_session_id.is_none() {
@@ -241,9 +337,11 @@ impl AgentManager {
                                 None
                             }
                         }
-                        AgentEvent::ToolUse { name, input, .. } => {
+                        AgentEvent::ToolUse { name, id, input } => {
+                            tool_names.insert(id.clone(), name.clone());
                             if verbose.show_tool_use {
                                 Some(AgentReplyEvent::ToolUse {
+                                    tool_id: id.clone(),
                                     tool: name.clone(),
                                     input: input.as_deref().unwrap_or("").to_string(),
                                 })
@@ -251,23 +349,40 @@ impl AgentManager {
                                 None
                             }
                         }
-                        AgentEvent::ToolResult { output, .. } => {
+                        AgentEvent::ToolResult { id, output, is_error } => {
+                            let tool = tool_names.remove(id).unwrap_or_default();
                             if verbose.show_tool_use {
                                 Some(AgentReplyEvent::ToolResult {
-                                    tool: String::new(),
+                                    tool_id: id.clone(),
+                                    tool,
                                     output: output.as_deref().unwrap_or("").to_string(),
+                                    is_error: *is_error,
                                 })
                             } else {
                                 None
                             }
                         }
-                        AgentEvent::TurnComplete { .. } => Some(AgentReplyEvent::Complete),
+                        AgentEvent::PermissionRequest { request_id, tool, input, options } => {
+                            Some(AgentReplyEvent::PermissionRequest {
+                                request_id: request_id.clone(),
+                                tool: tool.clone(),
+                                input: input.as_deref().unwrap_or("").to_string(),
+                                options: options.clone(),
+                            })
+                        }
+                        AgentEvent::TurnComplete { stop_reason, cost_usd, usage, .. } => {
+                            Some(AgentReplyEvent::Complete {
+                                stop_reason: *stop_reason,
+                                cost_usd: *cost_usd,
+                                usage: *usage,
+                            })
+                        }
                         AgentEvent::Error(e) => Some(AgentReplyEvent::Error { error: e.clone() }),
                         _ => None,
                     };
 
                     if let Some(re) = reply_event {
-                        let is_complete = matches!(re, AgentReplyEvent::Complete);
+                        let is_complete = matches!(re, AgentReplyEvent::Complete { .. });
                         self.session_hub()
                             .agent_acp_event(AgentReply {
                                 channel_kind: channel_kind.clone(),
@@ -296,7 +411,11 @@ impl AgentManager {
                             chat_id: chat_id.clone(),
                             message_id: message_id.clone(),
                             session_id: String::new(),
-                            event: AgentReplyEvent::Complete,
+                            event: AgentReplyEvent::Complete {
+                                stop_reason: StopReason::EndTurn,
+                                cost_usd: None,
+                                usage: None,
+                            },
                         })
                         .await;
                     self.session_hub()
@@ -328,34 +447,101 @@ impl AgentManager {
         &self,
         key: &str,
         kind: AgentKind,
-        profile: &str,
+        origin: &SessionOrigin,
+        workspace: &Path,
     ) -> Result<Option<String>, String> {
         if let Some(entry) = self.agents.get(key) {
             return Ok(entry.cli_session_id.clone());
         }
 
-        let workspace = config::data_dir().join("workspaces");
         if !workspace.exists() {
-            std::fs::create_dir_all(&workspace)
+            std::fs::create_dir_all(workspace)
                 .map_err(|e| format!("Failed to create workspace {:?}: {}", workspace, e))?;
         }
 
-        let port = config::DEFAULT_PORT;
-        crate::agent::manager_prompt::ensure_mcp_config(kind, &workspace, port);
+        let mcp_url = crate::agent::manager_prompt::mcp_url(config::DEFAULT_PORT, Some(key));
+
+        let system_prompt = load_agent_profile(&origin.profile)
+            .or_else(|| Some(crate::agent::manager_prompt::load_manager_prompt()));
+
+        let resume = self.previous_cli_session(key, kind, origin, workspace);
+        let resume_id = resume.as_ref().map(|(_, id)| id.as_str());
+        let cli_session_id = self
+            .spawn_agent(key, kind, workspace, system_prompt.as_deref(), resume_id, Some(&mcp_url))
+            .await?;
 
-        let system_prompt =
-            load_agent_profile(profile).or_else(|| Some(crate::agent::manager_prompt::load_manager_prompt()));
+        let tools_available = self.agents.get(key).is_some_and(|entry| entry.backend.tools_available());
+        if !tools_available {
+            self.session_hub().agent_system_text(
+                &origin.channel_kind,
+                &origin.chat_id,
+                format!("VibeAround tools (dispatch_task) are unavailable: {} does not accept the MCP server.", kind),
+            );
+        }
+
+        if let Some((path, id)) = resume {
+            if cli_session_id.as_deref() == Some(id.as_str()) {
+                eprintln!("[AgentManager][{}] resumed cli session {}", key, id);
+                if let Some(path) = path {
+                    match SessionWriter::reopen(&path) {
+                        Ok(writer) => {
+                            self.transcripts.insert(key.to_string(), writer);
+                        }
+                        Err(e) => eprintln!("[AgentManager][{}] failed to reopen transcript: {}", key, e),
+                    }
+                }
+            } else {
+                eprintln!("[AgentManager][{}] could not resume cli session {}", key, id);
+                // The open transcript (if any) belongs to the lost session; start a new one.
+                self.transcripts.remove(key);
+                self.session_hub().agent_system_text(
+                    &origin.channel_kind,
+                    &origin.chat_id,
+                    format!("Could not resume the previous {} session ({}). Started a new session.", kind, id),
+                );
+            }
+        }
 
+        Ok(cli_session_id)
+    }
+
+    /// The CLI session to resume when respawning `key`: the open transcript's session (the agent
+    /// died mid-run), else the chat's last unclosed session on disk (the daemon restarted).
+    /// The path is set when the transcript is on disk but not open.
+    fn previous_cli_session(
+        &self,
+        key: &str,
+        kind: AgentKind,
+        origin: &SessionOrigin,
+        workspace: &Path,
+    ) -> Option<(Option<PathBuf>, String)> {
+        if let Some(writer) = self.transcripts.get(key) {
+            return writer.header.cli_session_id.clone().map(|id| (None, id));
+        }
+        session_store::resumable_chat_session(&session_store::manager_sessions_dir(), kind, origin, workspace)
+            .map(|(meta, id)| (Some(meta.path), id))
+    }
+
+    async fn spawn_agent(
+        &self,
+        key: &str,
+        kind: AgentKind,
+        workspace: &Path,
+        system_prompt: Option<&str>,
+        resume_session_id: Option<&str>,
+        mcp_url: Option<&str>,
+    ) -> Result<Option<String>, String> {
         let mut backend = agent::create_backend(kind);
-        let cli_session_id = backend.start(&workspace, system_prompt.as_deref()).await?;
+        let cli_session_id = backend.start(key, workspace, system_prompt, resume_session_id, mcp_url).await?;
 
         eprintln!("[AgentManager] spawned agent: {}", key);
 
         self.agents.insert(
             key.to_string(),
             AgentProcess {
-                backend,
+                backend: Arc::from(backend),
                 cli_session_id: cli_session_id.clone(),
+                route: None,
             },
         );
 
@@ -367,8 +553,177 @@ impl AgentManager {
         Ok(cli_session_id)
     }
 
+    /// Run `message` on the worker agent for (`workspace`, `kind`), spawning it if needed.
+    /// Output is streamed into the chat of the calling agent (`caller` = its agent key) when known.
+    /// Returns the worker's final text.
+    pub async fn dispatch_task(
+        &self,
+        caller: Option<&str>,
+        workspace: PathBuf,
+        message: &str,
+        kind: Option<AgentKind>,
+    ) -> Result<String, String> {
+        let cfg = config::ensure_loaded();
+        let kind = kind
+            .or_else(|| AgentKind::from_str_loose(&cfg.default_agent))
+            .unwrap_or(AgentKind::Claude);
+        let key = worker_key(&workspace, kind);
+        let pfx = format!("[AgentManager][{}]", key);
+
+        let route = caller.and_then(|c| self.agents.get(c).and_then(|e| e.route.clone()));
+        if route.is_none() {
+            eprintln!("{} caller {:?} unknown; worker output will not be streamed", pfx, caller);
+        }
+
+        let lock = self
+            .worker_locks
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone();
+        let _guard = lock.lock().await;
+
+        if !self.agents.contains_key(&key) {
+            std::fs::create_dir_all(&workspace)
+                .map_err(|e| format!("Failed to create workspace {:?}: {}", workspace, e))?;
+            self.spawn_agent(&key, kind, &workspace, None, None, None).await?;
+        }
+
+        eprintln!("{} → task={}", pfx, truncate(message, 80));
+
+        self.open_transcript(
+            &key,
+            kind,
+            &session_store::workspace_sessions_dir(&workspace),
+            "worker",
+            &workspace,
+            None,
+            message,
+        );
+        self.record(&key, |t| t.append_user_message(message));
+
+        let backend = {
+            let mut entry = self
+                .agents
+                .get_mut(&key)
+                .ok_or_else(|| "Worker agent exited before the task was sent".to_string())?;
+            entry.route = route.clone();
+            Arc::clone(&entry.backend)
+        };
+        let mut rx = backend.subscribe();
+        backend.send_message_fire(message, &[]).await?;
+        let deadline = tokio::time::Instant::now() + DISPATCH_TASK_TIMEOUT;
+
+        let verbose = route
+            .as_ref()
+            .map(|r| relayed_events(&cfg, &r.channel_kind))
+            .unwrap_or_default();
+        let mut output = String::new();
+        let mut tool_names: HashMap<String, String> = HashMap::new();
+
+        loop {
+            let Ok(received) = tokio::time::timeout_at(deadline, rx.recv()).await else {
+                eprintln!("{} task timed out after {}s", pfx, DISPATCH_TASK_TIMEOUT.as_secs());
+                self.record(&key, |t| {
+                    t.append_agent_event(&key, &AgentEvent::Error("Task timed out".to_string()))
+                });
+                // The worker may still be mid-turn; a fresh one is spawned for the next task.
+                self.kill_agent(&key).await;
+                return Err(format!(
+                    "The task did not finish within {} minutes",
+                    DISPATCH_TASK_TIMEOUT.as_secs() / 60
+                ));
+            };
+            let event = match received {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    eprintln!("{} event stream lagged by {} events", pfx, n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    self.record(&key, |t| {
+                        t.append_agent_event(&key, &AgentEvent::Error("Worker agent exited".to_string()))
+                    });
+                    self.kill_agent(&key).await;
+                    return Err("Worker agent exited before finishing the task".to_string());
+                }
+            };
+
+            self.record(&key, |t| t.append_agent_event(&key, &event));
+
+            let reply_event = match &event {
+                AgentEvent::Text(t) => {
+                    output.push_str(t);
+                    Some(AgentReplyEvent::Token { delta: t.clone() })
+                }
+                AgentEvent::Thinking(t) if verbose.show_thinking => {
+                    Some(AgentReplyEvent::Thinking { text: t.clone() })
+                }
+                AgentEvent::ToolUse { name, id, input } if verbose.show_tool_use => {
+                    tool_names.insert(id.clone(), name.clone());
+                    Some(AgentReplyEvent::ToolUse {
+                        tool_id: id.clone(),
+                        tool: name.clone(),
+                        input: input.as_deref().unwrap_or("").to_string(),
+                    })
+                }
+                AgentEvent::ToolResult { id, output, is_error } if verbose.show_tool_use => {
+                    Some(AgentReplyEvent::ToolResult {
+                        tool_id: id.clone(),
+                        tool: tool_names.remove(id).unwrap_or_default(),
+                        output: output.as_deref().unwrap_or("").to_string(),
+                        is_error: *is_error,
+                    })
+                }
+                AgentEvent::PermissionRequest { request_id, tool, input, options } => {
+                    Some(AgentReplyEvent::PermissionRequest {
+                        request_id: request_id.clone(),
+                        tool: tool.clone(),
+                        input: input.as_deref().unwrap_or("").to_string(),
+                        options: options.clone(),
+                    })
+                }
+                _ => None,
+            };
+
+            if let (Some(re), Some(r)) = (reply_event, route.as_ref()) {
+                self.session_hub()
+                    .agent_acp_event(AgentReply {
+                        channel_kind: r.channel_kind.clone(),
+                        chat_id: r.chat_id.clone(),
+                        message_id: r.message_id.clone(),
+                        session_id: String::new(),
+                        event: re,
+                    })
+                    .await;
+            }
+
+            match event {
+                AgentEvent::TurnComplete { session_id, stop_reason, .. } => {
+                    if let Some(id) = &session_id {
+                        self.record_cli_session_id(&key, id);
+                    }
+                    if let Some(mut entry) = self.agents.get_mut(&key) {
+                        if entry.cli_session_id.is_none() {
+                            entry.cli_session_id = session_id;
+                        }
+                    }
+                    if stop_reason == StopReason::Cancelled {
+                        return Err("The task was cancelled by the user".to_string());
+                    }
+                    break;
+                }
+                AgentEvent::Error(e) => return Err(e),
+                _ => {}
+            }
+        }
+
+        eprintln!("{} task complete ({} chars)", pfx, output.len());
+        Ok(output)
+    }
+
     pub async fn kill_agent(&self, key: &str) {
-        if let Some((_, mut process)) = self.agents.remove(key) {
+        self.transcripts.remove(key);
+        if let Some((_, process)) = self.agents.remove(key) {
             process.backend.shutdown().await;
             let _ = self.hub_tx.send(HubEvent::OnAgentKilled {
                 key: key.to_string(),
@@ -390,6 +745,109 @@ impl AgentManager {
         }
     }
 
+    /// Kill the chat's agents and mark their sessions closed so they are not resumed.
+    pub async fn close_chat_agents(&self, channel_kind: &str, chat_id: &str, reason: Option<&str>) {
+        let prefix = format!("{}:{}:", channel_kind, chat_id);
+        self.transcripts.retain(|key, _| !key.starts_with(&prefix));
+        session_store::close_chat_sessions(&session_store::manager_sessions_dir(), channel_kind, chat_id, reason);
+        self.kill_chat_agents(channel_kind, chat_id).await;
+    }
+
+    /// Whether a live agent is writing the transcript at `path`, i.e. its CLI session is in use.
+    pub fn is_transcript_open(&self, path: &Path) -> bool {
+        self.transcripts.iter().any(|t| t.path == path)
+    }
+
+    /// Cancel the running turn of every agent reporting to this chat, including workers
+    /// running a task dispatched from it. Agent processes stay alive.
+    pub async fn cancel_chat_turn(&self, channel_kind: &str, chat_id: &str) {
+        let keys: Vec<String> = self
+            .agents
+            .iter()
+            .filter(|e| Self::reports_to(e.key(), e.value(), channel_kind, chat_id))
+            .map(|e| e.key().clone())
+            .collect();
+        for key in keys {
+            let Some(backend) = self.agents.get(&key).map(|e| Arc::clone(&e.backend)) else { continue };
+            match backend.cancel().await {
+                Ok(()) => eprintln!("[AgentManager] cancel sent: {}", key),
+                Err(e) => eprintln!("[AgentManager] cancel failed for {}: {}", key, e),
+            }
+        }
+    }
+
+    /// Whether the agent under `key` belongs to the chat or is currently working for it.
+    fn reports_to(key: &str, process: &AgentProcess, channel_kind: &str, chat_id: &str) -> bool {
+        key.starts_with(&format!("{}:{}:", channel_kind, chat_id))
+            || process
+                .route
+                .as_ref()
+                .is_some_and(|r| r.channel_kind == channel_kind && r.chat_id == chat_id)
+    }
+
+    /// Hand a permission answer to whichever agent reporting to this chat is waiting on `request_id`
+    /// (the chat's own agents, or a worker currently running a task dispatched from it).
+    fn resolve_chat_permission(
+        &self,
+        channel_kind: &str,
+        chat_id: &str,
+        request_id: &str,
+        option_id: Option<&str>,
+    ) -> bool {
+        self.agents
+            .iter()
+            .filter(|e| Self::reports_to(e.key(), e.value(), channel_kind, chat_id))
+            .any(|e| e.value().backend.resolve_permission(request_id, option_id))
+    }
+
+    /// Start a transcript file for `key` unless one is already open.
+    /// The first message becomes the session summary.
+    #[allow(clippy::too_many_arguments)]
+    fn open_transcript(
+        &self,
+        key: &str,
+        kind: AgentKind,
+        sessions_dir: &Path,
+        role: &str,
+        workspace: &Path,
+        origin: Option<SessionOrigin>,
+        first_message: &str,
+    ) {
+        if self.transcripts.contains_key(key) {
+            return;
+        }
+        let cli_session_id = self.agents.get(key).and_then(|e| e.cli_session_id.clone());
+        let workspace = workspace.display().to_string();
+        match SessionWriter::create(
+            sessions_dir,
+            kind,
+            role,
+            &workspace,
+            cli_session_id.as_deref(),
+            Some(truncate(first_message, 80)),
+            origin,
+        ) {
+            Ok(writer) => {
+                self.transcripts.insert(key.to_string(), writer);
+            }
+            Err(e) => eprintln!("[AgentManager][{}] failed to create transcript: {}", key, e),
+        }
+    }
+
+    fn record(&self, key: &str, f: impl FnOnce(&mut SessionWriter)) {
+        if let Some(mut writer) = self.transcripts.get_mut(key) {
+            f(&mut writer);
+        }
+    }
+
+    fn record_cli_session_id(&self, key: &str, cli_session_id: &str) {
+        self.record(key, |t| {
+            if t.header.cli_session_id.as_deref() != Some(cli_session_id) {
+                t.update_cli_session_id(cli_session_id);
+            }
+        });
+    }
+
     async fn get_session_cli_kind(&self, channel_kind: &str, chat_id: &str) -> Option<String> {
         self.session_hub().get_session_cli_kind(channel_kind, chat_id).await
     }
@@ -399,8 +857,51 @@ impl AgentManager {
     }
 }
 
+/// Move staged attachments into `<workspace>/attachments/` so the agent can open them.
+fn import_attachments(workspace: &Path, attachments: &[Attachment]) -> Vec<PromptFile> {
+    let dir = workspace.join("attachments");
+    attachments
+        .iter()
+        .filter_map(|att| {
+            let staged = att.local_path.as_ref()?;
+            std::fs::create_dir_all(&dir).ok()?;
+            let dest = dir.join(staged.file_name()?);
+            if std::fs::rename(staged, &dest).is_err() {
+                // Staging may be on another filesystem
+                if let Err(e) = std::fs::copy(staged, &dest) {
+                    eprintln!("[AgentManager] failed to import attachment {}: {}", staged.display(), e);
+                    return None;
+                }
+                let _ = std::fs::remove_file(staged);
+            }
+            Some(PromptFile {
+                path: dest,
+                name: att.file_name.clone(),
+                mime_type: att
+                    .mime_type
+                    .clone()
+                    .unwrap_or_else(|| "application/octet-stream".to_string()),
+            })
+        })
+        .collect()
+}
+
+fn agent_profile_dir(profile: &str) -> PathBuf {
+    config::data_dir().join("agents").join(profile).join("profile")
+}
+
+/// Whether `profile` can be selected: "default" (the Manager prompt) or a directory under
+/// ~/.vibearound/agents/<profile>/profile/.
+pub fn agent_profile_exists(profile: &str) -> bool {
+    if profile == "default" {
+        return true;
+    }
+    let safe = !profile.is_empty() && !profile.starts_with('.') && !profile.contains(['/', '\\']);
+    safe && agent_profile_dir(profile).is_dir()
+}
+
 fn load_agent_profile(profile: &str) -> Option<String> {
-    let profile_dir = config::data_dir().join("agents").join(profile).join("profile");
+    let profile_dir = agent_profile_dir(profile);
     if !profile_dir.exists() {
         return None;
     }
@@ -425,6 +926,14 @@ fn load_agent_profile(profile: &str) -> Option<String> {
     }
 }
 
-fn truncate(s: &str, max: usize) -> &str {
-    if s.len() <= max { s } else { &s[..max] }
+/// At most `max` bytes of `s`, cut back to a char boundary.
+pub(crate) fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
 }
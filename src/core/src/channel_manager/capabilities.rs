@@ -0,0 +1,293 @@
+//! Channel capabilities negotiated in the `initialize` handshake.
+//!
+//! A plugin answers `initialize` with `{ "capabilities": { ... } }`. ChannelManager keeps the
+//! result on the channel handle and adapts outbound notifications to it: token streams are
+//! buffered into chunked `send_text` messages when the platform cannot edit a message in place,
+//! and permission requests become numbered text choices when it has no buttons.
+//!
+//! Plugins that predate the handshake send no capabilities and get `PluginCapabilities::legacy()`,
+//! which is exactly what the host sent before negotiation existed.
+//!
+//! Built-in transports that can edit messages stream token deltas through a `StreamingEditor`.
+
+use std::future::Future;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+
+/// What a channel transport can render.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginCapabilities {
+    /// Can edit a sent message, so `agent_token` deltas stream into one message.
+    pub edit_message: bool,
+    /// Can show inline buttons for `permission_request` options.
+    pub buttons: bool,
+    /// Can reply inside a thread.
+    pub threads: bool,
+    /// Can upload files to the chat.
+    pub file_upload: bool,
+    /// Renders Markdown in message text.
+    pub markdown: bool,
+    /// Longest text the platform accepts in one message; longer `send_text` is split.
+    pub max_message_length: Option<usize>,
+}
+
+impl PluginCapabilities {
+    /// Plugins without a capabilities object get every notification as before.
+    pub fn legacy() -> Self {
+        Self {
+            edit_message: true,
+            buttons: true,
+            threads: false,
+            file_upload: false,
+            markdown: true,
+            max_message_length: None,
+        }
+    }
+
+    /// Internal channels (web) render everything.
+    pub fn full() -> Self {
+        Self {
+            edit_message: true,
+            buttons: true,
+            threads: true,
+            file_upload: true,
+            markdown: true,
+            max_message_length: None,
+        }
+    }
+
+    /// Parse the `initialize` result. Missing fields are `false` once a plugin opts in by sending
+    /// a capabilities object at all; no object means `legacy()`.
+    pub fn from_initialize_result(result: &serde_json::Value) -> Self {
+        let Some(caps) = result.get("capabilities").filter(|c| c.is_object()) else {
+            return Self::legacy();
+        };
+        let flag = |key: &str| caps.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        Self {
+            edit_message: flag("editMessage"),
+            buttons: flag("buttons"),
+            threads: flag("threads"),
+            file_upload: flag("fileUpload"),
+            markdown: flag("markdown"),
+            max_message_length: caps
+                .get("maxMessageLength")
+                .and_then(|v| v.as_u64())
+                .filter(|&n| n > 0)
+                .map(|n| n as usize),
+        }
+    }
+
+    /// Split `text` into messages the platform accepts, preferring line breaks.
+    pub fn split_text(&self, text: &str) -> Vec<String> {
+        match self.max_message_length {
+            Some(max) => split_text_at(text, max),
+            None => vec![text.to_string()],
+        }
+    }
+}
+
+/// Split `text` into pieces of at most `max` characters, cutting at the last line break that fits.
+pub fn split_text_at(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > max {
+        let limit = rest.char_indices().nth(max).map(|(i, _)| i).unwrap_or(rest.len());
+        let cut = match rest[..limit].rfind('\n') {
+            Some(i) if i > 0 => i,
+            _ => limit,
+        };
+        chunks.push(rest[..cut].trim_end_matches('\n').to_string());
+        rest = rest[cut..].trim_start_matches('\n');
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+/// The message an agent reply is currently streaming into.
+struct Stream<M> {
+    message_id: Option<M>,
+    text: String,
+    last_edit: Instant,
+}
+
+/// Streams agent token deltas into one message per chat for transports that edit messages in
+/// place: the message is edited at most once per `edit_interval` and rolls over into a new one
+/// past `limit` characters. Transports pass a `put` callback that edits the given message, or
+/// sends a new one for `None`, and returns the id of the message it wrote.
+pub struct StreamingEditor<K, M> {
+    streams: DashMap<K, Stream<M>>,
+    limit: usize,
+    edit_interval: Duration,
+}
+
+impl<K: Eq + Hash + Clone, M: Clone> StreamingEditor<K, M> {
+    pub fn new(limit: usize, edit_interval: Duration) -> Self {
+        Self { streams: DashMap::new(), limit, edit_interval }
+    }
+
+    /// Forget the chat's stream without flushing it (a new turn starts).
+    pub fn reset(&self, chat: &K) {
+        self.streams.remove(chat);
+    }
+
+    /// Append a token delta to the chat's streaming message.
+    pub async fn push<F, Fut>(&self, chat: &K, delta: &str, put: F)
+    where
+        F: Fn(Option<M>, String) -> Fut,
+        Fut: Future<Output = Option<M>>,
+    {
+        let (message_id, text) = {
+            let mut stream = self.streams.entry(chat.clone()).or_insert_with(|| Stream {
+                message_id: None,
+                text: String::new(),
+                last_edit: Instant::now(),
+            });
+            stream.text.push_str(delta);
+            if stream.message_id.is_some() && stream.last_edit.elapsed() < self.edit_interval {
+                return;
+            }
+            stream.last_edit = Instant::now();
+            (stream.message_id.clone(), stream.text.clone())
+        };
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let mut chunks = split_text_at(&text, self.limit);
+        if chunks.len() > 1 {
+            // The current message is full: finalize it, post any further full chunks, and stream
+            // the last chunk (plus whatever arrived meanwhile) into a new message.
+            let last = chunks.pop().unwrap_or_default();
+            if let Some(mut stream) = self.streams.get_mut(chat) {
+                let arrived = stream.text.get(text.len()..).unwrap_or_default().to_string();
+                stream.message_id = None;
+                stream.text = last.clone() + arrived.as_str();
+            }
+            let mut chunks = chunks.into_iter();
+            if let Some(first) = chunks.next() {
+                put(message_id, first).await;
+            }
+            for chunk in chunks {
+                put(None, chunk).await;
+            }
+            let new_id = put(None, last).await;
+            if let Some(mut stream) = self.streams.get_mut(chat) {
+                stream.message_id = new_id;
+            }
+            return;
+        }
+
+        let posted = put(message_id, text).await;
+        if let Some(mut stream) = self.streams.get_mut(chat) {
+            stream.message_id = stream.message_id.take().or(posted);
+        }
+    }
+
+    /// Flush the streamed reply's final text and forget it.
+    pub async fn finish<F, Fut>(&self, chat: &K, put: F)
+    where
+        F: Fn(Option<M>, String) -> Fut,
+        Fut: Future<Output = Option<M>>,
+    {
+        let Some((_, stream)) = self.streams.remove(chat) else { return };
+        if stream.text.trim().is_empty() {
+            return;
+        }
+        let mut chunks = split_text_at(&stream.text, self.limit).into_iter();
+        if let Some(first) = chunks.next() {
+            put(stream.message_id, first).await;
+        }
+        for chunk in chunks {
+            put(None, chunk).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn split_text_at_prefers_line_breaks() {
+        assert_eq!(split_text_at("short", 10), vec!["short"]);
+        assert_eq!(split_text_at("", 10), vec![""]);
+        assert_eq!(split_text_at("aaaa\nbbbb\ncccc", 10), vec!["aaaa\nbbbb", "cccc"]);
+        assert_eq!(split_text_at("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        // Counts characters, not bytes, and never cuts inside one.
+        assert_eq!(split_text_at("ééééé", 2), vec!["éé", "éé", "é"]);
+        for chunk in split_text_at("x\n\n\nyyyyyyyyyyyy\nz", 5) {
+            assert!(chunk.chars().count() <= 5, "{chunk:?}");
+        }
+    }
+
+    #[test]
+    fn capabilities_come_from_the_initialize_result() {
+        assert_eq!(PluginCapabilities::from_initialize_result(&json!({})), PluginCapabilities::legacy());
+        assert_eq!(
+            PluginCapabilities::from_initialize_result(&json!({ "capabilities": null })),
+            PluginCapabilities::legacy()
+        );
+
+        let caps = PluginCapabilities::from_initialize_result(&json!({
+            "capabilities": { "buttons": true, "fileUpload": true, "maxMessageLength": 4000, "threads": "yes" }
+        }));
+        assert_eq!(
+            caps,
+            PluginCapabilities {
+                edit_message: false,
+                buttons: true,
+                threads: false,
+                file_upload: true,
+                markdown: false,
+                max_message_length: Some(4000),
+            }
+        );
+
+        let caps = PluginCapabilities::from_initialize_result(&json!({ "capabilities": { "maxMessageLength": 0 } }));
+        assert_eq!(caps.max_message_length, None);
+        assert_eq!(caps.split_text("a\nb"), vec!["a\nb"]);
+    }
+
+    #[tokio::test]
+    async fn a_full_stream_rolls_over_without_losing_line_breaks() {
+        let editor = StreamingEditor::<u8, usize>::new(10, Duration::ZERO);
+        let sent = Arc::new(Mutex::new(Vec::<(Option<usize>, String)>::new()));
+        let put = |id: Option<usize>, text: String| {
+            let sent = sent.clone();
+            async move {
+                let mut sent = sent.lock().unwrap();
+                sent.push((id, text));
+                Some(id.unwrap_or(sent.len()))
+            }
+        };
+
+        editor.push(&0, "line one\n", put).await;
+        editor.push(&0, "two\nthree and four\nfive", put).await;
+        editor.finish(&0, put).await;
+
+        let sent = sent.lock().unwrap().clone();
+        assert!(sent.iter().all(|(_, text)| text.chars().count() <= 10), "{sent:?}");
+        // The final text of each message, in order, reads back as the whole reply.
+        let mut messages: Vec<(usize, String)> = Vec::new();
+        for (i, (id, text)) in sent.iter().enumerate() {
+            let id = id.unwrap_or(i + 1);
+            match messages.iter_mut().find(|(m, _)| *m == id) {
+                Some((_, t)) => *t = text.clone(),
+                None => messages.push((id, text.clone())),
+            }
+        }
+        let texts: Vec<_> = messages.into_iter().map(|(_, t)| t).collect();
+        assert_eq!(texts, vec!["line one", "two", "three and ", "four\nfive"]);
+    }
+}
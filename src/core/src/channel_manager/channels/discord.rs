@@ -0,0 +1,1018 @@
+//! Built-in Discord channel (Gateway WebSocket + REST API v10).
+//!
+//! Used for `channels.discord` when no external Discord plugin is installed. Each conversation is
+//! a Discord thread: mentioning the bot in a server channel opens a thread on that message, and
+//! the thread id is the `chat_id`, so one thread is one SessionHub session. DMs are one chat per
+//! DM channel. Application commands (`/agent`, `/profile`, `/stop`, `/new`, `/status`, `/help`)
+//! are registered on READY and fed through the same command handling as typed slash commands.
+//! Replies stream by editing one message and roll over into a new one at 2000 characters.
+//!
+//! Heartbeats must be acknowledged before the next one is due; a connection that stops
+//! acknowledging is dropped, and a dropped connection RESUMEs its session (replaying missed
+//! events) instead of identifying again.
+//!
+//! `channels.discord.api_base_url` and `gateway_url` point the channel at another server, e.g. a
+//! local mock of the REST API and gateway.
+
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::channel_manager::capabilities::{split_text_at, StreamingEditor};
+use crate::channel_manager::{str_field, ChannelManager};
+use crate::config;
+use crate::session_hub::types::{permission_callback_value, ChannelNotification};
+
+pub const DEFAULT_API_BASE_URL: &str = "https://discord.com/api/v10";
+pub const DEFAULT_GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+/// Query appended to READY's `resume_gateway_url`.
+const GATEWAY_QUERY: &str = "?v=10&encoding=json";
+/// Discord rejects message content longer than this.
+pub const DISCORD_MESSAGE_LIMIT: usize = 2000;
+/// GUILDS | GUILD_MESSAGES | DIRECT_MESSAGES | MESSAGE_CONTENT.
+const GATEWAY_INTENTS: u64 = (1 << 0) | (1 << 9) | (1 << 12) | (1 << 15);
+/// Message edits are rate limited per channel; stay around one per second.
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+/// Buttons per action row.
+const BUTTONS_PER_ROW: usize = 5;
+
+// Gateway opcodes.
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_RESUME: u64 = 6;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+const OP_HEARTBEAT_ACK: u64 = 11;
+/// Close codes after which the session cannot be resumed (invalid seq, session timed out).
+const CLOSE_CODES_NOT_RESUMABLE: &[u16] = &[4007, 4009];
+
+// Interaction types and callback types.
+const INTERACTION_APPLICATION_COMMAND: u64 = 2;
+const INTERACTION_MESSAGE_COMPONENT: u64 = 3;
+const CALLBACK_CHANNEL_MESSAGE: u64 = 4;
+const CALLBACK_DEFERRED_UPDATE_MESSAGE: u64 = 6;
+/// Message flag: only the user who triggered the interaction sees the message.
+const FLAG_EPHEMERAL: u64 = 1 << 6;
+
+/// Gateway dispatch events (`t`, `d`) handed from the read loop to a handler.
+type DispatchTx = mpsc::UnboundedSender<(String, serde_json::Value)>;
+
+pub struct DiscordChannel {
+    name: String,
+    api_base_url: String,
+    gateway_url: String,
+    bot_token: String,
+    client: reqwest::Client,
+    channel_hub: Arc<ChannelManager>,
+    /// Filled in from READY.
+    bot_user_id: std::sync::RwLock<String>,
+    /// Per chat: the reply being streamed.
+    streams: StreamingEditor<String, String>,
+    /// Threads opened by (or for) the bot; messages in them need no mention.
+    threads: DashMap<String, ()>,
+}
+
+/// What outlives one gateway connection so the next one can RESUME instead of IDENTIFY.
+#[derive(Debug, Default)]
+struct GatewaySession {
+    session_id: Option<String>,
+    resume_url: Option<String>,
+    sequence: Option<u64>,
+}
+
+impl GatewaySession {
+    fn resumable(&self) -> bool {
+        self.session_id.is_some() && self.sequence.is_some()
+    }
+
+    /// Where to connect: READY's `resume_gateway_url` when resuming, otherwise `gateway_url`.
+    fn url(&self, gateway_url: &str) -> String {
+        match &self.resume_url {
+            Some(url) if self.resumable() => format!("{}{}", url.trim_end_matches('/'), GATEWAY_QUERY),
+            _ => gateway_url.to_string(),
+        }
+    }
+}
+
+/// Start the built-in Discord channel and register it with ChannelManager.
+/// Returns the transport task's abort handle.
+pub fn start(channel_hub: &Arc<ChannelManager>, channel_name: &str) -> Result<AbortHandle, String> {
+    let raw_config = config::ensure_loaded()
+        .channel_raw_config(channel_name)
+        .ok_or_else(|| format!("channels.{} is not configured", channel_name))?;
+    let field = |key: &str| {
+        raw_config
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    let bot_token = field("bot_token").ok_or_else(|| format!("channels.{}.bot_token is missing", channel_name))?;
+
+    let channel = Arc::new(DiscordChannel {
+        name: channel_name.to_string(),
+        api_base_url: field("api_base_url")
+            .unwrap_or_else(|| DEFAULT_API_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string(),
+        gateway_url: field("gateway_url").unwrap_or_else(|| DEFAULT_GATEWAY_URL.to_string()),
+        bot_token,
+        client: reqwest::Client::builder()
+            .timeout(API_TIMEOUT)
+            .build()
+            .map_err(|e| format!("reqwest client: {}", e))?,
+        channel_hub: Arc::clone(channel_hub),
+        bot_user_id: std::sync::RwLock::new(String::new()),
+        streams: StreamingEditor::new(DISCORD_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+        threads: DashMap::new(),
+    });
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move { channel.run(outbound_rx).await });
+    let abort = task.abort_handle();
+    channel_hub.start_builtin_plugin(channel_name, outbound_tx, abort.clone());
+    eprintln!("[{}] built-in Discord channel started (gateway)", channel_name);
+    Ok(abort)
+}
+
+impl DiscordChannel {
+    async fn run(self: Arc<Self>, mut outbound_rx: mpsc::UnboundedReceiver<ChannelNotification>) {
+        let outbound = async {
+            while let Some(notif) = outbound_rx.recv().await {
+                self.render(notif).await;
+            }
+        };
+        // Events are handled apart from the read loop so slow REST calls never hold up heartbeats.
+        // Interactions get their own queue: they must be answered within 3 seconds, even while a
+        // message is still waiting for its thread to be created.
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (interactions_tx, interactions_rx) = mpsc::unbounded_channel();
+        tokio::select! {
+            _ = self.gateway_loop(&events_tx, &interactions_tx) => {}
+            _ = self.handle_dispatches(events_rx) => {}
+            _ = self.handle_dispatches(interactions_rx) => {}
+            _ = outbound => {}
+        }
+    }
+
+    async fn handle_dispatches(&self, mut rx: mpsc::UnboundedReceiver<(String, serde_json::Value)>) {
+        while let Some((event, data)) = rx.recv().await {
+            self.handle_dispatch(&event, &data).await;
+        }
+    }
+
+    /// Call a REST endpoint. Returns the JSON body (`Null` for 204).
+    async fn api(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value, String> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}{}", self.api_base_url, path))
+            .header(reqwest::header::AUTHORIZATION, format!("Bot {}", self.bot_token));
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        let resp = request.send().await.map_err(|e| format!("{} {}: {}", method, path, e))?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| format!("{} {}: {}", method, path, e))?;
+        if !status.is_success() {
+            return Err(format!("{} {}: {} {}", method, path, status, text));
+        }
+        if text.is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&text).map_err(|e| format!("{} {}: invalid response: {}", method, path, e))
+    }
+
+    // -----------------------------------------------------------------------
+    // Gateway
+    // -----------------------------------------------------------------------
+
+    async fn gateway_loop(&self, events_tx: &DispatchTx, interactions_tx: &DispatchTx) {
+        let mut session = GatewaySession::default();
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let started = Instant::now();
+            match self.serve_gateway(&mut session, events_tx, interactions_tx).await {
+                Ok(()) => eprintln!("[{}] gateway asked to reconnect", self.name),
+                Err(e) => eprintln!(
+                    "[{}] gateway error: {} — reconnecting in {}s",
+                    self.name,
+                    e,
+                    backoff.as_secs()
+                ),
+            }
+            if started.elapsed() > MAX_RECONNECT_BACKOFF {
+                backoff = Duration::from_secs(1);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        }
+    }
+
+    /// One gateway connection: Hello → Identify (or Resume) → queue dispatch events, heartbeating
+    /// until the socket closes, a heartbeat goes unacknowledged, or Discord asks for a reconnect.
+    async fn serve_gateway(
+        &self,
+        session: &mut GatewaySession,
+        events_tx: &DispatchTx,
+        interactions_tx: &DispatchTx,
+    ) -> Result<(), String> {
+        let (ws, _) = tokio_tungstenite::connect_async(session.url(&self.gateway_url))
+            .await
+            .map_err(|e| format!("connect: {}", e))?;
+        let (mut write, mut read) = ws.split();
+        let mut heartbeat: Option<tokio::time::Interval> = None;
+        let mut awaiting_ack = false;
+
+        loop {
+            let frame = tokio::select! {
+                frame = read.next() => frame,
+                _ = async {
+                    match heartbeat.as_mut() {
+                        Some(interval) => { interval.tick().await; }
+                        None => std::future::pending::<()>().await,
+                    }
+                } => {
+                    // No ACK since the last beat: the connection is dead even if the socket is not.
+                    if awaiting_ack {
+                        return Err("heartbeat not acknowledged".to_string());
+                    }
+                    let beat = serde_json::json!({ "op": OP_HEARTBEAT, "d": session.sequence }).to_string();
+                    write.send(WsMessage::text(beat)).await.map_err(|e| format!("heartbeat: {}", e))?;
+                    awaiting_ack = true;
+                    continue;
+                }
+            };
+            let Some(frame) = frame else { return Ok(()) };
+            let text = match frame.map_err(|e| format!("read: {}", e))? {
+                WsMessage::Text(text) => text,
+                WsMessage::Ping(data) => {
+                    let _ = write.send(WsMessage::Pong(data)).await;
+                    continue;
+                }
+                WsMessage::Close(close) => {
+                    if close.as_ref().is_some_and(|c| CLOSE_CODES_NOT_RESUMABLE.contains(&u16::from(c.code))) {
+                        *session = GatewaySession::default();
+                    }
+                    return Err(format!("closed: {}", close.map(|c| c.to_string()).unwrap_or_default()));
+                }
+                _ => continue,
+            };
+            let Ok(payload) = serde_json::from_str::<serde_json::Value>(text.as_str()) else {
+                continue;
+            };
+            if let Some(s) = payload.get("s").and_then(|v| v.as_u64()) {
+                session.sequence = Some(s);
+            }
+            let data = payload.get("d").cloned().unwrap_or(serde_json::Value::Null);
+
+            match payload.get("op").and_then(|v| v.as_u64()).unwrap_or(u64::MAX) {
+                OP_HELLO => {
+                    let interval_ms = data.get("heartbeat_interval").and_then(|v| v.as_u64()).unwrap_or(41_250);
+                    let period = Duration::from_millis(interval_ms);
+                    heartbeat = Some(tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+                    awaiting_ack = false;
+                    let hello_reply = match (&session.session_id, session.sequence) {
+                        (Some(session_id), Some(seq)) => serde_json::json!({
+                            "op": OP_RESUME,
+                            "d": { "token": self.bot_token, "session_id": session_id, "seq": seq },
+                        }),
+                        _ => serde_json::json!({
+                            "op": OP_IDENTIFY,
+                            "d": {
+                                "token": self.bot_token,
+                                "intents": GATEWAY_INTENTS,
+                                "properties": {
+                                    "os": std::env::consts::OS,
+                                    "browser": "vibearound",
+                                    "device": "vibearound",
+                                },
+                            }
+                        }),
+                    };
+                    write
+                        .send(WsMessage::text(hello_reply.to_string()))
+                        .await
+                        .map_err(|e| format!("identify: {}", e))?;
+                }
+                OP_HEARTBEAT => {
+                    let beat = serde_json::json!({ "op": OP_HEARTBEAT, "d": session.sequence }).to_string();
+                    write.send(WsMessage::text(beat)).await.map_err(|e| format!("heartbeat: {}", e))?;
+                }
+                OP_HEARTBEAT_ACK => awaiting_ack = false,
+                OP_RECONNECT => return Ok(()),
+                OP_INVALID_SESSION => {
+                    // `d` says whether the session can still be resumed.
+                    if data.as_bool() != Some(true) {
+                        *session = GatewaySession::default();
+                    }
+                    return Err("invalid session".to_string());
+                }
+                OP_DISPATCH => {
+                    let event = payload.get("t").and_then(|v| v.as_str()).unwrap_or("");
+                    match event {
+                        "READY" => {
+                            session.session_id = data.get("session_id").and_then(|v| v.as_str()).map(String::from);
+                            session.resume_url =
+                                data.get("resume_gateway_url").and_then(|v| v.as_str()).map(String::from);
+                        }
+                        "RESUMED" => eprintln!("[{}] gateway session resumed", self.name),
+                        _ => {}
+                    }
+                    let queue = if event == "INTERACTION_CREATE" { interactions_tx } else { events_tx };
+                    let _ = queue.send((event.to_string(), data));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn handle_dispatch(&self, event: &str, data: &serde_json::Value) {
+        match event {
+            "READY" => {
+                let user_id = data.pointer("/user/id").and_then(|v| v.as_str()).unwrap_or("").to_string();
+                *self.bot_user_id.write().unwrap() = user_id;
+                if let Some(app_id) = data.pointer("/application/id").and_then(|v| v.as_str()) {
+                    if let Err(e) = self.register_commands(app_id).await {
+                        eprintln!("[{}] failed to register application commands: {}", self.name, e);
+                    }
+                }
+                eprintln!("[{}] gateway ready", self.name);
+            }
+            "MESSAGE_CREATE" => self.handle_message(data).await,
+            "INTERACTION_CREATE" => self.handle_interaction(data).await,
+            _ => {}
+        }
+    }
+
+    /// Global application commands mirroring the chat commands (see `commands::help_text`).
+    async fn register_commands(&self, application_id: &str) -> Result<(), String> {
+        let string_option = |name: &str, description: &str| {
+            serde_json::json!([{ "type": 3, "name": name, "description": description, "required": false }])
+        };
+        let commands = serde_json::json!([
+            { "name": "agent", "description": "Show or switch the agent", "options": string_option("name", "Agent to switch to") },
+            { "name": "profile", "description": "Show or switch the agent profile", "options": string_option("name", "Profile to switch to") },
+            { "name": "stop", "description": "Stop the current reply" },
+            { "name": "new", "description": "Start a new session" },
+            { "name": "status", "description": "Show the current session" },
+            { "name": "help", "description": "List commands" },
+        ]);
+        self.api(
+            reqwest::Method::PUT,
+            &format!("/applications/{}/commands", application_id),
+            Some(commands),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn handle_message(&self, data: &serde_json::Value) {
+        if data.pointer("/author/bot").and_then(|v| v.as_bool()) == Some(true) {
+            return;
+        }
+        let channel_id = str_field(data, "channel_id");
+        let message_id = str_field(data, "id");
+        let is_dm = data.get("guild_id").is_none();
+        let bot_user_id = self.bot_user_id.read().unwrap().clone();
+        let mentioned = data
+            .get("mentions")
+            .and_then(|v| v.as_array())
+            .is_some_and(|m| m.iter().any(|u| str_field(u, "id") == bot_user_id));
+
+        let chat_id = if is_dm || self.threads.contains_key(&channel_id) {
+            channel_id.clone()
+        } else if mentioned {
+            // A mention in a server channel (or an unknown thread) starts or joins a session thread.
+            match self.thread_for(data, &channel_id, &message_id).await {
+                Some(thread_id) => thread_id,
+                None => return,
+            }
+        } else {
+            return;
+        };
+
+        let text = strip_mentions(&str_field(data, "content"));
+        if text.is_empty() {
+            return;
+        }
+        let params = serde_json::json!({
+            "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+            "messageId": message_id,
+            "text": text,
+            "sender": {
+                "id": data.pointer("/author/id").and_then(|v| v.as_str()).unwrap_or(""),
+                "name": data.pointer("/author/username").and_then(|v| v.as_str()),
+            },
+        });
+        self.dispatch_inbound("on_message", params).await;
+    }
+
+    /// The thread a mentioning message belongs to: the channel itself when it already is a thread
+    /// (types 10–12), otherwise a new thread started on the message.
+    async fn thread_for(&self, data: &serde_json::Value, channel_id: &str, message_id: &str) -> Option<String> {
+        let channel = self
+            .api(reqwest::Method::GET, &format!("/channels/{}", channel_id), None)
+            .await
+            .ok()?;
+        if is_thread(Some(&channel)) {
+            self.threads.insert(channel_id.to_string(), ());
+            return Some(channel_id.to_string());
+        }
+        let title: String = strip_mentions(&str_field(data, "content")).chars().take(80).collect();
+        let thread = self
+            .api(
+                reqwest::Method::POST,
+                &format!("/channels/{}/messages/{}/threads", channel_id, message_id),
+                Some(serde_json::json!({
+                    "name": if title.is_empty() { "VibeAround session".to_string() } else { title },
+                    "auto_archive_duration": 1440,
+                })),
+            )
+            .await;
+        match thread {
+            Ok(thread) => {
+                let thread_id = str_field(&thread, "id");
+                self.threads.insert(thread_id.clone(), ());
+                Some(thread_id)
+            }
+            Err(e) => {
+                eprintln!("[{}] failed to start a thread: {}", self.name, e);
+                None
+            }
+        }
+    }
+
+    async fn handle_interaction(&self, data: &serde_json::Value) {
+        let id = str_field(data, "id");
+        let token = str_field(data, "token");
+        let chat_id = str_field(data, "channel_id");
+        let sender_id = data
+            .pointer("/member/user/id")
+            .or_else(|| data.pointer("/user/id"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let callback_path = format!("/interactions/{}/{}/callback", id, token);
+
+        match data.get("type").and_then(|v| v.as_u64()) {
+            Some(INTERACTION_APPLICATION_COMMAND) => {
+                let name = data.pointer("/data/name").and_then(|v| v.as_str()).unwrap_or("");
+                let arg = data
+                    .pointer("/data/options/0/value")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let text = format!("/{} {}", name, arg).trim().to_string();
+                // Interactions must be answered within 3 seconds; the command's own reply follows.
+                let _ = self
+                    .api(
+                        reqwest::Method::POST,
+                        &callback_path,
+                        Some(serde_json::json!({
+                            "type": CALLBACK_CHANNEL_MESSAGE,
+                            "data": { "content": format!("`{}`", text) },
+                        })),
+                    )
+                    .await;
+                // A command inside a thread makes it a session thread; in a plain server channel
+                // it runs without the bot following every later message there.
+                if is_thread(data.get("channel")) {
+                    self.threads.insert(chat_id.clone(), ());
+                }
+                let params = serde_json::json!({
+                    "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+                    "messageId": id,
+                    "text": text,
+                    "sender": { "id": sender_id },
+                });
+                self.dispatch_inbound("on_message", params).await;
+            }
+            Some(INTERACTION_MESSAGE_COMPONENT) => {
+                let value = data.pointer("/data/custom_id").and_then(|v| v.as_str()).unwrap_or("");
+                if value.is_empty() {
+                    return;
+                }
+                // Acknowledge now; the prompt is only changed once the click has been admitted.
+                let _ = self
+                    .api(
+                        reqwest::Method::POST,
+                        &callback_path,
+                        Some(serde_json::json!({ "type": CALLBACK_DEFERRED_UPDATE_MESSAGE })),
+                    )
+                    .await;
+                let params = serde_json::json!({
+                    "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+                    "data": { "value": value },
+                    "sender": { "id": sender_id },
+                });
+                let admitted = self.dispatch_inbound("on_callback", params).await;
+
+                let webhook_path = format!("/webhooks/{}/{}", str_field(data, "application_id"), token);
+                let result = if admitted {
+                    // Replace the buttons with the choice so the prompt cannot be answered twice.
+                    let prompt = data.pointer("/message/content").and_then(|v| v.as_str()).unwrap_or("");
+                    let label = data
+                        .pointer("/message/components")
+                        .and_then(|rows| find_button_label(rows, value))
+                        .unwrap_or_else(|| value.to_string());
+                    self.api(
+                        reqwest::Method::PATCH,
+                        &format!("{}/messages/@original", webhook_path),
+                        Some(serde_json::json!({
+                            "content": format!("{}\n\n→ {}", prompt, label),
+                            "components": [],
+                        })),
+                    )
+                    .await
+                } else {
+                    self.api(
+                        reqwest::Method::POST,
+                        &webhook_path,
+                        Some(serde_json::json!({
+                            "content": "You are not allowed to answer this prompt.",
+                            "flags": FLAG_EPHEMERAL,
+                        })),
+                    )
+                    .await
+                };
+                if let Err(e) = result {
+                    eprintln!("[{}] {}", self.name, e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns false when ChannelManager rejected the sender.
+    async fn dispatch_inbound(&self, method: &str, params: serde_json::Value) -> bool {
+        let msg = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.channel_hub.handle_inbound_jsonrpc(&self.name, msg).await
+    }
+
+    // -----------------------------------------------------------------------
+    // Outbound
+    // -----------------------------------------------------------------------
+
+    async fn render(&self, notif: ChannelNotification) {
+        match notif {
+            ChannelNotification::AgentStart { chat_id, .. } => {
+                self.streams.reset(&chat_id);
+                let _ = self
+                    .api(reqwest::Method::POST, &format!("/channels/{}/typing", chat_id), None)
+                    .await;
+            }
+            ChannelNotification::AgentThinking { chat_id, text, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_text(&chat_id, &format!("💭 {}", text), None).await;
+            }
+            ChannelNotification::AgentToken { chat_id, delta, .. } => {
+                self.stream_token(&chat_id, &delta).await;
+            }
+            ChannelNotification::AgentToolUse { chat_id, tool, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_text(&chat_id, &format!("🔧 {}", tool), None).await;
+            }
+            ChannelNotification::AgentToolResult { .. } => {}
+            ChannelNotification::MessageQueued { .. } => {}
+            ChannelNotification::AgentEnd { chat_id, .. } => {
+                self.finish_stream(&chat_id).await;
+            }
+            ChannelNotification::AgentError { chat_id, error, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_text(&chat_id, &format!("❌ {}", error), None).await;
+            }
+            ChannelNotification::SendText { chat_id, text, reply_to, .. } => {
+                self.send_text(&chat_id, &text, reply_to).await;
+            }
+            ChannelNotification::PermissionRequest {
+                chat_id,
+                request_id,
+                tool,
+                input,
+                options,
+                timeout_secs,
+                default_allow,
+                ..
+            } => {
+                self.finish_stream(&chat_id).await;
+                let text = format!(
+                    "🔐 **{}** wants permission:\n{}\n\nNo answer in {}s means {}.",
+                    tool,
+                    input,
+                    timeout_secs,
+                    if default_allow { "allow" } else { "deny" }
+                );
+                let text: String = text.chars().take(DISCORD_MESSAGE_LIMIT).collect();
+                let buttons = options
+                    .iter()
+                    .map(|o| {
+                        // 1 primary, 3 success, 4 danger.
+                        let style = if o.kind.starts_with("allow") {
+                            3
+                        } else if o.kind.starts_with("reject") {
+                            4
+                        } else {
+                            1
+                        };
+                        serde_json::json!({
+                            "type": 2,
+                            "style": style,
+                            "label": o.label.chars().take(80).collect::<String>(),
+                            "custom_id": permission_callback_value(&request_id, &o.id),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let rows = buttons
+                    .chunks(BUTTONS_PER_ROW)
+                    .map(|row| serde_json::json!({ "type": 1, "components": row }))
+                    .collect::<Vec<_>>();
+                let result = self
+                    .api(
+                        reqwest::Method::POST,
+                        &format!("/channels/{}/messages", chat_id),
+                        Some(serde_json::json!({ "content": text, "components": rows })),
+                    )
+                    .await;
+                if let Err(e) = result {
+                    eprintln!("[{}] failed to send permission request: {}", self.name, e);
+                }
+            }
+        }
+    }
+
+    async fn stream_token(&self, chat_id: &str, delta: &str) {
+        self.streams
+            .push(&chat_id.to_string(), delta, |id, text| async move { self.put_message(chat_id, id, &text).await })
+            .await;
+    }
+
+    async fn finish_stream(&self, chat_id: &str) {
+        self.streams
+            .finish(&chat_id.to_string(), |id, text| async move { self.put_message(chat_id, id, &text).await })
+            .await;
+    }
+
+    /// Edit `message_id`, or post a new message when there is none yet. Returns its id.
+    async fn put_message(&self, chat_id: &str, message_id: Option<String>, text: &str) -> Option<String> {
+        let result = match &message_id {
+            Some(id) => {
+                self.api(
+                    reqwest::Method::PATCH,
+                    &format!("/channels/{}/messages/{}", chat_id, id),
+                    Some(serde_json::json!({ "content": text })),
+                )
+                .await
+            }
+            None => {
+                self.api(
+                    reqwest::Method::POST,
+                    &format!("/channels/{}/messages", chat_id),
+                    Some(serde_json::json!({ "content": text })),
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(v) => message_id.or_else(|| v.get("id").and_then(|id| id.as_str()).map(String::from)),
+            Err(e) => {
+                eprintln!("[{}] {}", self.name, e);
+                message_id
+            }
+        }
+    }
+
+    async fn send_text(&self, chat_id: &str, text: &str, reply_to: Option<String>) {
+        for (i, chunk) in split_text_at(text, DISCORD_MESSAGE_LIMIT).into_iter().enumerate() {
+            let mut body = serde_json::json!({ "content": chunk });
+            if let Some(id) = reply_to.as_deref().filter(|_| i == 0) {
+                body["message_reference"] = serde_json::json!({ "message_id": id, "fail_if_not_exists": false });
+            }
+            if let Err(e) = self
+                .api(reqwest::Method::POST, &format!("/channels/{}/messages", chat_id), Some(body))
+                .await
+            {
+                eprintln!("[{}] {}", self.name, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Channel types 10–12 are threads (announcement, public, private).
+fn is_thread(channel: Option<&serde_json::Value>) -> bool {
+    matches!(channel.and_then(|c| c.get("type")).and_then(|v| v.as_u64()), Some(10..=12))
+}
+
+fn find_button_label(rows: &serde_json::Value, custom_id: &str) -> Option<String> {
+    rows.as_array()?
+        .iter()
+        .filter_map(|row| row.get("components")?.as_array())
+        .flatten()
+        .find(|button| str_field(button, "custom_id") == custom_id)
+        .map(|button| str_field(button, "label"))
+}
+
+static USER_MENTION: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"<@!?\d+>").unwrap());
+
+/// Drop `<@123>` / `<@!123>` user mentions (the bot's own mention starts a server message).
+fn strip_mentions(text: &str) -> String {
+    USER_MENTION.replace_all(text, "").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use axum::body::Bytes;
+    use axum::extract::ws::{Message as MockWsMessage, WebSocket, WebSocketUpgrade};
+    use axum::extract::{Path, State};
+    use axum::http::Method;
+    use axum::response::IntoResponse;
+    use axum::Json;
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::session_hub::SessionHub;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// One gateway connection as seen by the mock: what it was opened on, frames to push to the
+    /// client (dropping the sender closes the socket) and frames the client sent.
+    struct GatewayConn {
+        path: String,
+        to_client: mpsc::UnboundedSender<String>,
+        from_client: mpsc::UnboundedReceiver<String>,
+    }
+
+    impl GatewayConn {
+        fn send(&self, payload: serde_json::Value) {
+            self.to_client.send(payload.to_string()).unwrap();
+        }
+
+        async fn recv(&mut self) -> serde_json::Value {
+            let frame = tokio::time::timeout(WAIT, self.from_client.recv()).await.expect("gateway frame").unwrap();
+            serde_json::from_str(&frame).unwrap()
+        }
+    }
+
+    /// Local stand-in for the Discord REST API and gateway.
+    #[derive(Clone)]
+    struct MockDiscord {
+        conns: mpsc::UnboundedSender<GatewayConn>,
+        /// REST calls as (method, path, body).
+        calls: mpsc::UnboundedSender<(String, String, serde_json::Value)>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    async fn rest(
+        State(mock): State<MockDiscord>,
+        method: Method,
+        Path(path): Path<String>,
+        body: Bytes,
+    ) -> Json<serde_json::Value> {
+        let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+        let _ = mock.calls.send((method.to_string(), format!("/{}", path), body));
+        let id = mock.next_id.fetch_add(1, Ordering::SeqCst).to_string();
+        Json(match (method.as_str(), path.as_str()) {
+            ("GET", "channels/T1") => json!({ "id": "T1", "type": 11 }),
+            ("GET", _) => json!({ "id": path.trim_start_matches("channels/"), "type": 0 }),
+            _ => json!({ "id": id }),
+        })
+    }
+
+    async fn gateway(
+        State(mock): State<MockDiscord>,
+        uri: axum::http::Uri,
+        upgrade: WebSocketUpgrade,
+    ) -> impl IntoResponse {
+        upgrade.on_upgrade(move |ws| async move {
+            let (to_client, client_rx) = mpsc::unbounded_channel();
+            let (client_tx, from_client) = mpsc::unbounded_channel();
+            let _ = mock.conns.send(GatewayConn { path: uri.path().to_string(), to_client, from_client });
+            relay(ws, client_rx, client_tx).await;
+        })
+    }
+
+    async fn relay(
+        mut ws: WebSocket,
+        mut to_client: mpsc::UnboundedReceiver<String>,
+        from_client: mpsc::UnboundedSender<String>,
+    ) {
+        loop {
+            tokio::select! {
+                payload = to_client.recv() => {
+                    let Some(payload) = payload else { break };
+                    if ws.send(MockWsMessage::Text(payload.into())).await.is_err() {
+                        break;
+                    }
+                }
+                frame = ws.recv() => match frame {
+                    Some(Ok(MockWsMessage::Text(text))) => {
+                        let _ = from_client.send(text.to_string());
+                    }
+                    Some(Ok(_)) => {}
+                    _ => break,
+                },
+            }
+        }
+    }
+
+    struct Harness {
+        addr: std::net::SocketAddr,
+        channel: Arc<DiscordChannel>,
+        conns: mpsc::UnboundedReceiver<GatewayConn>,
+        calls: mpsc::UnboundedReceiver<(String, String, serde_json::Value)>,
+    }
+
+    impl Harness {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (conns_tx, conns) = mpsc::unbounded_channel();
+            let (calls_tx, calls) = mpsc::unbounded_channel();
+            let mock = MockDiscord { conns: conns_tx, calls: calls_tx, next_id: Arc::new(AtomicU64::new(100)) };
+            let router = axum::Router::new()
+                .route("/api/{*path}", axum::routing::any(rest))
+                .route("/gateway", axum::routing::get(gateway))
+                .route("/resume", axum::routing::get(gateway))
+                .with_state(mock);
+            tokio::spawn(async move { axum::serve(listener, router).await });
+
+            let data_dir = std::env::temp_dir().join(format!("vibearound-discord-test-{}", std::process::id()));
+            let channel_hub = Arc::new(ChannelManager::with_config(Arc::new(Config::default()), data_dir));
+            channel_hub.set_session_hub(Arc::new(SessionHub::new()));
+            let channel = Arc::new(DiscordChannel {
+                name: "discord".to_string(),
+                api_base_url: format!("http://{}/api", addr),
+                gateway_url: format!("ws://{}/gateway", addr),
+                bot_token: "bot-token".to_string(),
+                client: reqwest::Client::new(),
+                channel_hub,
+                bot_user_id: std::sync::RwLock::new("42".to_string()),
+                streams: StreamingEditor::new(DISCORD_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+                threads: DashMap::new(),
+            });
+            Self { addr, channel, conns, calls }
+        }
+
+        async fn next_conn(&mut self) -> GatewayConn {
+            tokio::time::timeout(WAIT, self.conns.recv()).await.expect("gateway connection").unwrap()
+        }
+
+        async fn next_call(&mut self) -> (String, String, serde_json::Value) {
+            tokio::time::timeout(WAIT, self.calls.recv()).await.expect("REST call").unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_missed_heartbeat_ack_reconnects_and_resumes() {
+        let mut discord = Harness::start().await;
+        let (_outbound, outbound_rx) = mpsc::unbounded_channel();
+        tokio::spawn(Arc::clone(&discord.channel).run(outbound_rx));
+
+        let mut first = discord.next_conn().await;
+        assert_eq!(first.path, "/gateway");
+        first.send(json!({ "op": OP_HELLO, "d": { "heartbeat_interval": 100 } }));
+        let identify = first.recv().await;
+        assert_eq!(identify["op"], OP_IDENTIFY);
+        assert_eq!(identify["d"]["token"], "bot-token");
+        first.send(json!({
+            "op": OP_DISPATCH,
+            "t": "READY",
+            "s": 1,
+            "d": {
+                "session_id": "session-1",
+                "resume_gateway_url": format!("ws://{}/resume", discord.addr),
+                "user": { "id": "42" },
+            },
+        }));
+
+        // An acknowledged heartbeat keeps the connection; the next one goes unanswered.
+        assert_eq!(first.recv().await, json!({ "op": OP_HEARTBEAT, "d": 1 }));
+        first.send(json!({ "op": OP_HEARTBEAT_ACK }));
+        assert_eq!(first.recv().await, json!({ "op": OP_HEARTBEAT, "d": 1 }));
+
+        let mut second = discord.next_conn().await;
+        assert_eq!(second.path, "/resume");
+        second.send(json!({ "op": OP_HELLO, "d": { "heartbeat_interval": 10_000 } }));
+        assert_eq!(
+            second.recv().await,
+            json!({ "op": OP_RESUME, "d": { "token": "bot-token", "session_id": "session-1", "seq": 1 } })
+        );
+        drop(first);
+    }
+
+    #[tokio::test]
+    async fn a_session_that_cannot_resume_identifies_again() {
+        let mut discord = Harness::start().await;
+        let (_outbound, outbound_rx) = mpsc::unbounded_channel();
+        tokio::spawn(Arc::clone(&discord.channel).run(outbound_rx));
+
+        let mut first = discord.next_conn().await;
+        first.send(json!({ "op": OP_HELLO, "d": { "heartbeat_interval": 10_000 } }));
+        first.recv().await;
+        first.send(json!({
+            "op": OP_DISPATCH,
+            "t": "READY",
+            "s": 1,
+            "d": { "session_id": "session-1", "resume_gateway_url": format!("ws://{}/resume", discord.addr) },
+        }));
+        first.send(json!({ "op": OP_INVALID_SESSION, "d": false }));
+
+        let mut second = discord.next_conn().await;
+        assert_eq!(second.path, "/gateway");
+        second.send(json!({ "op": OP_HELLO, "d": { "heartbeat_interval": 10_000 } }));
+        assert_eq!(second.recv().await["op"], OP_IDENTIFY);
+    }
+
+    fn slash_command(channel: serde_json::Value) -> serde_json::Value {
+        json!({
+            "id": "i1",
+            "token": "t1",
+            "type": INTERACTION_APPLICATION_COMMAND,
+            "guild_id": "G1",
+            "channel_id": channel["id"],
+            "channel": channel,
+            "member": { "user": { "id": "U1" } },
+            "data": { "name": "status" },
+        })
+    }
+
+    #[tokio::test]
+    async fn slash_commands_only_register_threads() {
+        let mut discord = Harness::start().await;
+
+        discord
+            .channel
+            .handle_dispatch("INTERACTION_CREATE", &slash_command(json!({ "id": "C1", "type": 0 })))
+            .await;
+        let (method, path, body) = discord.next_call().await;
+        assert_eq!((method.as_str(), path.as_str()), ("POST", "/interactions/i1/t1/callback"));
+        assert_eq!(body["data"]["content"], "`/status`");
+        assert!(!discord.channel.threads.contains_key("C1"), "a server channel is not followed");
+
+        discord
+            .channel
+            .handle_dispatch("INTERACTION_CREATE", &slash_command(json!({ "id": "T9", "type": 11 })))
+            .await;
+        discord.next_call().await;
+        assert!(discord.channel.threads.contains_key("T9"));
+
+        // Without a registered thread, a message in the channel needs a mention.
+        let message = json!({
+            "id": "m1",
+            "channel_id": "C1",
+            "guild_id": "G1",
+            "author": { "id": "U1" },
+            "content": "hello",
+            "mentions": [],
+        });
+        discord.channel.handle_dispatch("MESSAGE_CREATE", &message).await;
+        let mut mentioned = message.clone();
+        mentioned["mentions"] = json!([{ "id": "42" }]);
+        mentioned["content"] = json!("<@42> hello");
+        discord.channel.handle_dispatch("MESSAGE_CREATE", &mentioned).await;
+        // The first REST call after the plain message is the mention's thread lookup.
+        let (method, path, _) = discord.next_call().await;
+        assert_eq!((method.as_str(), path.as_str()), ("GET", "/channels/C1"));
+        let (method, path, _) = discord.next_call().await;
+        assert_eq!((method.as_str(), path.as_str()), ("POST", "/channels/C1/messages/m1/threads"));
+    }
+
+    #[test]
+    fn resume_url_is_used_only_with_a_session() {
+        let mut session = GatewaySession::default();
+        assert_eq!(session.url(DEFAULT_GATEWAY_URL), DEFAULT_GATEWAY_URL);
+        session.resume_url = Some("wss://resume.example/".to_string());
+        session.session_id = Some("s".to_string());
+        assert_eq!(session.url(DEFAULT_GATEWAY_URL), DEFAULT_GATEWAY_URL, "no sequence yet");
+        session.sequence = Some(7);
+        assert_eq!(session.url(DEFAULT_GATEWAY_URL), "wss://resume.example?v=10&encoding=json");
+    }
+
+    #[test]
+    fn mentions_and_button_labels() {
+        assert_eq!(strip_mentions("<@42> run the <@!7> tests "), "run the  tests");
+        let rows = json!([{ "type": 1, "components": [
+            { "type": 2, "label": "Allow", "custom_id": "perm:r:allow" },
+            { "type": 2, "label": "Deny", "custom_id": "perm:r:deny" },
+        ] }]);
+        assert_eq!(find_button_label(&rows, "perm:r:deny").as_deref(), Some("Deny"));
+        assert_eq!(find_button_label(&rows, "perm:r:other"), None);
+    }
+}
@@ -0,0 +1,812 @@
+//! Built-in email channel (IMAP polling + SMTP replies) for long-running, asynchronous tasks.
+//!
+//! Used for `channels.email` when no external email plugin is installed. Unseen mail in the
+//! mailbox is fetched every `poll_interval_secs`; each thread is one chat, keyed by the root
+//! Message-ID of its `References` / `In-Reply-To` chain. The body (quoted history stripped) is
+//! queued into the SessionHub like any other channel message. When the turn ends, one reply is
+//! sent in the same thread with the answer as the body and the full transcript (thinking, tool
+//! calls and results) attached as `transcript.md`.
+//!
+//! Email cannot show buttons, so permission prompts arrive as numbered text and are answered by
+//! replying with the number. Set `tls: false` to talk to a plain local stand-in such as GreenMail
+//! (IMAP 3143, SMTP 3025).
+//!
+//! A From address is trivial to forge, so the channel never runs open: `allowed_senders` must
+//! list addresses (compared ignoring case), and mail is only dispatched when the receiving
+//! server's `Authentication-Results` header, identified by `authserv_id`, shows a DMARC pass, or a
+//! DKIM / SPF pass aligned with the From domain. Only that server's header counts: any other one
+//! may have been written by the sender. `verify_sender: false` turns the header check off for
+//! local servers that add none.
+
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use mail_parser::{HeaderValue, MessageParser};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::channel_manager::capabilities::PluginCapabilities;
+use crate::channel_manager::ChannelManager;
+use crate::config;
+use crate::session_hub::types::ChannelNotification;
+
+#[derive(Debug, Clone, Deserialize)]
+struct EmailConfig {
+    imap_host: String,
+    #[serde(default)]
+    imap_port: Option<u16>,
+    smtp_host: String,
+    #[serde(default)]
+    smtp_port: Option<u16>,
+    username: String,
+    #[serde(default)]
+    password: String,
+    /// The channel's own address; defaults to `username`.
+    #[serde(default)]
+    address: Option<String>,
+    /// Implicit TLS for IMAP (993) and TLS for SMTP (465 implicit, otherwise STARTTLS).
+    #[serde(default = "default_tls")]
+    tls: bool,
+    #[serde(default = "default_mailbox")]
+    mailbox: String,
+    #[serde(default = "default_poll_interval_secs")]
+    poll_interval_secs: u64,
+    /// Require an `Authentication-Results` pass for the From domain.
+    #[serde(default = "default_verify_sender")]
+    verify_sender: bool,
+    /// authserv-id of the receiving server, whose `Authentication-Results` header is trusted.
+    /// Required while `verify_sender` is on.
+    #[serde(default)]
+    authserv_id: Option<String>,
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_poll_interval_secs() -> u64 {
+    30
+}
+
+fn default_verify_sender() -> bool {
+    true
+}
+
+impl EmailConfig {
+    fn imap_port(&self) -> u16 {
+        self.imap_port.unwrap_or(if self.tls { 993 } else { 143 })
+    }
+
+    fn smtp_port(&self) -> u16 {
+        self.smtp_port.unwrap_or(if self.tls { 465 } else { 25 })
+    }
+
+    fn address(&self) -> &str {
+        self.address.as_deref().unwrap_or(&self.username)
+    }
+}
+
+/// Where and how to answer a thread.
+#[derive(Clone)]
+struct ThreadInfo {
+    /// Address of whoever wrote last.
+    reply_to: String,
+    subject: String,
+    last_message_id: String,
+    /// Every Message-ID in the thread so far, oldest first.
+    references: Vec<String>,
+}
+
+/// The turn being answered in a chat.
+#[derive(Default)]
+struct Turn {
+    reply: String,
+    transcript: String,
+}
+
+pub struct EmailChannel {
+    name: String,
+    cfg: EmailConfig,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    channel_hub: Arc<ChannelManager>,
+    /// Message-ID (received or sent) → chat_id of its thread.
+    roots: DashMap<String, String>,
+    threads: DashMap<String, ThreadInfo>,
+    turns: DashMap<String, Turn>,
+}
+
+/// Start the built-in email channel and register it with ChannelManager.
+/// Returns the transport task's abort handle.
+pub fn start(channel_hub: &Arc<ChannelManager>, channel_name: &str) -> Result<AbortHandle, String> {
+    let loaded = config::ensure_loaded();
+    let raw_config = loaded
+        .channel_raw_config(channel_name)
+        .ok_or_else(|| format!("channels.{} is not configured", channel_name))?;
+    let access = loaded.channel_access(channel_name);
+    if access.allowed_senders.iter().chain(&access.admins).all(|id| id == "*") {
+        return Err(format!(
+            "channels.{}.allowed_senders must list the addresses that may send tasks (email is never open)",
+            channel_name
+        ));
+    }
+    let cfg: EmailConfig =
+        serde_json::from_value(raw_config).map_err(|e| format!("channels.{}: {}", channel_name, e))?;
+    cfg.address()
+        .parse::<Mailbox>()
+        .map_err(|e| format!("channels.{}.address: {}", channel_name, e))?;
+    if cfg.verify_sender && cfg.authserv_id.as_deref().is_none_or(|id| id.trim().is_empty()) {
+        return Err(format!(
+            "channels.{}.authserv_id must name the mail server whose Authentication-Results to trust \
+             (or set verify_sender: false)",
+            channel_name
+        ));
+    }
+
+    let smtp = if !cfg.tls {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.smtp_host)
+    } else if cfg.smtp_port() == 465 {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.smtp_host).map_err(|e| format!("smtp: {}", e))?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.smtp_host).map_err(|e| format!("smtp: {}", e))?
+    };
+    let mut smtp = smtp.port(cfg.smtp_port());
+    if !cfg.password.is_empty() {
+        smtp = smtp.credentials(Credentials::new(cfg.username.clone(), cfg.password.clone()));
+    }
+
+    let channel = Arc::new(EmailChannel {
+        name: channel_name.to_string(),
+        mailer: smtp.build(),
+        cfg,
+        channel_hub: Arc::clone(channel_hub),
+        roots: DashMap::new(),
+        threads: DashMap::new(),
+        turns: DashMap::new(),
+    });
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move { channel.run(outbound_rx).await });
+    let abort = task.abort_handle();
+    // Deltas are collected here into one reply per turn, so they are taken raw; only buttons are
+    // missing.
+    let capabilities = PluginCapabilities {
+        buttons: false,
+        file_upload: false,
+        markdown: false,
+        ..PluginCapabilities::full()
+    };
+    channel_hub.start_builtin_plugin_with_capabilities(channel_name, outbound_tx, abort.clone(), capabilities);
+    eprintln!("[{}] built-in email channel started (IMAP polling)", channel_name);
+    Ok(abort)
+}
+
+impl EmailChannel {
+    async fn run(self: Arc<Self>, mut outbound_rx: mpsc::UnboundedReceiver<ChannelNotification>) {
+        let poll = async {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.cfg.poll_interval_secs.max(5)));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                match self.fetch_unseen().await {
+                    Ok(mails) => {
+                        for raw in mails {
+                            self.handle_mail(&raw).await;
+                        }
+                    }
+                    Err(e) => eprintln!("[{}] IMAP poll failed: {}", self.name, e),
+                }
+            }
+        };
+        let outbound = async {
+            while let Some(notif) = outbound_rx.recv().await {
+                self.render(notif).await;
+            }
+        };
+        tokio::select! {
+            _ = poll => {}
+            _ = outbound => {}
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Inbound (IMAP)
+    // -----------------------------------------------------------------------
+
+    /// Fetch the raw source of every unseen message; fetching marks them seen.
+    async fn fetch_unseen(&self) -> Result<Vec<Vec<u8>>, String> {
+        let host = self.cfg.imap_host.as_str();
+        let tcp = tokio::net::TcpStream::connect((host, self.cfg.imap_port()))
+            .await
+            .map_err(|e| format!("connect {}:{}: {}", host, self.cfg.imap_port(), e))?;
+        if !self.cfg.tls {
+            return self.fetch_unseen_over(tcp).await;
+        }
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls_config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_string())
+            .map_err(|e| format!("invalid imap_host {:?}: {}", host, e))?;
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(tls_config))
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| format!("TLS handshake with {}: {}", host, e))?;
+        self.fetch_unseen_over(tls).await
+    }
+
+    async fn fetch_unseen_over<T>(&self, stream: T) -> Result<Vec<Vec<u8>>, String>
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + std::fmt::Debug + Send,
+    {
+        let mut client = async_imap::Client::new(stream);
+        let _greeting = client.read_response().await;
+        let mut session = client
+            .login(&self.cfg.username, &self.cfg.password)
+            .await
+            .map_err(|(e, _)| format!("login: {}", e))?;
+        session
+            .select(&self.cfg.mailbox)
+            .await
+            .map_err(|e| format!("select {}: {}", self.cfg.mailbox, e))?;
+        let mut uids: Vec<u32> = session
+            .uid_search("UNSEEN")
+            .await
+            .map_err(|e| format!("search: {}", e))?
+            .into_iter()
+            .collect();
+        uids.sort_unstable();
+
+        let mut mails = Vec::new();
+        if !uids.is_empty() {
+            let set = uids.iter().map(|uid| uid.to_string()).collect::<Vec<_>>().join(",");
+            let fetches = session
+                .uid_fetch(&set, "RFC822")
+                .await
+                .map_err(|e| format!("fetch: {}", e))?;
+            futures_util::pin_mut!(fetches);
+            while let Some(fetch) = futures_util::StreamExt::next(&mut fetches).await {
+                let fetch = fetch.map_err(|e| format!("fetch: {}", e))?;
+                if let Some(body) = fetch.body() {
+                    mails.push(body.to_vec());
+                }
+            }
+        }
+        let _ = session.logout().await;
+        Ok(mails)
+    }
+
+    async fn handle_mail(&self, raw: &[u8]) {
+        let Some(message) = MessageParser::default().parse(raw) else {
+            eprintln!("[{}] skipping unparsable message", self.name);
+            return;
+        };
+        let Some(from) = message.from().and_then(|a| a.first()) else { return };
+        let Some(address) = from.address().map(|a| a.trim().to_lowercase()) else { return };
+        if address.eq_ignore_ascii_case(self.cfg.address()) {
+            return;
+        }
+        if self.cfg.verify_sender && !self.sender_authenticated(&message, &address) {
+            eprintln!("[{}] dropping mail from {}: not authenticated for its domain", self.name, address);
+            return;
+        }
+
+        let message_id = message
+            .message_id()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}@{}", uuid::Uuid::new_v4(), domain_of(&address)));
+        let in_reply_to = message_ids(message.in_reply_to());
+        let references = message_ids(message.references());
+        // A known id wins (replies to our own mail); otherwise the oldest reference is the root.
+        let chat_id = in_reply_to
+            .iter()
+            .chain(references.iter())
+            .find_map(|id| self.roots.get(id).map(|root| root.clone()))
+            .or_else(|| references.first().cloned())
+            .or_else(|| in_reply_to.first().cloned())
+            .unwrap_or_else(|| message_id.clone());
+        self.roots.insert(message_id.clone(), chat_id.clone());
+
+        let subject = message.subject().unwrap_or("(no subject)").trim().to_string();
+        let mut thread_refs = references;
+        for id in in_reply_to {
+            if !thread_refs.contains(&id) {
+                thread_refs.push(id);
+            }
+        }
+        thread_refs.push(message_id.clone());
+        self.threads.insert(
+            chat_id.clone(),
+            ThreadInfo {
+                reply_to: address.clone(),
+                subject,
+                last_message_id: message_id.clone(),
+                references: thread_refs,
+            },
+        );
+
+        let text = message
+            .body_text(0)
+            .map(|body| strip_quoted(&body))
+            .unwrap_or_default();
+        if text.is_empty() {
+            return;
+        }
+        let params = serde_json::json!({
+            "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+            "messageId": message_id,
+            "text": text,
+            "sender": { "id": address, "name": from.name() },
+        });
+        let msg = serde_json::json!({ "jsonrpc": "2.0", "method": "on_message", "params": params });
+        self.channel_hub.handle_inbound_jsonrpc(&self.name, msg).await;
+    }
+
+    /// Whether the trusted `Authentication-Results` header vouches for the From domain.
+    fn sender_authenticated(&self, message: &mail_parser::Message<'_>, address: &str) -> bool {
+        let Some(trusted_id) = self.cfg.authserv_id.as_deref().map(str::trim).filter(|id| !id.is_empty()) else {
+            return false;
+        };
+        message
+            .headers_raw()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Authentication-Results"))
+            .map(|(_, value)| value)
+            .find(|value| authserv_id(value).eq_ignore_ascii_case(trusted_id))
+            .is_some_and(|value| authentication_passed(value, domain_of(address)))
+    }
+
+    // -----------------------------------------------------------------------
+    // Outbound (SMTP)
+    // -----------------------------------------------------------------------
+
+    async fn render(&self, notif: ChannelNotification) {
+        match notif {
+            ChannelNotification::AgentStart { chat_id, .. } => {
+                self.turns.insert(chat_id, Turn::default());
+            }
+            ChannelNotification::AgentThinking { chat_id, text, .. } => {
+                self.append_transcript(&chat_id, &format!("_Thinking:_ {}\n\n", text));
+            }
+            ChannelNotification::AgentToken { chat_id, delta, .. } => {
+                if let Some(mut turn) = self.turns.get_mut(&chat_id) {
+                    turn.reply.push_str(&delta);
+                    turn.transcript.push_str(&delta);
+                }
+            }
+            ChannelNotification::AgentToolUse { chat_id, tool, input, .. } => {
+                self.append_transcript(&chat_id, &format!("\n\n**Tool: {}**\n\n```\n{}\n```\n\n", tool, input));
+            }
+            ChannelNotification::AgentToolResult { chat_id, tool, output, .. } => {
+                self.append_transcript(
+                    &chat_id,
+                    &format!("**Result: {}**\n\n```\n{}\n```\n\n", tool, output),
+                );
+            }
+            ChannelNotification::AgentEnd { chat_id, .. } => {
+                let turn = self.turns.remove(&chat_id).map(|(_, t)| t).unwrap_or_default();
+                let body = if turn.reply.trim().is_empty() {
+                    "(The agent finished without a text reply; see the transcript.)".to_string()
+                } else {
+                    turn.reply
+                };
+                self.send_reply(&chat_id, &body, Some(turn.transcript)).await;
+            }
+            ChannelNotification::AgentError { chat_id, error, .. } => {
+                let turn = self.turns.remove(&chat_id).map(|(_, t)| t).unwrap_or_default();
+                let mut body = format!("The agent failed: {}", error);
+                if !turn.reply.trim().is_empty() {
+                    body.push_str(&format!("\n\nPartial reply:\n\n{}", turn.reply));
+                }
+                self.send_reply(&chat_id, &body, Some(turn.transcript)).await;
+            }
+            // Command replies, notices and permission prompts need an answer now, not at turn end.
+            ChannelNotification::SendText { chat_id, text, .. } => {
+                self.append_transcript(&chat_id, &format!("\n\n{}\n\n", text));
+                self.send_reply(&chat_id, &text, None).await;
+            }
+            // Rendered as numbered SendText by ChannelManager (no buttons capability).
+            ChannelNotification::PermissionRequest { .. } => {}
+            ChannelNotification::MessageQueued { .. } => {}
+        }
+    }
+
+    fn append_transcript(&self, chat_id: &str, text: &str) {
+        if let Some(mut turn) = self.turns.get_mut(chat_id) {
+            turn.transcript.push_str(text);
+        }
+    }
+
+    /// Reply in the chat's thread to whoever wrote last, optionally attaching a transcript.
+    async fn send_reply(&self, chat_id: &str, body: &str, transcript: Option<String>) {
+        let Some(thread) = self.threads.get(chat_id).map(|t| t.clone()) else {
+            eprintln!("[{}] no thread to reply to for chat {}", self.name, chat_id);
+            return;
+        };
+        let message_id = format!("{}@{}", uuid::Uuid::new_v4(), domain_of(self.cfg.address()));
+        let subject = if thread.subject.to_lowercase().starts_with("re:") {
+            thread.subject.clone()
+        } else {
+            format!("Re: {}", thread.subject)
+        };
+        let (Ok(from), Ok(to)) = (self.cfg.address().parse::<Mailbox>(), thread.reply_to.parse::<Mailbox>()) else {
+            eprintln!("[{}] invalid address for chat {}", self.name, chat_id);
+            return;
+        };
+        let builder = lettre::Message::builder()
+            .from(from)
+            .to(to)
+            .subject(subject)
+            .message_id(Some(format!("<{}>", message_id)))
+            .in_reply_to(format!("<{}>", thread.last_message_id))
+            .references(
+                thread
+                    .references
+                    .iter()
+                    .map(|id| format!("<{}>", id))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        let email = match transcript.filter(|t| !t.trim().is_empty()) {
+            Some(transcript) => builder.multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(body.to_string()))
+                    .singlepart(
+                        Attachment::new("transcript.md".to_string())
+                            .body(transcript, ContentType::parse("text/markdown; charset=utf-8").unwrap()),
+                    ),
+            ),
+            None => builder.body(body.to_string()),
+        };
+        let email = match email {
+            Ok(email) => email,
+            Err(e) => {
+                eprintln!("[{}] failed to build reply: {}", self.name, e);
+                return;
+            }
+        };
+        if let Err(e) = self.mailer.send(email).await {
+            eprintln!("[{}] SMTP send failed: {}", self.name, e);
+            return;
+        }
+        self.roots.insert(message_id.clone(), chat_id.to_string());
+        if let Some(mut thread) = self.threads.get_mut(chat_id) {
+            thread.references.push(message_id.clone());
+            thread.last_message_id = message_id;
+        }
+    }
+}
+
+/// Message-IDs in an `In-Reply-To` / `References` header (angle brackets already stripped).
+fn message_ids(value: &HeaderValue<'_>) -> Vec<String> {
+    match value {
+        HeaderValue::Text(id) => vec![id.to_string()],
+        HeaderValue::TextList(ids) => ids.iter().map(|id| id.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn domain_of(address: &str) -> &str {
+    address.rsplit_once('@').map(|(_, d)| d).unwrap_or("localhost")
+}
+
+static HEADER_COMMENT: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"\([^()]*\)").unwrap());
+
+/// The authserv-id an `Authentication-Results` value starts with.
+fn authserv_id(value: &str) -> &str {
+    value.split(';').next().unwrap_or("").split_whitespace().next().unwrap_or("")
+}
+
+/// Whether an `Authentication-Results` value (RFC 8601) holds a DMARC pass for `from_domain`, or
+/// a DKIM (`header.d`) or SPF (`smtp.mailfrom`) pass for it or a parent domain.
+fn authentication_passed(value: &str, from_domain: &str) -> bool {
+    let value = HEADER_COMMENT.replace_all(value, " ");
+    let aligned = |domain: &str| {
+        let domain = domain.trim_matches('"').trim_end_matches('.');
+        !domain.is_empty()
+            && (from_domain.eq_ignore_ascii_case(domain)
+                || from_domain.to_lowercase().ends_with(&format!(".{}", domain.to_lowercase())))
+    };
+    value.split(';').skip(1).any(|result| {
+        let mut tokens = result.split_whitespace();
+        let Some((method, outcome)) = tokens.next().and_then(|t| t.split_once('=')) else {
+            return false;
+        };
+        if !outcome.eq_ignore_ascii_case("pass") {
+            return false;
+        }
+        let props: Vec<(&str, &str)> = tokens.filter_map(|t| t.split_once('=')).collect();
+        let prop = |name: &str| props.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| *v);
+        match method.to_lowercase().as_str() {
+            "dmarc" => prop("header.from").is_some_and(|d| from_domain.eq_ignore_ascii_case(d.trim_matches('"'))),
+            "dkim" => prop("header.d").is_some_and(aligned),
+            "spf" => prop("smtp.mailfrom").is_some_and(|m| aligned(m.rsplit_once('@').map_or(m, |(_, d)| d))),
+            _ => false,
+        }
+    })
+}
+
+static QUOTE_HEADER: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"^On .+wrote:\s*$").unwrap());
+
+/// Keep only the new text of a reply: stop at the first quoted line or "On … wrote:" header.
+fn strip_quoted(body: &str) -> String {
+    body.lines()
+        .take_while(|line| !line.starts_with('>') && !QUOTE_HEADER.is_match(line) && line.trim_end() != "--")
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+
+    use mail_parser::MimeHeaders;
+
+    use super::*;
+    use crate::agent::StopReason;
+    use crate::config::Config;
+    use crate::session_hub::types::AgentEvent;
+    use crate::session_hub::SessionHub;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// Serve one IMAP session that has `mails` unseen, enough for `fetch_unseen`.
+    async fn mock_imap(mails: Vec<String>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"* OK mock IMAP ready\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let (tag, command) = line.split_once(' ').unwrap_or((&line, ""));
+                let command = command.to_ascii_uppercase();
+                let mut reply = String::new();
+                if command.starts_with("SELECT") {
+                    reply.push_str(&format!("* {} EXISTS\r\n* FLAGS (\\Seen)\r\n", mails.len()));
+                } else if command.starts_with("UID SEARCH") {
+                    let uids: Vec<String> = (1..=mails.len()).map(|uid| uid.to_string()).collect();
+                    reply.push_str(&format!("* SEARCH {}\r\n", uids.join(" ")));
+                } else if command.starts_with("UID FETCH") {
+                    for (i, mail) in mails.iter().enumerate() {
+                        let uid = i + 1;
+                        reply.push_str(&format!("* {uid} FETCH (UID {uid} RFC822 {{{}}}\r\n{mail})\r\n", mail.len()));
+                    }
+                } else if command.starts_with("LOGOUT") {
+                    reply.push_str("* BYE\r\n");
+                }
+                reply.push_str(&format!("{} OK done\r\n", tag));
+                write.write_all(reply.as_bytes()).await.unwrap();
+                if command.starts_with("LOGOUT") {
+                    break;
+                }
+            }
+        });
+        port
+    }
+
+    /// Accept SMTP sessions and hand over each message's DATA.
+    async fn mock_smtp() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let verb = line.split_whitespace().next().unwrap_or("").to_ascii_uppercase();
+                        let reply = match verb.as_str() {
+                            "DATA" => {
+                                write.write_all(b"354 go ahead\r\n").await.unwrap();
+                                let mut data = String::new();
+                                while let Ok(Some(line)) = lines.next_line().await {
+                                    if line == "." {
+                                        break;
+                                    }
+                                    data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                                    data.push_str("\r\n");
+                                }
+                                let _ = tx.send(data);
+                                "250 queued"
+                            }
+                            "QUIT" => "221 bye",
+                            _ => "250 OK",
+                        };
+                        write.write_all(format!("{}\r\n", reply).as_bytes()).await.unwrap();
+                        if verb == "QUIT" {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (port, rx)
+    }
+
+    fn mock_channel(imap_port: u16, smtp_port: u16) -> (Arc<EmailChannel>, broadcast::Receiver<AgentEvent>) {
+        let cfg: EmailConfig = serde_json::from_value(serde_json::json!({
+            "imap_host": "127.0.0.1",
+            "imap_port": imap_port,
+            "smtp_host": "127.0.0.1",
+            "smtp_port": smtp_port,
+            "username": "agent@example.com",
+            "tls": false,
+            "authserv_id": "mx.example.com",
+        }))
+        .unwrap();
+        let data_dir = std::env::temp_dir().join(format!("vibearound-email-test-{}", std::process::id()));
+        let channel_hub = Arc::new(ChannelManager::with_config(Arc::new(Config::default()), data_dir));
+        let session_hub = Arc::new(SessionHub::new());
+        channel_hub.set_session_hub(Arc::clone(&session_hub));
+        let events = session_hub.subscribe_agent_events();
+        let channel = Arc::new(EmailChannel {
+            name: "email".to_string(),
+            mailer: AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1").port(smtp_port).build(),
+            cfg,
+            channel_hub,
+            roots: DashMap::new(),
+            threads: DashMap::new(),
+            turns: DashMap::new(),
+        });
+        (channel, events)
+    }
+
+    fn mail(message_id: &str, in_reply_to: Option<&str>, references: &str, body: &str) -> String {
+        let mut mail = String::from(
+            "Authentication-Results: mx.example.com; dmarc=pass header.from=example.com\r\n\
+             From: Alice <Alice@Example.com>\r\n\
+             To: agent@example.com\r\n\
+             Subject: Nightly build\r\n",
+        );
+        mail.push_str(&format!("Message-ID: <{}>\r\n", message_id));
+        if let Some(id) = in_reply_to {
+            mail.push_str(&format!("In-Reply-To: <{}>\r\n", id));
+        }
+        if !references.is_empty() {
+            mail.push_str(&format!("References: {}\r\n", references));
+        }
+        mail.push_str(&format!("\r\n{}\r\n", body));
+        mail
+    }
+
+    async fn next_message(events: &mut broadcast::Receiver<AgentEvent>) -> (String, String) {
+        match tokio::time::timeout(WAIT, events.recv()).await.expect("agent event").unwrap() {
+            AgentEvent::OnReceiveMessage { chat_id, message, .. } => (chat_id, message.text),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn mail_threads_map_to_chats_and_replies_carry_the_transcript() {
+        let imap_port = mock_imap(vec![
+            mail("root@example.com", None, "", "Run the nightly build."),
+            mail(
+                "second@example.com",
+                Some("reply-1@example.com"),
+                "<root@example.com> <reply-1@example.com>",
+                "Also the docs.\r\n\r\nOn Mon, Bob wrote:\r\n> Run the nightly build.",
+            ),
+            mail("other@example.com", None, "", "Unrelated."),
+        ])
+        .await;
+        let (smtp_port, mut sent) = mock_smtp().await;
+        let (channel, mut events) = mock_channel(imap_port, smtp_port);
+
+        let mails = channel.fetch_unseen().await.unwrap();
+        assert_eq!(mails.len(), 3);
+        for raw in &mails {
+            channel.handle_mail(raw).await;
+        }
+        assert_eq!(next_message(&mut events).await, ("root@example.com".into(), "Run the nightly build.".into()));
+        // A reply joins the thread of its oldest reference (and waits behind the running turn).
+        assert_eq!(channel.roots.get("second@example.com").as_deref().map(String::as_str), Some("root@example.com"));
+        assert_eq!(next_message(&mut events).await, ("other@example.com".into(), "Unrelated.".into()));
+
+        let notif = |kind: &str| {
+            let (channel_kind, chat_id) = ("email".to_string(), "root@example.com".to_string());
+            match kind {
+                "start" => ChannelNotification::AgentStart { channel_kind, chat_id, message_id: "m".into() },
+                "tool" => ChannelNotification::AgentToolUse {
+                    channel_kind,
+                    chat_id,
+                    tool_id: "t1".into(),
+                    tool: "Bash".into(),
+                    input: "make docs".into(),
+                },
+                "token" => ChannelNotification::AgentToken { channel_kind, chat_id, delta: "Build is green.".into() },
+                _ => ChannelNotification::AgentEnd {
+                    channel_kind,
+                    chat_id,
+                    stop_reason: StopReason::EndTurn,
+                    cost_usd: None,
+                    usage: None,
+                },
+            }
+        };
+        for kind in ["start", "tool", "token", "end"] {
+            channel.render(notif(kind)).await;
+        }
+
+        let data = tokio::time::timeout(WAIT, sent.recv()).await.expect("SMTP message").unwrap();
+        let reply = MessageParser::default().parse(data.as_bytes()).unwrap();
+        assert_eq!(reply.subject(), Some("Re: Nightly build"));
+        assert_eq!(reply.to().and_then(|a| a.first()).and_then(|a| a.address()), Some("alice@example.com"));
+        assert_eq!(message_ids(reply.in_reply_to()), ["second@example.com"]);
+        assert_eq!(
+            message_ids(reply.references()),
+            ["root@example.com", "reply-1@example.com", "second@example.com"]
+        );
+        assert_eq!(reply.body_text(0).as_deref().map(str::trim), Some("Build is green."));
+        let transcript = reply.attachment(0).expect("transcript attachment");
+        assert_eq!(transcript.attachment_name(), Some("transcript.md"));
+        let transcript = String::from_utf8_lossy(transcript.contents());
+        assert!(transcript.contains("**Tool: Bash**") && transcript.contains("make docs"), "{transcript}");
+
+        // The sent reply's id is part of the thread: an answer to it lands in the same chat.
+        let reply_id = reply.message_id().unwrap().to_string();
+        channel.handle_mail(mail("third@example.com", Some(&reply_id), "", "Thanks").as_bytes()).await;
+        assert_eq!(channel.roots.get("third@example.com").as_deref().map(String::as_str), Some("root@example.com"));
+    }
+
+    #[tokio::test]
+    async fn mail_without_the_trusted_authentication_results_is_dropped() {
+        let (channel, mut events) = mock_channel(1, 1);
+        let forged = mail("forged@example.com", None, "", "rm -rf")
+            .replace("mx.example.com;", "mx.attacker.net;");
+        channel.handle_mail(forged.as_bytes()).await;
+        channel.handle_mail(mail("ok@example.com", None, "", "hello").as_bytes()).await;
+        assert_eq!(next_message(&mut events).await.0, "ok@example.com");
+    }
+
+    #[test]
+    fn strip_quoted_keeps_only_the_new_text() {
+        assert_eq!(strip_quoted("Sounds good.\n\nOn Tue, 1 Apr 2025, Bob wrote:\n> earlier"), "Sounds good.");
+        assert_eq!(strip_quoted("Top line\n> quoted\nafter quote"), "Top line");
+        assert_eq!(strip_quoted("Body\n-- \nSignature"), "Body");
+        assert_eq!(strip_quoted("> only quoted"), "");
+        assert_eq!(strip_quoted("  no quotes at all  \n"), "no quotes at all");
+    }
+
+    #[test]
+    fn authentication_results_pass_for_the_from_domain() {
+        let dmarc = "mx.example.net; dkim=pass header.d=mail.example.com; dmarc=pass (p=reject) header.from=example.com";
+        assert!(authentication_passed(dmarc, "example.com"));
+        assert!(authentication_passed("mx.example.net; dkim=pass header.i=@example.com header.d=example.com", "example.com"));
+        assert!(authentication_passed("mx.example.net; spf=pass smtp.mailfrom=bounce@example.com", "eu.example.com"));
+        assert!(authentication_passed("mx.example.net;\r\n\tspf=pass (sender SPF authorized) smtp.mailfrom=example.com", "example.com"));
+    }
+
+    #[test]
+    fn authentication_results_reject_other_domains_and_failures() {
+        assert!(!authentication_passed("mx.example.net; dkim=pass header.d=attacker.com", "example.com"));
+        assert!(!authentication_passed("mx.example.net; dkim=pass header.d=notexample.com", "example.com"));
+        assert!(!authentication_passed("mx.example.net; spf=softfail smtp.mailfrom=example.com", "example.com"));
+        assert!(!authentication_passed("mx.example.net; dkim=fail header.d=example.com; dmarc=fail header.from=example.com", "example.com"));
+        assert!(!authentication_passed("mx.example.net; none", "example.com"));
+        // The authserv-id is not a result.
+        assert!(!authentication_passed("dkim=pass header.d=example.com", "example.com"));
+    }
+
+    #[test]
+    fn authserv_id_is_the_first_token() {
+        assert_eq!(authserv_id("mx.google.com;\r\n dkim=pass header.d=example.com"), "mx.google.com");
+        assert_eq!(authserv_id("mx.example.net 1; spf=pass"), "mx.example.net");
+    }
+}
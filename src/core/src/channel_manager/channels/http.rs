@@ -0,0 +1,357 @@
+//! Internal `http` channel: drive agents from CI, issue trackers or cron over plain HTTP.
+//!
+//! The web server accepts `POST /api/channels/http/{chat_id}/messages`, checks the request's HMAC
+//! signature against `channels.http.secret`, and feeds it to ChannelManager as `on_message`.
+//! The reply goes back one of two ways:
+//! - streamed on the same request as Server-Sent Events (`Accept: text/event-stream`), via
+//!   `subscribe_reply`; nothing is posted to the callback for that message
+//! - posted once to `channels.http.callback_url` when the turn ends, signed with the same secret
+//!
+//! The channel has no buttons: permission requests arrive as numbered text (an SSE `text` event or
+//! a `text` callback) and are answered by posting the number as the next message.
+//!
+//! Signatures are `sha256=<hex HMAC-SHA256 of "<timestamp>.<raw body>">` in the
+//! `X-VibeAround-Signature` header, with the unix timestamp (seconds) in `X-VibeAround-Timestamp`.
+//! Requests whose timestamp is more than `SIGNATURE_MAX_AGE` away from now are rejected, and a
+//! signature is accepted only once within that window, so a captured request cannot be replayed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::channel_manager::capabilities::PluginCapabilities;
+use crate::config;
+use crate::session_hub::types::{ChannelNotification, ChatId, MessageId};
+
+pub const CHANNEL_NAME: &str = "http";
+pub const SIGNATURE_HEADER: &str = "x-vibearound-signature";
+pub const TIMESTAMP_HEADER: &str = "x-vibearound-timestamp";
+/// How far a signed request's timestamp may be from the server clock.
+pub const SIGNATURE_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a streamed message is remembered while it waits in the queue for its turn.
+const STREAMED_REPLY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Outbound sink to one SSE response.
+pub type HttpChatSink = mpsc::UnboundedSender<ChannelNotification>;
+
+/// The turn being answered in a chat, collected for the callback.
+struct Turn {
+    message_id: MessageId,
+    text: String,
+    /// The caller streams this reply over SSE, so no callback is posted.
+    streamed: bool,
+}
+
+pub struct HttpChannelManager {
+    /// Open SSE responses per chat.
+    subscribers: DashMap<ChatId, Vec<HttpChatSink>>,
+    turns: DashMap<ChatId, Turn>,
+    /// Messages whose reply is streamed over SSE, until their turn starts.
+    streamed: DashMap<MessageId, Instant>,
+    /// Signatures accepted within `SIGNATURE_MAX_AGE`, with the request timestamp.
+    seen_signatures: Mutex<HashMap<String, u64>>,
+    client: reqwest::Client,
+}
+
+/// `channels.http` settings the channel itself needs (allowlists are handled by ChannelManager).
+pub struct HttpChannelConfig {
+    pub secret: String,
+    pub callback_url: Option<String>,
+}
+
+impl HttpChannelConfig {
+    /// `None` when the channel is not configured. An empty secret is kept so callers can refuse
+    /// with a clear error instead of accepting unsigned requests.
+    pub fn load() -> Option<Self> {
+        let raw = config::ensure_loaded().channel_raw_config(CHANNEL_NAME)?;
+        let field = |key: &str| {
+            raw.get(key)
+                .and_then(|v| v.as_str())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        Some(Self {
+            secret: field("secret").unwrap_or_default(),
+            callback_url: field("callback_url"),
+        })
+    }
+}
+
+impl HttpChannelManager {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            subscribers: DashMap::new(),
+            turns: DashMap::new(),
+            streamed: DashMap::new(),
+            seen_signatures: Mutex::new(HashMap::new()),
+            client: reqwest::Client::builder()
+                .timeout(CALLBACK_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// What the channel renders: streamed text, but no buttons, threads or uploads.
+    pub fn capabilities() -> PluginCapabilities {
+        PluginCapabilities { buttons: false, threads: false, file_upload: false, ..PluginCapabilities::full() }
+    }
+
+    pub fn sender(
+        &self,
+    ) -> (
+        mpsc::UnboundedSender<ChannelNotification>,
+        mpsc::UnboundedReceiver<ChannelNotification>,
+    ) {
+        mpsc::unbounded_channel()
+    }
+
+    /// Receive the chat's notifications (for an SSE response). Dropping the receiver unsubscribes.
+    pub fn subscribe(&self, chat_id: &str) -> mpsc::UnboundedReceiver<ChannelNotification> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscribers.entry(chat_id.to_string()).or_default().push(tx);
+        rx
+    }
+
+    /// `subscribe` for a caller that streams the reply to `message_id`; that reply is not posted
+    /// to the callback URL.
+    pub fn subscribe_reply(&self, chat_id: &str, message_id: &str) -> mpsc::UnboundedReceiver<ChannelNotification> {
+        self.streamed.retain(|_, since| since.elapsed() < STREAMED_REPLY_TTL);
+        self.streamed.insert(message_id.to_string(), Instant::now());
+        self.subscribe(chat_id)
+    }
+
+    /// Accept a verified request's signature once: `false` when it was already used within
+    /// `SIGNATURE_MAX_AGE` (a replay).
+    pub fn claim_signature(&self, timestamp: &str, signature: &str) -> bool {
+        let Ok(timestamp) = timestamp.trim().parse::<u64>() else {
+            return false;
+        };
+        let now = unix_now();
+        let mut seen = self.seen_signatures.lock().unwrap_or_else(|e| e.into_inner());
+        seen.retain(|_, ts| now.abs_diff(*ts) <= SIGNATURE_MAX_AGE.as_secs());
+        seen.insert(signature.trim().to_ascii_lowercase(), timestamp).is_none()
+    }
+
+    pub fn dispatch_notification(&self, notif: ChannelNotification) {
+        let chat_id = super::web::chat_id_of_notification(&notif).to_string();
+
+        if let Some(mut sinks) = self.subscribers.get_mut(&chat_id) {
+            sinks.retain(|sink| sink.send(notif.clone()).is_ok());
+        }
+        self.subscribers.remove_if(&chat_id, |_, sinks| sinks.is_empty());
+
+        if let Some(payload) = self.callback_payload(chat_id, notif) {
+            self.post_callback(payload);
+        }
+    }
+
+    /// What to post to the callback URL for a notification, if anything.
+    fn callback_payload(&self, chat_id: ChatId, notif: ChannelNotification) -> Option<serde_json::Value> {
+        match notif {
+            ChannelNotification::AgentStart { message_id, .. } => {
+                let streamed = self.streamed.remove(&message_id).is_some();
+                self.turns.insert(chat_id, Turn { message_id, text: String::new(), streamed });
+                None
+            }
+            ChannelNotification::AgentToken { delta, .. } => {
+                if let Some(mut turn) = self.turns.get_mut(&chat_id) {
+                    turn.text.push_str(&delta);
+                }
+                None
+            }
+            ChannelNotification::AgentEnd { stop_reason, .. } => {
+                let turn = self.turns.remove(&chat_id).map(|(_, t)| t);
+                (!turn.as_ref().is_some_and(|t| t.streamed)).then(|| {
+                    serde_json::json!({
+                        "event": "reply",
+                        "chatId": chat_id,
+                        "messageId": turn.as_ref().map(|t| t.message_id.clone()),
+                        "text": turn.map(|t| t.text).unwrap_or_default(),
+                        "stopReason": stop_reason.as_str(),
+                    })
+                })
+            }
+            ChannelNotification::AgentError { error, .. } => {
+                let turn = self.turns.remove(&chat_id).map(|(_, t)| t);
+                (!turn.as_ref().is_some_and(|t| t.streamed)).then(|| {
+                    serde_json::json!({
+                        "event": "error",
+                        "chatId": chat_id,
+                        "messageId": turn.as_ref().map(|t| t.message_id.clone()),
+                        "text": turn.map(|t| t.text).unwrap_or_default(),
+                        "error": error,
+                    })
+                })
+            }
+            // Command replies, notices (e.g. a rejected sender) and permission prompts.
+            ChannelNotification::SendText { text, reply_to, .. } => {
+                let streamed = match &reply_to {
+                    Some(id) => self.streamed.contains_key(id),
+                    None => self.turns.get(&chat_id).is_some_and(|t| t.streamed),
+                };
+                (!streamed).then(|| {
+                    serde_json::json!({
+                        "event": "text",
+                        "chatId": chat_id,
+                        "messageId": reply_to,
+                        "text": text,
+                    })
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn post_callback(&self, payload: serde_json::Value) {
+        let Some(cfg) = HttpChannelConfig::load() else { return };
+        let Some(url) = cfg.callback_url else { return };
+        let body = payload.to_string();
+        let timestamp = unix_now();
+        let request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(&cfg.secret, timestamp, body.as_bytes()))
+            .body(body);
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(resp) if !resp.status().is_success() => {
+                    eprintln!("[{}] callback {} returned {}", CHANNEL_NAME, url, resp.status());
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("[{}] callback {} failed: {}", CHANNEL_NAME, url, e),
+            }
+        });
+    }
+}
+
+/// Signature header value for `body` sent at `timestamp`: `sha256=<hex>`.
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mac = signing_mac(secret, timestamp, body).finalize().into_bytes();
+    format!("sha256={}", mac.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+/// Check the timestamp and signature headers of a request against `body`.
+pub fn verify(secret: &str, timestamp: &str, body: &[u8], signature: &str) -> Result<(), &'static str> {
+    let timestamp: u64 = timestamp.trim().parse().map_err(|_| "Missing or invalid timestamp")?;
+    if unix_now().abs_diff(timestamp) > SIGNATURE_MAX_AGE.as_secs() {
+        return Err("Timestamp too old or too far in the future");
+    }
+    let signature = signature
+        .trim()
+        .strip_prefix("sha256=")
+        .and_then(decode_hex)
+        .ok_or("Invalid signature")?;
+    signing_mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .map_err(|_| "Invalid signature")
+}
+
+fn signing_mac(secret: &str, timestamp: u64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_requests_verify() {
+        let now = unix_now();
+        let signature = sign("s3cret", now, b"{\"text\":\"hi\"}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(verify("s3cret", &now.to_string(), b"{\"text\":\"hi\"}", &signature), Ok(()));
+    }
+
+    #[test]
+    fn tampered_or_stale_requests_are_rejected() {
+        let now = unix_now();
+        let signature = sign("s3cret", now, b"body");
+        assert!(verify("other", &now.to_string(), b"body", &signature).is_err());
+        assert!(verify("s3cret", &now.to_string(), b"body!", &signature).is_err());
+        // The timestamp is part of the signed message.
+        assert!(verify("s3cret", &(now - 1).to_string(), b"body", &signature).is_err());
+        assert!(verify("s3cret", "", b"body", &signature).is_err());
+        assert!(verify("s3cret", &now.to_string(), b"body", "sha256=zz").is_err());
+
+        let stale = now - SIGNATURE_MAX_AGE.as_secs() - 1;
+        assert!(verify("s3cret", &stale.to_string(), b"body", &sign("s3cret", stale, b"body")).is_err());
+        let future = now + SIGNATURE_MAX_AGE.as_secs() + 60;
+        assert!(verify("s3cret", &future.to_string(), b"body", &sign("s3cret", future, b"body")).is_err());
+    }
+
+    #[test]
+    fn a_signature_is_accepted_once() {
+        let channel = HttpChannelManager::new();
+        let now = unix_now().to_string();
+        let signature = sign("s3cret", unix_now(), b"body");
+        assert!(channel.claim_signature(&now, &signature));
+        assert!(!channel.claim_signature(&now, &signature));
+        assert!(!channel.claim_signature(&now, &signature.to_uppercase().replace("SHA256=", "sha256=")));
+        assert!(channel.claim_signature(&now, &sign("s3cret", unix_now(), b"other body")));
+    }
+
+    #[test]
+    fn streamed_replies_are_not_posted_to_the_callback() {
+        let channel = HttpChannelManager::new();
+        let notif = |message_id: &str, kind: &str| {
+            let (channel_kind, chat_id) = (CHANNEL_NAME.to_string(), "ci".to_string());
+            match kind {
+                "start" => ChannelNotification::AgentStart { channel_kind, chat_id, message_id: message_id.into() },
+                "token" => ChannelNotification::AgentToken { channel_kind, chat_id, delta: "done".into() },
+                "prompt" => {
+                    ChannelNotification::SendText { channel_kind, chat_id, text: "1. Allow".into(), reply_to: None }
+                }
+                _ => ChannelNotification::AgentEnd {
+                    channel_kind,
+                    chat_id,
+                    stop_reason: Default::default(),
+                    cost_usd: None,
+                    usage: None,
+                },
+            }
+        };
+        let payloads = |message_id: &str| {
+            ["start", "token", "prompt", "end"]
+                .map(|kind| channel.callback_payload("ci".into(), notif(message_id, kind)))
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+        };
+
+        let _rx = channel.subscribe_reply("ci", "m1");
+        assert!(payloads("m1").is_empty());
+
+        let posted = payloads("m2");
+        assert_eq!(posted.len(), 2);
+        assert_eq!(posted[0]["event"], "text");
+        assert_eq!(posted[1]["event"], "reply");
+        assert_eq!(posted[1]["messageId"], "m2");
+        assert_eq!(posted[1]["text"], "done");
+    }
+}
@@ -0,0 +1,1478 @@
+//! Built-in Matrix channel (client-server API v3, long-polling `/sync`).
+//!
+//! Used for `channels.matrix` when no external Matrix plugin is installed; works against any
+//! homeserver, including a local Synapse or Conduit. Each joined room is one chat; invites are
+//! accepted from `allowed_senders` and declined from anyone else. The first message of a reply
+//! answers the user's event through `m.relates_to` (inside its thread when the user wrote in one),
+//! and streaming edits it with `m.replace`, rolling into a new message past MATRIX_MESSAGE_LIMIT.
+//! `m.file` / `m.image` (and audio / video) events from allowed senders are downloaded, up to
+//! `attachments.max_size_mb`, and passed on as inbound attachments.
+//!
+//! End-to-end encryption runs through matrix-sdk-crypto's `OlmMachine`, fed from the same `/sync`
+//! loop: the bot publishes device and one-time keys for the access token's device, takes room keys
+//! from to-device messages, decrypts `m.room.encrypted` events and encrypted media, and encrypts
+//! its replies for every device of the room's members. Keys are kept in a SQLite store under
+//! `~/.vibearound/matrix/`, encrypted at rest when `store_passphrase` is set. The bot does not
+//! cross-sign or verify devices: clients show its device as unverified, and it decrypts messages
+//! from any device of a sender (the allowlist still applies to the sender).
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Read as _;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use matrix_sdk_crypto::types::events::room::encrypted::EncryptedEvent;
+use matrix_sdk_crypto::types::requests::{AnyOutgoingRequest, ToDeviceRequest};
+use matrix_sdk_crypto::{
+    AttachmentDecryptor, DecryptionSettings, EncryptionSettings, EncryptionSyncChanges, MediaEncryptionInfo,
+    OlmMachine, TrustRequirement,
+};
+use matrix_sdk_sqlite::SqliteCryptoStore;
+use ruma::api::auth_scheme::{AccessToken, SendAccessToken};
+use ruma::api::client::keys::get_keys;
+use ruma::api::client::message::send_message_event;
+use ruma::api::client::sync::sync_events::DeviceLists;
+use ruma::api::client::to_device::send_event_to_device;
+use ruma::api::path_builder::VersionHistory;
+use ruma::api::{IncomingResponse, OutgoingRequest, SupportedVersions};
+use ruma::events::{AnyToDeviceEvent, MessageLikeEventContent as _};
+use ruma::serde::Raw;
+use ruma::{OneTimeKeyAlgorithm, OwnedDeviceId, OwnedUserId, RoomId, TransactionId, UInt, UserId};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::channel_manager::capabilities::{PluginCapabilities, StreamingEditor};
+use crate::channel_manager::{str_field, ChannelManager};
+use crate::session_hub::types::ChannelNotification;
+
+/// Well under the homeserver's 64 KiB event limit, leaving room for the edit envelope.
+pub const MATRIX_MESSAGE_LIMIT: usize = 16_000;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longer than SYNC_TIMEOUT so a held `/sync` request is not cut off.
+const API_TIMEOUT: Duration = Duration::from_secs(60);
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+/// Room keys are accepted from any device; who may talk to the bot is decided by `allowed_senders`.
+const DECRYPTION_SETTINGS: DecryptionSettings =
+    DecryptionSettings { sender_device_trust_requirement: TrustRequirement::Untrusted };
+
+/// The event a reply in this room answers.
+#[derive(Clone)]
+struct ReplyTarget {
+    event_id: String,
+    /// Thread root when the user wrote inside a thread.
+    thread_root: Option<String>,
+}
+
+/// A user's `m.room.message`, decrypted if it arrived encrypted.
+#[derive(Debug, PartialEq)]
+struct RoomMessage {
+    sender: String,
+    event_id: String,
+    thread_root: Option<String>,
+    msgtype: String,
+    /// Body without the reply fallback; empty for media.
+    text: String,
+    /// The event content of `m.file` / `m.image` / `m.audio` / `m.video`.
+    media: Option<serde_json::Value>,
+}
+
+enum DownloadError {
+    /// Bytes received before the download passed the limit.
+    TooLarge(u64),
+    Failed(String),
+}
+
+pub struct MatrixChannel {
+    name: String,
+    homeserver_url: String,
+    access_token: String,
+    client: reqwest::Client,
+    channel_hub: Arc<ChannelManager>,
+    /// Filled in from `/account/whoami`.
+    user_id: std::sync::RwLock<String>,
+    streams: StreamingEditor<String, String>,
+    reply_targets: DashMap<String, ReplyTarget>,
+    /// Crypto stores live in per-device directories under this one.
+    store_dir: PathBuf,
+    store_passphrase: Option<String>,
+    /// Opened after `/account/whoami` names the device; unset when the access token has none.
+    olm: OnceLock<OlmMachine>,
+    /// Held while sending the machine's outgoing requests, so none goes out twice.
+    crypto_requests: tokio::sync::Mutex<()>,
+    /// Held while sharing a room key, so concurrent sends do not create two.
+    room_keys: tokio::sync::Mutex<()>,
+    /// Whether each room is encrypted, from `m.room.encryption` state.
+    encrypted_rooms: DashMap<String, bool>,
+    /// Joined members of each room, dropped when a membership event arrives.
+    members: DashMap<String, Vec<OwnedUserId>>,
+}
+
+/// Start the built-in Matrix channel and register it with ChannelManager.
+/// Returns the transport task's abort handle.
+pub fn start(channel_hub: &Arc<ChannelManager>, channel_name: &str) -> Result<AbortHandle, String> {
+    let raw_config = channel_hub
+        .config()
+        .channel_raw_config(channel_name)
+        .ok_or_else(|| format!("channels.{} is not configured", channel_name))?;
+    let optional = |key: &str| {
+        raw_config
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+    };
+    let field = |key: &str| optional(key).ok_or_else(|| format!("channels.{}.{} is missing", channel_name, key));
+    let channel = MatrixChannel::new(
+        channel_name,
+        field("homeserver_url")?.trim_end_matches('/'),
+        &field("access_token")?,
+        channel_hub,
+        optional("store_passphrase"),
+    )?;
+    let abort = Arc::new(channel).spawn();
+    eprintln!("[{}] built-in Matrix channel started (sync)", channel_name);
+    Ok(abort)
+}
+
+impl MatrixChannel {
+    fn new(
+        name: &str,
+        homeserver_url: &str,
+        access_token: &str,
+        channel_hub: &Arc<ChannelManager>,
+        store_passphrase: Option<String>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            name: name.to_string(),
+            homeserver_url: homeserver_url.to_string(),
+            access_token: access_token.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(API_TIMEOUT)
+                .build()
+                .map_err(|e| format!("reqwest client: {}", e))?,
+            channel_hub: Arc::clone(channel_hub),
+            user_id: std::sync::RwLock::new(String::new()),
+            streams: StreamingEditor::new(MATRIX_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+            reply_targets: DashMap::new(),
+            store_dir: channel_hub.data_dir().join("matrix"),
+            store_passphrase,
+            olm: OnceLock::new(),
+            crypto_requests: tokio::sync::Mutex::new(()),
+            room_keys: tokio::sync::Mutex::new(()),
+            encrypted_rooms: DashMap::new(),
+            members: DashMap::new(),
+        })
+    }
+
+    /// Run the transport and register it with ChannelManager.
+    fn spawn(self: Arc<Self>) -> AbortHandle {
+        let channel_hub = Arc::clone(&self.channel_hub);
+        let name = self.name.clone();
+        let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move { self.run(outbound_rx).await });
+        let abort = task.abort_handle();
+        // Matrix has no buttons; permission prompts become numbered text answered by a reply.
+        let capabilities = PluginCapabilities {
+            buttons: false,
+            markdown: false,
+            max_message_length: Some(MATRIX_MESSAGE_LIMIT),
+            ..PluginCapabilities::full()
+        };
+        channel_hub.start_builtin_plugin_with_capabilities(&name, outbound_tx, abort.clone(), capabilities);
+        abort
+    }
+
+    async fn run(self: Arc<Self>, mut outbound_rx: mpsc::UnboundedReceiver<ChannelNotification>) {
+        let outbound = async {
+            while let Some(notif) = outbound_rx.recv().await {
+                self.render(notif).await;
+            }
+        };
+        tokio::select! {
+            _ = self.sync_loop() => {}
+            _ = outbound => {}
+        }
+    }
+
+    /// Call a client-server endpoint (`path` relative to `/_matrix`). Returns the JSON body.
+    async fn api(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value, String> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}/_matrix{}", self.homeserver_url, path))
+            .bearer_auth(&self.access_token);
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        let resp = request.send().await.map_err(|e| format!("{} {}: {}", method, path, e))?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| format!("{} {}: {}", method, path, e))?;
+        if !status.is_success() {
+            return Err(format!("{} {}: {} {}", method, path, status, text));
+        }
+        serde_json::from_str(&text).map_err(|e| format!("{} {}: invalid response: {}", method, path, e))
+    }
+
+    /// Send one of the typed requests the crypto machine produces.
+    async fn send_ruma<R>(&self, request: R) -> Result<R::IncomingResponse, String>
+    where
+        R: OutgoingRequest<Authentication = AccessToken, PathBuilder = VersionHistory>,
+    {
+        let versions = SupportedVersions::from_parts(&["v1.1".to_string()], &BTreeMap::new());
+        let request = request
+            .try_into_http_request::<Vec<u8>>(
+                &self.homeserver_url,
+                SendAccessToken::IfRequired(&self.access_token),
+                Cow::Owned(versions),
+            )
+            .map_err(|e| format!("building request: {}", e))?;
+        let target = format!("{} {}", request.method(), request.uri().path());
+        let request = reqwest::Request::try_from(request).map_err(|e| format!("{}: {}", target, e))?;
+        let resp = self.client.execute(request).await.map_err(|e| format!("{}: {}", target, e))?;
+        let status = resp.status();
+        let body = resp.bytes().await.map_err(|e| format!("{}: {}", target, e))?;
+        let response = http::Response::builder()
+            .status(status)
+            .body(body)
+            .map_err(|e| format!("{}: {}", target, e))?;
+        R::IncomingResponse::try_from_http_response(response).map_err(|e| format!("{}: {}", target, e))
+    }
+
+    fn is_allowed(&self, sender: &str) -> bool {
+        self.channel_hub.config().channel_access(&self.name).is_allowed(sender)
+    }
+
+    // -----------------------------------------------------------------------
+    // Inbound (/sync)
+    // -----------------------------------------------------------------------
+
+    async fn sync_loop(&self) {
+        let mut backoff = Duration::from_secs(1);
+        let mut since: Option<String> = None;
+        loop {
+            if self.user_id.read().unwrap().is_empty() {
+                if let Err(e) = self.sign_in().await {
+                    eprintln!("[{}] sign-in failed: {} — retrying in {}s", self.name, e, backoff.as_secs());
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                    continue;
+                }
+            }
+            // Publish keys before the first sync so other devices can start encrypting for us.
+            self.send_crypto_requests().await;
+
+            // The first sync only records the position, so history is not replayed on startup.
+            let path = match &since {
+                Some(since) => format!(
+                    "/client/v3/sync?timeout={}&since={}",
+                    SYNC_TIMEOUT.as_millis(),
+                    urlencoding::encode(since)
+                ),
+                None => "/client/v3/sync?timeout=0".to_string(),
+            };
+            match self.api(reqwest::Method::GET, &path, None).await {
+                Ok(sync) => {
+                    backoff = Duration::from_secs(1);
+                    // To-device messages carry room keys and are not sent again once `since`
+                    // moves past them, so they are taken before anything else.
+                    self.receive_crypto_changes(&sync).await;
+                    self.handle_sync(&sync, since.is_none()).await;
+                    since = sync.get("next_batch").and_then(|v| v.as_str()).map(String::from).or(since);
+                }
+                Err(e) => {
+                    eprintln!("[{}] sync failed: {} — retrying in {}s", self.name, e, backoff.as_secs());
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /// Look up the bot's user and device, and open the device's crypto store.
+    async fn sign_in(&self) -> Result<(), String> {
+        let whoami = self.api(reqwest::Method::GET, "/client/v3/account/whoami", None).await?;
+        let user_id = str_field(&whoami, "user_id");
+        let device_id = str_field(&whoami, "device_id");
+        if device_id.is_empty() {
+            eprintln!(
+                "[{}] the access token has no device, so encrypted rooms cannot be read; use a token from /login",
+                self.name
+            );
+        } else if self.olm.get().is_none() {
+            let machine = self.open_olm_machine(&user_id, &device_id).await?;
+            let _ = self.olm.set(machine);
+        }
+        eprintln!("[{}] signed in as {} (device {})", self.name, user_id, device_id);
+        *self.user_id.write().unwrap() = user_id;
+        Ok(())
+    }
+
+    async fn open_olm_machine(&self, user_id: &str, device_id: &str) -> Result<OlmMachine, String> {
+        let user_id = UserId::parse(user_id).map_err(|e| format!("user id {}: {}", user_id, e))?;
+        let device_id = OwnedDeviceId::from(device_id);
+        // One store per device: a new access token means a new device with its own keys.
+        let dir_name: String = format!("{}_{}", user_id, device_id)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-') { c } else { '_' })
+            .collect();
+        let store = SqliteCryptoStore::open(self.store_dir.join(dir_name), self.store_passphrase.as_deref())
+            .await
+            .map_err(|e| format!("crypto store: {}", e))?;
+        OlmMachine::with_store(&user_id, &device_id, store, None)
+            .await
+            .map_err(|e| format!("crypto store: {}", e))
+    }
+
+    /// Pass the sync's to-device messages, device list changes and key counts to the crypto
+    /// machine, then send whatever requests that produced.
+    async fn receive_crypto_changes(&self, sync: &serde_json::Value) {
+        let Some(olm) = self.olm.get() else { return };
+        let to_device_events: Vec<Raw<AnyToDeviceEvent>> = sync_field(sync, "/to_device/events").unwrap_or_default();
+        let changed_devices: DeviceLists = sync_field(sync, "/device_lists").unwrap_or_default();
+        let one_time_keys_counts: BTreeMap<OneTimeKeyAlgorithm, UInt> =
+            sync_field(sync, "/device_one_time_keys_count").unwrap_or_default();
+        let unused_fallback_keys: Option<Vec<OneTimeKeyAlgorithm>> =
+            sync_field(sync, "/device_unused_fallback_key_types");
+        let changes = EncryptionSyncChanges {
+            to_device_events,
+            changed_devices: &changed_devices,
+            one_time_keys_counts: &one_time_keys_counts,
+            unused_fallback_keys: unused_fallback_keys.as_deref(),
+            next_batch_token: sync.get("next_batch").and_then(|v| v.as_str()).map(String::from),
+        };
+        if let Err(e) = olm.receive_sync_changes(changes, &DECRYPTION_SETTINGS).await {
+            eprintln!("[{}] failed to process encryption updates: {}", self.name, e);
+        }
+        self.send_crypto_requests().await;
+    }
+
+    /// Send the crypto machine's pending requests (key uploads, key queries, to-device messages).
+    /// Failed requests stay pending and are retried on the next sync.
+    async fn send_crypto_requests(&self) {
+        let Some(olm) = self.olm.get() else { return };
+        let _guard = self.crypto_requests.lock().await;
+        let requests = match olm.outgoing_requests().await {
+            Ok(requests) => requests,
+            Err(e) => {
+                eprintln!("[{}] crypto store: {}", self.name, e);
+                return;
+            }
+        };
+        for request in requests {
+            if let Err(e) = self.send_crypto_request(olm, request.request_id(), request.request()).await {
+                eprintln!("[{}] encryption request failed: {}", self.name, e);
+            }
+        }
+    }
+
+    async fn send_crypto_request(
+        &self,
+        olm: &OlmMachine,
+        request_id: &TransactionId,
+        request: &AnyOutgoingRequest,
+    ) -> Result<(), String> {
+        match request {
+            AnyOutgoingRequest::KeysUpload(request) => {
+                let response = self.send_ruma(request.clone()).await?;
+                olm.mark_request_as_sent(request_id, &response).await
+            }
+            AnyOutgoingRequest::KeysQuery(request) => {
+                let mut query = get_keys::v3::Request::new();
+                query.device_keys = request.device_keys.clone();
+                query.timeout = request.timeout;
+                let response = self.send_ruma(query).await?;
+                olm.mark_request_as_sent(request_id, &response).await
+            }
+            AnyOutgoingRequest::KeysClaim(request) => {
+                let response = self.send_ruma(request.clone()).await?;
+                olm.mark_request_as_sent(request_id, &response).await
+            }
+            AnyOutgoingRequest::ToDeviceRequest(request) => return self.send_to_device(olm, request).await,
+            AnyOutgoingRequest::SignatureUpload(request) => {
+                let response = self.send_ruma(request.clone()).await?;
+                olm.mark_request_as_sent(request_id, &response).await
+            }
+            AnyOutgoingRequest::RoomMessage(request) => {
+                let body = Raw::new(&*request.content).map_err(|e| e.to_string())?.cast_unchecked();
+                let response = self
+                    .send_ruma(send_message_event::v3::Request::new_raw(
+                        request.room_id.clone(),
+                        request.txn_id.clone(),
+                        request.content.event_type(),
+                        body,
+                    ))
+                    .await?;
+                olm.mark_request_as_sent(request_id, &response).await
+            }
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    async fn send_to_device(&self, olm: &OlmMachine, request: &ToDeviceRequest) -> Result<(), String> {
+        let response = self
+            .send_ruma(send_event_to_device::v3::Request::new_raw(
+                request.event_type.clone(),
+                request.txn_id.clone(),
+                request.messages.clone(),
+            ))
+            .await?;
+        olm.mark_request_as_sent(&request.txn_id, &response).await.map_err(|e| e.to_string())
+    }
+
+    /// Join rooms the bot is invited to by an allowed sender and decline the other invites.
+    async fn answer_invites(&self, sync: &serde_json::Value) {
+        let Some(invites) = sync.pointer("/rooms/invite").and_then(|v| v.as_object()) else { return };
+        let user_id = self.user_id.read().unwrap().clone();
+        for (room_id, room) in invites {
+            let inviter = inviter(room, &user_id).unwrap_or_default();
+            let (action, done) = if self.is_allowed(&inviter) { ("join", "joined") } else { ("leave", "declined") };
+            let path = format!("/client/v3/rooms/{}/{}", urlencoding::encode(room_id), action);
+            match self.api(reqwest::Method::POST, &path, Some(serde_json::json!({}))).await {
+                Ok(_) => eprintln!("[{}] {} invite to {} from {}", self.name, done, room_id, inviter),
+                Err(e) => eprintln!("[{}] failed to {} {}: {}", self.name, action, room_id, e),
+            }
+        }
+    }
+
+    /// Handle one `/sync` response. The initial sync only answers invites and learns room state.
+    async fn handle_sync(&self, sync: &serde_json::Value, initial: bool) {
+        self.answer_invites(sync).await;
+        let Some(joined) = sync.pointer("/rooms/join").and_then(|v| v.as_object()) else { return };
+        for (room_id, room) in joined {
+            let timeline = event_list(room, "/timeline/events");
+            for event in event_list(room, "/state/events").iter().chain(&timeline) {
+                match event.get("type").and_then(|v| v.as_str()) {
+                    Some("m.room.encryption") => {
+                        self.encrypted_rooms.insert(room_id.clone(), true);
+                    }
+                    Some("m.room.member") => {
+                        self.members.remove(room_id);
+                    }
+                    _ => {}
+                }
+            }
+            if initial {
+                continue;
+            }
+            for event in &timeline {
+                self.handle_event(room_id, event).await;
+            }
+        }
+    }
+
+    async fn handle_event(&self, room_id: &str, event: &serde_json::Value) {
+        let user_id = self.user_id.read().unwrap().clone();
+        if str_field(event, "sender") == user_id {
+            return;
+        }
+        let decrypted;
+        let event = if event.get("type").and_then(|v| v.as_str()) == Some("m.room.encrypted") {
+            self.encrypted_rooms.insert(room_id.to_string(), true);
+            let Some(plain) = self.decrypt(room_id, event).await else { return };
+            decrypted = plain;
+            &decrypted
+        } else {
+            event
+        };
+        let Some(message) = parse_room_message(event, &user_id) else { return };
+
+        let attachments = match &message.media {
+            None => Vec::new(),
+            Some(content) => {
+                // Files from senders outside allowed_senders are never downloaded; the message
+                // still goes to ChannelManager, which turns the sender away with a notice.
+                let download = self.is_allowed(&message.sender);
+                let Some(attachment) = self.attachment(content, &message.msgtype, download).await else { return };
+                vec![attachment]
+            }
+        };
+        self.reply_targets.insert(
+            room_id.to_string(),
+            ReplyTarget { event_id: message.event_id.clone(), thread_root: message.thread_root },
+        );
+
+        let params = serde_json::json!({
+            "channelId": ChannelNotification::plugin_channel_id(&self.name, room_id),
+            "messageId": message.event_id,
+            "text": message.text,
+            "sender": { "id": message.sender },
+            "attachments": attachments,
+        });
+        let msg = serde_json::json!({ "jsonrpc": "2.0", "method": "on_message", "params": params });
+        self.channel_hub.handle_inbound_jsonrpc(&self.name, msg).await;
+    }
+
+    /// Decrypt an `m.room.encrypted` event into the plaintext event, keeping the sync's sender and
+    /// event id and the unencrypted `m.relates_to` (clients put relations outside the ciphertext).
+    async fn decrypt(&self, room_id: &str, event: &serde_json::Value) -> Option<serde_json::Value> {
+        let event_id = str_field(event, "event_id");
+        let Some(olm) = self.olm.get() else {
+            eprintln!("[{}] cannot decrypt {} in {}: no device keys", self.name, event_id, room_id);
+            return None;
+        };
+        let room = RoomId::parse(room_id).ok()?;
+        let raw = Raw::<EncryptedEvent>::from_json(serde_json::value::to_raw_value(event).ok()?);
+        let decrypted = match olm.decrypt_room_event(&raw, &room, &DECRYPTION_SETTINGS).await {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                eprintln!("[{}] could not decrypt {} in {}: {}", self.name, event_id, room_id, e);
+                return None;
+            }
+        };
+        let mut plain: serde_json::Value = serde_json::from_str(decrypted.event.json().get()).ok()?;
+        plain["sender"] = event["sender"].clone();
+        plain["event_id"] = event["event_id"].clone();
+        if let Some(relates_to) = event.pointer("/content/m.relates_to") {
+            if plain.pointer("/content/m.relates_to").is_none() && plain["content"].is_object() {
+                plain["content"]["m.relates_to"] = relates_to.clone();
+            }
+        }
+        Some(plain)
+    }
+
+    /// The inbound attachment for a media event. Its bytes are downloaded (and decrypted) inline
+    /// when `download` is set and the file fits `attachments.max_size_mb`; otherwise it is only
+    /// described, with its size, and ChannelManager turns it down with a notice.
+    async fn attachment(
+        &self,
+        content: &serde_json::Value,
+        msgtype: &str,
+        download: bool,
+    ) -> Option<serde_json::Value> {
+        use base64::Engine as _;
+
+        // Encrypted media has its url and key in `file` instead of `url`.
+        let file = content.get("file").filter(|f| f.is_object());
+        let url = str_field(file.unwrap_or(content), "url");
+        let Some((server, media_id)) = url.strip_prefix("mxc://").and_then(|rest| rest.split_once('/')) else {
+            eprintln!("[{}] {} without an mxc:// url, skipping", self.name, msgtype);
+            return None;
+        };
+        let file_name = content
+            .get("filename")
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| content.get("body").and_then(|v| v.as_str()).unwrap_or(media_id));
+        let mut attachment = serde_json::json!({
+            "fileKey": url,
+            "fileName": file_name,
+            "type": msgtype.trim_start_matches("m."),
+            "mimeType": content.pointer("/info/mimetype").and_then(|v| v.as_str()),
+            "size": content.pointer("/info/size").and_then(|v| v.as_u64()),
+        });
+        let max_bytes = self.channel_hub.config().max_attachment_bytes;
+        if !download || attachment["size"].as_u64().is_some_and(|size| size > max_bytes) {
+            return Some(attachment);
+        }
+        let bytes = match self.download_media(server, media_id, max_bytes).await {
+            Ok(bytes) => bytes,
+            Err(DownloadError::TooLarge(received)) => {
+                attachment["size"] = received.into();
+                return Some(attachment);
+            }
+            Err(DownloadError::Failed(e)) => {
+                eprintln!("[{}] failed to download {}: {}", self.name, url, e);
+                return None;
+            }
+        };
+        let bytes = match file {
+            Some(file) => match decrypt_media(file, &bytes) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    eprintln!("[{}] failed to decrypt {}: {}", self.name, url, e);
+                    return None;
+                }
+            },
+            None => bytes,
+        };
+        attachment["data"] = base64::engine::general_purpose::STANDARD.encode(&bytes).into();
+        Some(attachment)
+    }
+
+    /// Download `mxc://server/media_id`, giving up once more than `max_bytes` arrive.
+    async fn download_media(&self, server: &str, media_id: &str, max_bytes: u64) -> Result<Vec<u8>, DownloadError> {
+        let mut last_error = String::new();
+        // Authenticated media (Matrix 1.11) first, then the legacy unauthenticated endpoint.
+        for path in [
+            format!("/client/v1/media/download/{}/{}", server, media_id),
+            format!("/media/v3/download/{}/{}", server, media_id),
+        ] {
+            let resp = self
+                .client
+                .get(format!("{}/_matrix{}", self.homeserver_url, path))
+                .bearer_auth(&self.access_token)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            let mut resp = match resp {
+                Ok(resp) => resp,
+                Err(e) => {
+                    last_error = e.to_string();
+                    continue;
+                }
+            };
+            if let Some(len) = resp.content_length().filter(|&len| len > max_bytes) {
+                return Err(DownloadError::TooLarge(len));
+            }
+            let mut bytes = Vec::new();
+            loop {
+                match resp.chunk().await {
+                    Ok(Some(chunk)) => {
+                        bytes.extend_from_slice(&chunk);
+                        if bytes.len() as u64 > max_bytes {
+                            return Err(DownloadError::TooLarge(bytes.len() as u64));
+                        }
+                    }
+                    Ok(None) => return Ok(bytes),
+                    Err(e) => return Err(DownloadError::Failed(e.to_string())),
+                }
+            }
+        }
+        Err(DownloadError::Failed(last_error))
+    }
+
+    // -----------------------------------------------------------------------
+    // Outbound
+    // -----------------------------------------------------------------------
+
+    async fn render(&self, notif: ChannelNotification) {
+        match notif {
+            ChannelNotification::AgentStart { chat_id, .. } => {
+                self.streams.reset(&chat_id);
+                self.set_typing(&chat_id, true).await;
+            }
+            ChannelNotification::AgentThinking { chat_id, text, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_message(&chat_id, &format!("💭 {}", text), "m.notice").await;
+            }
+            ChannelNotification::AgentToken { chat_id, delta, .. } => {
+                self.stream_token(&chat_id, &delta).await;
+            }
+            ChannelNotification::AgentToolUse { chat_id, tool, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_message(&chat_id, &format!("🔧 {}", tool), "m.notice").await;
+            }
+            ChannelNotification::AgentToolResult { .. } => {}
+            ChannelNotification::MessageQueued { .. } => {}
+            ChannelNotification::AgentEnd { chat_id, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.set_typing(&chat_id, false).await;
+            }
+            ChannelNotification::AgentError { chat_id, error, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.set_typing(&chat_id, false).await;
+                self.send_message(&chat_id, &format!("❌ {}", error), "m.notice").await;
+            }
+            ChannelNotification::SendText { chat_id, text, .. } => {
+                self.send_message(&chat_id, &text, "m.text").await;
+            }
+            // Rendered as numbered SendText by ChannelManager (no buttons capability).
+            ChannelNotification::PermissionRequest { .. } => {}
+        }
+    }
+
+    async fn stream_token(&self, room_id: &str, delta: &str) {
+        self.streams
+            .push(&room_id.to_string(), delta, |id, text| async move { self.put_message(room_id, id, &text).await })
+            .await;
+    }
+
+    async fn finish_stream(&self, room_id: &str) {
+        self.streams
+            .finish(&room_id.to_string(), |id, text| async move { self.put_message(room_id, id, &text).await })
+            .await;
+    }
+
+    /// Replace `event_id`'s text (`m.replace`), or send a new message when there is none yet.
+    /// Returns the original event's id.
+    async fn put_message(&self, room_id: &str, event_id: Option<String>, text: &str) -> Option<String> {
+        let content = match &event_id {
+            Some(id) => serde_json::json!({
+                "msgtype": "m.text",
+                "body": format!("* {}", text),
+                "m.new_content": { "msgtype": "m.text", "body": text },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": id },
+            }),
+            None => self.reply_content(room_id, text, "m.text"),
+        };
+        match self.send_event(room_id, content).await {
+            Ok(sent) => event_id.or(Some(sent)),
+            Err(e) => {
+                eprintln!("[{}] {}", self.name, e);
+                event_id
+            }
+        }
+    }
+
+    async fn send_message(&self, room_id: &str, text: &str, msgtype: &str) {
+        let content = self.reply_content(room_id, text, msgtype);
+        if let Err(e) = self.send_event(room_id, content).await {
+            eprintln!("[{}] {}", self.name, e);
+        }
+    }
+
+    /// Message content answering the room's last inbound event, inside its thread if it had one.
+    fn reply_content(&self, room_id: &str, text: &str, msgtype: &str) -> serde_json::Value {
+        let mut content = serde_json::json!({ "msgtype": msgtype, "body": text });
+        if let Some(target) = self.reply_targets.get(room_id).map(|t| t.clone()) {
+            content["m.relates_to"] = match target.thread_root {
+                Some(root) => serde_json::json!({
+                    "rel_type": "m.thread",
+                    "event_id": root,
+                    "is_falling_back": true,
+                    "m.in_reply_to": { "event_id": target.event_id },
+                }),
+                None => serde_json::json!({ "m.in_reply_to": { "event_id": target.event_id } }),
+            };
+        }
+        content
+    }
+
+    /// Send an `m.room.message`, encrypted when the room is.
+    async fn send_event(&self, room_id: &str, content: serde_json::Value) -> Result<String, String> {
+        let (event_type, content) = if self.is_encrypted(room_id).await {
+            ("m.room.encrypted", self.encrypt(room_id, &content).await?)
+        } else {
+            ("m.room.message", content)
+        };
+        let path = format!(
+            "/client/v3/rooms/{}/send/{}/{}",
+            urlencoding::encode(room_id),
+            event_type,
+            uuid::Uuid::new_v4()
+        );
+        let sent = self.api(reqwest::Method::PUT, &path, Some(content)).await?;
+        Ok(str_field(&sent, "event_id"))
+    }
+
+    /// Whether the room has `m.room.encryption` state. Unless the homeserver says it has none,
+    /// the room is treated as encrypted so a reply is never sent in the clear by mistake.
+    async fn is_encrypted(&self, room_id: &str) -> bool {
+        if let Some(encrypted) = self.encrypted_rooms.get(room_id) {
+            return *encrypted;
+        }
+        let path = format!("/client/v3/rooms/{}/state/m.room.encryption/", urlencoding::encode(room_id));
+        match self.api(reqwest::Method::GET, &path, None).await {
+            Ok(_) => {
+                self.encrypted_rooms.insert(room_id.to_string(), true);
+                true
+            }
+            Err(e) if e.contains("M_NOT_FOUND") => {
+                self.encrypted_rooms.insert(room_id.to_string(), false);
+                false
+            }
+            Err(e) => {
+                eprintln!("[{}] could not read the encryption state of {}: {}", self.name, room_id, e);
+                true
+            }
+        }
+    }
+
+    /// Encrypt `m.room.message` content for the room, first sharing the room key with every
+    /// device of its members that does not have it yet.
+    async fn encrypt(&self, room_id: &str, content: &serde_json::Value) -> Result<serde_json::Value, String> {
+        let olm = self.olm.get().ok_or("cannot send to an encrypted room without device keys")?;
+        let room = RoomId::parse(room_id).map_err(|e| format!("room id {}: {}", room_id, e))?;
+        {
+            let _guard = self.room_keys.lock().await;
+            let members = self.room_members(room_id).await?;
+            let members: Vec<&UserId> = members.iter().map(|m| m.as_ref()).collect();
+            olm.update_tracked_users(members.clone()).await.map_err(|e| e.to_string())?;
+            // Fetches the device lists of newly tracked members.
+            self.send_crypto_requests().await;
+            if let Some((request_id, request)) =
+                olm.get_missing_sessions(members.clone().into_iter()).await.map_err(|e| e.to_string())?
+            {
+                let response = self.send_ruma(request).await?;
+                olm.mark_request_as_sent(&request_id, &response).await.map_err(|e| e.to_string())?;
+            }
+            let requests = olm
+                .share_room_key(&room, members.into_iter(), EncryptionSettings::default())
+                .await
+                .map_err(|e| e.to_string())?;
+            for request in requests {
+                self.send_to_device(olm, &request).await?;
+            }
+        }
+        let raw = Raw::from_json(serde_json::value::to_raw_value(content).map_err(|e| e.to_string())?);
+        let encrypted = olm
+            .encrypt_room_event_raw(&room, "m.room.message", &raw)
+            .await
+            .map_err(|e| e.to_string())?;
+        serde_json::to_value(&encrypted).map_err(|e| e.to_string())
+    }
+
+    async fn room_members(&self, room_id: &str) -> Result<Vec<OwnedUserId>, String> {
+        if let Some(members) = self.members.get(room_id) {
+            return Ok(members.clone());
+        }
+        let path = format!("/client/v3/rooms/{}/joined_members", urlencoding::encode(room_id));
+        let joined = self.api(reqwest::Method::GET, &path, None).await?;
+        let members: Vec<OwnedUserId> = joined
+            .get("joined")
+            .and_then(|v| v.as_object())
+            .map(|joined| joined.keys().filter_map(|id| UserId::parse(id).ok()).collect())
+            .unwrap_or_default();
+        self.members.insert(room_id.to_string(), members.clone());
+        Ok(members)
+    }
+
+    async fn set_typing(&self, room_id: &str, typing: bool) {
+        let user_id = self.user_id.read().unwrap().clone();
+        if user_id.is_empty() {
+            return;
+        }
+        let path = format!(
+            "/client/v3/rooms/{}/typing/{}",
+            urlencoding::encode(room_id),
+            urlencoding::encode(&user_id)
+        );
+        let _ = self
+            .api(
+                reqwest::Method::PUT,
+                &path,
+                Some(serde_json::json!({ "typing": typing, "timeout": 30000 })),
+            )
+            .await;
+    }
+}
+
+/// Parse a user's `m.room.message` into what is forwarded. Other event types, the bot's own
+/// messages, edits and messages with nothing to forward give `None`.
+fn parse_room_message(event: &serde_json::Value, own_user_id: &str) -> Option<RoomMessage> {
+    let sender = str_field(event, "sender");
+    if sender == own_user_id || event.get("type").and_then(|v| v.as_str()) != Some("m.room.message") {
+        return None;
+    }
+    let content = event.get("content")?;
+    let relates_to = content.get("m.relates_to");
+    let rel_type = relates_to.and_then(|r| r.get("rel_type")).and_then(|v| v.as_str());
+    if rel_type == Some("m.replace") {
+        // Edits of earlier messages are not re-sent to the agent.
+        return None;
+    }
+    let thread_root = relates_to
+        .filter(|_| rel_type == Some("m.thread"))
+        .and_then(|r| r.get("event_id"))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let msgtype = str_field(content, "msgtype");
+    let (text, media) = match msgtype.as_str() {
+        "m.text" | "m.notice" | "m.emote" => (strip_reply_fallback(&str_field(content, "body")), None),
+        "m.file" | "m.image" | "m.audio" | "m.video" => (String::new(), Some(content.clone())),
+        _ => return None,
+    };
+    if text.is_empty() && media.is_none() {
+        return None;
+    }
+    Some(RoomMessage { sender, event_id: str_field(event, "event_id"), thread_root, msgtype, text, media })
+}
+
+/// Who invited `user_id`, from the invited room's stripped `m.room.member` state.
+fn inviter(room: &serde_json::Value, user_id: &str) -> Option<String> {
+    room.pointer("/invite_state/events")?
+        .as_array()?
+        .iter()
+        .find(|e| {
+            str_field(e, "type") == "m.room.member"
+                && str_field(e, "state_key") == user_id
+                && e.pointer("/content/membership").and_then(|v| v.as_str()) == Some("invite")
+        })
+        .map(|e| str_field(e, "sender"))
+        .filter(|sender| !sender.is_empty())
+}
+
+fn event_list(room: &serde_json::Value, pointer: &str) -> Vec<serde_json::Value> {
+    room.pointer(pointer).and_then(|v| v.as_array()).cloned().unwrap_or_default()
+}
+
+fn sync_field<T: serde::de::DeserializeOwned>(sync: &serde_json::Value, pointer: &str) -> Option<T> {
+    serde_json::from_value(sync.pointer(pointer)?.clone()).ok()
+}
+
+/// Decrypt downloaded media with the key in the event's `file`; also checks its SHA-256.
+fn decrypt_media(file: &serde_json::Value, ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+    let info: MediaEncryptionInfo = serde_json::from_value(file.clone()).map_err(|e| e.to_string())?;
+    let mut reader = std::io::Cursor::new(ciphertext);
+    let mut decryptor = AttachmentDecryptor::new(&mut reader, info).map_err(|e| e.to_string())?;
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    decryptor.read_to_end(&mut plaintext).map_err(|e| e.to_string())?;
+    Ok(plaintext)
+}
+
+/// Drop the `> <@user> quoted text` fallback that clients prepend to replies.
+fn strip_reply_fallback(body: &str) -> String {
+    let mut lines = body.lines().peekable();
+    if lines.peek().is_some_and(|l| l.starts_with("> ")) {
+        while lines.next_if(|l| l.starts_with('>')).is_some() {}
+    }
+    lines.collect::<Vec<_>>().join("\n").trim().to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, Method, StatusCode, Uri};
+    use axum::response::{IntoResponse, Response};
+    use axum::Json;
+    use matrix_sdk_crypto::AttachmentEncryptor;
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::session_hub::types::AgentEvent;
+    use crate::session_hub::SessionHub;
+    use crate::settings;
+
+    const WAIT: Duration = Duration::from_secs(10);
+    const BOT: &str = "@bot:localhost";
+    const ALICE: &str = "@alice:localhost";
+    const EVE: &str = "@eve:localhost";
+
+    #[test]
+    fn strip_reply_fallback_drops_the_quoted_lines() {
+        assert_eq!(strip_reply_fallback("> <@alice:localhost> earlier\n> more\n\nthe reply"), "the reply");
+        assert_eq!(strip_reply_fallback("just text"), "just text");
+        assert_eq!(strip_reply_fallback("first\n> quoted later"), "first\n> quoted later");
+        assert_eq!(strip_reply_fallback(">no space\nbody"), ">no space\nbody");
+        assert_eq!(strip_reply_fallback("> <@alice:localhost> only a quote"), "");
+    }
+
+    #[test]
+    fn room_messages_are_parsed_from_events() {
+        let event = |sender: &str, content: serde_json::Value| {
+            json!({ "type": "m.room.message", "sender": sender, "event_id": "$1", "content": content })
+        };
+
+        let threaded = event(ALICE, json!({
+            "msgtype": "m.text",
+            "body": "> <@bot:localhost> earlier answer\n\nthanks",
+            "m.relates_to": { "rel_type": "m.thread", "event_id": "$root", "m.in_reply_to": { "event_id": "$0" } },
+        }));
+        assert_eq!(
+            parse_room_message(&threaded, BOT),
+            Some(RoomMessage {
+                sender: ALICE.into(),
+                event_id: "$1".into(),
+                thread_root: Some("$root".into()),
+                msgtype: "m.text".into(),
+                text: "thanks".into(),
+                media: None,
+            })
+        );
+
+        // A plain reply is not a thread.
+        let reply = event(ALICE, json!({
+            "msgtype": "m.notice", "body": "ok", "m.relates_to": { "m.in_reply_to": { "event_id": "$0" } },
+        }));
+        assert_eq!(parse_room_message(&reply, BOT).unwrap().thread_root, None);
+
+        let image = json!({ "msgtype": "m.image", "body": "cat.png", "url": "mxc://localhost/cat" });
+        let parsed = parse_room_message(&event(ALICE, image.clone()), BOT).unwrap();
+        assert_eq!((parsed.text.as_str(), parsed.media), ("", Some(image)));
+
+        // The bot's own messages, edits, other msgtypes and other event types are not forwarded.
+        assert_eq!(parse_room_message(&event(BOT, json!({ "msgtype": "m.text", "body": "hi" })), BOT), None);
+        let edit = json!({
+            "msgtype": "m.text", "body": "* hi", "m.relates_to": { "rel_type": "m.replace", "event_id": "$0" },
+        });
+        assert_eq!(parse_room_message(&event(ALICE, edit), BOT), None);
+        assert_eq!(parse_room_message(&event(ALICE, json!({ "msgtype": "m.location", "body": "here" })), BOT), None);
+        assert_eq!(parse_room_message(&event(ALICE, json!({ "msgtype": "m.text", "body": "  " })), BOT), None);
+        let reaction = json!({ "type": "m.reaction", "sender": ALICE, "content": { "body": "hi" } });
+        assert_eq!(parse_room_message(&reaction, BOT), None);
+    }
+
+    #[test]
+    fn the_inviter_comes_from_the_invite_state() {
+        let room = json!({ "invite_state": { "events": [
+            { "type": "m.room.name", "state_key": "", "sender": EVE, "content": { "name": "x" } },
+            { "type": "m.room.member", "state_key": EVE, "sender": EVE, "content": { "membership": "join" } },
+            { "type": "m.room.member", "state_key": BOT, "sender": ALICE, "content": { "membership": "invite" } },
+        ] } });
+        assert_eq!(inviter(&room, BOT).as_deref(), Some(ALICE));
+        assert_eq!(inviter(&room, "@other:localhost"), None);
+        assert_eq!(inviter(&json!({}), BOT), None);
+    }
+
+    /// What the mock homeserver holds: per-user inboxes that its `/sync` drains, and the keys
+    /// devices published.
+    #[derive(Default)]
+    struct Server {
+        /// (room, event) waiting for each user's next sync.
+        timeline: HashMap<String, Vec<(String, serde_json::Value)>>,
+        /// (room, inviter) waiting for each user's next sync.
+        invites: HashMap<String, Vec<(String, String)>>,
+        to_device: HashMap<String, Vec<serde_json::Value>>,
+        device_keys: HashMap<String, (String, serde_json::Value)>,
+        one_time_keys: HashMap<String, Vec<(String, serde_json::Value)>>,
+        encrypted_rooms: HashSet<String>,
+        media: HashMap<String, Vec<u8>>,
+        /// Users that have made an incremental (long-polling) sync.
+        polling: HashSet<String>,
+        next_id: u64,
+    }
+
+    impl Server {
+        fn take_sync(&mut self, user: &str, initial: bool) -> serde_json::Value {
+            let mut join = serde_json::Map::new();
+            let mut room_entry = |room: &str| {
+                join.entry(room.to_string())
+                    .or_insert_with(|| json!({ "timeline": { "events": [] }, "state": { "events": [] } }))
+                    .clone()
+            };
+            let mut rooms: BTreeMap<String, serde_json::Value> = BTreeMap::new();
+            for (room, event) in self.timeline.remove(user).unwrap_or_default() {
+                let entry = rooms.entry(room.clone()).or_insert_with(|| room_entry(&room));
+                entry["timeline"]["events"].as_array_mut().unwrap().push(event);
+            }
+            if initial {
+                for room in &self.encrypted_rooms {
+                    let entry = rooms.entry(room.clone()).or_insert_with(|| room_entry(room));
+                    entry["state"]["events"].as_array_mut().unwrap().push(json!({
+                        "type": "m.room.encryption",
+                        "state_key": "",
+                        "sender": ALICE,
+                        "event_id": "$encryption",
+                        "content": { "algorithm": "m.megolm.v1.aes-sha2" },
+                    }));
+                }
+            }
+            let invite: serde_json::Map<String, serde_json::Value> = self
+                .invites
+                .remove(user)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(room, inviter)| {
+                    let state = json!({ "invite_state": { "events": [{
+                        "type": "m.room.member",
+                        "state_key": user,
+                        "sender": inviter,
+                        "content": { "membership": "invite" },
+                    }] } });
+                    (room, state)
+                })
+                .collect();
+            self.next_id += 1;
+            json!({
+                "next_batch": format!("s{}", self.next_id),
+                "rooms": { "join": rooms, "invite": invite },
+                "to_device": { "events": self.to_device.remove(user).unwrap_or_default() },
+                "device_one_time_keys_count": {
+                    "signed_curve25519": self.one_time_keys.get(user).map_or(0, Vec::len),
+                },
+                "device_lists": { "changed": [], "left": [] },
+            })
+        }
+
+        fn has_pending(&self, user: &str) -> bool {
+            [self.timeline.get(user).map(Vec::len), self.invites.get(user).map(Vec::len)]
+                .into_iter()
+                .chain([self.to_device.get(user).map(Vec::len)])
+                .any(|n| n.unwrap_or(0) > 0)
+        }
+    }
+
+    /// A homeserver with just enough of the client-server API for MatrixChannel and its crypto
+    /// machine. Every user is a member of every room.
+    #[derive(Clone)]
+    struct Homeserver {
+        server: Arc<Mutex<Server>>,
+        /// Joins, leaves, room sends and media downloads, as (user, "METHOD what", body).
+        calls: mpsc::UnboundedSender<(String, String, serde_json::Value)>,
+    }
+
+    fn user_for(token: &str) -> Option<(&'static str, &'static str)> {
+        match token {
+            "bot-token" => Some((BOT, "BOTDEVICE")),
+            "alice-token" => Some((ALICE, "ALICEDEVICE")),
+            "eve-token" => Some((EVE, "EVEDEVICE")),
+            _ => None,
+        }
+    }
+
+    fn matrix_error(status: StatusCode, errcode: &str) -> Response {
+        (status, Json(json!({ "errcode": errcode, "error": errcode }))).into_response()
+    }
+
+    async fn handle(
+        State(hs): State<Homeserver>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let token = headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or("");
+        let Some((user, device)) = user_for(token) else {
+            return matrix_error(StatusCode::UNAUTHORIZED, "M_UNKNOWN_TOKEN");
+        };
+        let segments: Vec<String> = uri
+            .path()
+            .trim_start_matches("/_matrix/")
+            .split('/')
+            .map(|s| urlencoding::decode(s).unwrap().into_owned())
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or(json!({}));
+        let record = |what: String, body: serde_json::Value| {
+            let _ = hs.calls.send((user.to_string(), what, body));
+        };
+
+        let reply = match (method.as_str(), segments.as_slice()) {
+            ("GET", ["client", "v3", "account", "whoami"]) => json!({ "user_id": user, "device_id": device }),
+            ("GET", ["client", "v3", "sync"]) => {
+                let initial = !uri.query().unwrap_or("").contains("since=");
+                let deadline = Instant::now() + Duration::from_millis(300);
+                loop {
+                    {
+                        let mut server = hs.server.lock().unwrap();
+                        if initial || server.has_pending(user) || Instant::now() >= deadline {
+                            if !initial {
+                                server.polling.insert(user.to_string());
+                            }
+                            break server.take_sync(user, initial);
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            }
+            ("POST", ["client", "v3", "keys", "upload"]) => {
+                let mut server = hs.server.lock().unwrap();
+                if let Some(keys) = body.get("device_keys") {
+                    server.device_keys.insert(user.to_string(), (device.to_string(), keys.clone()));
+                }
+                let uploaded = body.get("one_time_keys").and_then(|v| v.as_object()).cloned().unwrap_or_default();
+                let keys = server.one_time_keys.entry(user.to_string()).or_default();
+                keys.extend(uploaded);
+                json!({ "one_time_key_counts": { "signed_curve25519": keys.len() } })
+            }
+            ("POST", ["client", "v3", "keys", "query"]) => {
+                let server = hs.server.lock().unwrap();
+                let mut device_keys = serde_json::Map::new();
+                for queried in body["device_keys"].as_object().map(|m| m.keys()).into_iter().flatten() {
+                    let devices = match server.device_keys.get(queried) {
+                        Some((device, keys)) => json!({ device.clone(): keys }),
+                        None => json!({}),
+                    };
+                    device_keys.insert(queried.clone(), devices);
+                }
+                json!({ "device_keys": device_keys, "failures": {} })
+            }
+            ("POST", ["client", "v3", "keys", "claim"]) => {
+                let mut server = hs.server.lock().unwrap();
+                let mut claimed = serde_json::Map::new();
+                for (claimed_user, devices) in body["one_time_keys"].as_object().cloned().unwrap_or_default() {
+                    let devices: Vec<String> =
+                        devices.as_object().map(|d| d.keys().cloned().collect()).unwrap_or_default();
+                    for device in devices {
+                        let keys = server.one_time_keys.entry(claimed_user.clone()).or_default();
+                        if !keys.is_empty() {
+                            let (key_id, key) = keys.remove(0);
+                            claimed.insert(claimed_user.clone(), json!({ device: { key_id: key } }));
+                        }
+                    }
+                }
+                json!({ "one_time_keys": claimed, "failures": {} })
+            }
+            ("PUT", ["client", "v3", "sendToDevice", event_type, _txn]) => {
+                let mut server = hs.server.lock().unwrap();
+                for (recipient, devices) in body["messages"].as_object().cloned().unwrap_or_default() {
+                    for content in devices.as_object().map(|d| d.values().cloned().collect()).unwrap_or_else(Vec::new) {
+                        server.to_device.entry(recipient.clone()).or_default().push(json!({
+                            "type": event_type,
+                            "sender": user,
+                            "content": content,
+                        }));
+                    }
+                }
+                json!({})
+            }
+            ("PUT", ["client", "v3", "rooms", room, "send", event_type, _txn]) => {
+                let mut server = hs.server.lock().unwrap();
+                server.next_id += 1;
+                let event_id = format!("$event{}", server.next_id);
+                let event = json!({
+                    "type": event_type,
+                    "sender": user,
+                    "event_id": event_id,
+                    "room_id": room,
+                    "origin_server_ts": 1_700_000_000_000u64 + server.next_id,
+                    "content": body,
+                });
+                for member in [BOT, ALICE, EVE] {
+                    server.timeline.entry(member.to_string()).or_default().push((room.to_string(), event.clone()));
+                }
+                record(format!("PUT send {} {}", room, event_type), body);
+                json!({ "event_id": event_id })
+            }
+            ("GET", ["client", "v3", "rooms", _room, "joined_members"]) => {
+                json!({ "joined": { BOT: {}, ALICE: {} } })
+            }
+            ("GET", ["client", "v3", "rooms", room, "state", "m.room.encryption", ""]) => {
+                if !hs.server.lock().unwrap().encrypted_rooms.contains(*room) {
+                    return matrix_error(StatusCode::NOT_FOUND, "M_NOT_FOUND");
+                }
+                json!({ "algorithm": "m.megolm.v1.aes-sha2" })
+            }
+            ("PUT", ["client", "v3", "rooms", _room, "typing", _user]) => json!({}),
+            ("POST", ["client", "v3", "rooms", room, action @ ("join" | "leave")]) => {
+                record(format!("POST {} {}", action, room), body);
+                json!({ "room_id": room })
+            }
+            ("GET", ["client", "v1", "media", "download", _server, media_id]) => {
+                record(format!("GET media {}", media_id), json!(null));
+                let Some(bytes) = hs.server.lock().unwrap().media.get(*media_id).cloned() else {
+                    return matrix_error(StatusCode::NOT_FOUND, "M_NOT_FOUND");
+                };
+                return bytes.into_response();
+            }
+            _ => return matrix_error(StatusCode::NOT_FOUND, "M_UNRECOGNIZED"),
+        };
+        Json(reply).into_response()
+    }
+
+    struct Harness {
+        url: String,
+        hs: Homeserver,
+        calls: mpsc::UnboundedReceiver<(String, String, serde_json::Value)>,
+        data_dir: PathBuf,
+    }
+
+    /// A running MatrixChannel and the agent events its messages produce.
+    struct Client {
+        channel: Arc<MatrixChannel>,
+        channel_hub: Arc<ChannelManager>,
+        events: broadcast::Receiver<AgentEvent>,
+    }
+
+    impl Client {
+        async fn next_message(&mut self) -> (String, crate::session_hub::types::InboundMessage) {
+            loop {
+                match tokio::time::timeout(WAIT, self.events.recv()).await.expect("agent event").unwrap() {
+                    AgentEvent::OnReceiveMessage { chat_id, message, .. } => return (chat_id, message),
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    impl Harness {
+        async fn start(test: &str) -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let (calls_tx, calls) = mpsc::unbounded_channel();
+            let hs = Homeserver { server: Arc::new(Mutex::new(Server::default())), calls: calls_tx };
+            let router = axum::Router::new().fallback(handle).with_state(hs.clone());
+            tokio::spawn(async move { axum::serve(listener, router).await });
+            let data_dir = std::env::temp_dir().join(format!("vibearound-matrix-test-{}-{}", std::process::id(), test));
+            let _ = std::fs::remove_dir_all(&data_dir);
+            Self { url, hs, calls, data_dir }
+        }
+
+        /// Sign `token`'s user in with `config` (nothing is read from ~/.vibearound) and wait
+        /// until it has published its keys and is long-polling.
+        async fn connect(&self, token: &str, config: Config) -> Client {
+            let (user, _) = user_for(token).unwrap();
+            let channel_hub = Arc::new(ChannelManager::with_config(Arc::new(config), self.data_dir.join(user)));
+            let session_hub = Arc::new(SessionHub::new());
+            channel_hub.set_session_hub(Arc::clone(&session_hub));
+            let events = session_hub.subscribe_agent_events();
+            let channel = Arc::new(MatrixChannel::new("matrix", &self.url, token, &channel_hub, None).unwrap());
+            Arc::clone(&channel).spawn();
+            self.wait_until(|s| s.device_keys.contains_key(user) && s.polling.contains(user)).await;
+            Client { channel, channel_hub, events }
+        }
+
+        async fn wait_until(&self, ready: impl Fn(&Server) -> bool) {
+            let deadline = Instant::now() + WAIT;
+            while !ready(&self.hs.server.lock().unwrap()) {
+                assert!(Instant::now() < deadline, "timed out waiting for the homeserver state");
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+
+        fn server(&self) -> std::sync::MutexGuard<'_, Server> {
+            self.hs.server.lock().unwrap()
+        }
+
+        /// Deliver a plain (unencrypted) event to the bot's next sync.
+        fn push_event(&self, room: &str, sender: &str, content: serde_json::Value) {
+            let mut server = self.server();
+            server.next_id += 1;
+            let event = json!({
+                "type": "m.room.message",
+                "sender": sender,
+                "event_id": format!("$pushed{}", server.next_id),
+                "room_id": room,
+                "origin_server_ts": 1_700_000_000_000u64,
+                "content": content,
+            });
+            server.timeline.entry(BOT.to_string()).or_default().push((room.to_string(), event));
+        }
+
+        async fn next_call(&mut self) -> (String, String, serde_json::Value) {
+            tokio::time::timeout(WAIT, self.calls.recv()).await.expect("homeserver call").unwrap()
+        }
+
+        /// The next call, skipping those made by other users.
+        async fn next_call_by(&mut self, user: &str) -> (String, serde_json::Value) {
+            loop {
+                let (by, what, body) = self.next_call().await;
+                if by == user {
+                    return (what, body);
+                }
+            }
+        }
+    }
+
+    fn bot_config(max_attachment_bytes: u64) -> Config {
+        let parsed = settings::parse(r#"{ "channels": { "matrix": { "allowed_senders": ["@alice:localhost"] } } }"#)
+            .unwrap_or_else(|issues| panic!("{:?}", issues));
+        let mut config = Config::from_settings(parsed.settings, &parsed.root);
+        config.max_attachment_bytes = max_attachment_bytes;
+        config
+    }
+
+    #[tokio::test]
+    async fn invites_and_files_follow_the_allowlist_and_size_limit() {
+        let mut hs = Harness::start("allowlist").await;
+        let mut bot = hs.connect("bot-token", bot_config(1000)).await;
+
+        // Only the allowed sender's invite is accepted.
+        {
+            let mut server = hs.server();
+            let invites = server.invites.entry(BOT.to_string()).or_default();
+            invites.push(("!fromalice:localhost".into(), ALICE.into()));
+            invites.push(("!fromeve:localhost".into(), EVE.into()));
+        }
+        let mut answers = vec![hs.next_call_by(BOT).await.0, hs.next_call_by(BOT).await.0];
+        answers.sort();
+        assert_eq!(answers, ["POST join !fromalice:localhost", "POST leave !fromeve:localhost"]);
+
+        // A file from a sender outside the allowlist is never downloaded; they get the notice.
+        hs.server().media.insert("evefile".into(), b"from eve".to_vec());
+        hs.push_event("!eve:localhost", EVE, json!({
+            "msgtype": "m.file", "body": "eve.txt", "url": "mxc://localhost/evefile", "info": { "size": 8 },
+        }));
+        let (what, body) = hs.next_call_by(BOT).await;
+        assert_eq!(what, "PUT send !eve:localhost m.room.message");
+        assert!(body["body"].as_str().unwrap().contains("not allowed"), "{body}");
+
+        // A file declared larger than the limit is not downloaded either.
+        hs.push_event("!big:localhost", ALICE, json!({
+            "msgtype": "m.file", "body": "big.bin", "url": "mxc://localhost/big", "info": { "size": 5000 },
+        }));
+        let (what, body) = hs.next_call_by(BOT).await;
+        assert_eq!(what, "PUT send !big:localhost m.room.message");
+        assert!(body["body"].as_str().unwrap().contains("too large"), "{body}");
+
+        // One that understates its size is cut off once the download passes the limit.
+        hs.server().media.insert("liar".into(), vec![0; 5000]);
+        hs.push_event("!liar:localhost", ALICE, json!({
+            "msgtype": "m.file", "body": "liar.bin", "url": "mxc://localhost/liar", "info": { "size": 10 },
+        }));
+        assert_eq!(hs.next_call_by(BOT).await.0, "GET media liar");
+        let (what, body) = hs.next_call_by(BOT).await;
+        assert_eq!(what, "PUT send !liar:localhost m.room.message");
+        assert!(body["body"].as_str().unwrap().contains("too large"), "{body}");
+
+        // Files within the limit are downloaded and staged.
+        hs.server().media.insert("cat".into(), b"meow".to_vec());
+        hs.push_event("!ok:localhost", ALICE, json!({
+            "msgtype": "m.image", "body": "cat.png", "url": "mxc://localhost/cat", "info": { "mimetype": "image/png" },
+        }));
+        assert_eq!(hs.next_call_by(BOT).await.0, "GET media cat");
+        let message = loop {
+            let (chat_id, message) = bot.next_message().await;
+            if chat_id == "!ok:localhost" {
+                break message;
+            }
+        };
+        assert_eq!(message.sender_id, ALICE);
+        assert_eq!(message.attachments.len(), 1);
+        let attachment = &message.attachments[0];
+        assert_eq!((attachment.file_name.as_str(), attachment.resource_type.as_str()), ("cat.png", "image"));
+        assert_eq!(std::fs::read(attachment.local_path.as_ref().unwrap()).unwrap(), b"meow");
+    }
+
+    #[tokio::test]
+    async fn encrypted_rooms_round_trip() {
+        let mut hs = Harness::start("e2ee").await;
+        for room in ["!secret:localhost", "!files:localhost"] {
+            hs.server().encrypted_rooms.insert(room.to_string());
+        }
+        let mut bot = hs.connect("bot-token", bot_config(1000)).await;
+        let mut alice = hs.connect("alice-token", Config::default()).await;
+
+        // Alice's client shares a room key with the bot's device and sends ciphertext only.
+        alice
+            .channel
+            .send_message("!secret:localhost", "the launch code is 1234", "m.text")
+            .await;
+        let (what, body) = hs.next_call_by(ALICE).await;
+        assert_eq!(what, "PUT send !secret:localhost m.room.encrypted");
+        assert_eq!(body["algorithm"], "m.megolm.v1.aes-sha2");
+        assert!(!body.to_string().contains("launch code"), "{body}");
+
+        let (chat_id, message) = bot.next_message().await;
+        assert_eq!(chat_id, "!secret:localhost");
+        assert_eq!(message.sender_id, ALICE);
+        assert_eq!(message.text, "the launch code is 1234");
+
+        // The bot's reply is encrypted for Alice's device.
+        bot.channel_hub
+            .send_notification(ChannelNotification::SendText {
+                channel_kind: "matrix".into(),
+                chat_id: "!secret:localhost".into(),
+                text: "noted".into(),
+                reply_to: None,
+            })
+            .await;
+        let (what, body) = hs.next_call_by(BOT).await;
+        assert_eq!(what, "PUT send !secret:localhost m.room.encrypted");
+        assert!(!body.to_string().contains("noted"), "{body}");
+        let (chat_id, message) = alice.next_message().await;
+        assert_eq!((chat_id.as_str(), message.sender_id.as_str()), ("!secret:localhost", BOT));
+        assert_eq!(message.text, "noted");
+
+        // Encrypted media is downloaded and decrypted with the key inside the encrypted event.
+        let mut plaintext: &[u8] = b"top secret notes";
+        let mut encryptor = AttachmentEncryptor::new(&mut plaintext);
+        let mut ciphertext = Vec::new();
+        encryptor.read_to_end(&mut ciphertext).unwrap();
+        let mut file = serde_json::to_value(encryptor.finish()).unwrap();
+        file["url"] = json!("mxc://localhost/notes");
+        hs.server().media.insert("notes".into(), ciphertext);
+        alice
+            .channel
+            .send_event("!files:localhost", json!({ "msgtype": "m.file", "body": "notes.txt", "file": file }))
+            .await
+            .unwrap();
+        let (chat_id, message) = bot.next_message().await;
+        assert_eq!(chat_id, "!files:localhost");
+        let attachment = &message.attachments[0];
+        assert_eq!(attachment.file_name, "notes.txt");
+        assert_eq!(std::fs::read(attachment.local_path.as_ref().unwrap()).unwrap(), b"top secret notes");
+    }
+}
@@ -1,3 +1,9 @@
 //! Internal channel transport implementations.
 
+pub mod discord;
+pub mod email;
+pub mod http;
+pub mod matrix;
+pub mod slack;
+pub mod telegram;
 pub mod web;
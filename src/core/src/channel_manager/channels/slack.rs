@@ -0,0 +1,806 @@
+//! Built-in Slack channel (Socket Mode).
+//!
+//! Used for `channels.slack` when no external Slack plugin is installed. Socket Mode needs no
+//! public URL: `apps.connections.open` (app-level `xapp-` token) returns a WebSocket URL that
+//! delivers Events API and interactivity envelopes, each acknowledged by `envelope_id`. Replies
+//! go through the Web API with the bot `xoxb-` token.
+//!
+//! Chats map to threads: a mention in a channel starts a thread and `chat_id` is
+//! `<channel>/<thread_ts>`; a direct message conversation is one chat, `<channel>`. Agent tokens
+//! stream into one message via `chat.update`; tool use and permission prompts are Block Kit
+//! messages, and permission buttons come back as `on_callback`.
+//!
+//! `channels.slack.api_base_url` (default `https://slack.com/api`) points the channel at another
+//! server, e.g. a local mock of the Web API and Socket Mode endpoint.
+
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::agent_manager::truncate;
+use crate::channel_manager::capabilities::{split_text_at, StreamingEditor};
+use crate::channel_manager::{str_field, ChannelManager};
+use crate::config;
+use crate::session_hub::types::{permission_callback_value, ChannelNotification};
+
+pub const DEFAULT_API_BASE_URL: &str = "https://slack.com/api";
+/// Slack accepts up to 40k characters but recommends staying under 4k per message.
+pub const SLACK_MESSAGE_LIMIT: usize = 4000;
+/// chat.update is rate limited to roughly one call per second per channel.
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+/// Message timestamps remembered to drop the duplicate `message` + `app_mention` pair and
+/// Slack's redeliveries, which come within minutes.
+const SEEN_MESSAGES_CAP: usize = 1000;
+const SEEN_MESSAGES_TTL: Duration = Duration::from_secs(10 * 60);
+/// Threads followed without a mention; the oldest are forgotten first.
+const THREADS_CAP: usize = 10_000;
+const THREAD_IDLE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct SlackChannel {
+    name: String,
+    api_base_url: String,
+    app_token: String,
+    bot_token: String,
+    client: reqwest::Client,
+    channel_hub: Arc<ChannelManager>,
+    /// Per chat: the reply being streamed.
+    streams: StreamingEditor<String, String>,
+    /// Threads the bot is part of, with their last activity; replies in them need no mention.
+    threads: DashMap<String, Instant>,
+    seen: DashMap<String, Instant>,
+}
+
+/// Start the built-in Slack channel and register it with ChannelManager.
+/// Returns the transport task's abort handle.
+pub fn start(channel_hub: &Arc<ChannelManager>, channel_name: &str) -> Result<AbortHandle, String> {
+    let raw_config = config::ensure_loaded()
+        .channel_raw_config(channel_name)
+        .ok_or_else(|| format!("channels.{} is not configured", channel_name))?;
+    let field = |key: &str| {
+        raw_config
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    let app_token = field("app_token").ok_or_else(|| format!("channels.{}.app_token is missing", channel_name))?;
+    let bot_token = field("bot_token").ok_or_else(|| format!("channels.{}.bot_token is missing", channel_name))?;
+
+    let channel = Arc::new(SlackChannel {
+        name: channel_name.to_string(),
+        api_base_url: field("api_base_url")
+            .unwrap_or_else(|| DEFAULT_API_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string(),
+        app_token,
+        bot_token,
+        client: reqwest::Client::builder()
+            .timeout(API_TIMEOUT)
+            .build()
+            .map_err(|e| format!("reqwest client: {}", e))?,
+        channel_hub: Arc::clone(channel_hub),
+        streams: StreamingEditor::new(SLACK_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+        threads: DashMap::new(),
+        seen: DashMap::new(),
+    });
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move { channel.run(outbound_rx).await });
+    let abort = task.abort_handle();
+    channel_hub.start_builtin_plugin(channel_name, outbound_tx, abort.clone());
+    eprintln!("[{}] built-in Slack channel started (Socket Mode)", channel_name);
+    Ok(abort)
+}
+
+impl SlackChannel {
+    async fn run(self: Arc<Self>, mut outbound_rx: mpsc::UnboundedReceiver<ChannelNotification>) {
+        let outbound = async {
+            while let Some(notif) = outbound_rx.recv().await {
+                self.render(notif).await;
+            }
+        };
+        tokio::select! {
+            _ = self.socket_loop() => {}
+            _ = outbound => {}
+        }
+    }
+
+    /// Call a Web API method; Slack reports failures as `{ "ok": false, "error": ... }`.
+    async fn api(&self, method: &str, token: &str, body: serde_json::Value) -> Result<serde_json::Value, String> {
+        let url = format!("{}/{}", self.api_base_url, method);
+        let resp = self
+            .client
+            .post(&url)
+            .bearer_auth(token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json; charset=utf-8")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", method, e))?;
+        let text = resp.text().await.map_err(|e| format!("{}: {}", method, e))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| format!("{}: invalid response: {}", method, e))?;
+        if value.get("ok").and_then(|v| v.as_bool()) == Some(true) {
+            Ok(value)
+        } else {
+            let error = value.get("error").and_then(|v| v.as_str()).unwrap_or("unknown error");
+            Err(format!("{}: {}", method, error))
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Inbound (Socket Mode)
+    // -----------------------------------------------------------------------
+
+    async fn socket_loop(&self) {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let started = Instant::now();
+            match self.serve_socket().await {
+                // Slack asks clients to reconnect periodically ("disconnect" envelope).
+                Ok(()) => eprintln!("[{}] Socket Mode connection closed; reconnecting", self.name),
+                Err(e) => eprintln!(
+                    "[{}] Socket Mode error: {} — reconnecting in {}s",
+                    self.name,
+                    e,
+                    backoff.as_secs()
+                ),
+            }
+            if started.elapsed() > MAX_RECONNECT_BACKOFF {
+                backoff = Duration::from_secs(1);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        }
+    }
+
+    async fn serve_socket(&self) -> Result<(), String> {
+        let opened = self
+            .api("apps.connections.open", &self.app_token, serde_json::json!({}))
+            .await?;
+        let url = opened
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or("apps.connections.open returned no url")?;
+        let (ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| format!("connect: {}", e))?;
+        let (mut write, mut read) = ws.split();
+
+        while let Some(frame) = read.next().await {
+            let frame = frame.map_err(|e| format!("read: {}", e))?;
+            let text = match frame {
+                WsMessage::Text(text) => text,
+                WsMessage::Ping(data) => {
+                    let _ = write.send(WsMessage::Pong(data)).await;
+                    continue;
+                }
+                WsMessage::Close(_) => return Ok(()),
+                _ => continue,
+            };
+            let Ok(envelope) = serde_json::from_str::<serde_json::Value>(text.as_str()) else {
+                continue;
+            };
+            // Acknowledge first: Slack retries envelopes that are not acked within 3 seconds.
+            if let Some(envelope_id) = envelope.get("envelope_id").and_then(|v| v.as_str()) {
+                let ack = serde_json::json!({ "envelope_id": envelope_id }).to_string();
+                write
+                    .send(WsMessage::text(ack))
+                    .await
+                    .map_err(|e| format!("ack: {}", e))?;
+            }
+            let payload = envelope.get("payload").cloned().unwrap_or(serde_json::Value::Null);
+            match envelope.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+                "hello" => eprintln!("[{}] Socket Mode connected", self.name),
+                "disconnect" => return Ok(()),
+                "events_api" => {
+                    if let Some(event) = payload.get("event") {
+                        self.handle_event(event).await;
+                    }
+                }
+                "interactive" => self.handle_interaction(&payload).await,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: &serde_json::Value) {
+        let kind = str_field(event, "type");
+        if kind != "message" && kind != "app_mention" {
+            return;
+        }
+        // Skip the bot's own posts, edits, joins and other subtyped messages.
+        if event.get("bot_id").is_some() || event.get("subtype").is_some() {
+            return;
+        }
+        let channel = str_field(event, "channel");
+        let ts = str_field(event, "ts");
+        let thread_ts = event.get("thread_ts").and_then(|v| v.as_str());
+        if channel.is_empty() || ts.is_empty() {
+            return;
+        }
+        let chat_id = chat_id_for(&channel, thread_ts, &ts);
+
+        // Channel messages only count when they mention the bot or continue one of its threads.
+        if kind == "message" && !is_direct(&channel) && !self.threads.contains_key(&chat_id) {
+            return;
+        }
+        if self.seen.insert(format!("{}:{}", channel, ts), Instant::now()).is_some() {
+            return;
+        }
+        evict(&self.seen, SEEN_MESSAGES_TTL, SEEN_MESSAGES_CAP);
+        self.threads.insert(chat_id.clone(), Instant::now());
+        evict(&self.threads, THREAD_IDLE_TTL, THREADS_CAP);
+
+        let text = strip_mentions(&str_field(event, "text"));
+        if text.is_empty() {
+            return;
+        }
+        let params = serde_json::json!({
+            "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+            "messageId": ts,
+            "text": text,
+            "sender": { "id": str_field(event, "user") },
+        });
+        self.dispatch_inbound("on_message", params).await;
+    }
+
+    /// Block Kit button clicks (`block_actions`).
+    async fn handle_interaction(&self, payload: &serde_json::Value) {
+        if str_field(payload, "type") != "block_actions" {
+            return;
+        }
+        let channel = payload.pointer("/channel/id").and_then(|v| v.as_str()).unwrap_or("");
+        let message_ts = payload.pointer("/container/message_ts").and_then(|v| v.as_str()).unwrap_or("");
+        let thread_ts = payload
+            .pointer("/container/thread_ts")
+            .or_else(|| payload.pointer("/message/thread_ts"))
+            .and_then(|v| v.as_str());
+        let Some(action) = payload.pointer("/actions/0") else { return };
+        let value = str_field(action, "value");
+        if channel.is_empty() || value.is_empty() {
+            return;
+        }
+        let chat_id = chat_id_for(channel, thread_ts, message_ts);
+
+        let params = serde_json::json!({
+            "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id),
+            "data": { "value": value },
+            "sender": { "id": payload.pointer("/user/id").and_then(|v| v.as_str()).unwrap_or("") },
+        });
+        if !self.dispatch_inbound("on_callback", params).await {
+            return;
+        }
+
+        // Replace the buttons with the choice so the prompt cannot be answered twice. Only an
+        // admitted click does this; others leave the prompt for someone who may answer it.
+        if !message_ts.is_empty() {
+            let label = action.pointer("/text/text").and_then(|v| v.as_str()).unwrap_or(&value);
+            let prompt = payload
+                .pointer("/message/text")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let text = format!("{}\n\n→ {}", prompt, label);
+            let _ = self
+                .api(
+                    "chat.update",
+                    &self.bot_token,
+                    serde_json::json!({ "channel": channel, "ts": message_ts, "text": text, "blocks": [] }),
+                )
+                .await;
+        }
+    }
+
+    /// Returns false when ChannelManager rejected the sender.
+    async fn dispatch_inbound(&self, method: &str, params: serde_json::Value) -> bool {
+        let msg = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.channel_hub.handle_inbound_jsonrpc(&self.name, msg).await
+    }
+
+    // -----------------------------------------------------------------------
+    // Outbound
+    // -----------------------------------------------------------------------
+
+    async fn render(&self, notif: ChannelNotification) {
+        match notif {
+            ChannelNotification::AgentStart { chat_id, .. } => {
+                self.streams.reset(&chat_id);
+            }
+            ChannelNotification::AgentThinking { chat_id, text, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.post_blocks(&chat_id, &text, vec![context_block(&format!(":thought_balloon: {}", text))])
+                    .await;
+            }
+            ChannelNotification::AgentToken { chat_id, delta, .. } => {
+                self.stream_token(&chat_id, &delta).await;
+            }
+            ChannelNotification::AgentToolUse { chat_id, tool, input, .. } => {
+                self.finish_stream(&chat_id).await;
+                let summary = format!(":wrench: *{}*", tool);
+                let mut blocks = vec![section_block(&summary)];
+                if !input.trim().is_empty() {
+                    blocks.push(context_block(truncate(&input, 300)));
+                }
+                self.post_blocks(&chat_id, &format!("Using tool: {}", tool), blocks).await;
+            }
+            ChannelNotification::AgentToolResult { .. } => {}
+            ChannelNotification::MessageQueued { .. } => {}
+            ChannelNotification::AgentEnd { chat_id, .. } => {
+                self.finish_stream(&chat_id).await;
+            }
+            ChannelNotification::AgentError { chat_id, error, .. } => {
+                self.finish_stream(&chat_id).await;
+                self.send_text(&chat_id, &format!(":x: {}", error)).await;
+            }
+            ChannelNotification::SendText { chat_id, text, .. } => {
+                self.send_text(&chat_id, &text).await;
+            }
+            ChannelNotification::PermissionRequest {
+                chat_id,
+                request_id,
+                tool,
+                input,
+                options,
+                timeout_secs,
+                default_allow,
+                ..
+            } => {
+                self.finish_stream(&chat_id).await;
+                let prompt = format!(":lock: *{}* wants permission", tool);
+                let buttons = options
+                    .iter()
+                    .enumerate()
+                    .map(|(i, o)| {
+                        let mut button = serde_json::json!({
+                            "type": "button",
+                            "action_id": format!("permission_{}", i),
+                            "text": { "type": "plain_text", "text": o.label },
+                            "value": permission_callback_value(&request_id, &o.id),
+                        });
+                        if o.kind.starts_with("allow") {
+                            button["style"] = "primary".into();
+                        } else if o.kind.starts_with("reject") {
+                            button["style"] = "danger".into();
+                        }
+                        button
+                    })
+                    .collect::<Vec<_>>();
+                let blocks = vec![
+                    section_block(&prompt),
+                    context_block(truncate(&input, 1500)),
+                    serde_json::json!({ "type": "actions", "elements": buttons }),
+                    context_block(&format!(
+                        "No answer in {}s means {}.",
+                        timeout_secs,
+                        if default_allow { "allow" } else { "deny" }
+                    )),
+                ];
+                self.post_blocks(&chat_id, &format!("{} wants permission", tool), blocks).await;
+            }
+        }
+    }
+
+    async fn stream_token(&self, chat_id: &str, delta: &str) {
+        self.streams
+            .push(&chat_id.to_string(), delta, |ts, text| async move { self.put_message(chat_id, ts, &text).await })
+            .await;
+    }
+
+    async fn finish_stream(&self, chat_id: &str) {
+        self.streams
+            .finish(&chat_id.to_string(), |ts, text| async move { self.put_message(chat_id, ts, &text).await })
+            .await;
+    }
+
+    /// `chat.update` the message at `ts`, or `chat.postMessage` a new one. Returns its ts.
+    async fn put_message(&self, chat_id: &str, ts: Option<String>, text: &str) -> Option<String> {
+        let (channel, thread_ts) = split_chat_id(chat_id);
+        let result = match &ts {
+            Some(ts) => {
+                self.api(
+                    "chat.update",
+                    &self.bot_token,
+                    serde_json::json!({ "channel": channel, "ts": ts, "text": text }),
+                )
+                .await
+            }
+            None => {
+                self.api(
+                    "chat.postMessage",
+                    &self.bot_token,
+                    serde_json::json!({ "channel": channel, "thread_ts": thread_ts, "text": text }),
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(v) => ts.or_else(|| v.get("ts").and_then(|t| t.as_str()).map(String::from)),
+            Err(e) => {
+                eprintln!("[{}] {}", self.name, e);
+                ts
+            }
+        }
+    }
+
+    async fn send_text(&self, chat_id: &str, text: &str) {
+        for chunk in split_text_at(text, SLACK_MESSAGE_LIMIT) {
+            self.put_message(chat_id, None, &chunk).await;
+        }
+    }
+
+    async fn post_blocks(&self, chat_id: &str, fallback_text: &str, blocks: Vec<serde_json::Value>) {
+        let (channel, thread_ts) = split_chat_id(chat_id);
+        let body = serde_json::json!({
+            "channel": channel,
+            "thread_ts": thread_ts,
+            "text": truncate(fallback_text, SLACK_MESSAGE_LIMIT),
+            "blocks": blocks,
+        });
+        if let Err(e) = self.api("chat.postMessage", &self.bot_token, body).await {
+            eprintln!("[{}] {}", self.name, e);
+        }
+    }
+}
+
+/// Past `cap` entries, forget those older than `ttl`, then the oldest until a tenth below `cap`.
+fn evict(map: &DashMap<String, Instant>, ttl: Duration, cap: usize) {
+    if map.len() <= cap {
+        return;
+    }
+    map.retain(|_, at| at.elapsed() < ttl);
+    let excess = map.len().saturating_sub(cap - cap / 10);
+    if excess > 0 {
+        let mut by_age: Vec<(String, Instant)> = map.iter().map(|e| (e.key().clone(), *e.value())).collect();
+        by_age.sort_by_key(|(_, at)| *at);
+        for (key, _) in by_age.into_iter().take(excess) {
+            map.remove(&key);
+        }
+    }
+}
+
+/// Direct-message conversations have ids starting with `D`.
+fn is_direct(channel: &str) -> bool {
+    channel.starts_with('D')
+}
+
+/// `<channel>` for DMs, `<channel>/<thread root ts>` elsewhere.
+fn chat_id_for(channel: &str, thread_ts: Option<&str>, ts: &str) -> String {
+    if is_direct(channel) {
+        channel.to_string()
+    } else {
+        format!("{}/{}", channel, thread_ts.filter(|t| !t.is_empty()).unwrap_or(ts))
+    }
+}
+
+fn split_chat_id(chat_id: &str) -> (&str, Option<&str>) {
+    match chat_id.split_once('/') {
+        Some((channel, thread_ts)) => (channel, Some(thread_ts)),
+        None => (chat_id, None),
+    }
+}
+
+static USER_MENTION: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"<@[A-Z0-9]+>").unwrap());
+
+/// Drop `<@U123>` user mentions (the bot's own mention starts every channel message).
+fn strip_mentions(text: &str) -> String {
+    USER_MENTION.replace_all(text, "").trim().to_string()
+}
+
+fn section_block(markdown: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": truncate(markdown, 3000) },
+    })
+}
+
+fn context_block(markdown: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": truncate(markdown, 3000) }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use axum::extract::ws::{Message as MockWsMessage, WebSocket, WebSocketUpgrade};
+    use axum::extract::{Path, State};
+    use axum::http::HeaderMap;
+    use axum::response::IntoResponse;
+    use axum::Json;
+    use serde_json::json;
+    use tokio::sync::{broadcast, Mutex};
+
+    use super::*;
+    use crate::agent::{PermissionChoice, StopReason};
+    use crate::config::Config;
+    use crate::session_hub::types::AgentEvent;
+    use crate::session_hub::SessionHub;
+
+    const WAIT: Duration = Duration::from_secs(5);
+    const CHAT: &str = "C123/1000.000100";
+
+    type SocketPipes = (mpsc::UnboundedReceiver<String>, mpsc::UnboundedSender<String>);
+
+    /// Local stand-in for the Slack Web API and the Socket Mode endpoint.
+    #[derive(Clone)]
+    struct MockSlack {
+        addr: std::net::SocketAddr,
+        /// Web API calls made with the bot token, as (method, body).
+        calls: mpsc::UnboundedSender<(String, serde_json::Value)>,
+        /// Envelopes to push down the socket and where the client's frames go; taken by the first
+        /// connection.
+        socket: Arc<Mutex<Option<SocketPipes>>>,
+        next_ts: Arc<AtomicU64>,
+    }
+
+    async fn web_api(
+        State(mock): State<MockSlack>,
+        Path(method): Path<String>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> Json<serde_json::Value> {
+        let token = if method == "apps.connections.open" { "xapp-test" } else { "xoxb-test" };
+        let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or("");
+        if auth != format!("Bearer {}", token) {
+            return Json(json!({ "ok": false, "error": "invalid_auth" }));
+        }
+        match method.as_str() {
+            "apps.connections.open" => Json(json!({ "ok": true, "url": format!("ws://{}/socket", mock.addr) })),
+            "chat.postMessage" | "chat.update" => {
+                let ts = match body.get("ts").and_then(|v| v.as_str()) {
+                    Some(ts) => ts.to_string(),
+                    None => format!("2000.{:06}", mock.next_ts.fetch_add(1, Ordering::SeqCst)),
+                };
+                let _ = mock.calls.send((method, body));
+                Json(json!({ "ok": true, "ts": ts }))
+            }
+            _ => Json(json!({ "ok": false, "error": "unknown_method" })),
+        }
+    }
+
+    async fn socket(State(mock): State<MockSlack>, upgrade: WebSocketUpgrade) -> impl IntoResponse {
+        let pipes = mock.socket.lock().await.take();
+        upgrade.on_upgrade(move |ws| async move {
+            if let Some((envelopes, frames)) = pipes {
+                relay(ws, envelopes, frames).await;
+            }
+        })
+    }
+
+    async fn relay(
+        mut ws: WebSocket,
+        mut envelopes: mpsc::UnboundedReceiver<String>,
+        frames: mpsc::UnboundedSender<String>,
+    ) {
+        loop {
+            tokio::select! {
+                envelope = envelopes.recv() => {
+                    let Some(envelope) = envelope else { break };
+                    if ws.send(MockWsMessage::Text(envelope.into())).await.is_err() {
+                        break;
+                    }
+                }
+                frame = ws.recv() => match frame {
+                    Some(Ok(MockWsMessage::Text(text))) => {
+                        let _ = frames.send(text.to_string());
+                    }
+                    Some(Ok(_)) => {}
+                    _ => break,
+                },
+            }
+        }
+    }
+
+    /// A SlackChannel wired to a MockSlack, with handles on both sides.
+    struct Harness {
+        outbound: mpsc::UnboundedSender<ChannelNotification>,
+        envelopes: mpsc::UnboundedSender<String>,
+        frames: mpsc::UnboundedReceiver<String>,
+        calls: mpsc::UnboundedReceiver<(String, serde_json::Value)>,
+        events: broadcast::Receiver<AgentEvent>,
+    }
+
+    impl Harness {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (calls_tx, calls) = mpsc::unbounded_channel();
+            let (envelopes, envelopes_rx) = mpsc::unbounded_channel();
+            let (frames_tx, frames) = mpsc::unbounded_channel();
+            let mock = MockSlack {
+                addr,
+                calls: calls_tx,
+                socket: Arc::new(Mutex::new(Some((envelopes_rx, frames_tx)))),
+                next_ts: Arc::new(AtomicU64::new(1)),
+            };
+            let router = axum::Router::new()
+                .route("/api/{method}", axum::routing::post(web_api))
+                .route("/socket", axum::routing::get(socket))
+                .with_state(mock);
+            tokio::spawn(async move { axum::serve(listener, router).await });
+
+            // An open channel with default settings; nothing is read from ~/.vibearound.
+            let data_dir = std::env::temp_dir().join(format!("vibearound-slack-test-{}", std::process::id()));
+            let channel_hub = Arc::new(ChannelManager::with_config(Arc::new(Config::default()), data_dir));
+            let session_hub = Arc::new(SessionHub::new());
+            channel_hub.set_session_hub(Arc::clone(&session_hub));
+            let events = session_hub.subscribe_agent_events();
+            let channel = Arc::new(SlackChannel {
+                name: "slack".to_string(),
+                api_base_url: format!("http://{}/api", addr),
+                app_token: "xapp-test".to_string(),
+                bot_token: "xoxb-test".to_string(),
+                client: reqwest::Client::new(),
+                channel_hub,
+                streams: StreamingEditor::new(SLACK_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+                threads: DashMap::new(),
+                seen: DashMap::new(),
+            });
+            let (outbound, outbound_rx) = mpsc::unbounded_channel();
+            tokio::spawn(channel.run(outbound_rx));
+            Self { outbound, envelopes, frames, calls, events }
+        }
+
+        fn send_envelope(&self, envelope: serde_json::Value) {
+            self.envelopes.send(envelope.to_string()).unwrap();
+        }
+
+        fn notify(&self, notif: ChannelNotification) {
+            self.outbound.send(notif).unwrap();
+        }
+
+        async fn next_frame(&mut self) -> serde_json::Value {
+            let frame = tokio::time::timeout(WAIT, self.frames.recv()).await.expect("socket frame").unwrap();
+            serde_json::from_str(&frame).unwrap()
+        }
+
+        async fn next_call(&mut self) -> (String, serde_json::Value) {
+            tokio::time::timeout(WAIT, self.calls.recv()).await.expect("Web API call").unwrap()
+        }
+
+        async fn next_event(&mut self) -> AgentEvent {
+            tokio::time::timeout(WAIT, self.events.recv()).await.expect("agent event").unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn socket_mode_round_trip() {
+        let mut slack = Harness::start().await;
+        slack.send_envelope(json!({ "type": "hello" }));
+
+        // A mention inside a thread is acked by envelope id and becomes a message for the thread's chat.
+        slack.send_envelope(json!({
+            "envelope_id": "env-1",
+            "type": "events_api",
+            "payload": { "event": {
+                "type": "app_mention",
+                "channel": "C123",
+                "user": "U1",
+                "ts": "1001.000200",
+                "thread_ts": "1000.000100",
+                "text": "<@UBOT> hello there",
+            } },
+        }));
+        assert_eq!(slack.next_frame().await, json!({ "envelope_id": "env-1" }));
+        match slack.next_event().await {
+            AgentEvent::OnReceiveMessage { channel_kind, chat_id, message } => {
+                assert_eq!(channel_kind, "slack");
+                assert_eq!(chat_id, CHAT);
+                assert_eq!(message.text, "hello there");
+                assert_eq!(message.sender_id, "U1");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        // Tokens stream into one threaded message: posted once, then edited with the full reply.
+        slack.notify(ChannelNotification::AgentStart {
+            channel_kind: "slack".into(),
+            chat_id: CHAT.into(),
+            message_id: "1001.000200".into(),
+        });
+        for delta in ["Hello", ", world"] {
+            slack.notify(ChannelNotification::AgentToken {
+                channel_kind: "slack".into(),
+                chat_id: CHAT.into(),
+                delta: delta.into(),
+            });
+        }
+        slack.notify(ChannelNotification::AgentEnd {
+            channel_kind: "slack".into(),
+            chat_id: CHAT.into(),
+            stop_reason: StopReason::EndTurn,
+            cost_usd: None,
+            usage: None,
+        });
+        let (method, body) = slack.next_call().await;
+        assert_eq!(method, "chat.postMessage");
+        assert_eq!(body["channel"], "C123");
+        assert_eq!(body["thread_ts"], "1000.000100");
+        assert_eq!(body["text"], "Hello");
+        let (method, body) = slack.next_call().await;
+        assert_eq!(method, "chat.update");
+        assert_eq!(body["ts"], "2000.000001");
+        assert_eq!(body["text"], "Hello, world");
+
+        // A permission prompt carries one button per option; a click answers the request and
+        // strips the buttons.
+        slack.notify(ChannelNotification::PermissionRequest {
+            channel_kind: "slack".into(),
+            chat_id: CHAT.into(),
+            request_id: "req-1".into(),
+            tool: "Bash".into(),
+            input: "cargo test".into(),
+            options: vec![
+                PermissionChoice { id: "allow".into(), label: "Allow".into(), kind: "allow_once".into() },
+                PermissionChoice { id: "reject".into(), label: "Deny".into(), kind: "reject_once".into() },
+            ],
+            timeout_secs: 60,
+            default_allow: false,
+        });
+        let (method, body) = slack.next_call().await;
+        assert_eq!(method, "chat.postMessage");
+        assert_eq!(body["thread_ts"], "1000.000100");
+        let buttons = &body["blocks"][2]["elements"];
+        assert_eq!(buttons[0]["value"], permission_callback_value("req-1", "allow"));
+        assert_eq!(buttons[0]["style"], "primary");
+        assert_eq!(buttons[1]["style"], "danger");
+
+        slack.send_envelope(json!({
+            "envelope_id": "env-2",
+            "type": "interactive",
+            "payload": {
+                "type": "block_actions",
+                "user": { "id": "U1" },
+                "channel": { "id": "C123" },
+                "container": { "message_ts": "2000.000002", "thread_ts": "1000.000100" },
+                "message": { "text": "Bash wants permission" },
+                "actions": [buttons[0].clone()],
+            },
+        }));
+        assert_eq!(slack.next_frame().await, json!({ "envelope_id": "env-2" }));
+        match slack.next_event().await {
+            AgentEvent::OnPermissionResponse { channel_kind, chat_id, request_id, option_id } => {
+                assert_eq!(channel_kind, "slack");
+                assert_eq!(chat_id, CHAT);
+                assert_eq!(request_id, "req-1");
+                assert_eq!(option_id.as_deref(), Some("allow"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        let (method, body) = slack.next_call().await;
+        assert_eq!(method, "chat.update");
+        assert_eq!(body["ts"], "2000.000002");
+        assert_eq!(body["blocks"], json!([]));
+        assert_eq!(body["text"], "Bash wants permission\n\n→ Allow");
+    }
+
+    #[test]
+    fn evict_forgets_expired_then_oldest_entries() {
+        let map = DashMap::new();
+        let now = Instant::now();
+        for i in 0..10u64 {
+            map.insert(format!("k{}", i), now - Duration::from_secs(100 - i));
+        }
+        evict(&map, Duration::from_secs(1000), 10);
+        assert_eq!(map.len(), 10, "at the cap nothing is evicted");
+
+        map.insert("k10".to_string(), now);
+        evict(&map, Duration::from_secs(1000), 10);
+        assert_eq!(map.len(), 9);
+        assert!(!map.contains_key("k0") && !map.contains_key("k1") && map.contains_key("k10"));
+
+        map.insert("k11".to_string(), now);
+        map.insert("k12".to_string(), now);
+        evict(&map, Duration::from_secs(50), 10);
+        assert_eq!(map.len(), 3, "expired entries go first");
+    }
+}
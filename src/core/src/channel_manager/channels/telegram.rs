@@ -0,0 +1,311 @@
+//! Built-in Telegram channel (teloxide, long polling).
+//!
+//! Used for `channels.telegram` when no external Telegram plugin is installed. Updates are turned
+//! into the same `on_message` / `on_callback` JSON-RPC the plugins send, so commands, allowlists
+//! and permission callbacks behave identically. Outbound notifications are rendered natively:
+//! agent tokens stream into one message that is edited in place, permission options become an
+//! inline keyboard, and text is split at Telegram's message length limit.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use teloxide::prelude::*;
+use teloxide::types::{
+    AllowedUpdate, ChatAction, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ReplyParameters,
+    UpdateKind,
+};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::channel_manager::capabilities::{split_text_at, StreamingEditor};
+use crate::channel_manager::ChannelManager;
+use crate::config;
+use crate::session_hub::types::{permission_callback_value, ChannelNotification};
+
+/// Telegram rejects messages longer than this.
+pub const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+/// Long-poll timeout; must stay below the HTTP client's request timeout.
+const POLL_TIMEOUT_SECS: u32 = 10;
+/// Telegram allows roughly one edit per second per chat.
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1200);
+/// Telegram limits callback_data to 64 bytes.
+const CALLBACK_DATA_LIMIT: usize = 64;
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(60);
+
+pub struct TelegramChannel {
+    name: String,
+    bot: Bot,
+    channel_hub: Arc<ChannelManager>,
+    /// Per chat: the reply being streamed.
+    streams: StreamingEditor<i64, MessageId>,
+    /// Short callback_data → full callback value, for values over Telegram's 64-byte limit.
+    callbacks: DashMap<String, String>,
+    next_callback: AtomicU64,
+}
+
+/// Start the built-in Telegram channel and register it with ChannelManager.
+/// Returns the transport task's abort handle.
+pub fn start(channel_hub: &Arc<ChannelManager>, channel_name: &str) -> Result<AbortHandle, String> {
+    let raw_config = config::ensure_loaded()
+        .channel_raw_config(channel_name)
+        .ok_or_else(|| format!("channels.{} is not configured", channel_name))?;
+    let token = raw_config
+        .get("bot_token")
+        .and_then(|v| v.as_str())
+        .filter(|t| !t.trim().is_empty())
+        .ok_or_else(|| format!("channels.{}.bot_token is missing", channel_name))?;
+
+    let channel = Arc::new(TelegramChannel {
+        name: channel_name.to_string(),
+        bot: Bot::new(token.trim()),
+        channel_hub: Arc::clone(channel_hub),
+        streams: StreamingEditor::new(TELEGRAM_MESSAGE_LIMIT, STREAM_EDIT_INTERVAL),
+        callbacks: DashMap::new(),
+        next_callback: AtomicU64::new(1),
+    });
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(async move { channel.run(outbound_rx).await });
+    let abort = task.abort_handle();
+    channel_hub.start_builtin_plugin(channel_name, outbound_tx, abort.clone());
+    eprintln!("[{}] built-in Telegram channel started (long polling)", channel_name);
+    Ok(abort)
+}
+
+impl TelegramChannel {
+    async fn run(self: Arc<Self>, mut outbound_rx: mpsc::UnboundedReceiver<ChannelNotification>) {
+        let outbound = async {
+            while let Some(notif) = outbound_rx.recv().await {
+                self.render(notif).await;
+            }
+        };
+        tokio::select! {
+            _ = self.poll_updates() => {}
+            _ = outbound => {}
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Inbound
+    // -----------------------------------------------------------------------
+
+    async fn poll_updates(&self) {
+        let mut offset: i32 = 0;
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            let result = self
+                .bot
+                .get_updates()
+                .offset(offset)
+                .timeout(POLL_TIMEOUT_SECS)
+                .allowed_updates(vec![AllowedUpdate::Message, AllowedUpdate::CallbackQuery])
+                .await;
+            match result {
+                Ok(updates) => {
+                    backoff = Duration::from_secs(1);
+                    for update in updates {
+                        offset = update.id.0 as i32 + 1;
+                        self.handle_update(update.kind).await;
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[{}] getUpdates failed: {} — retrying in {}s",
+                        self.name,
+                        e,
+                        backoff.as_secs()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_POLL_BACKOFF);
+                }
+            }
+        }
+    }
+
+    async fn handle_update(&self, kind: UpdateKind) {
+        match kind {
+            UpdateKind::Message(msg) => {
+                let text = msg.text().or(msg.caption()).unwrap_or("").trim().to_string();
+                if text.is_empty() {
+                    return;
+                }
+                let sender = msg.from.as_ref();
+                let params = serde_json::json!({
+                    "channelId": ChannelNotification::plugin_channel_id(&self.name, &msg.chat.id.0.to_string()),
+                    "messageId": msg.id.0.to_string(),
+                    "text": text,
+                    "sender": {
+                        "id": sender.map(|u| u.id.0.to_string()).unwrap_or_default(),
+                        "name": sender.and_then(|u| u.username.clone()),
+                    },
+                });
+                self.dispatch_inbound("on_message", params).await;
+            }
+            UpdateKind::CallbackQuery(query) => {
+                // Stop the button's loading spinner whatever happens next.
+                let _ = self.bot.answer_callback_query(query.id.clone()).await;
+                let Some(chat_id) = query.message.as_ref().map(|m| m.chat().id) else {
+                    return;
+                };
+                let Some(data) = query.data.as_deref().filter(|d| !d.is_empty()) else {
+                    return;
+                };
+                let value = self
+                    .callbacks
+                    .remove(data)
+                    .map(|(_, v)| v)
+                    .unwrap_or_else(|| data.to_string());
+                let params = serde_json::json!({
+                    "channelId": ChannelNotification::plugin_channel_id(&self.name, &chat_id.0.to_string()),
+                    "data": { "value": value },
+                    "sender": { "id": query.from.id.0.to_string() },
+                });
+                self.dispatch_inbound("on_callback", params).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn dispatch_inbound(&self, method: &str, params: serde_json::Value) {
+        let msg = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.channel_hub.handle_inbound_jsonrpc(&self.name, msg).await;
+    }
+
+    // -----------------------------------------------------------------------
+    // Outbound
+    // -----------------------------------------------------------------------
+
+    async fn render(&self, notif: ChannelNotification) {
+        match notif {
+            ChannelNotification::AgentStart { chat_id, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.streams.reset(&chat.0);
+                let _ = self.bot.send_chat_action(chat, ChatAction::Typing).await;
+            }
+            ChannelNotification::AgentThinking { chat_id, text, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.finish_stream(chat).await;
+                self.send_text(chat, &format!("💭 {}", text), None).await;
+            }
+            ChannelNotification::AgentToken { chat_id, delta, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.stream_token(chat, &delta).await;
+            }
+            ChannelNotification::AgentToolUse { chat_id, tool, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.finish_stream(chat).await;
+                self.send_text(chat, &format!("🔧 {}", tool), None).await;
+            }
+            ChannelNotification::AgentToolResult { .. } => {}
+            ChannelNotification::MessageQueued { .. } => {}
+            ChannelNotification::AgentEnd { chat_id, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.finish_stream(chat).await;
+            }
+            ChannelNotification::AgentError { chat_id, error, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.finish_stream(chat).await;
+                self.send_text(chat, &format!("❌ {}", error), None).await;
+            }
+            ChannelNotification::SendText { chat_id, text, reply_to, .. } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                let reply_to = reply_to.and_then(|id| id.parse::<i32>().ok()).map(MessageId);
+                self.send_text(chat, &text, reply_to).await;
+            }
+            ChannelNotification::PermissionRequest {
+                chat_id,
+                request_id,
+                tool,
+                input,
+                options,
+                timeout_secs,
+                default_allow,
+                ..
+            } => {
+                let Some(chat) = parse_chat_id(&chat_id) else { return };
+                self.finish_stream(chat).await;
+                let text = format!(
+                    "🔐 {} wants permission:\n{}\n\nNo answer in {}s means {}.",
+                    tool,
+                    input,
+                    timeout_secs,
+                    if default_allow { "allow" } else { "deny" }
+                );
+                let buttons = options
+                    .iter()
+                    .map(|o| {
+                        let value = permission_callback_value(&request_id, &o.id);
+                        vec![InlineKeyboardButton::callback(o.label.clone(), self.callback_data(value))]
+                    })
+                    .collect::<Vec<_>>();
+                let text: String = text.chars().take(TELEGRAM_MESSAGE_LIMIT).collect();
+                if let Err(e) = self
+                    .bot
+                    .send_message(chat, text)
+                    .reply_markup(InlineKeyboardMarkup::new(buttons))
+                    .await
+                {
+                    eprintln!("[{}] failed to send permission request: {}", self.name, e);
+                }
+            }
+        }
+    }
+
+    async fn stream_token(&self, chat: ChatId, delta: &str) {
+        self.streams
+            .push(&chat.0, delta, |id, text| async move { self.put_message(chat, id, &text).await })
+            .await;
+    }
+
+    async fn finish_stream(&self, chat: ChatId) {
+        self.streams
+            .finish(&chat.0, |id, text| async move { self.put_message(chat, id, &text).await })
+            .await;
+    }
+
+    /// Edit `message_id` to `text`, or send a new message when there is none yet.
+    async fn put_message(&self, chat: ChatId, message_id: Option<MessageId>, text: &str) -> Option<MessageId> {
+        match message_id {
+            Some(id) => {
+                // "message is not modified" is expected when nothing changed since the last edit.
+                let _ = self.bot.edit_message_text(chat, id, text).await;
+                Some(id)
+            }
+            None => match self.bot.send_message(chat, text).await {
+                Ok(sent) => Some(sent.id),
+                Err(e) => {
+                    eprintln!("[{}] failed to send message: {}", self.name, e);
+                    None
+                }
+            },
+        }
+    }
+
+    async fn send_text(&self, chat: ChatId, text: &str, reply_to: Option<MessageId>) {
+        for (i, chunk) in split_text_at(text, TELEGRAM_MESSAGE_LIMIT).into_iter().enumerate() {
+            let mut request = self.bot.send_message(chat, chunk);
+            if let Some(id) = reply_to.filter(|_| i == 0) {
+                request = request.reply_parameters(ReplyParameters::new(id));
+            }
+            if let Err(e) = request.await {
+                eprintln!("[{}] failed to send message: {}", self.name, e);
+                return;
+            }
+        }
+    }
+
+    /// callback_data for a button; values over Telegram's limit are replaced by a short key.
+    fn callback_data(&self, value: String) -> String {
+        if value.len() <= CALLBACK_DATA_LIMIT {
+            return value;
+        }
+        let key = format!("cb:{}", self.next_callback.fetch_add(1, Ordering::Relaxed));
+        self.callbacks.insert(key.clone(), value);
+        key
+    }
+}
+
+fn parse_chat_id(chat_id: &str) -> Option<ChatId> {
+    chat_id.parse::<i64>().ok().map(ChatId)
+}
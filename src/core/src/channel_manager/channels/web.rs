@@ -1,3 +1,4 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use dashmap::DashMap;
@@ -8,26 +9,53 @@ use crate::session_hub::types::ChannelNotification;
 /// Outbound sink to a single web chat connection.
 pub type WebChatSink = mpsc::UnboundedSender<ChannelNotification>;
 
+/// Identifies one connection among a chat's sinks.
+pub type ConnectionId = u64;
+
+/// Everything attached to one chat_id.
+#[derive(Default)]
+struct WebChat {
+    /// Every open connection (browser tab) showing this chat.
+    sinks: Vec<(ConnectionId, WebChatSink)>,
+    /// Events of the turn in progress, from `AgentStart` on, replayed to connections that join
+    /// mid-stream. Consecutive token deltas are merged.
+    turn: Vec<ChannelNotification>,
+}
+
 /// Internal web channel manager.
 ///
-/// One manager owns many chat connections keyed by chat_id.
+/// One manager owns many chats keyed by chat_id; each chat fans out to all of its connections,
+/// so a chat survives page refreshes and can be open in several tabs.
 pub struct WebChannelManager {
-    connections: DashMap<String, WebChatSink>,
+    chats: DashMap<String, WebChat>,
+    next_connection_id: AtomicU64,
 }
 
 impl WebChannelManager {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
-            connections: DashMap::new(),
+            chats: DashMap::new(),
+            next_connection_id: AtomicU64::new(1),
         })
     }
 
-    pub fn register_connection(&self, chat_id: String, sink: WebChatSink) {
-        self.connections.insert(chat_id, sink);
+    /// Attach a connection to `chat_id`, first replaying the turn in progress (if any) to it.
+    pub fn register_connection(&self, chat_id: String, sink: WebChatSink) -> ConnectionId {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        // Replaying under the entry lock keeps the replay and live events in order.
+        let mut chat = self.chats.entry(chat_id).or_default();
+        for notif in &chat.turn {
+            let _ = sink.send(notif.clone());
+        }
+        chat.sinks.push((id, sink));
+        id
     }
 
-    pub fn unregister_connection(&self, chat_id: &str) {
-        self.connections.remove(chat_id);
+    pub fn unregister_connection(&self, chat_id: &str, connection_id: ConnectionId) {
+        if let Some(mut chat) = self.chats.get_mut(chat_id) {
+            chat.sinks.retain(|(id, _)| *id != connection_id);
+        }
+        self.chats.remove_if(chat_id, |_, chat| chat.sinks.is_empty() && chat.turn.is_empty());
     }
 
     pub fn sender(
@@ -40,14 +68,32 @@ impl WebChannelManager {
     }
 
     pub fn dispatch_notification(&self, notif: ChannelNotification) {
-        let chat_id = chat_id_of_notification(&notif);
-        if let Some(entry) = self.connections.get(chat_id) {
-            let _ = entry.send(notif);
+        let chat_id = chat_id_of_notification(&notif).to_string();
+        let mut chat = self.chats.entry(chat_id.clone()).or_default();
+
+        match &notif {
+            ChannelNotification::AgentStart { .. } => chat.turn = vec![notif.clone()],
+            ChannelNotification::AgentEnd { .. } | ChannelNotification::AgentError { .. } => chat.turn.clear(),
+            // About a later message, not the turn being replayed.
+            ChannelNotification::MessageQueued { .. } => {}
+            _ if chat.turn.is_empty() => {}
+            ChannelNotification::AgentToken { delta, .. } => match chat.turn.last_mut() {
+                Some(ChannelNotification::AgentToken { delta: buffered, .. }) => buffered.push_str(delta),
+                _ => chat.turn.push(notif.clone()),
+            },
+            _ => chat.turn.push(notif.clone()),
+        }
+
+        chat.sinks.retain(|(_, sink)| sink.send(notif.clone()).is_ok());
+        let idle = chat.sinks.is_empty() && chat.turn.is_empty();
+        drop(chat);
+        if idle {
+            self.chats.remove_if(&chat_id, |_, chat| chat.sinks.is_empty() && chat.turn.is_empty());
         }
     }
 }
 
-fn chat_id_of_notification(notif: &ChannelNotification) -> &str {
+pub(crate) fn chat_id_of_notification(notif: &ChannelNotification) -> &str {
     match notif {
         ChannelNotification::AgentStart { chat_id, .. } => chat_id,
         ChannelNotification::AgentThinking { chat_id, .. } => chat_id,
@@ -57,5 +103,7 @@ fn chat_id_of_notification(notif: &ChannelNotification) -> &str {
         ChannelNotification::AgentEnd { chat_id, .. } => chat_id,
         ChannelNotification::AgentError { chat_id, .. } => chat_id,
         ChannelNotification::SendText { chat_id, .. } => chat_id,
+        ChannelNotification::PermissionRequest { chat_id, .. } => chat_id,
+        ChannelNotification::MessageQueued { chat_id, .. } => chat_id,
     }
 }
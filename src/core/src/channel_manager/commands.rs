@@ -0,0 +1,69 @@
+//! In-chat slash commands.
+//!
+//! Text messages starting with `/` are parsed here before they reach SessionHub, so every
+//! channel (external plugins and the internal web channel) gets the same command set.
+//! Unrecognized commands are passed through to the agent unchanged.
+
+/// A slash command recognized by ChannelManager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatCommand {
+    /// `/agent [kind]` — show or switch the agent CLI.
+    Agent(Option<String>),
+    /// `/profile [name]` — show or switch the agent profile.
+    Profile(Option<String>),
+    /// `/cd [path | -]` — show the workspace, bind a project directory, or go back to the default.
+    Cd(Option<String>),
+    /// `/stop` — cancel the running turn.
+    Stop,
+    /// `/new` — close the session; the next message starts a fresh one.
+    New,
+    /// `/status` — show the session's agent, CLI session id, profile and queue.
+    Status,
+    /// `/help` — list commands.
+    Help,
+}
+
+impl ChatCommand {
+    /// Commands that change or tear down the session; limited to the channel's admins.
+    pub fn requires_admin(&self) -> bool {
+        matches!(
+            self,
+            ChatCommand::Agent(Some(_)) | ChatCommand::Profile(Some(_)) | ChatCommand::Cd(Some(_)) | ChatCommand::New
+        )
+    }
+}
+
+/// Parse `text` as a slash command. Returns `None` for plain text and unknown commands.
+pub fn parse_command(text: &str) -> Option<ChatCommand> {
+    let rest = text.trim().strip_prefix('/')?;
+    let (name, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    // Telegram appends the bot username in groups: "/status@my_bot".
+    let name = name.split('@').next().unwrap_or(name).to_lowercase();
+    // The rest of the line, so paths with spaces work for /cd.
+    let arg = Some(arg.trim().to_string()).filter(|a| !a.is_empty());
+
+    match name.as_str() {
+        "agent" => Some(ChatCommand::Agent(arg)),
+        "profile" => Some(ChatCommand::Profile(arg)),
+        "cd" => Some(ChatCommand::Cd(arg)),
+        "stop" => Some(ChatCommand::Stop),
+        "new" => Some(ChatCommand::New),
+        "status" => Some(ChatCommand::Status),
+        "help" => Some(ChatCommand::Help),
+        _ => None,
+    }
+}
+
+pub fn help_text() -> String {
+    [
+        "Commands:",
+        "/agent [name] — show or switch the agent (claude, gemini, opencode, codex; switching is admin-only)",
+        "/profile [name] — show or switch the agent profile (switching is admin-only)",
+        "/cd [path] — show or set the project directory (/cd - for the default; setting is admin-only)",
+        "/stop — stop the current reply",
+        "/new — start a new session (admin-only)",
+        "/status — show the current session",
+        "/help — show this message",
+    ]
+    .join("\n")
+}
@@ -0,0 +1,122 @@
+//! How to launch a channel plugin process.
+//!
+//! A plugin directory either contains a `plugin.json` manifest, which may name any executable
+//! that speaks the JSON-RPC stdio protocol (Python, a Rust binary, ...), or the default Node
+//! layout with a built `dist/main.js`:
+//!
+//! ```json
+//! {
+//!   "command": "python3",
+//!   "args": ["main.py"],
+//!   "env": { "PYTHONUNBUFFERED": "1" },
+//!   "cwd": "."
+//! }
+//! ```
+//!
+//! A `command` containing a path separator is resolved against the plugin directory; a bare name
+//! is looked up on PATH. `cwd` is relative to the plugin directory and defaults to it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+pub const MANIFEST_FILE: &str = "plugin.json";
+const NODE_ENTRY: &str = "dist/main.js";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PluginManifest {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    cwd: Option<String>,
+}
+
+/// A resolved command line for one plugin process.
+#[derive(Debug, Clone)]
+pub struct PluginLaunch {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: PathBuf,
+}
+
+impl PluginLaunch {
+    /// `program arg1 arg2`, for logs.
+    pub fn display(&self) -> String {
+        std::iter::once(self.program.display().to_string())
+            .chain(self.args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// True when `dir` holds something `load` can launch.
+pub fn is_plugin_dir(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).is_file() || dir.join(NODE_ENTRY).is_file()
+}
+
+/// Read `plugin.json`, or fall back to `node dist/main.js`.
+pub fn load(plugin_dir: &Path) -> Result<PluginLaunch, String> {
+    let manifest_path = plugin_dir.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        let entry_point = plugin_dir.join(NODE_ENTRY);
+        if !entry_point.is_file() {
+            return Err(format!(
+                "plugin entry not found: {} — run `npm run build` in {} or add a {}",
+                entry_point.display(),
+                plugin_dir.display(),
+                MANIFEST_FILE
+            ));
+        }
+        return Ok(PluginLaunch {
+            program: PathBuf::from("node"),
+            args: vec![entry_point.to_string_lossy().into_owned()],
+            env: BTreeMap::new(),
+            cwd: plugin_dir.to_path_buf(),
+        });
+    }
+
+    let data = std::fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("failed to read {}: {}", manifest_path.display(), e))?;
+    let manifest: PluginManifest = serde_json::from_str(&data)
+        .map_err(|e| format!("invalid {}: {}", manifest_path.display(), e))?;
+
+    if manifest.command.trim().is_empty() {
+        return Err(format!("{}: \"command\" is empty", manifest_path.display()));
+    }
+    let program = if manifest.command.contains('/') || manifest.command.contains('\\') {
+        let path = plugin_dir.join(&manifest.command);
+        if !path.is_file() {
+            return Err(format!(
+                "{}: command not found: {}",
+                manifest_path.display(),
+                path.display()
+            ));
+        }
+        path
+    } else {
+        PathBuf::from(&manifest.command)
+    };
+    let cwd = match &manifest.cwd {
+        Some(cwd) => plugin_dir.join(cwd),
+        None => plugin_dir.to_path_buf(),
+    };
+    if !cwd.is_dir() {
+        return Err(format!(
+            "{}: cwd is not a directory: {}",
+            manifest_path.display(),
+            cwd.display()
+        ));
+    }
+
+    Ok(PluginLaunch {
+        program,
+        args: manifest.args,
+        env: manifest.env,
+        cwd,
+    })
+}
@@ -1,16 +1,25 @@
 //! ChannelManager: manages channel transports and protocol I/O.
 //!
 //! Responsibilities:
-//! - Spawn external channel plugin processes (Node.js)
+//! - Spawn external channel plugin processes (Node.js by default, or any command from `plugin.json`)
+//!   and restart them with backoff when they exit
 //! - Register internal channel transports
 //! - Parse JSON-RPC messages from channel transports → InboundMessage
+//! - Handle in-chat slash commands (/agent, /stop, /new, /status, ...)
+//! - Download message attachments from plugins (`fetch_attachment`) before queueing
+//! - Host → plugin JSON-RPC requests with timeouts (`call_plugin`)
 //! - Forward ChannelNotification → channel transport
 //! - Route inbound messages to SessionHub
 
+pub mod capabilities;
 pub mod channels;
+pub mod commands;
+pub mod manifest;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -18,33 +27,171 @@ use tokio::process::Command;
 use tokio::sync::{broadcast, mpsc, oneshot, Mutex, OnceCell};
 use tokio::task::AbortHandle;
 
-use crate::config;
+use crate::agent::{AgentKind, PermissionChoice};
+use crate::agent_manager::truncate;
+use crate::config::{self, Config};
+use capabilities::PluginCapabilities;
+use channels::web::chat_id_of_notification;
+use commands::ChatCommand;
 use crate::session_hub::types::*;
 use crate::session_hub::SessionHub;
 
 type StdinWriter = Arc<Mutex<tokio::process::ChildStdin>>;
-type PendingRequests = Arc<DashMap<u64, oneshot::Sender<Result<serde_json::Value, String>>>>;
+type PendingRequests = Arc<DashMap<u64, oneshot::Sender<Result<serde_json::Value, PluginCallError>>>>;
+
+/// Default timeout for `call_plugin`.
+pub const PLUGIN_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+const FETCH_ATTACHMENT_TIMEOUT: Duration = Duration::from_secs(60);
+const PLUGIN_RESTART_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const PLUGIN_RESTART_MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A plugin that stays up this long is considered healthy again; its backoff resets.
+const PLUGIN_STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// Why a host → plugin request (`call_plugin`) did not produce a result.
+#[derive(Debug, Clone)]
+pub enum PluginCallError {
+    /// No external plugin is registered for the channel (not configured, restarting, or internal).
+    Unavailable,
+    /// Writing the request to the plugin's stdin failed.
+    Write(String),
+    /// The plugin exited before answering.
+    Disconnected,
+    TimedOut(Duration),
+    /// The plugin answered with a JSON-RPC error object.
+    Remote { code: i64, message: String },
+}
+
+impl PluginCallError {
+    /// JSON-RPC "method not found": the plugin does not implement this request.
+    pub fn is_method_not_found(&self) -> bool {
+        matches!(self, PluginCallError::Remote { code: -32601, .. })
+    }
+}
+
+impl std::fmt::Display for PluginCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginCallError::Unavailable => write!(f, "channel plugin is not running"),
+            PluginCallError::Write(e) => write!(f, "failed to write request: {}", e),
+            PluginCallError::Disconnected => write!(f, "plugin exited"),
+            PluginCallError::TimedOut(d) => write!(f, "timed out after {}s", d.as_secs()),
+            PluginCallError::Remote { code, message } => write!(f, "{} (code {})", message, code),
+        }
+    }
+}
 
 enum ChannelHandle {
     External {
         stdin: StdinWriter,
-        abort: AbortHandle,
+        pending: PendingRequests,
+        /// Distinguishes successive runs of the same plugin (see `PluginRegistration`).
+        generation: u64,
+        /// Filled in from the `initialize` response; `legacy()` until it arrives.
+        capabilities: Arc<std::sync::RwLock<PluginCapabilities>>,
     },
     Internal {
         outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+        capabilities: PluginCapabilities,
     },
 }
 
 pub struct ChannelManager {
     channels: DashMap<ChannelKind, ChannelHandle>,
+    /// Supervisor task per external plugin; aborting it stops the plugin for good.
+    supervisors: DashMap<ChannelKind, AbortHandle>,
     session_hub: OnceCell<Arc<SessionHub>>,
+    /// JSON-RPC ids for host → plugin requests (1 is reserved for `initialize`).
+    next_request_id: AtomicU64,
+    next_plugin_generation: AtomicU64,
+    hub_tx: broadcast::Sender<HubEvent>,
+    /// Agent tokens held back for channels that cannot edit messages, flushed as `send_text`.
+    stream_buffers: DashMap<(ChannelKind, ChatId), String>,
+    /// Permission requests shown as numbered text (channels without buttons), keyed by request id.
+    text_choices: DashMap<String, TextChoice>,
+    /// Config and data directory to use instead of the global ~/.vibearound (see `with_config`).
+    config_override: Option<(Arc<Config>, PathBuf)>,
+}
+
+/// A permission request rendered as "1. Allow / 2. Deny" text. Options of requests pending in the
+/// same chat are numbered consecutively, so each number picks exactly one option.
+struct TextChoice {
+    channel_kind: ChannelKind,
+    chat_id: ChatId,
+    /// Number shown for the first option.
+    first: usize,
+    option_ids: Vec<String>,
+}
+
+impl TextChoice {
+    fn in_chat(&self, channel_kind: &str, chat_id: &str) -> bool {
+        self.channel_kind == channel_kind && self.chat_id == chat_id
+    }
+
+    fn option_for(&self, n: usize) -> Option<&String> {
+        n.checked_sub(self.first).and_then(|i| self.option_ids.get(i))
+    }
+}
+
+/// Unregisters a plugin run's ChannelHandle when the run ends or is aborted, unless a newer run
+/// of the same channel has already replaced it.
+struct PluginRegistration {
+    manager: Arc<ChannelManager>,
+    channel_name: String,
+    generation: u64,
+}
+
+impl Drop for PluginRegistration {
+    fn drop(&mut self) {
+        self.manager.channels.remove_if(&self.channel_name, |_, h| {
+            matches!(h, ChannelHandle::External { generation, .. } if *generation == self.generation)
+        });
+    }
 }
 
 impl ChannelManager {
     pub fn new() -> Self {
+        let (hub_tx, _) = broadcast::channel(64);
         Self {
             channels: DashMap::new(),
+            supervisors: DashMap::new(),
             session_hub: OnceCell::new(),
+            next_request_id: AtomicU64::new(2),
+            next_plugin_generation: AtomicU64::new(1),
+            hub_tx,
+            stream_buffers: DashMap::new(),
+            text_choices: DashMap::new(),
+            config_override: None,
+        }
+    }
+
+    /// A manager that reads `config` and stages files under `data_dir` instead of the global
+    /// config and ~/.vibearound, e.g. for tests.
+    pub fn with_config(config: Arc<Config>, data_dir: PathBuf) -> Self {
+        Self { config_override: Some((config, data_dir)), ..Self::new() }
+    }
+
+    pub(crate) fn config(&self) -> Arc<Config> {
+        match &self.config_override {
+            Some((config, _)) => Arc::clone(config),
+            None => config::ensure_loaded(),
+        }
+    }
+
+    pub(crate) fn data_dir(&self) -> PathBuf {
+        match &self.config_override {
+            Some((_, data_dir)) => data_dir.clone(),
+            None => config::data_dir(),
+        }
+    }
+
+    /// Warn at startup when a channel has no `allowed_senders`, so anyone who can reach it can
+    /// run agents.
+    fn warn_if_open(&self, channel_name: &str) {
+        if self.config().channel_access(channel_name).is_open() {
+            eprintln!(
+                "[{}] ⚠️  no allowed_senders configured — anyone who can reach this bot can run agents",
+                channel_name
+            );
         }
     }
 
@@ -110,10 +257,30 @@ impl ChannelManager {
             ChannelEvent::OnTurnCompleted {
                 channel_kind,
                 chat_id,
+                stop_reason,
+                cost_usd,
+                usage,
             } => {
                 self.send_notification(ChannelNotification::AgentEnd {
                     channel_kind,
                     chat_id,
+                    stop_reason,
+                    cost_usd,
+                    usage,
+                })
+                .await;
+            }
+            ChannelEvent::OnMessageQueued {
+                channel_kind,
+                chat_id,
+                message_id,
+                position,
+            } => {
+                self.send_notification(ChannelNotification::MessageQueued {
+                    channel_kind,
+                    chat_id,
+                    message_id,
+                    position,
                 })
                 .await;
             }
@@ -175,6 +342,7 @@ impl ChannelManager {
                             self.send_notification(ChannelNotification::AgentToolUse {
                                 channel_kind,
                                 chat_id,
+                                tool_id: str_field(&payload, "tool_id"),
                                 tool: payload
                                     .get("tool")
                                     .and_then(|v| v.as_str())
@@ -192,6 +360,8 @@ impl ChannelManager {
                             self.send_notification(ChannelNotification::AgentToolResult {
                                 channel_kind,
                                 chat_id,
+                                tool_id: str_field(&payload, "tool_id"),
+                                is_error: payload.get("is_error").and_then(|v| v.as_bool()).unwrap_or(false),
                                 tool: payload
                                     .get("tool")
                                     .and_then(|v| v.as_str())
@@ -205,6 +375,33 @@ impl ChannelManager {
                             })
                             .await;
                         }
+                        "permission_request" => {
+                            let options = payload
+                                .get("options")
+                                .and_then(|v| v.as_array())
+                                .map(|arr| {
+                                    arr.iter()
+                                        .map(|o| PermissionChoice {
+                                            id: str_field(o, "id"),
+                                            label: str_field(o, "label"),
+                                            kind: str_field(o, "kind"),
+                                        })
+                                        .collect()
+                                })
+                                .unwrap_or_default();
+                            let perms = self.config().permissions.clone();
+                            self.send_notification(ChannelNotification::PermissionRequest {
+                                channel_kind,
+                                chat_id,
+                                request_id: str_field(&payload, "request_id"),
+                                tool: str_field(&payload, "tool"),
+                                input: str_field(&payload, "input"),
+                                options,
+                                timeout_secs: perms.timeout_secs,
+                                default_allow: perms.default_allow,
+                            })
+                            .await;
+                        }
                         _ => {
                             let _ = message_id;
                         }
@@ -215,50 +412,94 @@ impl ChannelManager {
         }
     }
 
+    /// Start a channel plugin under a supervisor that restarts it with exponential backoff
+    /// whenever the process exits. Returns the supervisor's abort handle (aborting it kills the
+    /// plugin), or `None` when the channel is not configured or the plugin cannot be launched.
     pub async fn start_plugin(
         self: &Arc<Self>,
         plugin_dir: PathBuf,
         channel_name: &str,
     ) -> Option<AbortHandle> {
         let prefix = format!("[{}]", channel_name);
-        let cfg = config::ensure_loaded();
+        let cfg = self.config();
 
-        let raw_config = match cfg.channel_raw_config(channel_name) {
-            Some(v) => v,
-            None => {
-                eprintln!("{} config=missing channels.{} — plugin disabled", prefix, channel_name);
-                return None;
-            }
-        };
+        if cfg.channel_raw_config(channel_name).is_none() {
+            eprintln!("{} config=missing channels.{} — plugin disabled", prefix, channel_name);
+            return None;
+        }
+        self.warn_if_open(channel_name);
+
+        if let Err(e) = manifest::load(&plugin_dir) {
+            eprintln!("{} {}", prefix, e);
+            return None;
+        }
 
-        let entry_point = plugin_dir.join("dist").join("main.js");
-        if !entry_point.exists() {
+        let this = Arc::clone(self);
+        let name = channel_name.to_string();
+        let handle = tokio::spawn(async move { this.supervise_plugin(plugin_dir, name).await });
+        let abort = handle.abort_handle();
+        if let Some(previous) = self.supervisors.insert(channel_name.to_string(), abort.clone()) {
+            previous.abort();
+        }
+        Some(abort)
+    }
+
+    /// Run the plugin until it exits, then restart it: 1s, 2s, 4s … up to PLUGIN_RESTART_MAX_BACKOFF.
+    /// A run that stayed up for PLUGIN_STABLE_RUN resets the backoff.
+    async fn supervise_plugin(self: Arc<Self>, plugin_dir: PathBuf, channel_name: String) {
+        let prefix = format!("[{}]", channel_name);
+        let mut restarts: u32 = 0;
+        let mut backoff = PLUGIN_RESTART_MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let error = match self.run_plugin(&plugin_dir, &channel_name, restarts).await {
+                Ok(()) => "plugin process exited".to_string(),
+                Err(e) => e,
+            };
+            if started.elapsed() >= PLUGIN_STABLE_RUN {
+                backoff = PLUGIN_RESTART_MIN_BACKOFF;
+            }
             eprintln!(
-                "{} plugin entry not found: {} — run `npm run build` in {}",
+                "{} {} — restarting in {}s (restarts so far: {})",
                 prefix,
-                entry_point.display(),
-                plugin_dir.display()
+                error,
+                backoff.as_secs(),
+                restarts
             );
-            return None;
+            let _ = self.hub_tx.send(HubEvent::OnPluginFailed {
+                channel: channel_name.clone(),
+                error,
+                restarts,
+                retry_in_secs: backoff.as_secs(),
+            });
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(PLUGIN_RESTART_MAX_BACKOFF);
+            restarts += 1;
         }
+    }
 
-        eprintln!("{} spawning plugin process: node {}", prefix, entry_point.display());
+    /// Spawn the plugin once, send `initialize`, and pump its stdout until the process exits.
+    /// The channel stays registered for exactly as long as this future runs.
+    async fn run_plugin(self: &Arc<Self>, plugin_dir: &Path, channel_name: &str, restarts: u32) -> Result<(), String> {
+        let prefix = format!("[{}]", channel_name);
+        // Re-read per run so a restart picks up edited settings.
+        let raw_config = self.config()
+            .channel_raw_config(channel_name)
+            .ok_or_else(|| format!("config=missing channels.{}", channel_name))?;
+        let launch = manifest::load(plugin_dir)?;
+
+        eprintln!("{} spawning plugin process: {}", prefix, launch.display());
 
-        let mut child = match Command::new("node")
-            .arg(entry_point.to_str().unwrap())
-            .current_dir(&plugin_dir)
+        let mut child = Command::new(&launch.program)
+            .args(&launch.args)
+            .envs(&launch.env)
+            .current_dir(&launch.cwd)
             .stdin(std::process::Stdio::piped())
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
             .kill_on_drop(true)
             .spawn()
-        {
-            Ok(c) => c,
-            Err(e) => {
-                eprintln!("{} failed to spawn plugin: {}", prefix, e);
-                return None;
-            }
-        };
+            .map_err(|e| format!("failed to spawn plugin: {}", e))?;
 
         let stdin = child.stdin.take().expect("stdin piped");
         let stdout = child.stdout.take().expect("stdout piped");
@@ -279,10 +520,10 @@ impl ChannelManager {
         {
             let mut guard = stdin_writer.lock().await;
             let line = serde_json::to_string(&init_req).unwrap() + "\n";
-            if let Err(e) = guard.write_all(line.as_bytes()).await {
-                eprintln!("{} failed to write initialize: {}", prefix, e);
-                return None;
-            }
+            guard
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| format!("failed to write initialize: {}", e))?;
             let _ = guard.flush().await;
         }
 
@@ -295,88 +536,188 @@ impl ChannelManager {
             }
         });
 
-        let channel_name_owned = channel_name.to_string();
-        let prefix_stdout = prefix.clone();
-        let hub = Arc::clone(self);
-        let handle = tokio::spawn(async move {
-            let _child = child;
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
+        let generation = self.next_plugin_generation.fetch_add(1, Ordering::Relaxed);
+        let capabilities = Arc::new(std::sync::RwLock::new(PluginCapabilities::legacy()));
+        self.channels.insert(
+            channel_name.to_string(),
+            ChannelHandle::External {
+                stdin: stdin_writer,
+                pending: Arc::clone(&pending),
+                generation,
+                capabilities: Arc::clone(&capabilities),
+            },
+        );
+        let _registration = PluginRegistration {
+            manager: Arc::clone(self),
+            channel_name: channel_name.to_string(),
+            generation,
+        };
+        eprintln!("{} registered external channel", prefix);
+        let _ = self.hub_tx.send(HubEvent::OnPluginStarted {
+            channel: channel_name.to_string(),
+            restarts,
+        });
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                let line = line.trim().to_string();
-                if line.is_empty() {
+        let reader = BufReader::new(stdout);
+        let mut lines = reader.lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let line = line.trim().to_string();
+            if line.is_empty() {
+                continue;
+            }
+
+            let msg: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!(
+                        "{} invalid JSON from plugin: {} — {}",
+                        prefix,
+                        e,
+                        truncate(&line, 120)
+                    );
                     continue;
                 }
+            };
 
-                let msg: serde_json::Value = match serde_json::from_str(&line) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!(
-                            "{} invalid JSON from plugin: {} — {}",
-                            prefix_stdout,
-                            e,
-                            &line[..line.len().min(120)]
-                        );
-                        continue;
-                    }
-                };
-
-                if let Some(id) = msg.get("id") {
-                    if let Some(id_val) = id.as_u64() {
-                        if let Some((_, tx)) = pending.remove(&id_val) {
-                            if let Some(err) = msg.get("error") {
-                                let err_msg = err
+            if let Some(id) = msg.get("id") {
+                if let Some(id_val) = id.as_u64() {
+                    if let Some((_, tx)) = pending.remove(&id_val) {
+                        if let Some(err) = msg.get("error") {
+                            let _ = tx.send(Err(PluginCallError::Remote {
+                                code: err.get("code").and_then(|c| c.as_i64()).unwrap_or(0),
+                                message: err
                                     .get("message")
                                     .and_then(|m| m.as_str())
                                     .unwrap_or("unknown error")
-                                    .to_string();
-                                let _ = tx.send(Err(err_msg));
-                            } else {
-                                let result = msg.get("result").cloned().unwrap_or(serde_json::Value::Null);
-                                let _ = tx.send(Ok(result));
-                            }
+                                    .to_string(),
+                            }));
+                        } else {
+                            let result = msg.get("result").cloned().unwrap_or(serde_json::Value::Null);
+                            let _ = tx.send(Ok(result));
                         }
                     }
-                    if id.as_u64() == Some(1) {
-                        eprintln!("{} event=plugin_ready", prefix_stdout);
-                    }
-                    continue;
                 }
-
-                hub.handle_inbound_jsonrpc(&channel_name_owned, msg).await;
+                if id.as_u64() == Some(1) {
+                    let result = msg.get("result").cloned().unwrap_or(serde_json::Value::Null);
+                    let caps = PluginCapabilities::from_initialize_result(&result);
+                    eprintln!("{} event=plugin_ready capabilities={:?}", prefix, caps);
+                    *capabilities.write().unwrap() = caps;
+                }
+                continue;
             }
 
-            eprintln!("{} stdout reader exited", prefix_stdout);
-        });
+            self.handle_inbound_jsonrpc(channel_name, msg).await;
+        }
 
-        let abort = handle.abort_handle();
+        eprintln!("{} stdout reader exited", prefix);
+        // Dropping the senders fails every outstanding call_plugin with Disconnected.
+        pending.clear();
+        let status = child.wait().await.map_err(|e| format!("failed to wait for plugin: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("plugin process exited with {}", status))
+        }
+    }
 
-        self.channels.insert(
-            channel_name.to_string(),
-            ChannelHandle::External {
-                stdin: stdin_writer,
-                abort: abort.clone(),
-            },
-        );
+    /// Stop an external or built-in channel plugin: abort its supervisor (dropping the child kills
+    /// the process) and unregister it. Internal channels without a task (web) are left alone.
+    pub fn stop_plugin(&self, channel_name: &str) -> bool {
+        let Some((_, supervisor)) = self.supervisors.remove(channel_name) else {
+            return false;
+        };
+        supervisor.abort();
+        self.channels.remove(channel_name);
+        let _ = self.hub_tx.send(HubEvent::OnPluginStopped { channel: channel_name.to_string() });
+        eprintln!("[{}] plugin stopped", channel_name);
+        true
+    }
 
-        eprintln!("{} registered external channel", prefix);
-        Some(abort)
+    /// Prepare `chat_id` on `channel_kind` to resume a stored conversation
+    /// (see `SessionHub::prepare_resumed_route`).
+    pub async fn prepare_resumed_route(
+        &self,
+        channel_kind: &str,
+        chat_id: &str,
+        cli_kind: &str,
+        profile: &str,
+        workspace: PathBuf,
+    ) {
+        self.session_hub()
+            .prepare_resumed_route(channel_kind, chat_id, cli_kind, profile, workspace)
+            .await;
+    }
+
+    /// Plugin lifecycle events (started / failed / stopped) for the status board.
+    pub fn subscribe(&self) -> broadcast::Receiver<HubEvent> {
+        self.hub_tx.subscribe()
     }
 
     pub fn start_internal_plugin(
         &self,
         channel_name: &str,
         outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+    ) {
+        self.register_internal(channel_name, outbound_tx, PluginCapabilities::full());
+    }
+
+    /// Like `start_internal_plugin`, for internal transports that render less than the web UI.
+    pub fn start_internal_plugin_with_capabilities(
+        &self,
+        channel_name: &str,
+        outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+        capabilities: PluginCapabilities,
+    ) {
+        self.register_internal(channel_name, outbound_tx, capabilities);
+    }
+
+    fn register_internal(
+        &self,
+        channel_name: &str,
+        outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+        capabilities: PluginCapabilities,
     ) {
         self.channels.insert(
             channel_name.to_string(),
-            ChannelHandle::Internal { outbound_tx },
+            ChannelHandle::Internal { outbound_tx, capabilities },
         );
         eprintln!("[{}] registered internal channel", channel_name);
     }
 
-    pub async fn handle_inbound_jsonrpc(&self, channel_name: &str, msg: serde_json::Value) {
+    /// Register a built-in channel implemented in Rust (e.g. Telegram). `task` runs the transport;
+    /// `stop_plugin` aborts it like an external plugin's supervisor.
+    pub fn start_builtin_plugin(
+        &self,
+        channel_name: &str,
+        outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+        task: AbortHandle,
+    ) {
+        self.start_builtin_plugin_with_capabilities(channel_name, outbound_tx, task, PluginCapabilities::full());
+    }
+
+    /// Like `start_builtin_plugin`, for transports that cannot edit messages or show buttons
+    /// (e.g. email); notifications are adapted as they are for a plugin declaring the same.
+    pub fn start_builtin_plugin_with_capabilities(
+        &self,
+        channel_name: &str,
+        outbound_tx: mpsc::UnboundedSender<ChannelNotification>,
+        task: AbortHandle,
+        capabilities: PluginCapabilities,
+    ) {
+        self.warn_if_open(channel_name);
+        if let Some(previous) = self.supervisors.insert(channel_name.to_string(), task) {
+            previous.abort();
+        }
+        self.register_internal(channel_name, outbound_tx, capabilities);
+        let _ = self.hub_tx.send(HubEvent::OnPluginStarted {
+            channel: channel_name.to_string(),
+            restarts: 0,
+        });
+    }
+
+    /// Handle a notification from a channel. Returns false when its sender was rejected by the
+    /// allowlist, so transports can leave e.g. a permission prompt untouched.
+    pub async fn handle_inbound_jsonrpc(self: &Arc<Self>, channel_name: &str, msg: serde_json::Value) -> bool {
         let prefix = format!("[{}]", channel_name);
         let method = msg.get("method").and_then(|m| m.as_str()).unwrap_or("");
         let params = msg.get("params").cloned().unwrap_or(serde_json::Value::Null);
@@ -385,12 +726,58 @@ impl ChannelManager {
             "on_message" => {
                 if let Some(inbound) = parse_on_message(&params, channel_name) {
                     eprintln!("{} on_message text={}", prefix, truncate(&inbound.text, 80));
-                    self.session_hub().channel_request_message(inbound).await;
+                    if !self.admit_sender(&inbound).await {
+                        return false;
+                    }
+                    if self.answer_text_choice(&inbound).await {
+                        return true;
+                    }
+                    if let Some(command) = commands::parse_command(&inbound.text) {
+                        self.handle_command(command, &inbound).await;
+                    } else if inbound.attachments.is_empty() {
+                        self.session_hub().channel_request_message(inbound).await;
+                    } else {
+                        // Downloads are answered through this plugin's stdout reader, which is our caller,
+                        // so they run in a task; the queue slot is taken now to keep arrival order.
+                        self.session_hub().channel_reserve_message(inbound.clone()).await;
+                        let this = Arc::clone(self);
+                        tokio::spawn(async move {
+                            let inbound = this.stage_attachments(inbound, &params).await;
+                            this.session_hub().channel_staged_message(inbound).await;
+                        });
+                    }
                 }
             }
             "on_callback" => {
-                if let Some(inbound) = parse_on_callback(&params, channel_name) {
+                if let Some((chat_id, request_id, option_id)) =
+                    parse_permission_callback_params(&params, channel_name)
+                {
+                    // Answering a permission request approves tool use, so it goes through the allowlist too.
+                    let sender_id = sender_id_of(&params);
+                    if !self.config().channel_access(channel_name).is_allowed(&sender_id) {
+                        eprintln!(
+                            "{} rejected permission callback from sender={} chat_id={}",
+                            prefix, sender_id, chat_id
+                        );
+                        return false;
+                    }
+                    eprintln!(
+                        "{} on_callback permission request_id={} option={}",
+                        prefix, request_id, option_id
+                    );
+                    self.session_hub()
+                        .channel_request_permission_response(
+                            channel_name,
+                            &chat_id,
+                            &request_id,
+                            Some(option_id),
+                        )
+                        .await;
+                } else if let Some(inbound) = parse_on_callback(&params, channel_name) {
                     eprintln!("{} on_callback text={}", prefix, truncate(&inbound.text, 80));
+                    if !self.admit_sender(&inbound).await {
+                        return false;
+                    }
                     self.session_hub().channel_request_message(inbound).await;
                 }
             }
@@ -403,31 +790,455 @@ impl ChannelManager {
                 eprintln!("{} unknown notification: {}", prefix, other);
             }
         }
+        true
+    }
+
+    async fn handle_command(&self, command: ChatCommand, msg: &InboundMessage) {
+        let hub = self.session_hub();
+        let (channel_kind, chat_id) = (msg.channel_kind.as_str(), msg.chat_id.as_str());
+        eprintln!("[{}] command {:?} chat_id={}", channel_kind, command, chat_id);
+
+        if command.requires_admin()
+            && !self.config().channel_access(channel_kind).is_admin(&msg.sender_id)
+        {
+            eprintln!("[{}] rejected admin command from sender={}", channel_kind, msg.sender_id);
+            self.reply(msg, "Only admins can use this command.".to_string()).await;
+            return;
+        }
+
+        let reply = match command {
+            ChatCommand::Agent(Some(kind)) => {
+                hub.channel_request_switch_agent_kind(channel_kind, chat_id, &kind).await;
+                return;
+            }
+            ChatCommand::Profile(Some(profile)) => {
+                hub.channel_request_switch_profile(channel_kind, chat_id, &profile).await;
+                return;
+            }
+            ChatCommand::Cd(Some(path)) => {
+                let workspace = (path != "-").then(|| crate::workspace::resolve_project_path(&path));
+                hub.channel_request_set_workspace(channel_kind, chat_id, workspace).await;
+                return;
+            }
+            ChatCommand::Stop => {
+                hub.channel_request_stop(channel_kind, chat_id).await;
+                return;
+            }
+            ChatCommand::New => {
+                hub.channel_request_close(channel_kind, chat_id).await;
+                "Session closed. Your next message starts a new session.".to_string()
+            }
+            ChatCommand::Agent(None) => {
+                let current = hub
+                    .get_session_cli_kind(channel_kind, chat_id)
+                    .await
+                    .unwrap_or_else(|| self.config().default_agent.clone());
+                let enabled: Vec<String> = AgentKind::enabled().iter().map(|k| k.to_string()).collect();
+                format!(
+                    "Current agent: {}\nAvailable: {}\nUse /agent <name> to switch.",
+                    current,
+                    enabled.join(", ")
+                )
+            }
+            ChatCommand::Profile(None) => {
+                let current = hub
+                    .get_session_profile(channel_kind, chat_id)
+                    .await
+                    .unwrap_or_else(|| "default".to_string());
+                format!("Current profile: {}\nUse /profile <name> to switch.", current)
+            }
+            ChatCommand::Cd(None) => {
+                let binding = hub.get_session_workspace(channel_kind, chat_id).await;
+                let workspace = crate::workspace::chat_workspace(channel_kind, chat_id, binding.as_deref());
+                format!("Workspace: {}\nUse /cd <path> to change it.", workspace.display())
+            }
+            ChatCommand::Status => match hub.session_status(channel_kind, chat_id).await {
+                Some(status) => format!(
+                    "Agent: {}\nSession ID: {}\nProfile: {}\nWorkspace: {}\nBusy: {}\nQueued messages: {}",
+                    status
+                        .cli_kind
+                        .unwrap_or_else(|| self.config().default_agent.clone()),
+                    status.cli_session_id.as_deref().unwrap_or("(not started)"),
+                    status.profile,
+                    status.workspace.display(),
+                    if status.busy { "yes" } else { "no" },
+                    status.queue_len,
+                ),
+                None => "No active session. Send a message to start one.".to_string(),
+            },
+            ChatCommand::Help => commands::help_text(),
+        };
+
+        self.reply(msg, reply).await;
+    }
+
+    /// Check the sender against the channel's `allowed_senders`; rejected senders get a notice.
+    async fn admit_sender(&self, msg: &InboundMessage) -> bool {
+        let access = self.config().channel_access(&msg.channel_kind);
+        if access.is_allowed(&msg.sender_id) {
+            return true;
+        }
+        eprintln!(
+            "[{}] rejected sender={} chat_id={}",
+            msg.channel_kind, msg.sender_id, msg.chat_id
+        );
+        let id = if msg.sender_id.is_empty() { "unknown" } else { msg.sender_id.as_str() };
+        self.reply(
+            msg,
+            format!(
+                "Sorry, you are not allowed to use this bot. Ask its owner to add your id ({}) to allowed_senders.",
+                id
+            ),
+        )
+        .await;
+        false
+    }
+
+    /// Send a text reply to the chat `msg` came from, quoting it when the channel gave a message id.
+    async fn reply(&self, msg: &InboundMessage, text: String) {
+        self.send_notification(ChannelNotification::SendText {
+            channel_kind: msg.channel_kind.clone(),
+            chat_id: msg.chat_id.clone(),
+            text,
+            reply_to: Some(msg.message_id.clone()).filter(|id| !id.is_empty()),
+        })
+        .await;
+    }
+
+    /// Download each attachment to the staging directory and set its `local_path`.
+    /// Bytes come inline (`attachments[i].data`, base64) or from the plugin's `fetch_attachment`.
+    /// Attachments that cannot be downloaded, or are larger than `attachments.max_size_mb`, are
+    /// dropped with a notice to the chat.
+    async fn stage_attachments(&self, mut msg: InboundMessage, params: &serde_json::Value) -> InboundMessage {
+        use base64::Engine as _;
+
+        let max_bytes = self.config().max_attachment_bytes;
+        let too_large = |size: u64| {
+            format!("file is too large ({} MB, limit {} MB)", size.div_ceil(1 << 20), max_bytes >> 20)
+        };
+        let staging_dir = self.data_dir()
+            .join("attachments")
+            .join(sanitize_file_name(&msg.channel_kind))
+            .join(sanitize_file_name(&msg.chat_id));
+        let mut staged = Vec::new();
+
+        for (i, mut att) in std::mem::take(&mut msg.attachments).into_iter().enumerate() {
+            let declared_size = params.pointer(&format!("/attachments/{}/size", i)).and_then(|v| v.as_u64());
+            let inline = params
+                .pointer(&format!("/attachments/{}/data", i))
+                .and_then(|v| v.as_str())
+                .map(|data| serde_json::json!({ "data": data }));
+            let result = match (declared_size, inline) {
+                (Some(size), _) if size > max_bytes => Err(too_large(size)),
+                (_, Some(v)) => Ok(v),
+                (_, None) => self.fetch_attachment(&msg.channel_kind, &msg.chat_id, &att, max_bytes).await,
+            };
+            let saved = async {
+                let result = result?;
+                let data = str_field(&result, "data");
+                // Base64 is 4 characters per 3 bytes; reject before decoding.
+                let decoded_len = (data.len() as u64 / 4) * 3;
+                if decoded_len > max_bytes {
+                    return Err(too_large(decoded_len));
+                }
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| format!("invalid base64: {}", e))?;
+                if bytes.len() as u64 > max_bytes {
+                    return Err(too_large(bytes.len() as u64));
+                }
+                tokio::fs::create_dir_all(&staging_dir)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let path = staging_dir.join(format!(
+                    "{}_{}",
+                    uuid::Uuid::new_v4().simple(),
+                    sanitize_file_name(&att.file_name)
+                ));
+                tokio::fs::write(&path, bytes).await.map_err(|e| e.to_string())?;
+                let mime = result.get("mimeType").and_then(|v| v.as_str()).map(String::from);
+                Ok::<_, String>((path, mime))
+            }
+            .await;
+
+            match saved {
+                Ok((path, mime)) => {
+                    att.mime_type = att
+                        .mime_type
+                        .or(mime)
+                        .or_else(|| Some(mime_guess::from_path(&att.file_name).first_or_octet_stream().to_string()));
+                    att.local_path = Some(path);
+                    staged.push(att);
+                }
+                Err(e) => {
+                    eprintln!("[{}] failed to fetch attachment {}: {}", msg.channel_kind, att.file_name, e);
+                    self.send_notification(ChannelNotification::SendText {
+                        channel_kind: msg.channel_kind.clone(),
+                        chat_id: msg.chat_id.clone(),
+                        text: format!("Could not download attachment {}: {}", att.file_name, e),
+                        reply_to: None,
+                    })
+                    .await;
+                }
+            }
+        }
+
+        msg.attachments = staged;
+        msg
+    }
+
+    /// Ask the external plugin for an attachment's bytes. Result: `{ data: <base64>, mimeType? }`.
+    /// `maxBytes` lets the plugin refuse oversized files before downloading them.
+    async fn fetch_attachment(
+        &self,
+        channel_kind: &str,
+        chat_id: &str,
+        att: &Attachment,
+        max_bytes: u64,
+    ) -> Result<serde_json::Value, String> {
+        self.call_plugin_with_timeout(
+            channel_kind,
+            "fetch_attachment",
+            serde_json::json!({
+                "channelId": ChannelNotification::plugin_channel_id(channel_kind, chat_id),
+                "messageId": att.message_id,
+                "fileKey": att.file_key,
+                "fileName": att.file_name,
+                "type": att.resource_type,
+                "maxBytes": max_bytes,
+            }),
+            FETCH_ATTACHMENT_TIMEOUT,
+        )
+        .await
+        .map_err(|e| match e {
+            PluginCallError::Unavailable => "this channel cannot fetch attachments".to_string(),
+            e => e.to_string(),
+        })
+    }
+
+    /// Send a JSON-RPC request to an external channel plugin and wait for its response
+    /// (`PLUGIN_CALL_TIMEOUT`). Use this instead of `send_notification` when the caller needs
+    /// to know whether the plugin actually did the thing.
+    pub async fn call_plugin(
+        &self,
+        channel_kind: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, PluginCallError> {
+        self.call_plugin_with_timeout(channel_kind, method, params, PLUGIN_CALL_TIMEOUT)
+            .await
+    }
+
+    pub async fn call_plugin_with_timeout(
+        &self,
+        channel_kind: &str,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, PluginCallError> {
+        let (stdin, pending) = match self.channels.get(channel_kind).as_deref() {
+            Some(ChannelHandle::External { stdin, pending, .. }) => (Arc::clone(stdin), Arc::clone(pending)),
+            _ => return Err(PluginCallError::Unavailable),
+        };
+
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        pending.insert(id, tx);
+
+        let req = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        {
+            let line = serde_json::to_string(&req).unwrap() + "\n";
+            let mut guard = stdin.lock().await;
+            if let Err(e) = guard.write_all(line.as_bytes()).await {
+                pending.remove(&id);
+                return Err(PluginCallError::Write(e.to_string()));
+            }
+            let _ = guard.flush().await;
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(PluginCallError::Disconnected),
+            Err(_) => {
+                pending.remove(&id);
+                eprintln!("[{}] {} (id {}) timed out after {}s", channel_kind, method, id, timeout.as_secs());
+                Err(PluginCallError::TimedOut(timeout))
+            }
+        }
+    }
+
+    /// What the channel can render (see `capabilities`).
+    pub fn capabilities(&self, channel_kind: &str) -> PluginCapabilities {
+        match self.channels.get(channel_kind).as_deref() {
+            Some(ChannelHandle::External { capabilities, .. }) => capabilities.read().unwrap().clone(),
+            Some(ChannelHandle::Internal { capabilities, .. }) => capabilities.clone(),
+            None => PluginCapabilities::legacy(),
+        }
     }
 
     pub async fn send_notification(&self, notif: ChannelNotification) {
-        let channel_kind = channel_kind_of_notification(&notif).to_string();
+        let caps = self.capabilities(channel_kind_of_notification(&notif));
+        for notif in self.adapt_notification(&caps, notif) {
+            self.dispatch_notification(notif).await;
+        }
+    }
+
+    /// Rewrite a notification into what the channel can render: buffer tokens when messages
+    /// cannot be edited, turn permission buttons into numbered text, split over-long text.
+    fn adapt_notification(&self, caps: &PluginCapabilities, notif: ChannelNotification) -> Vec<ChannelNotification> {
+        let mut out = Vec::new();
+        let key = (
+            channel_kind_of_notification(&notif).to_string(),
+            chat_id_of_notification(&notif).to_string(),
+        );
 
-        if let Some(handle) = self.channels.get(&channel_kind) {
-            match handle.value() {
-                ChannelHandle::External { stdin, abort } => {
-                    let _keep_alive = abort;
-                    let json = notif.to_jsonrpc();
-                    let line = serde_json::to_string(&json).unwrap() + "\n";
-                    let mut guard = stdin.lock().await;
-                    if let Err(e) = guard.write_all(line.as_bytes()).await {
-                        eprintln!("[{}] failed to write to channel stdin: {}", channel_kind, e);
+        if !caps.edit_message {
+            if let ChannelNotification::AgentToken { delta, .. } = &notif {
+                self.stream_buffers.entry(key).or_default().push_str(delta);
+                return out;
+            }
+            if !matches!(
+                notif,
+                ChannelNotification::AgentThinking { .. } | ChannelNotification::MessageQueued { .. }
+            ) {
+                if let Some((_, text)) = self.stream_buffers.remove(&key) {
+                    if !text.trim().is_empty() {
+                        out.push(ChannelNotification::SendText {
+                            channel_kind: key.0.clone(),
+                            chat_id: key.1.clone(),
+                            text,
+                            reply_to: None,
+                        });
                     }
-                    let _ = guard.flush().await;
                 }
-                ChannelHandle::Internal { outbound_tx } => {
-                    if let Err(e) = outbound_tx.send(notif) {
-                        eprintln!("[{}] failed to send to internal channel: {}", channel_kind, e);
-                    }
+            }
+        }
+
+        let notif = match notif {
+            ChannelNotification::PermissionRequest {
+                channel_kind,
+                chat_id,
+                request_id,
+                tool,
+                input,
+                options,
+                timeout_secs,
+                default_allow,
+            } if !caps.buttons => {
+                let first = self
+                    .text_choices
+                    .iter()
+                    .filter(|c| c.in_chat(&key.0, &key.1))
+                    .map(|c| c.first + c.option_ids.len())
+                    .max()
+                    .unwrap_or(1);
+                let mut text = format!("🔐 {} wants permission:\n{}\n", tool, input);
+                for (i, option) in options.iter().enumerate() {
+                    text.push_str(&format!("\n{}. {}", first + i, option.label));
                 }
+                text.push_str(&format!(
+                    "\n\nReply with a number. No answer in {}s means {}.",
+                    timeout_secs,
+                    if default_allow { "allow" } else { "deny" }
+                ));
+                self.text_choices.insert(
+                    request_id,
+                    TextChoice {
+                        channel_kind: key.0.clone(),
+                        chat_id: key.1.clone(),
+                        first,
+                        option_ids: options.into_iter().map(|o| o.id).collect(),
+                    },
+                );
+                ChannelNotification::SendText { channel_kind, chat_id, text, reply_to: None }
+            }
+            ChannelNotification::AgentEnd { .. } | ChannelNotification::AgentError { .. } => {
+                self.text_choices.retain(|_, c| !c.in_chat(&key.0, &key.1));
+                notif
+            }
+            other => other,
+        };
+        out.push(notif);
+
+        out.into_iter()
+            .flat_map(|notif| match notif {
+                ChannelNotification::SendText { channel_kind, chat_id, text, reply_to } => caps
+                    .split_text(&text)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, text)| ChannelNotification::SendText {
+                        channel_kind: channel_kind.clone(),
+                        chat_id: chat_id.clone(),
+                        text,
+                        reply_to: reply_to.clone().filter(|_| i == 0),
+                    })
+                    .collect::<Vec<_>>(),
+                other => vec![other],
+            })
+            .collect()
+    }
+
+    /// A bare number answering a permission request that was shown as numbered text. Numbers that
+    /// match no pending option are left for the agent.
+    async fn answer_text_choice(&self, msg: &InboundMessage) -> bool {
+        let Ok(n) = msg.text.trim().parse::<usize>() else {
+            return false;
+        };
+        let Some(request_id) = self
+            .text_choices
+            .iter()
+            .find(|c| c.in_chat(&msg.channel_kind, &msg.chat_id) && c.option_for(n).is_some())
+            .map(|c| c.key().clone())
+        else {
+            return false;
+        };
+        let Some((request_id, choice)) = self.text_choices.remove(&request_id) else {
+            return false;
+        };
+        let Some(option_id) = choice.option_for(n).cloned() else {
+            return false;
+        };
+        eprintln!(
+            "[{}] text choice permission request_id={} option={}",
+            msg.channel_kind, request_id, n
+        );
+        self.session_hub()
+            .channel_request_permission_response(&msg.channel_kind, &msg.chat_id, &request_id, Some(option_id))
+            .await;
+        true
+    }
+
+    async fn dispatch_notification(&self, notif: ChannelNotification) {
+        let channel_kind = channel_kind_of_notification(&notif).to_string();
+
+        let target = match self.channels.get(&channel_kind).as_deref() {
+            Some(ChannelHandle::External { stdin, .. }) => Ok(Arc::clone(stdin)),
+            Some(ChannelHandle::Internal { outbound_tx, .. }) => Err(Some(outbound_tx.clone())),
+            None => Err(None),
+        };
+        match target {
+            Ok(stdin) => {
+                let json = notif.to_jsonrpc();
+                let line = serde_json::to_string(&json).unwrap() + "\n";
+                let mut guard = stdin.lock().await;
+                if let Err(e) = guard.write_all(line.as_bytes()).await {
+                    eprintln!("[{}] failed to write to channel stdin: {}", channel_kind, e);
+                }
+                let _ = guard.flush().await;
+            }
+            Err(Some(outbound_tx)) => {
+                if let Err(e) = outbound_tx.send(notif) {
+                    eprintln!("[{}] failed to send to internal channel: {}", channel_kind, e);
+                }
+            }
+            Err(None) => {
+                eprintln!("[ChannelManager] no channel for kind '{}'", channel_kind);
             }
-        } else {
-            eprintln!("[ChannelManager] no channel for kind '{}'", channel_kind);
         }
     }
 }
@@ -442,6 +1253,36 @@ fn channel_kind_of_notification(notif: &ChannelNotification) -> &str {
         ChannelNotification::AgentEnd { channel_kind, .. } => channel_kind,
         ChannelNotification::AgentError { channel_kind, .. } => channel_kind,
         ChannelNotification::SendText { channel_kind, .. } => channel_kind,
+        ChannelNotification::PermissionRequest { channel_kind, .. } => channel_kind,
+        ChannelNotification::MessageQueued { channel_kind, .. } => channel_kind,
+    }
+}
+
+fn str_field(v: &serde_json::Value, key: &str) -> String {
+    v.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string()
+}
+
+/// Recognize an `on_callback` that answers a permission request; returns (chat_id, request_id, option_id).
+fn parse_permission_callback_params(
+    params: &serde_json::Value,
+    channel_name: &str,
+) -> Option<(String, String, String)> {
+    let raw_channel_id = params.get("channelId")?.as_str()?;
+    let value = params.get("data")?.get("value")?.as_str()?;
+    let (request_id, option_id) = parse_permission_callback(value)?;
+    let chat_id = raw_channel_id
+        .strip_prefix(&format!("{}:", channel_name))
+        .unwrap_or(raw_channel_id)
+        .to_string();
+    Some((chat_id, request_id, option_id))
+}
+
+/// `sender.id` from on_message / on_callback params; numeric ids are stringified.
+fn sender_id_of(params: &serde_json::Value) -> String {
+    match params.get("sender").and_then(|s| s.get("id")) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Number(n)) => n.to_string(),
+        _ => String::new(),
     }
 }
 
@@ -449,15 +1290,34 @@ fn parse_on_message(params: &serde_json::Value, channel_name: &str) -> Option<In
     let raw_channel_id = params.get("channelId")?.as_str()?.to_string();
     let text = params.get("text").and_then(|v| v.as_str()).unwrap_or("").to_string();
     let message_id = params.get("messageId").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    let sender_id = params
-        .get("sender")
-        .and_then(|s| s.get("id"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
+    let sender_id = sender_id_of(params);
     let reply_to = params.get("replyTo").and_then(|v| v.as_str()).map(|s| s.to_string());
+    let attachments: Vec<Attachment> = params
+        .get("attachments")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .map(|a| {
+                    let file_key = str_field(a, "fileKey");
+                    let file_name = a
+                        .get("fileName")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or(&file_key)
+                        .to_string();
+                    Attachment {
+                        message_id: message_id.clone(),
+                        file_key,
+                        file_name,
+                        resource_type: a.get("type").and_then(|v| v.as_str()).unwrap_or("file").to_string(),
+                        mime_type: a.get("mimeType").and_then(|v| v.as_str()).map(String::from),
+                        local_path: None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
 
-    if text.is_empty() {
+    if text.is_empty() && attachments.is_empty() {
         return None;
     }
 
@@ -472,19 +1332,14 @@ fn parse_on_message(params: &serde_json::Value, channel_name: &str) -> Option<In
         message_id,
         text,
         sender_id,
-        attachments: vec![],
+        attachments,
         parent_id: reply_to,
     })
 }
 
 fn parse_on_callback(params: &serde_json::Value, channel_name: &str) -> Option<InboundMessage> {
     let raw_channel_id = params.get("channelId")?.as_str()?.to_string();
-    let sender_id = params
-        .get("sender")
-        .and_then(|s| s.get("id"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
-        .to_string();
+    let sender_id = sender_id_of(params);
     let action_value = params
         .get("data")
         .and_then(|d| d.get("value"))
@@ -508,6 +1363,70 @@ fn parse_on_callback(params: &serde_json::Value, channel_name: &str) -> Option<I
     })
 }
 
-fn truncate(s: &str, max: usize) -> &str {
-    if s.len() <= max { s } else { &s[..max] }
+
+/// Keep a platform-supplied name safe to use as a single path component.
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() { "file".to_string() } else { cleaned.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::PermissionChoice;
+
+    fn permission_request(chat_id: &str, request_id: &str) -> ChannelNotification {
+        ChannelNotification::PermissionRequest {
+            channel_kind: "sms".into(),
+            chat_id: chat_id.into(),
+            request_id: request_id.into(),
+            tool: "Bash".into(),
+            input: "ls".into(),
+            options: ["allow", "deny"]
+                .map(|id| PermissionChoice { id: id.into(), label: id.into(), kind: String::new() })
+                .into(),
+            timeout_secs: 60,
+            default_allow: false,
+        }
+    }
+
+    fn text_of(notifs: Vec<ChannelNotification>) -> String {
+        match notifs.as_slice() {
+            [ChannelNotification::SendText { text, .. }] => text.clone(),
+            other => panic!("expected one send_text, got {:?}", other.len()),
+        }
+    }
+
+    #[test]
+    fn pending_text_choices_are_numbered_uniquely_per_chat() {
+        let manager = ChannelManager::new();
+        let caps = PluginCapabilities { buttons: false, ..PluginCapabilities::full() };
+
+        let first = text_of(manager.adapt_notification(&caps, permission_request("a", "r1")));
+        let second = text_of(manager.adapt_notification(&caps, permission_request("a", "r2")));
+        let other_chat = text_of(manager.adapt_notification(&caps, permission_request("b", "r3")));
+        assert!(first.contains("\n1. allow\n2. deny"), "{first}");
+        assert!(second.contains("\n3. allow\n4. deny"), "{second}");
+        assert!(other_chat.contains("\n1. allow\n2. deny"), "{other_chat}");
+
+        // Both requests stay pending, each under its own id.
+        let r1 = manager.text_choices.get("r1").unwrap();
+        let r2 = manager.text_choices.get("r2").unwrap();
+        assert_eq!(r1.option_for(2).map(String::as_str), Some("deny"));
+        assert_eq!(r1.option_for(3), None);
+        assert_eq!(r2.option_for(3).map(String::as_str), Some("allow"));
+        assert_eq!(r2.option_for(1), None);
+        drop((r1, r2));
+
+        manager.adapt_notification(
+            &caps,
+            ChannelNotification::AgentError { channel_kind: "sms".into(), chat_id: "a".into(), error: "x".into() },
+        );
+        assert!(manager.text_choices.get("r1").is_none() && manager.text_choices.get("r2").is_none());
+        assert!(manager.text_choices.get("r3").is_some());
+    }
 }
@@ -1,11 +1,14 @@
-//! Global config singleton. Load settings.json once; desktop and server both call
-//! `ensure_loaded()` so the first caller does the work, later callers get the same instance.
+//! Global config singleton. Desktop and server both call `ensure_loaded()`; the first caller
+//! loads settings.json, later callers get a snapshot of the current config.
+//! `reload()` re-reads the file and swaps the snapshot atomically; callers holding an older
+//! `Arc<Config>` keep a consistent view until they call `ensure_loaded()` again.
 //! All config comes from ~/.vibearound/settings.json.
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use std::sync::Once;
-use std::sync::OnceLock;
+use std::sync::{Arc, Once, OnceLock, RwLock};
 
+use crate::settings::{self, ChannelSettings, Settings};
 use crate::tunnels::TunnelProvider;
 
 /// Default server port for both standalone server and desktop-spawned server.
@@ -13,15 +16,31 @@ pub const DEFAULT_PORT: u16 = 12358;
 
 /// Minimal default settings.json content, embedded at compile time.
 const DEFAULT_SETTINGS_JSON: &str = r#"{
+  "version": 1,
   "working_dir": ""
 }"#;
 
-/// Data directory: ~/.vibearound
-pub fn data_dir() -> PathBuf {
+/// The user's home directory ($HOME, else %USERPROFILE%).
+fn home_dir() -> PathBuf {
     let home = std::env::var("HOME")
         .or_else(|_| std::env::var("USERPROFILE"))
         .unwrap_or_else(|_| "/tmp".into());
-    PathBuf::from(home).join(".vibearound")
+    PathBuf::from(home)
+}
+
+/// Data directory: ~/.vibearound
+pub fn data_dir() -> PathBuf {
+    home_dir().join(".vibearound")
+}
+
+/// Expand a leading `~` (alone or before a separator) to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            home_dir().join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
 }
 
 /// Ensure ~/.vibearound/ exists with settings.json and workspaces/.
@@ -55,7 +74,7 @@ fn ensure_rustls_provider() {
     });
 }
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
 
 /// Per-channel verbose/output settings for IM.
 #[derive(Debug, Clone)]
@@ -70,6 +89,114 @@ impl Default for ImVerboseConfig {
     }
 }
 
+/// Who may talk to a channel (settings.json `channels.<name>.allowed_senders` / `admins`).
+/// With both lists empty the channel is open to everyone, and everyone counts as an admin.
+/// Email addresses (`local@domain`) match case-insensitively; platform ids match exactly.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelAccess {
+    /// Sender ids allowed to message the bot; "*" allows anyone.
+    pub allowed_senders: Vec<String>,
+    /// Sender ids allowed to run admin commands. Admins are always allowed to message.
+    pub admins: Vec<String>,
+}
+
+impl ChannelAccess {
+    pub fn is_open(&self) -> bool {
+        self.allowed_senders.is_empty() && self.admins.is_empty()
+    }
+
+    pub fn is_allowed(&self, sender_id: &str) -> bool {
+        self.is_open()
+            || self.allowed_senders.iter().any(|s| s == "*")
+            || self.is_admin(sender_id)
+            || self.allowed_senders.iter().any(|s| same_sender(s, sender_id))
+    }
+
+    /// With no `admins` configured, every allowed sender is an admin.
+    pub fn is_admin(&self, sender_id: &str) -> bool {
+        if self.admins.is_empty() {
+            return self.allowed_senders.is_empty()
+                || self.allowed_senders.iter().any(|s| s == "*" || same_sender(s, sender_id));
+        }
+        self.admins.iter().any(|s| same_sender(s, sender_id))
+    }
+}
+
+/// An allowlist entry matching a sender id. Ids shaped like email addresses compare ignoring
+/// ASCII case (mail servers and clients disagree on it); Matrix ids (`@user:server`) and other
+/// platform ids must match exactly.
+fn same_sender(entry: &str, sender_id: &str) -> bool {
+    let is_email = |id: &str| id.find('@').is_some_and(|at| at > 0 && id[at + 1..].contains('.'));
+    !sender_id.is_empty()
+        && (entry == sender_id || (is_email(entry) && is_email(sender_id) && entry.eq_ignore_ascii_case(sender_id)))
+}
+
+/// How agent tool-permission requests are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PermissionMode {
+    /// Approve every request automatically (agents run unattended).
+    #[default]
+    Auto,
+    /// Relay each request to the chat that owns the turn and wait for a choice.
+    Ask,
+}
+
+impl PermissionMode {
+    pub fn from_config(s: &str) -> Self {
+        Self::from_name(s).unwrap_or_default()
+    }
+
+    /// Strict parse; `None` for unknown modes.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Some(PermissionMode::Auto),
+            "ask" | "interactive" | "prompt" => Some(PermissionMode::Ask),
+            _ => None,
+        }
+    }
+}
+
+/// Permission relay settings (settings.json `permissions`).
+#[derive(Debug, Clone)]
+pub struct PermissionConfig {
+    pub mode: PermissionMode,
+    /// How long to wait for the user's choice before applying the default.
+    pub timeout_secs: u64,
+    /// Decision applied on timeout: allow (true) or deny (false).
+    pub default_allow: bool,
+}
+
+impl Default for PermissionConfig {
+    fn default() -> Self {
+        Self { mode: PermissionMode::Auto, timeout_secs: 300, default_allow: false }
+    }
+}
+
+/// Maps chats to a project directory (settings.json `workspaces.rules[]`).
+/// `channel` / `chat_id` left out (or "*") match any.
+#[derive(Debug, Clone)]
+pub struct WorkspaceRule {
+    pub channel: Option<String>,
+    pub chat_id: Option<String>,
+    pub path: PathBuf,
+}
+
+impl WorkspaceRule {
+    fn matches(&self, channel_kind: &str, chat_id: &str) -> bool {
+        self.channel.as_deref().is_none_or(|c| c == channel_kind)
+            && self.chat_id.as_deref().is_none_or(|c| c == chat_id)
+    }
+}
+
+/// Web dashboard login settings (settings.json `auth`).
+#[derive(Debug, Clone, Default)]
+pub struct WebAuthConfig {
+    /// Access token. When unset, one is generated and kept in ~/.vibearound/dashboard_token.
+    pub token: Option<String>,
+    /// Optional password the login page accepts in addition to the token.
+    pub password: Option<String>,
+}
+
 /// Cached config from settings.json.
 pub struct Config {
     // --- Tunnel ---
@@ -82,10 +209,19 @@ pub struct Config {
     pub working_dir: PathBuf,
     pub preview_base_url: Option<String>,
     pub tmux_detach_others: bool,
+    /// Chat → project directory rules; the first match wins.
+    pub workspace_rules: Vec<WorkspaceRule>,
+    // --- Web dashboard ---
+    pub web_auth: WebAuthConfig,
     // --- Agents ---
     pub default_agent: String,
     pub enabled_agents: Vec<crate::agent::AgentKind>,
-    // --- Raw channels JSON (for dynamic plugin config) ---
+    // --- Permissions ---
+    pub permissions: PermissionConfig,
+    /// Largest attachment accepted from a chat, in bytes (settings.json `attachments.max_size_mb`).
+    pub max_attachment_bytes: u64,
+    // --- Channels: host-side settings, plus raw JSON passed to plugins ---
+    channels: BTreeMap<String, ChannelSettings>,
     raw_channels: serde_json::Value,
 }
 
@@ -106,116 +242,153 @@ impl Config {
 
     /// Get verbose config for a specific channel.
     pub fn channel_verbose(&self, name: &str) -> ImVerboseConfig {
-        parse_verbose_config(self.raw_channels.get(name))
+        self.channels
+            .get(name)
+            .map(|c| ImVerboseConfig {
+                show_thinking: c.verbose.show_thinking,
+                show_tool_use: c.verbose.show_tool_use,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get sender allowlist and admins for a specific channel.
+    pub fn channel_access(&self, name: &str) -> ChannelAccess {
+        let ids = |ids: &[settings::IdValue]| -> Vec<String> {
+            ids.iter().map(|id| id.as_string()).filter(|s| !s.is_empty()).collect()
+        };
+        self.channels
+            .get(name)
+            .map(|c| ChannelAccess { allowed_senders: ids(&c.allowed_senders), admins: ids(&c.admins) })
+            .unwrap_or_default()
+    }
+
+    /// Project directory configured for this chat, if any rule matches.
+    pub fn workspace_rule_for(&self, channel_kind: &str, chat_id: &str) -> Option<PathBuf> {
+        self.workspace_rules
+            .iter()
+            .find(|r| r.matches(channel_kind, chat_id))
+            .map(|r| r.path.clone())
     }
 }
 
-/// Ensure config is loaded (idempotent).
-pub fn ensure_loaded() -> &'static Config {
+/// Ensure config is loaded (idempotent) and return the current snapshot.
+pub fn ensure_loaded() -> Arc<Config> {
     ensure_rustls_provider();
-    CONFIG.get_or_init(|| {
+    let lock = CONFIG.get_or_init(|| {
         init_data_dir();
-        let path = data_dir().join("settings.json");
-        load_settings_from(&path)
-    })
+        let config = read_settings(&settings_path()).unwrap_or_else(|e| {
+            eprintln!("[VibeAround] {} — using defaults", e);
+            Config::default()
+        });
+        RwLock::new(Arc::new(config))
+    });
+    Arc::clone(&lock.read().unwrap_or_else(|e| e.into_inner()))
 }
 
-fn load_settings_from(path: &std::path::Path) -> Config {
-    let Ok(data) = std::fs::read_to_string(path) else {
-        return Config::default();
-    };
-    let Ok(root) = serde_json::from_str::<serde_json::Value>(&data) else {
-        return Config::default();
-    };
+/// Path of settings.json (~/.vibearound/settings.json).
+pub fn settings_path() -> PathBuf {
+    data_dir().join("settings.json")
+}
 
-    let tunnel_provider = root
-        .get("tunnel")
-        .and_then(|t| t.get("provider"))
-        .and_then(|p| p.as_str())
-        .map(TunnelProvider::from_config)
-        .unwrap_or_default();
-
-    let tunnel_ngrok = root.get("tunnel").and_then(|t| t.get("ngrok"));
-    let ngrok_auth_token = tunnel_ngrok
-        .and_then(|n| n.get("auth_token"))
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .filter(|s| !s.is_empty());
-    let ngrok_domain = tunnel_ngrok
-        .and_then(|n| n.get("domain"))
-        .and_then(|v| v.as_str())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-
-    let tunnel_cloudflare = root.get("tunnel").and_then(|t| t.get("cloudflare"));
-    let cloudflare_tunnel_token = tunnel_cloudflare
-        .and_then(|c| c.get("tunnel_token"))
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string())
-        .filter(|s| !s.is_empty());
-    let cloudflare_hostname = tunnel_cloudflare
-        .and_then(|c| c.get("hostname"))
-        .and_then(|v| v.as_str())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
-
-    let raw_channels = root
-        .get("channels")
-        .cloned()
-        .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
-
-    let working_dir = root
-        .get("working_dir")
-        .and_then(|v| v.as_str())
-        .map(|s| PathBuf::from(s.trim()))
-        .filter(|p| !p.as_os_str().is_empty())
-        .unwrap_or_else(default_working_dir);
-
-    let preview_base_url = root
-        .get("preview_base_url")
-        .or_else(|| root.get("tunnel").and_then(|t| t.get("preview_base_url")))
-        .and_then(|v| v.as_str())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty());
+/// Old and new config after a `reload()`.
+pub struct ConfigChange {
+    pub old: Arc<Config>,
+    pub new: Arc<Config>,
+}
 
-    let tmux_detach_others = root
-        .get("tmux")
-        .and_then(|t| t.get("detach_others"))
-        .and_then(|v| v.as_bool())
-        .unwrap_or(true);
+impl ConfigChange {
+    /// Channels present only in the new config.
+    pub fn channels_added(&self) -> Vec<String> {
+        let old = self.old.channel_names();
+        self.new.channel_names().into_iter().filter(|n| !old.contains(n)).collect()
+    }
 
-    let default_agent = root
-        .get("default_agent")
-        .and_then(|v| v.as_str())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .unwrap_or_else(|| "claude".to_string());
-
-    let enabled_agents = root
-        .get("enabled_agents")
-        .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.as_str())
-                .filter_map(crate::agent::AgentKind::from_str_loose)
-                .collect::<Vec<_>>()
-        })
-        .filter(|v| !v.is_empty())
-        .unwrap_or_else(|| crate::agent::AgentKind::all().to_vec());
-
-    Config {
-        tunnel_provider,
-        ngrok_auth_token,
-        ngrok_domain,
-        cloudflare_tunnel_token,
-        cloudflare_hostname,
-        working_dir,
-        preview_base_url,
-        tmux_detach_others,
-        default_agent,
-        enabled_agents,
-        raw_channels,
+    /// Channels present only in the old config.
+    pub fn channels_removed(&self) -> Vec<String> {
+        let new = self.new.channel_names();
+        self.old.channel_names().into_iter().filter(|n| !new.contains(n)).collect()
     }
+
+    /// Channels in both configs whose plugin config differs. Host-side keys (allowlists, verbose)
+    /// are read live and do not need a plugin restart.
+    pub fn channels_changed(&self) -> Vec<String> {
+        let plugin_view = |cfg: &Config, name: &str| {
+            cfg.channel_raw_config(name).map(|mut v| {
+                if let Some(obj) = v.as_object_mut() {
+                    for key in HOST_CHANNEL_KEYS {
+                        obj.remove(*key);
+                    }
+                }
+                v
+            })
+        };
+        self.new
+            .channel_names()
+            .into_iter()
+            .filter(|n| self.old.raw_channels.get(n).is_some())
+            .filter(|n| plugin_view(&self.old, n) != plugin_view(&self.new, n))
+            .collect()
+    }
+
+    /// Tunnel provider or credentials differ.
+    pub fn tunnel_changed(&self) -> bool {
+        let (a, b) = (&self.old, &self.new);
+        a.tunnel_provider != b.tunnel_provider
+            || a.ngrok_auth_token != b.ngrok_auth_token
+            || a.ngrok_domain != b.ngrok_domain
+            || a.cloudflare_tunnel_token != b.cloudflare_tunnel_token
+            || a.cloudflare_hostname != b.cloudflare_hostname
+    }
+
+    /// Settings that are only read at startup and need a restart to apply.
+    pub fn restart_required(&self) -> Vec<&'static str> {
+        let (a, b) = (&self.old, &self.new);
+        let mut keys = Vec::new();
+        if a.working_dir != b.working_dir {
+            keys.push("working_dir");
+        }
+        if a.web_auth.token != b.web_auth.token || a.web_auth.password != b.web_auth.password {
+            keys.push("auth");
+        }
+        keys
+    }
+}
+
+/// Channel config keys consumed by the host, not the plugin.
+const HOST_CHANNEL_KEYS: &[&str] = &["allowed_senders", "admins", "verbose"];
+
+/// Re-read settings.json and swap it in. On a read or parse error the current config stays.
+pub fn reload() -> Result<ConfigChange, String> {
+    let old = ensure_loaded();
+    let new = Arc::new(read_settings(&settings_path())?);
+    let lock = CONFIG.get().expect("config loaded above");
+    *lock.write().unwrap_or_else(|e| e.into_inner()) = Arc::clone(&new);
+    eprintln!("[VibeAround] settings.json reloaded");
+    Ok(ConfigChange { old, new })
+}
+
+fn read_settings(path: &std::path::Path) -> Result<Config, String> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(d) => d,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+    let parsed = match settings::parse(&data) {
+        Ok(parsed) => parsed,
+        Err(issues) => {
+            for issue in &issues {
+                eprintln!("[VibeAround][config] {}", issue);
+            }
+            return Err(format!(
+                "{:?} could not be loaded (run `vibearound-server check-config` for details)",
+                path
+            ));
+        }
+    };
+    for issue in &parsed.issues {
+        eprintln!("[VibeAround][config] {}", issue);
+    }
+    Ok(Config::from_settings(parsed.settings, &parsed.root))
 }
 
 /// Base URL for preview links.
@@ -229,21 +402,156 @@ pub fn preview_base_url() -> Option<String> {
         .or_else(|| cfg.ngrok_domain.as_ref().map(|d| format!("https://{}", d.trim())))
 }
 
-/// Parse verbose config from a channel JSON object.
-fn parse_verbose_config(channel_obj: Option<&serde_json::Value>) -> ImVerboseConfig {
-    let verbose = channel_obj.and_then(|c| c.get("verbose"));
-    ImVerboseConfig {
-        show_thinking: verbose
-            .and_then(|v| v.get("show_thinking"))
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false),
-        show_tool_use: verbose
-            .and_then(|v| v.get("show_tool_use"))
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false),
+/// Access token for the web dashboard: `auth.token` from settings.json, or a generated one stored in
+/// ~/.vibearound/dashboard_token so restarts and the desktop app see the same value.
+pub fn dashboard_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        if let Some(token) = ensure_loaded().web_auth.token.clone() {
+            return token;
+        }
+        let path = data_dir().join("dashboard_token");
+        if let Some(token) = std::fs::read_to_string(&path)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+        {
+            return token;
+        }
+        let token = random_token();
+        if let Err(e) = write_private_file(&path, token.as_bytes()) {
+            eprintln!("[VibeAround] Failed to write {:?}: {}", path, e);
+        }
+        token
+    })
+}
+
+/// Token baked into agents' MCP configs. Only accepted on /mcp; regenerated on every start.
+pub fn mcp_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(random_token)
+}
+
+/// Random 64-char hex string (two v4 UUIDs, ~244 bits of entropy).
+pub fn random_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// Write a secret file readable only by the current user.
+pub fn write_private_file(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        // `mode` only applies on creation; tighten files left by older versions too.
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(contents)
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, contents)
     }
 }
 
+impl Config {
+    /// Build the runtime config from typed settings. Values that failed validation fall back to defaults.
+    pub(crate) fn from_settings(s: Settings, root: &serde_json::Value) -> Config {
+        let defaults = Config::default();
+        let non_empty = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let working_dir = non_empty(s.working_dir).map(PathBuf::from).unwrap_or_else(default_working_dir);
+        let workspace_rules = s
+            .workspaces
+            .rules
+            .into_iter()
+            .filter(|r| !r.path.trim().is_empty())
+            .map(|r| {
+                let selector = |v: Option<String>| non_empty(v).filter(|s| s != "*");
+                WorkspaceRule {
+                    channel: selector(r.channel),
+                    chat_id: selector(r.chat_id.map(|id| id.as_string())),
+                    path: resolve_rule_path(r.path.trim(), &working_dir),
+                }
+            })
+            .collect();
+
+        let enabled_agents = s
+            .enabled_agents
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|n| crate::agent::AgentKind::from_str_loose(n))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|v| !v.is_empty())
+            .unwrap_or(defaults.enabled_agents);
+
+        let permissions = PermissionConfig {
+            mode: s
+                .permissions
+                .mode
+                .as_deref()
+                .and_then(PermissionMode::from_name)
+                .unwrap_or(defaults.permissions.mode),
+            timeout_secs: s
+                .permissions
+                .timeout_secs
+                .filter(|n| *n > 0)
+                .unwrap_or(defaults.permissions.timeout_secs),
+            default_allow: s
+                .permissions
+                .default
+                .map(|d| d.trim().eq_ignore_ascii_case("allow"))
+                .unwrap_or(defaults.permissions.default_allow),
+        };
+
+        Config {
+            tunnel_provider: s
+                .tunnel
+                .provider
+                .as_deref()
+                .map(TunnelProvider::from_config)
+                .unwrap_or_default(),
+            ngrok_auth_token: non_empty(s.tunnel.ngrok.auth_token),
+            ngrok_domain: non_empty(s.tunnel.ngrok.domain),
+            cloudflare_tunnel_token: non_empty(s.tunnel.cloudflare.tunnel_token),
+            cloudflare_hostname: non_empty(s.tunnel.cloudflare.hostname),
+            preview_base_url: non_empty(s.preview_base_url),
+            tmux_detach_others: s.tmux.detach_others.unwrap_or(defaults.tmux_detach_others),
+            workspace_rules,
+            web_auth: WebAuthConfig { token: non_empty(s.auth.token), password: non_empty(s.auth.password) },
+            default_agent: non_empty(s.default_agent)
+                .filter(|a| crate::agent::AgentKind::from_str_loose(a).is_some())
+                .unwrap_or(defaults.default_agent),
+            enabled_agents,
+            permissions,
+            max_attachment_bytes: s
+                .attachments
+                .max_size_mb
+                .filter(|n| *n > 0)
+                .map(|mb| mb.saturating_mul(1024 * 1024))
+                .unwrap_or(defaults.max_attachment_bytes),
+            channels: s.channels,
+            raw_channels: root
+                .get("channels")
+                .cloned()
+                .unwrap_or(serde_json::Value::Object(serde_json::Map::new())),
+            working_dir,
+        }
+    }
+}
+
+/// Workspace rule paths: `~` is the home directory; relative paths are under `working_dir`.
+fn resolve_rule_path(raw: &str, working_dir: &std::path::Path) -> PathBuf {
+    working_dir.join(expand_home(raw))
+}
+
 fn default_working_dir() -> PathBuf {
     data_dir()
 }
@@ -259,9 +567,137 @@ impl Default for Config {
             working_dir: default_working_dir(),
             preview_base_url: None,
             tmux_detach_others: true,
+            workspace_rules: Vec::new(),
+            web_auth: WebAuthConfig::default(),
             default_agent: "claude".to_string(),
             enabled_agents: crate::agent::AgentKind::all().to_vec(),
+            permissions: PermissionConfig::default(),
+            max_attachment_bytes: 25 * 1024 * 1024,
+            channels: BTreeMap::new(),
             raw_channels: serde_json::Value::Object(serde_json::Map::new()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(allowed_senders: &[&str], admins: &[&str]) -> ChannelAccess {
+        ChannelAccess {
+            allowed_senders: allowed_senders.iter().map(|s| s.to_string()).collect(),
+            admins: admins.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn open_channel_admits_everyone_as_admin() {
+        let open = access(&[], &[]);
+        assert!(open.is_open());
+        for sender in ["alice", "42", ""] {
+            assert!(open.is_allowed(sender));
+            assert!(open.is_admin(sender));
+        }
+    }
+
+    #[test]
+    fn allowlist_without_admins_makes_every_allowed_sender_an_admin() {
+        let listed = access(&["alice", "42"], &[]);
+        assert!(!listed.is_open());
+        assert!(listed.is_allowed("alice") && listed.is_admin("alice"));
+        assert!(listed.is_allowed("42") && listed.is_admin("42"));
+        assert!(!listed.is_allowed("bob") && !listed.is_admin("bob"));
+        assert!(!listed.is_allowed("") && !listed.is_admin(""));
+    }
+
+    #[test]
+    fn wildcard_admits_anyone() {
+        let wildcard = access(&["*"], &[]);
+        assert!(wildcard.is_allowed("bob") && wildcard.is_admin("bob"));
+
+        let wildcard_with_admins = access(&["*"], &["alice"]);
+        assert!(wildcard_with_admins.is_allowed("bob"));
+        assert!(!wildcard_with_admins.is_admin("bob"));
+        assert!(wildcard_with_admins.is_admin("alice"));
+    }
+
+    #[test]
+    fn admins_are_always_allowed_and_the_only_admins() {
+        let with_admins = access(&["alice"], &["root"]);
+        assert!(with_admins.is_allowed("root") && with_admins.is_admin("root"));
+        assert!(with_admins.is_allowed("alice") && !with_admins.is_admin("alice"));
+        assert!(!with_admins.is_allowed("") && !with_admins.is_admin(""));
+
+        // Admins alone also close the channel to everyone else.
+        let admins_only = access(&[], &["root"]);
+        assert!(!admins_only.is_open());
+        assert!(admins_only.is_allowed("root"));
+        assert!(!admins_only.is_allowed("alice"));
+    }
+
+    #[test]
+    fn email_senders_match_ignoring_case() {
+        let email = access(&["Alice@Example.com"], &["ROOT@example.com"]);
+        assert!(email.is_allowed("alice@example.com") && !email.is_admin("alice@example.com"));
+        assert!(email.is_admin("root@EXAMPLE.com"));
+        assert!(!email.is_allowed("mallory@example.com"));
+        // Platform ids stay exact.
+        let matrix = access(&["@Alice:example.org"], &[]);
+        assert!(matrix.is_allowed("@Alice:example.org") && !matrix.is_allowed("@alice:example.org"));
+        assert!(!access(&["U123ABC"], &[]).is_allowed("u123abc"));
+    }
+
+    #[test]
+    fn channel_access_reads_numeric_ids_and_drops_blank_ones() {
+        let parsed = settings::parse(
+            r#"{ "channels": { "telegram": { "allowed_senders": [123456789, " alice "], "admins": [" ", 42] } } }"#,
+        );
+        // The blank admin id is reported but does not stop the file from loading.
+        let parsed = parsed.unwrap_or_else(|issues| panic!("{:?}", issues));
+        let config = Config::from_settings(parsed.settings, &parsed.root);
+        let access = config.channel_access("telegram");
+        assert_eq!(access.allowed_senders, ["123456789", "alice"]);
+        assert_eq!(access.admins, ["42"]);
+        assert!(access.is_allowed("123456789"));
+        assert!(access.is_admin("42") && !access.is_admin("123456789"));
+        assert!(config.channel_access("slack").is_open());
+    }
+
+    #[test]
+    fn a_wrong_typed_value_does_not_disable_the_rest_of_the_config() {
+        let dir = std::env::temp_dir().join(format!("vibearound-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+        std::fs::write(
+            &path,
+            r#"{ "permissions": { "mode": "ask", "timeout_secs": "300" },
+                 "tunnel": { "provider": "ngrok", "ngrok": { "auth_token": "t" } },
+                 "channels": { "telegram": { "bot_token": "x", "verbose": { "show_thinking": "true" } } } }"#,
+        )
+        .unwrap();
+        let config = read_settings(&path).unwrap();
+        assert_eq!(config.channel_names(), ["telegram"]);
+        assert_eq!(config.permissions.mode, PermissionMode::Ask);
+        assert_eq!(config.tunnel_provider, TunnelProvider::Ngrok);
+        assert_eq!(config.ngrok_auth_token.as_deref(), Some("t"));
+        assert!(!config.channel_verbose("telegram").show_thinking);
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(read_settings(&path).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn attachment_size_limit_is_in_megabytes() {
+        let limit = |json: &str| {
+            let parsed = settings::parse(json).unwrap_or_else(|issues| panic!("{:?}", issues));
+            Config::from_settings(parsed.settings, &parsed.root).max_attachment_bytes
+        };
+        assert_eq!(limit(r#"{ "attachments": { "max_size_mb": 5 } }"#), 5 * 1024 * 1024);
+        assert_eq!(limit("{}"), Config::default().max_attachment_bytes);
+        let zero = r#"{ "attachments": { "max_size_mb": 0 } }"#;
+        assert_eq!(limit(zero), Config::default().max_attachment_bytes);
+        let issues = settings::parse(zero).unwrap().issues;
+        assert!(issues.iter().any(|i| i.path == "attachments.max_size_mb"), "{:?}", issues);
+    }
+}
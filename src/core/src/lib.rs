@@ -8,5 +8,6 @@ pub mod channel_manager;
 pub mod pty;
 pub mod service;
 pub mod session_store;
+pub mod settings;
 pub mod tunnels;
 pub mod workspace;
@@ -92,6 +92,8 @@ pub struct AgentStatusEntry {
 /// Channel plugin status entry.
 pub struct ChannelEntry {
     pub meta: ServiceMeta,
+    /// How many times the plugin supervisor has restarted the process.
+    pub restarts: u32,
 }
 
 /// Tunnel status entry.
@@ -185,16 +187,54 @@ impl ServiceStatusManager {
     pub fn register_channel(&self, kind: &str, abort_handle: AbortHandle) {
         let entry = ChannelEntry {
             meta: ServiceMeta::new(Some(abort_handle)),
+            restarts: 0,
         };
         self.channels.insert(kind.to_string(), entry);
         eprintln!("[ServiceStatus] registered channel: {}", kind);
         self.notify_change();
     }
 
+    /// A (re)started plugin process is up; uptime counts from now.
+    pub fn set_channel_running(&self, kind: &str, restarts: u32) {
+        if let Some(mut entry) = self.channels.get_mut(kind) {
+            entry.meta.started_at = unix_now_secs();
+            entry.restarts = restarts;
+            *entry.meta.status.write().unwrap() = ServiceStatus::Running;
+            drop(entry);
+            self.notify_change();
+        }
+    }
+
+    /// The plugin process exited and is waiting for its next restart.
+    pub fn set_channel_failed(&self, kind: &str, error: String, restarts: u32) {
+        if let Some(mut entry) = self.channels.get_mut(kind) {
+            entry.restarts = restarts;
+            *entry.meta.status.write().unwrap() = ServiceStatus::Failed { error };
+            drop(entry);
+            self.notify_change();
+        }
+    }
+
+    pub fn unregister_channel(&self, kind: &str) {
+        if self.channels.remove(kind).is_some() {
+            eprintln!("[ServiceStatus] unregistered channel: {}", kind);
+            self.notify_change();
+        }
+    }
+
     // -----------------------------------------------------------------------
     // Tunnel
     // -----------------------------------------------------------------------
 
+    /// Kill and forget the running tunnel (before starting one with new settings).
+    pub fn stop_tunnels(&self) {
+        for entry in self.tunnels.iter() {
+            entry.meta.kill();
+        }
+        self.tunnels.clear();
+        self.notify_change();
+    }
+
     pub fn register_tunnel(&self, provider: TunnelProvider, abort_handle: AbortHandle) {
         let entry = TunnelEntry {
             meta: ServiceMeta::new(Some(abort_handle)),
@@ -298,7 +338,11 @@ impl ServiceStatusManager {
                     name: capitalize(&key),
                     status: status_string(&entry.meta.current_status()),
                     uptime_secs: entry.meta.uptime_secs(),
-                    extra: serde_json::Map::new(),
+                    extra: {
+                        let mut m = serde_json::Map::new();
+                        m.insert("restarts".into(), entry.restarts.into());
+                        m
+                    },
                 }
             }).collect(),
             pty_session_count: pty_count,
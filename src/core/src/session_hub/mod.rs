@@ -10,6 +10,7 @@
 pub mod types;
 
 use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use tokio::sync::{broadcast, Mutex};
 
 use crate::session_hub::types::*;
@@ -27,6 +28,8 @@ struct Session {
     cli_kind: Option<String>,
     /// Profile name (e.g. "default").
     profile: String,
+    /// Project directory bound with `/cd` (None = config rule or per-chat default).
+    workspace: Option<PathBuf>,
     /// Whether the agent is currently processing a message.
     busy: bool,
     /// FIFO message queue for this session.
@@ -39,6 +42,7 @@ impl Session {
             cli_session_id: None,
             cli_kind: None,
             profile: "default".to_string(),
+            workspace: None,
             busy: false,
             queue: VecDeque::new(),
         }
@@ -93,33 +97,94 @@ impl SessionHub {
         sessions.get(&key).map(|session| session.profile.clone())
     }
 
+    pub async fn get_session_workspace(&self, channel_kind: &str, chat_id: &str) -> Option<PathBuf> {
+        let key = session_key(channel_kind, chat_id);
+        let sessions = self.sessions.lock().await;
+        sessions.get(&key).and_then(|session| session.workspace.clone())
+    }
+
+    pub async fn session_status(&self, channel_kind: &str, chat_id: &str) -> Option<SessionStatus> {
+        let key = session_key(channel_kind, chat_id);
+        let sessions = self.sessions.lock().await;
+        sessions.get(&key).map(|session| SessionStatus {
+            cli_kind: session.cli_kind.clone(),
+            cli_session_id: session.cli_session_id.clone(),
+            profile: session.profile.clone(),
+            workspace: crate::workspace::chat_workspace(channel_kind, chat_id, session.workspace.as_deref()),
+            busy: session.busy,
+            queue_len: session.queue.len(),
+        })
+    }
+
     /// Called by ChannelManager when a message arrives from a channel plugin.
     pub async fn channel_request_message(&self, msg: InboundMessage) {
         let key = session_key(&msg.channel_kind, &msg.chat_id);
+        self.enqueue_message(&key, msg, MessageStatus::Unreplied).await;
+        self.try_advance_session_queue(&key).await;
+    }
+
+    /// Take a queue slot for a message whose attachments are still being downloaded, so messages
+    /// sent after it are not run first. Complete it with `channel_staged_message`.
+    pub async fn channel_reserve_message(&self, msg: InboundMessage) {
+        let key = session_key(&msg.channel_kind, &msg.chat_id);
+        self.enqueue_message(&key, msg, MessageStatus::Staging).await;
+    }
+
+    /// Fill a slot taken by `channel_reserve_message` with the staged message and let it run.
+    pub async fn channel_staged_message(&self, msg: InboundMessage) {
+        let key = session_key(&msg.channel_kind, &msg.chat_id);
+        let reserved = {
+            let mut sessions = self.sessions.lock().await;
+            let slot = sessions.get_mut(&key).and_then(|session| {
+                session.queue.iter_mut().find(|q| {
+                    q.status == MessageStatus::Staging && q.message.message_id == msg.message_id
+                })
+            });
+            match slot {
+                Some(slot) => {
+                    slot.message = msg.clone();
+                    slot.status = MessageStatus::Unreplied;
+                    true
+                }
+                None => false,
+            }
+        };
+        // The session was reset while downloading: queue it as a new message.
+        if !reserved {
+            self.enqueue_message(&key, msg, MessageStatus::Unreplied).await;
+        }
+        self.try_advance_session_queue(&key).await;
+    }
+
+    async fn enqueue_message(&self, key: &str, msg: InboundMessage, status: MessageStatus) {
         let pfx = format!("[SessionHub][{}]", key);
 
         {
             let mut sessions = self.sessions.lock().await;
 
-            if !sessions.contains_key(&key) {
+            if !sessions.contains_key(key) {
                 eprintln!("{} creating new session", pfx);
-                sessions.insert(key.clone(), Session::new());
+                sessions.insert(key.to_string(), Session::new());
             }
 
-            let session = sessions.get_mut(&key).unwrap();
+            let session = sessions.get_mut(key).unwrap();
             session.queue.push_back(QueuedMessage {
                 message: msg.clone(),
-                status: MessageStatus::Unreplied,
+                status,
             });
 
             eprintln!("{} enqueued msg_id={} queue_len={}", pfx, msg.message_id, session.queue.len());
 
             if session.busy {
                 eprintln!("{} agent busy, message queued", pfx);
+                self.publish_channel_event(ChannelEvent::OnMessageQueued {
+                    channel_kind: msg.channel_kind.clone(),
+                    chat_id: msg.chat_id.clone(),
+                    message_id: msg.message_id.clone(),
+                    position: session.queue.len() - 1,
+                });
             }
         }
-
-        self.try_advance_session_queue(&key).await;
     }
 
     /// Called by AgentManager when an agent session becomes usable.
@@ -181,20 +246,42 @@ impl SessionHub {
                     payload: serde_json::json!({ "kind": "thinking", "text": text }),
                 });
             }
-            AgentReplyEvent::ToolUse { tool, input } => {
+            AgentReplyEvent::ToolUse { tool_id, tool, input } => {
                 self.publish_channel_event(ChannelEvent::OnAcpEvent {
                     channel_kind: reply.channel_kind.clone(),
                     chat_id: reply.chat_id.clone(),
                     message_id: reply.message_id.clone(),
-                    payload: serde_json::json!({ "kind": "tool_use", "tool": tool, "input": input }),
+                    payload: serde_json::json!({ "kind": "tool_use", "tool_id": tool_id, "tool": tool, "input": input }),
                 });
             }
-            AgentReplyEvent::ToolResult { tool, output } => {
+            AgentReplyEvent::ToolResult { tool_id, tool, output, is_error } => {
                 self.publish_channel_event(ChannelEvent::OnAcpEvent {
                     channel_kind: reply.channel_kind.clone(),
                     chat_id: reply.chat_id.clone(),
                     message_id: reply.message_id.clone(),
-                    payload: serde_json::json!({ "kind": "tool_result", "tool": tool, "output": output }),
+                    payload: serde_json::json!({
+                        "kind": "tool_result",
+                        "tool_id": tool_id,
+                        "tool": tool,
+                        "output": output,
+                        "is_error": is_error,
+                    }),
+                });
+            }
+            AgentReplyEvent::PermissionRequest { request_id, tool, input, options } => {
+                self.publish_channel_event(ChannelEvent::OnAcpEvent {
+                    channel_kind: reply.channel_kind.clone(),
+                    chat_id: reply.chat_id.clone(),
+                    message_id: reply.message_id.clone(),
+                    payload: serde_json::json!({
+                        "kind": "permission_request",
+                        "request_id": request_id,
+                        "tool": tool,
+                        "input": input,
+                        "options": options.iter().map(|o| serde_json::json!({
+                            "id": o.id, "label": o.label, "kind": o.kind,
+                        })).collect::<Vec<_>>(),
+                    }),
                 });
             }
             AgentReplyEvent::Error { error } => {
@@ -204,10 +291,13 @@ impl SessionHub {
                     error: error.clone(),
                 });
             }
-            AgentReplyEvent::Complete => {
+            AgentReplyEvent::Complete { stop_reason, cost_usd, usage } => {
                 self.publish_channel_event(ChannelEvent::OnTurnCompleted {
                     channel_kind: reply.channel_kind.clone(),
                     chat_id: reply.chat_id.clone(),
+                    stop_reason: *stop_reason,
+                    cost_usd: *cost_usd,
+                    usage: *usage,
                 });
             }
         }
@@ -248,38 +338,72 @@ impl SessionHub {
         );
     }
 
-    /// Requested by ChannelManager to stop the current runtime for a route.
-    pub async fn channel_request_stop(&self, channel_kind: &str, chat_id: &str) {
-        let key = session_key(channel_kind, chat_id);
-        let had_session = {
-            let sessions = self.sessions.lock().await;
-            sessions.contains_key(&key)
-        };
+    /// Called by AgentManager to post an informational message into a chat.
+    pub fn agent_system_text(&self, channel_kind: &str, chat_id: &str, text: String) {
+        self.publish_channel_event(ChannelEvent::OnSystemText {
+            channel_kind: channel_kind.to_string(),
+            chat_id: chat_id.to_string(),
+            text,
+            reply_to: None,
+        });
+    }
 
-        self.publish_agent_event(AgentEvent::OnStopRuntime {
+    /// Requested by ChannelManager when the user answers a permission request.
+    pub async fn channel_request_permission_response(
+        &self,
+        channel_kind: &str,
+        chat_id: &str,
+        request_id: &str,
+        option_id: Option<String>,
+    ) {
+        eprintln!(
+            "[SessionHub][{}] permission response request_id={} option={:?}",
+            session_key(channel_kind, chat_id),
+            request_id,
+            option_id,
+        );
+        self.publish_agent_event(AgentEvent::OnPermissionResponse {
             channel_kind: channel_kind.to_string(),
             chat_id: chat_id.to_string(),
+            request_id: request_id.to_string(),
+            option_id,
         });
+    }
 
-        {
-            let mut sessions = self.sessions.lock().await;
-            if let Some(session) = sessions.get_mut(&key) {
-                session.busy = false;
-                for queued in session.queue.iter_mut() {
-                    queued.status = MessageStatus::Unreplied;
-                }
-            }
+    /// Requested by ChannelManager to stop the running turn for a route.
+    /// The agent stays alive; the turn ends with a cancelled `TurnComplete`, which advances the queue.
+    pub async fn channel_request_stop(&self, channel_kind: &str, chat_id: &str) {
+        let key = session_key(channel_kind, chat_id);
+        let busy = {
+            let sessions = self.sessions.lock().await;
+            sessions.get(&key).is_some_and(|session| session.busy)
+        };
+
+        if busy {
+            self.publish_agent_event(AgentEvent::OnCancelTurn {
+                channel_kind: channel_kind.to_string(),
+                chat_id: chat_id.to_string(),
+            });
+        } else {
+            self.publish_channel_event(ChannelEvent::OnSystemText {
+                channel_kind: channel_kind.to_string(),
+                chat_id: chat_id.to_string(),
+                text: "Nothing to stop.".to_string(),
+                reply_to: None,
+            });
         }
 
-        eprintln!("[SessionHub][{}] channel requested stop handled had_session={}", key, had_session);
+        eprintln!("[SessionHub][{}] channel requested stop handled busy={}", key, busy);
     }
 
     /// Requested by ChannelManager to close the current route.
+    /// Unlike a stop, the closed CLI sessions are not resumed later.
     pub async fn channel_request_close(&self, channel_kind: &str, chat_id: &str) {
         let key = session_key(channel_kind, chat_id);
-        self.publish_agent_event(AgentEvent::OnStopRuntime {
+        self.publish_agent_event(AgentEvent::OnCloseRuntime {
             channel_kind: channel_kind.to_string(),
             chat_id: chat_id.to_string(),
+            reason: Some("closed by user".to_string()),
         });
 
         let removed = {
@@ -344,6 +468,100 @@ impl SessionHub {
         );
     }
 
+    /// Requested by ChannelManager to switch the agent profile for a route.
+    pub async fn channel_request_switch_profile(&self, channel_kind: &str, chat_id: &str, profile: &str) {
+        let key = session_key(channel_kind, chat_id);
+        if !crate::agent_manager::agent_profile_exists(profile) {
+            self.publish_channel_event(ChannelEvent::OnSystemText {
+                channel_kind: channel_kind.to_string(),
+                chat_id: chat_id.to_string(),
+                text: format!("Unknown profile: {}", profile),
+                reply_to: None,
+            });
+            return;
+        }
+
+        self.publish_agent_event(AgentEvent::OnStopRuntime {
+            channel_kind: channel_kind.to_string(),
+            chat_id: chat_id.to_string(),
+        });
+
+        {
+            let mut sessions = self.sessions.lock().await;
+            let session = sessions.entry(key.clone()).or_insert_with(Session::new);
+            session.profile = profile.to_string();
+            session.cli_session_id = None;
+            session.busy = false;
+        }
+
+        self.publish_channel_event(ChannelEvent::OnSystemText {
+            channel_kind: channel_kind.to_string(),
+            chat_id: chat_id.to_string(),
+            text: format!("Switched profile to {}.", profile),
+            reply_to: None,
+        });
+
+        eprintln!("[SessionHub][{}] channel requested switch_profile handled new_profile={}", key, profile);
+    }
+
+    /// Set up a fresh route to continue a stored conversation: its agent kind, profile and
+    /// workspace are taken over silently, so the route's first message resumes that session.
+    pub async fn prepare_resumed_route(
+        &self,
+        channel_kind: &str,
+        chat_id: &str,
+        cli_kind: &str,
+        profile: &str,
+        workspace: PathBuf,
+    ) {
+        let key = session_key(channel_kind, chat_id);
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.entry(key.clone()).or_insert_with(Session::new);
+        session.cli_kind = Some(cli_kind.to_string());
+        session.profile = profile.to_string();
+        session.workspace = Some(workspace);
+        session.cli_session_id = None;
+        eprintln!("[SessionHub][{}] prepared resumed route kind={} profile={}", key, cli_kind, profile);
+    }
+
+    /// Requested by ChannelManager to bind the route to a project directory.
+    /// `None` returns to the configured / per-chat default. The agent restarts in the new directory.
+    pub async fn channel_request_set_workspace(&self, channel_kind: &str, chat_id: &str, workspace: Option<PathBuf>) {
+        let key = session_key(channel_kind, chat_id);
+        if let Some(dir) = workspace.as_ref().filter(|dir| !dir.is_dir()) {
+            self.publish_channel_event(ChannelEvent::OnSystemText {
+                channel_kind: channel_kind.to_string(),
+                chat_id: chat_id.to_string(),
+                text: format!("Not a directory: {}", dir.display()),
+                reply_to: None,
+            });
+            return;
+        }
+
+        self.publish_agent_event(AgentEvent::OnStopRuntime {
+            channel_kind: channel_kind.to_string(),
+            chat_id: chat_id.to_string(),
+        });
+
+        let resolved = {
+            let mut sessions = self.sessions.lock().await;
+            let session = sessions.entry(key.clone()).or_insert_with(Session::new);
+            session.workspace = workspace;
+            session.cli_session_id = None;
+            session.busy = false;
+            crate::workspace::chat_workspace(channel_kind, chat_id, session.workspace.as_deref())
+        };
+
+        self.publish_channel_event(ChannelEvent::OnSystemText {
+            channel_kind: channel_kind.to_string(),
+            chat_id: chat_id.to_string(),
+            text: format!("Workspace set to {}.", resolved.display()),
+            reply_to: None,
+        });
+
+        eprintln!("[SessionHub][{}] channel requested set_workspace handled workspace={}", key, resolved.display());
+    }
+
     async fn try_advance_session_queue(&self, key: &str) {
         let dispatch_msg = {
             let mut sessions = self.sessions.lock().await;
@@ -358,6 +576,9 @@ impl SessionHub {
             let Some(front) = session.queue.front_mut() else {
                 return;
             };
+            if front.status == MessageStatus::Staging {
+                return;
+            }
 
             front.status = MessageStatus::Processing;
             session.busy = true;
@@ -7,7 +7,10 @@ pub enum HubEvent {
     OnAgentKilled { key: String },
     OnSessionCreated { key: String },
     OnSessionDestroyed { key: String },
-    OnPluginStarted { channel: String },
+    /// A channel plugin process came up; `restarts` counts supervisor restarts so far.
+    OnPluginStarted { channel: String, restarts: u32 },
+    /// A channel plugin process exited; the supervisor retries in `retry_in_secs`.
+    OnPluginFailed { channel: String, error: String, restarts: u32, retry_in_secs: u64 },
     OnPluginStopped { channel: String },
 }
 
@@ -44,12 +47,18 @@ pub struct Attachment {
     pub message_id: String,
     pub file_key: String,
     pub file_name: String,
+    /// Platform resource kind (e.g. "image", "file").
     pub resource_type: String,
+    pub mime_type: Option<String>,
+    /// Downloaded copy of the file, set by ChannelManager before the message is queued.
+    pub local_path: Option<std::path::PathBuf>,
 }
 
 /// Status of a queued message.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MessageStatus {
+    /// Holds the message's place while its attachments are downloaded.
+    Staging,
     Unreplied,
     Processing,
     Replied,
@@ -62,6 +71,18 @@ pub struct QueuedMessage {
     pub status: MessageStatus,
 }
 
+/// Read-only snapshot of a session, for status queries.
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    pub cli_kind: Option<String>,
+    pub cli_session_id: Option<CliSessionId>,
+    pub profile: String,
+    /// Resolved working directory of the session's agents.
+    pub workspace: std::path::PathBuf,
+    pub busy: bool,
+    pub queue_len: usize,
+}
+
 /// Lifecycle signal emitted when an agent session becomes usable.
 #[derive(Debug, Clone)]
 pub struct AgentReady {
@@ -102,10 +123,20 @@ pub enum AgentReplyEvent {
     Start,
     Token { delta: String },
     Thinking { text: String },
-    ToolUse { tool: String, input: String },
-    ToolResult { tool: String, output: String },
-    Complete,
+    ToolUse { tool_id: String, tool: String, input: String },
+    ToolResult { tool_id: String, tool: String, output: String, is_error: bool },
+    Complete {
+        stop_reason: crate::agent::StopReason,
+        cost_usd: Option<f64>,
+        usage: Option<crate::agent::TokenUsage>,
+    },
     Error { error: String },
+    PermissionRequest {
+        request_id: String,
+        tool: String,
+        input: String,
+        options: Vec<crate::agent::PermissionChoice>,
+    },
 }
 
 /// SessionHub -> AgentManager event skeleton for the new architecture.
@@ -127,11 +158,23 @@ pub enum AgentEvent {
         channel_kind: ChannelKind,
         chat_id: ChatId,
     },
+    /// Stop the running turn but keep the agent process (and its CLI session) alive.
+    OnCancelTurn {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+    },
     OnCloseRuntime {
         channel_kind: ChannelKind,
         chat_id: ChatId,
         reason: Option<String>,
     },
+    /// The user answered a permission request (`option_id: None` = dismissed).
+    OnPermissionResponse {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+        request_id: String,
+        option_id: Option<String>,
+    },
 }
 
 /// SessionHub -> ChannelManager event skeleton for the new architecture.
@@ -157,6 +200,16 @@ pub enum ChannelEvent {
     OnTurnCompleted {
         channel_kind: ChannelKind,
         chat_id: ChatId,
+        stop_reason: crate::agent::StopReason,
+        cost_usd: Option<f64>,
+        usage: Option<crate::agent::TokenUsage>,
+    },
+    /// A message was queued behind a running turn; `position` messages are ahead of it.
+    OnMessageQueued {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+        message_id: MessageId,
+        position: usize,
     },
     OnSessionClosed {
         channel_kind: ChannelKind,
@@ -188,15 +241,59 @@ pub enum ChannelNotification {
     AgentStart { channel_kind: ChannelKind, chat_id: ChatId, message_id: MessageId },
     AgentThinking { channel_kind: ChannelKind, chat_id: ChatId, text: String },
     AgentToken { channel_kind: ChannelKind, chat_id: ChatId, delta: String },
-    AgentToolUse { channel_kind: ChannelKind, chat_id: ChatId, tool: String, input: String },
-    AgentToolResult { channel_kind: ChannelKind, chat_id: ChatId, tool: String, output: String },
-    AgentEnd { channel_kind: ChannelKind, chat_id: ChatId },
+    /// A tool call started. `tool_id` pairs it with its `AgentToolResult` (empty if the agent gives none).
+    AgentToolUse { channel_kind: ChannelKind, chat_id: ChatId, tool_id: String, tool: String, input: String },
+    AgentToolResult {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+        tool_id: String,
+        tool: String,
+        output: String,
+        is_error: bool,
+    },
+    AgentEnd {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+        stop_reason: crate::agent::StopReason,
+        /// Cost of the turn when the agent reports it.
+        cost_usd: Option<f64>,
+        /// Token counts of the turn when the agent reports them.
+        usage: Option<crate::agent::TokenUsage>,
+    },
     AgentError { channel_kind: ChannelKind, chat_id: ChatId, error: String },
     SendText { channel_kind: ChannelKind, chat_id: ChatId, text: String, reply_to: Option<MessageId> },
+    /// Ask the user to approve a tool call. Each option's `callback_value` is sent back via `on_callback`.
+    PermissionRequest {
+        channel_kind: ChannelKind,
+        chat_id: ChatId,
+        request_id: String,
+        tool: String,
+        input: String,
+        options: Vec<crate::agent::PermissionChoice>,
+        timeout_secs: u64,
+        default_allow: bool,
+    },
+    /// The user's message waits behind the running turn; `position` messages are ahead of it.
+    MessageQueued { channel_kind: ChannelKind, chat_id: ChatId, message_id: MessageId, position: usize },
+}
+
+/// Prefix of `on_callback` values that answer a permission request: "perm:{request_id}:{option_id}".
+pub const PERMISSION_CALLBACK_PREFIX: &str = "perm:";
+
+/// Build the callback value a channel sends back when the user picks a permission option.
+pub fn permission_callback_value(request_id: &str, option_id: &str) -> String {
+    format!("{}{}:{}", PERMISSION_CALLBACK_PREFIX, request_id, option_id)
+}
+
+/// Parse a permission callback value into (request_id, option_id).
+pub fn parse_permission_callback(value: &str) -> Option<(String, String)> {
+    let rest = value.strip_prefix(PERMISSION_CALLBACK_PREFIX)?;
+    let (request_id, option_id) = rest.split_once(':')?;
+    Some((request_id.to_string(), option_id.to_string()))
 }
 
 impl ChannelNotification {
-    fn plugin_channel_id(channel_kind: &str, chat_id: &str) -> String {
+    pub fn plugin_channel_id(channel_kind: &str, chat_id: &str) -> String {
         format!("{}:{}", channel_kind, chat_id)
     }
 
@@ -214,17 +311,28 @@ impl ChannelNotification {
                 "jsonrpc": "2.0", "method": "agent_token",
                 "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id), "delta": delta }
             }),
-            Self::AgentToolUse { channel_kind, chat_id, tool, input } => serde_json::json!({
+            Self::AgentToolUse { channel_kind, chat_id, tool_id, tool, input } => serde_json::json!({
                 "jsonrpc": "2.0", "method": "agent_tool_use",
-                "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id), "tool": tool, "input": input }
+                "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id), "toolId": tool_id, "tool": tool, "input": input }
             }),
-            Self::AgentToolResult { channel_kind, chat_id, tool, output } => serde_json::json!({
+            Self::AgentToolResult { channel_kind, chat_id, tool_id, tool, output, is_error } => serde_json::json!({
                 "jsonrpc": "2.0", "method": "agent_tool_result",
-                "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id), "tool": tool, "output": output }
+                "params": {
+                    "channelId": Self::plugin_channel_id(channel_kind, chat_id),
+                    "toolId": tool_id,
+                    "tool": tool,
+                    "output": output,
+                    "isError": is_error,
+                }
             }),
-            Self::AgentEnd { channel_kind, chat_id } => serde_json::json!({
+            Self::AgentEnd { channel_kind, chat_id, stop_reason, cost_usd, usage } => serde_json::json!({
                 "jsonrpc": "2.0", "method": "agent_end",
-                "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id) }
+                "params": {
+                    "channelId": Self::plugin_channel_id(channel_kind, chat_id),
+                    "stopReason": stop_reason.as_str(),
+                    "costUsd": cost_usd,
+                    "usage": usage,
+                }
             }),
             Self::AgentError { channel_kind, chat_id, error } => serde_json::json!({
                 "jsonrpc": "2.0", "method": "agent_error",
@@ -234,6 +342,31 @@ impl ChannelNotification {
                 "jsonrpc": "2.0", "method": "send_text",
                 "params": { "channelId": Self::plugin_channel_id(channel_kind, chat_id), "text": text, "replyTo": reply_to }
             }),
+            Self::PermissionRequest { channel_kind, chat_id, request_id, tool, input, options, timeout_secs, default_allow } => serde_json::json!({
+                "jsonrpc": "2.0", "method": "permission_request",
+                "params": {
+                    "channelId": Self::plugin_channel_id(channel_kind, chat_id),
+                    "requestId": request_id,
+                    "tool": tool,
+                    "input": input,
+                    "options": options.iter().map(|o| serde_json::json!({
+                        "id": o.id,
+                        "label": o.label,
+                        "kind": o.kind,
+                        "value": permission_callback_value(request_id, &o.id),
+                    })).collect::<Vec<_>>(),
+                    "timeoutSecs": timeout_secs,
+                    "defaultDecision": if *default_allow { "allow" } else { "deny" },
+                }
+            }),
+            Self::MessageQueued { channel_kind, chat_id, message_id, position } => serde_json::json!({
+                "jsonrpc": "2.0", "method": "message_queued",
+                "params": {
+                    "channelId": Self::plugin_channel_id(channel_kind, chat_id),
+                    "messageId": message_id,
+                    "position": position,
+                }
+            }),
         }
     }
 }
@@ -35,6 +35,17 @@ pub struct SessionHeader {
     pub summary: Option<String>,
     /// ISO 8601 creation timestamp.
     pub created_at: String,
+    /// Chat the session was started from (Manager sessions driven from a channel).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<SessionOrigin>,
+}
+
+/// Channel route and profile of a chat-driven session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionOrigin {
+    pub channel_kind: String,
+    pub chat_id: String,
+    pub profile: String,
 }
 
 /// A single event line in the JSONL file.
@@ -65,6 +76,28 @@ pub struct SessionMeta {
     pub header: SessionHeader,
 }
 
+impl SessionMeta {
+    /// Stable id of the session: its file name without `.jsonl`.
+    pub fn id(&self) -> String {
+        self.path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// What a conversation list needs beyond the header; read from the events.
+#[derive(Debug, Clone)]
+pub struct SessionOverview {
+    /// Latest summary (a `summary_update` wins over the header).
+    pub summary: Option<String>,
+    /// Timestamp of the last event, or the creation time.
+    pub updated_at: String,
+    pub user_messages: usize,
+    pub closed: bool,
+}
+
 // ---------------------------------------------------------------------------
 // SessionWriter — append-only handle
 // ---------------------------------------------------------------------------
@@ -84,6 +117,7 @@ impl SessionWriter {
         workspace: &str,
         cli_session_id: Option<&str>,
         summary: Option<&str>,
+        origin: Option<SessionOrigin>,
     ) -> std::io::Result<Self> {
         fs::create_dir_all(sessions_dir)?;
 
@@ -104,6 +138,7 @@ impl SessionWriter {
             role: role.to_string(),
             summary: summary.map(String::from),
             created_at: now.to_rfc3339(),
+            origin,
         };
 
         let mut file = File::create(&path)?;
@@ -118,8 +153,9 @@ impl SessionWriter {
     /// Reopen an existing session file for appending.
     /// Used to continue writing to the same session across multiple turns.
     pub fn reopen(path: &Path) -> std::io::Result<Self> {
-        let header = crate::session_store::read_header(path)
+        let mut header = crate::session_store::read_header(path)
             .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "bad header"))?;
+        header.cli_session_id = get_cli_session_id(path);
         let file = fs::OpenOptions::new().append(true).open(path)?;
         eprintln!("[session] reopened {}", path.display());
         Ok(Self { file, path: path.to_path_buf(), header })
@@ -181,6 +217,19 @@ impl SessionWriter {
         self.append_line(&se);
     }
 
+    /// Mark the session as closed by the user; closed sessions are not resumed.
+    pub fn mark_closed(&mut self, reason: Option<&str>) {
+        let se = SessionEvent {
+            ts: chrono::Utc::now().to_rfc3339(),
+            role: "system".to_string(),
+            agent_id: None,
+            event: Some("session_closed".to_string()),
+            content: reason.map(String::from),
+            data: None,
+        };
+        self.append_line(&se);
+    }
+
     fn append_line(&mut self, event: &SessionEvent) {
         if let Ok(json) = serde_json::to_string(event) {
             if let Err(e) = writeln!(self.file, "{}", json) {
@@ -223,6 +272,142 @@ pub fn latest_session(sessions_dir: &Path, kind: AgentKind) -> Option<SessionMet
         .find(|s| s.header.kind == kind_str)
 }
 
+/// Find the latest session started from this chat with this agent kind, profile and workspace,
+/// and return it with the CLI session id to resume. None if that session was closed.
+pub fn resumable_chat_session(
+    sessions_dir: &Path,
+    kind: AgentKind,
+    origin: &SessionOrigin,
+    workspace: &Path,
+) -> Option<(SessionMeta, String)> {
+    let kind_str = kind.to_string();
+    let workspace = workspace.display().to_string();
+    let meta = list_sessions(sessions_dir).into_iter().find(|s| {
+        s.header.kind == kind_str
+            && s.header.workspace == workspace
+            && s.header.origin.as_ref().is_some_and(|o| {
+                o.channel_kind == origin.channel_kind && o.chat_id == origin.chat_id && o.profile == origin.profile
+            })
+    })?;
+    if is_closed(&meta.path) {
+        return None;
+    }
+    let cli_session_id = get_cli_session_id(&meta.path)?;
+    Some((meta, cli_session_id))
+}
+
+/// Mark every open session started from this chat as closed, so none of them is resumed.
+pub fn close_chat_sessions(sessions_dir: &Path, channel_kind: &str, chat_id: &str, reason: Option<&str>) {
+    for meta in list_sessions(sessions_dir) {
+        let from_chat = meta
+            .header
+            .origin
+            .as_ref()
+            .is_some_and(|o| o.channel_kind == channel_kind && o.chat_id == chat_id);
+        if !from_chat || is_closed(&meta.path) {
+            continue;
+        }
+        match SessionWriter::reopen(&meta.path) {
+            Ok(mut writer) => writer.mark_closed(reason),
+            Err(e) => eprintln!("[session] failed to close {}: {}", meta.path.display(), e),
+        }
+    }
+}
+
+/// Subdirectory of a sessions directory holding archived sessions; `list_sessions` skips it.
+pub const ARCHIVE_DIR: &str = "archived";
+
+/// Find a session by `SessionMeta::id`. Ids with path separators are rejected.
+pub fn find_session(sessions_dir: &Path, id: &str) -> Option<SessionMeta> {
+    if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+        return None;
+    }
+    let path = sessions_dir.join(format!("{}.jsonl", id));
+    let header = read_header(&path)?;
+    Some(SessionMeta { path, header })
+}
+
+/// Summary, last activity and state of a session, from one pass over its events.
+pub fn session_overview(meta: &SessionMeta) -> SessionOverview {
+    let mut overview = SessionOverview {
+        summary: meta.header.summary.clone(),
+        updated_at: meta.header.created_at.clone(),
+        user_messages: 0,
+        closed: false,
+    };
+    for event in read_events(&meta.path) {
+        match event.event.as_deref() {
+            Some("summary_update") => overview.summary = event.content.clone(),
+            Some("session_closed") => overview.closed = true,
+            None if event.role == "user" => overview.user_messages += 1,
+            _ => {}
+        }
+        overview.updated_at = event.ts;
+    }
+    overview
+}
+
+/// Move a session into `ARCHIVE_DIR`, closing it first so it is not resumed.
+pub fn archive_session(meta: &SessionMeta) -> std::io::Result<PathBuf> {
+    if !is_closed(&meta.path) {
+        SessionWriter::reopen(&meta.path)?.mark_closed(Some("archived"));
+    }
+    let dir = meta.path.parent().unwrap_or(Path::new(".")).join(ARCHIVE_DIR);
+    fs::create_dir_all(&dir)?;
+    let target = dir.join(meta.path.file_name().unwrap_or_default());
+    fs::rename(&meta.path, &target)?;
+    eprintln!("[session] archived {}", target.display());
+    Ok(target)
+}
+
+/// Copy a session into a new live chat file started from `origin`, so that chat resumes the same
+/// CLI session. The fork always lands in `manager_sessions_dir()`, even for an archived original.
+/// Close markers are dropped; the original is left as it is.
+pub fn fork_for_chat(meta: &SessionMeta, origin: SessionOrigin) -> std::io::Result<SessionMeta> {
+    let sessions_dir = manager_sessions_dir();
+    std::fs::create_dir_all(&sessions_dir)?;
+    let now = chrono::Utc::now();
+    let header = SessionHeader {
+        cli_session_id: get_cli_session_id(&meta.path),
+        created_at: now.to_rfc3339(),
+        origin: Some(origin),
+        ..meta.header.clone()
+    };
+    let id_prefix = header
+        .cli_session_id
+        .as_deref()
+        .map(|s| s.chars().take(8).collect::<String>())
+        .unwrap_or_else(uuid_short);
+    let path = sessions_dir.join(format!(
+        "{}_{}_{}_{}_{}.jsonl",
+        now.format("%Y-%m-%d"),
+        header.kind,
+        header.role,
+        id_prefix,
+        uuid_short()
+    ));
+
+    let mut file = File::create(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&header).unwrap())?;
+    for event in read_events(&meta.path) {
+        if event.event.as_deref() == Some("session_closed") {
+            continue;
+        }
+        if let Ok(json) = serde_json::to_string(&event) {
+            writeln!(file, "{}", json)?;
+        }
+    }
+    file.flush()?;
+    eprintln!("[session] forked {} -> {}", meta.path.display(), path.display());
+    Ok(SessionMeta { path, header })
+}
+
+pub fn is_closed(path: &Path) -> bool {
+    read_events(path)
+        .iter()
+        .any(|e| e.event.as_deref() == Some("session_closed"))
+}
+
 /// Read the header (first line) of a session file.
 pub fn read_header(path: &Path) -> Option<SessionHeader> {
     let file = File::open(path).ok()?;
@@ -288,12 +473,27 @@ fn agent_event_to_parts(event: &AgentEvent) -> (&'static str, Option<String>, Op
             None,
             Some(serde_json::json!({ "id": id, "output": output, "is_error": is_error })),
         ),
-        AgentEvent::TurnComplete { session_id, cost_usd } => (
+        AgentEvent::TurnComplete { session_id, cost_usd, usage, stop_reason } => (
             "turn_complete",
             None,
-            Some(serde_json::json!({ "session_id": session_id, "cost_usd": cost_usd })),
+            Some(serde_json::json!({
+                "session_id": session_id,
+                "cost_usd": cost_usd,
+                "usage": usage,
+                "stop_reason": stop_reason.as_str(),
+            })),
         ),
         AgentEvent::Error(e) => ("error", Some(e.clone()), None),
+        AgentEvent::PermissionRequest { request_id, tool, input, options } => (
+            "permission_request",
+            None,
+            Some(serde_json::json!({
+                "request_id": request_id,
+                "tool": tool,
+                "input": input,
+                "options": options.iter().map(|o| o.id.clone()).collect::<Vec<_>>(),
+            })),
+        ),
     }
 }
 
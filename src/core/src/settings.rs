@@ -0,0 +1,740 @@
+//! Typed settings.json model: schema, migration of older layouts, and validation.
+//!
+//! `config` parses settings.json through `parse()` and builds the runtime `Config` from the
+//! resulting [`Settings`]; `vibearound-server check-config` prints the same diagnostics without
+//! starting anything. Every issue carries the JSON path it refers to (e.g. `tunnel.provider`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::agent::AgentKind;
+use crate::config::PermissionMode;
+use crate::tunnels::TunnelProvider;
+
+/// Current settings.json layout version. Files without `version` are version 0 and get migrated.
+pub const SETTINGS_VERSION: u64 = 1;
+
+// ---------------------------------------------------------------------------
+// Schema
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u64,
+    /// Written by the desktop onboarding wizard.
+    pub onboarded: bool,
+    pub working_dir: Option<String>,
+    pub preview_base_url: Option<String>,
+    pub default_agent: Option<String>,
+    pub enabled_agents: Option<Vec<String>>,
+    pub tmux: TmuxSettings,
+    pub workspaces: WorkspacesSettings,
+    pub permissions: PermissionSettings,
+    pub attachments: AttachmentSettings,
+    pub auth: AuthSettings,
+    pub tunnel: TunnelSettings,
+    /// Channel name → config. Keys other than the host-side ones below are passed to the plugin as-is.
+    pub channels: BTreeMap<String, ChannelSettings>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TmuxSettings {
+    pub detach_others: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WorkspacesSettings {
+    pub rules: Vec<WorkspaceRuleSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspaceRuleSettings {
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub chat_id: Option<IdValue>,
+    pub path: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PermissionSettings {
+    pub mode: Option<String>,
+    pub timeout_secs: Option<u64>,
+    /// "allow" or "deny", applied when the user does not answer in time.
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AttachmentSettings {
+    /// Largest file accepted from a chat, in megabytes.
+    pub max_size_mb: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    pub token: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TunnelSettings {
+    pub provider: Option<String>,
+    pub ngrok: NgrokSettings,
+    pub cloudflare: CloudflareSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NgrokSettings {
+    pub auth_token: Option<String>,
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CloudflareSettings {
+    pub tunnel_token: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// Host-side keys of `channels.<name>`; everything else belongs to the plugin.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChannelSettings {
+    pub allowed_senders: Vec<IdValue>,
+    pub admins: Vec<IdValue>,
+    pub verbose: VerboseSettings,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct VerboseSettings {
+    pub show_thinking: bool,
+    pub show_tool_use: bool,
+}
+
+/// A sender or chat id; IMs disagree on whether ids are strings or numbers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged, expecting = "a string or number id")]
+pub enum IdValue {
+    Str(String),
+    Num(serde_json::Number),
+}
+
+impl IdValue {
+    pub fn as_string(&self) -> String {
+        match self {
+            IdValue::Str(s) => s.trim().to_string(),
+            IdValue::Num(n) => n.to_string(),
+        }
+    }
+}
+
+/// Keys the host understands, per object path. Anything else is reported as unknown
+/// (except under `channels.<name>`, which plugins own).
+const KNOWN_KEYS: &[(&str, &[&str])] = &[
+    (
+        "",
+        &[
+            "version",
+            "onboarded",
+            "working_dir",
+            "preview_base_url",
+            "default_agent",
+            "enabled_agents",
+            "tmux",
+            "workspaces",
+            "permissions",
+            "attachments",
+            "auth",
+            "tunnel",
+            "channels",
+        ],
+    ),
+    ("tmux", &["detach_others"]),
+    ("workspaces", &["rules"]),
+    ("permissions", &["mode", "timeout_secs", "default"]),
+    ("attachments", &["max_size_mb"]),
+    ("auth", &["token", "password"]),
+    ("tunnel", &["provider", "ngrok", "cloudflare"]),
+    ("tunnel.ngrok", &["auth_token", "domain"]),
+    ("tunnel.cloudflare", &["tunnel_token", "hostname"]),
+];
+
+// ---------------------------------------------------------------------------
+// Diagnostics
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueLevel {
+    /// The value is unusable and was ignored (or the whole file could not be loaded).
+    Error,
+    /// Loaded, but probably not what was meant (unknown key, migrated key).
+    Warning,
+}
+
+/// One problem found in settings.json.
+#[derive(Debug, Clone)]
+pub struct SettingsIssue {
+    pub level: IssueLevel,
+    /// JSON path, e.g. `tunnel.ngrok.auth_token` or `workspaces.rules[0].path`.
+    pub path: String,
+    pub message: String,
+}
+
+impl SettingsIssue {
+    fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { level: IssueLevel::Error, path: path.into(), message: message.into() }
+    }
+
+    fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { level: IssueLevel::Warning, path: path.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            IssueLevel::Error => "error",
+            IssueLevel::Warning => "warning",
+        };
+        let path = if self.path.is_empty() { "(root)" } else { self.path.as_str() };
+        write!(f, "{}: {}: {}", level, path, self.message)
+    }
+}
+
+/// Parsed settings, the migrated JSON (channels are passed to plugins from it), and non-fatal issues.
+pub struct ParsedSettings {
+    pub settings: Settings,
+    pub root: Value,
+    pub issues: Vec<SettingsIssue>,
+}
+
+// ---------------------------------------------------------------------------
+// Parse / migrate / validate
+// ---------------------------------------------------------------------------
+
+/// Parse settings.json text. `Err` means the file cannot be used at all (invalid JSON or not an
+/// object). A value of the wrong type is reported and falls back to its default; the rest of the
+/// file still applies.
+pub fn parse(data: &str) -> Result<ParsedSettings, Vec<SettingsIssue>> {
+    let mut root: Value = serde_json::from_str(data)
+        .map_err(|e| vec![SettingsIssue::error("", format!("invalid JSON: {}", e))])?;
+    if !root.is_object() {
+        return Err(vec![SettingsIssue::error("", "settings.json must be a JSON object")]);
+    }
+
+    let mut issues = migrate(&mut root);
+    issues.extend(unknown_keys(&root));
+    let settings = deserialize_lenient(&root, &mut issues);
+    issues.extend(validate(&settings, &root));
+    Ok(ParsedSettings { settings, root, issues })
+}
+
+/// Deserialize `root`, dropping each value serde rejects (reported as an error) until the rest
+/// deserializes. Dropped keys take their `#[serde(default)]`; a dropped array element is skipped.
+fn deserialize_lenient(root: &Value, issues: &mut Vec<SettingsIssue>) -> Settings {
+    let mut working = root.clone();
+    // Array elements removed so far, as (array path, original index), to report original paths.
+    let mut removed: Vec<(Vec<PathStep>, usize)> = Vec::new();
+    for _ in 0..MAX_DROPPED_VALUES {
+        let err = match serde_path_to_error::deserialize::<_, Settings>(&working) {
+            Ok(settings) => return settings,
+            Err(err) => err,
+        };
+        let steps = path_steps(err.path());
+        let message = err.into_inner().to_string();
+        if steps.is_empty() || !remove_at(&mut working, &steps) {
+            break;
+        }
+        let original = original_path(&removed, &steps);
+        if let Some((PathStep::Index(i), parent)) = original.split_last() {
+            removed.push((parent.to_vec(), *i));
+        }
+        issues.push(SettingsIssue::error(display_path(&original), format!("{}; ignored", message)));
+    }
+    issues.push(SettingsIssue::error("", "too many invalid values; using defaults for the whole file"));
+    Settings::default()
+}
+
+/// Upper bound on values `deserialize_lenient` drops before giving up.
+const MAX_DROPPED_VALUES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+fn path_steps(path: &serde_path_to_error::Path) -> Vec<PathStep> {
+    use serde_path_to_error::Segment;
+    path.iter()
+        .filter_map(|segment| match segment {
+            Segment::Map { key } => Some(PathStep::Key(key.clone())),
+            Segment::Seq { index } => Some(PathStep::Index(*index)),
+            Segment::Enum { .. } | Segment::Unknown => None,
+        })
+        .collect()
+}
+
+fn display_path(steps: &[PathStep]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        match step {
+            PathStep::Key(key) if out.is_empty() => out.push_str(key),
+            PathStep::Key(key) => {
+                out.push('.');
+                out.push_str(key);
+            }
+            PathStep::Index(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+/// Remove the value at `steps` from `root`; false if there is nothing there.
+fn remove_at(root: &mut Value, steps: &[PathStep]) -> bool {
+    let Some((last, parent)) = steps.split_last() else { return false };
+    let mut node = root;
+    for step in parent {
+        let next = match step {
+            PathStep::Key(key) => node.get_mut(key.as_str()),
+            PathStep::Index(i) => node.get_mut(*i),
+        };
+        let Some(next) = next else { return false };
+        node = next;
+    }
+    match (last, node) {
+        (PathStep::Key(key), Value::Object(map)) => map.remove(key).is_some(),
+        (PathStep::Index(i), Value::Array(items)) if *i < items.len() => {
+            items.remove(*i);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// `steps` (into the partly pruned copy) as a path into the original file, given the array
+/// elements already removed (in original coordinates).
+fn original_path(removed: &[(Vec<PathStep>, usize)], steps: &[PathStep]) -> Vec<PathStep> {
+    let mut original: Vec<PathStep> = Vec::with_capacity(steps.len());
+    for step in steps {
+        let mapped = match step {
+            PathStep::Index(index) => {
+                let mut earlier: Vec<usize> =
+                    removed.iter().filter(|(parent, _)| *parent == original).map(|(_, i)| *i).collect();
+                earlier.sort_unstable();
+                let mut index = *index;
+                for i in earlier {
+                    if i <= index {
+                        index += 1;
+                    }
+                }
+                PathStep::Index(index)
+            }
+            key => key.clone(),
+        };
+        original.push(mapped);
+    }
+    original
+}
+
+/// Read and check a settings file; used by `check-config`.
+pub fn check_file(path: &Path) -> Vec<SettingsIssue> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => match parse(&data) {
+            Ok(parsed) => parsed.issues,
+            Err(issues) => issues,
+        },
+        Err(e) => vec![SettingsIssue::error("", format!("cannot read {}: {}", path.display(), e))],
+    }
+}
+
+/// Upgrade older layouts in place to `SETTINGS_VERSION`. Each moved key is reported as a warning
+/// so the user can update the file; the in-memory config already uses the new location.
+pub fn migrate(root: &mut Value) -> Vec<SettingsIssue> {
+    let mut issues = Vec::new();
+    let version = root.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+
+    if version < 1 {
+        // v0 kept the preview URL under `tunnel`.
+        let legacy = root
+            .get_mut("tunnel")
+            .and_then(|t| t.as_object_mut())
+            .and_then(|t| t.remove("preview_base_url"));
+        if let Some(legacy) = legacy {
+            if root.get("preview_base_url").is_none() {
+                root["preview_base_url"] = legacy;
+                issues.push(SettingsIssue::warning(
+                    "tunnel.preview_base_url",
+                    "deprecated location; move it to top-level `preview_base_url`",
+                ));
+            } else {
+                issues.push(SettingsIssue::warning(
+                    "tunnel.preview_base_url",
+                    "ignored because top-level `preview_base_url` is set; remove it",
+                ));
+            }
+        }
+    }
+    if version > SETTINGS_VERSION {
+        issues.push(SettingsIssue::warning(
+            "version",
+            format!(
+                "settings version {} is newer than this build understands ({}); some keys may be ignored",
+                version, SETTINGS_VERSION
+            ),
+        ));
+    }
+
+    root["version"] = Value::from(version.max(SETTINGS_VERSION));
+    issues
+}
+
+fn unknown_keys(root: &Value) -> Vec<SettingsIssue> {
+    let mut issues = Vec::new();
+    for (path, known) in KNOWN_KEYS {
+        let obj = if path.is_empty() {
+            root.as_object()
+        } else {
+            path.split('.').try_fold(root, |v, key| v.get(key)).and_then(|v| v.as_object())
+        };
+        let Some(obj) = obj else { continue };
+        for key in obj.keys().filter(|k| !known.contains(&k.as_str())) {
+            let full = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            issues.push(SettingsIssue::warning(full, "unknown key (typo?); it is ignored"));
+        }
+    }
+    issues
+}
+
+/// Semantic checks on a well-typed file. Offending values fall back to their defaults.
+fn validate(s: &Settings, root: &Value) -> Vec<SettingsIssue> {
+    let mut issues = Vec::new();
+    let agent_names = || {
+        AgentKind::all().iter().map(|k| k.to_string()).collect::<Vec<_>>().join(", ")
+    };
+
+    if let Some(agents) = &s.enabled_agents {
+        for (i, name) in agents.iter().enumerate() {
+            if AgentKind::from_str_loose(name).is_none() {
+                issues.push(SettingsIssue::error(
+                    format!("enabled_agents[{}]", i),
+                    format!("unknown agent {:?} (expected one of {})", name, agent_names()),
+                ));
+            }
+        }
+        if !agents.is_empty() && agents.iter().all(|n| AgentKind::from_str_loose(n).is_none()) {
+            issues.push(SettingsIssue::error("enabled_agents", "no valid agents listed; all agents are enabled"));
+        }
+    }
+    if let Some(name) = s.default_agent.as_deref().filter(|n| !n.trim().is_empty()) {
+        match AgentKind::from_str_loose(name) {
+            None => issues.push(SettingsIssue::error(
+                "default_agent",
+                format!("unknown agent {:?} (expected one of {})", name, agent_names()),
+            )),
+            Some(kind) => {
+                let enabled = s.enabled_agents.as_ref().is_none_or(|list| {
+                    list.iter().any(|n| AgentKind::from_str_loose(n) == Some(kind))
+                });
+                if !enabled {
+                    issues.push(SettingsIssue::warning("default_agent", format!("{} is not in enabled_agents", kind)));
+                }
+            }
+        }
+    }
+
+    if let Some(mode) = &s.permissions.mode {
+        if PermissionMode::from_name(mode).is_none() {
+            issues.push(SettingsIssue::error(
+                "permissions.mode",
+                format!("unknown mode {:?} (expected \"auto\" or \"ask\"); using auto", mode),
+            ));
+        }
+    }
+    if s.permissions.timeout_secs == Some(0) {
+        issues.push(SettingsIssue::error("permissions.timeout_secs", "must be greater than 0"));
+    }
+    if s.attachments.max_size_mb == Some(0) {
+        issues.push(SettingsIssue::error("attachments.max_size_mb", "must be greater than 0"));
+    }
+    if let Some(default) = &s.permissions.default {
+        if !matches!(default.trim().to_lowercase().as_str(), "allow" | "deny") {
+            issues.push(SettingsIssue::error(
+                "permissions.default",
+                format!("expected \"allow\" or \"deny\", got {:?}; using deny", default),
+            ));
+        }
+    }
+
+    if let Some(provider) = s.tunnel.provider.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+        match TunnelProvider::from_name(provider) {
+            Some(TunnelProvider::Ngrok) if blank(&s.tunnel.ngrok.auth_token) => issues.push(SettingsIssue::error(
+                "tunnel.ngrok.auth_token",
+                "required when tunnel.provider is \"ngrok\"",
+            )),
+            Some(TunnelProvider::Cloudflare) => {
+                if blank(&s.tunnel.cloudflare.tunnel_token) {
+                    issues.push(SettingsIssue::error(
+                        "tunnel.cloudflare.tunnel_token",
+                        "required when tunnel.provider is \"cloudflare\"",
+                    ));
+                }
+                if blank(&s.tunnel.cloudflare.hostname) {
+                    issues.push(SettingsIssue::error(
+                        "tunnel.cloudflare.hostname",
+                        "required when tunnel.provider is \"cloudflare\"",
+                    ));
+                }
+            }
+            Some(_) => {}
+            None if provider.eq_ignore_ascii_case("none") => issues.push(SettingsIssue::warning(
+                "tunnel.provider",
+                "\"none\" is not supported yet; localtunnel is started instead",
+            )),
+            None => issues.push(SettingsIssue::error(
+                "tunnel.provider",
+                format!("unknown provider {:?} (expected localtunnel, ngrok or cloudflare); using localtunnel", provider),
+            )),
+        }
+    }
+
+    for (i, rule) in s.workspaces.rules.iter().enumerate() {
+        if rule.path.trim().is_empty() {
+            issues.push(SettingsIssue::error(format!("workspaces.rules[{}].path", i), "must not be empty"));
+        }
+    }
+
+    for (name, channel) in &s.channels {
+        for (key, ids) in [("allowed_senders", &channel.allowed_senders), ("admins", &channel.admins)] {
+            for (i, id) in ids.iter().enumerate() {
+                if id.as_string().is_empty() {
+                    issues.push(SettingsIssue::error(
+                        format!("channels.{}.{}[{}]", name, key, i),
+                        "empty sender id",
+                    ));
+                }
+            }
+        }
+    }
+    // Plugin-specific keys are passed through untyped; only the host's built-in channels are checked.
+    if root.pointer("/channels/http").is_some() {
+        issues.extend(required_str(
+            root,
+            "/channels/http/secret",
+            IssueLevel::Error,
+            "required; webhook requests are rejected until it is set",
+        ));
+    }
+    if let Some(slack) = root.pointer("/channels/slack") {
+        for (key, prefix) in [("app_token", "xapp-"), ("bot_token", "xoxb-")] {
+            match slack.get(key).and_then(|v| v.as_str()).map(str::trim) {
+                None | Some("") => issues.push(SettingsIssue::warning(
+                    format!("channels.slack.{}", key),
+                    "required by the built-in Slack channel",
+                )),
+                Some(token) if !token.starts_with(prefix) => issues.push(SettingsIssue::warning(
+                    format!("channels.slack.{}", key),
+                    format!("expected a token starting with {:?}", prefix),
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+    if root.pointer("/channels/discord").is_some() {
+        issues.extend(required_str(
+            root,
+            "/channels/discord/bot_token",
+            IssueLevel::Warning,
+            "required by the built-in Discord channel",
+        ));
+    }
+    if root.pointer("/channels/email").is_some() {
+        for key in ["imap_host", "smtp_host", "username"] {
+            issues.extend(required_str(
+                root,
+                &format!("/channels/email/{}", key),
+                IssueLevel::Warning,
+                "required by the built-in email channel",
+            ));
+        }
+        let listed = s.channels.get("email").is_some_and(|c| {
+            c.allowed_senders.iter().chain(&c.admins).any(|id| !matches!(id.as_string().as_str(), "" | "*"))
+        });
+        if !listed {
+            issues.push(SettingsIssue::warning(
+                "channels.email.allowed_senders",
+                "required by the built-in email channel; From addresses can be forged, so it is never open",
+            ));
+        }
+    }
+    if root.pointer("/channels/matrix").is_some() {
+        for key in ["homeserver_url", "access_token"] {
+            issues.extend(required_str(
+                root,
+                &format!("/channels/matrix/{}", key),
+                IssueLevel::Warning,
+                "required by the built-in Matrix channel",
+            ));
+        }
+    }
+
+    issues
+}
+
+/// An issue at `pointer` (a JSON pointer into the raw file) when the string there is missing or blank.
+fn required_str(root: &Value, pointer: &str, level: IssueLevel, message: &str) -> Option<SettingsIssue> {
+    let missing = root.pointer(pointer).and_then(|v| v.as_str()).is_none_or(|v| v.trim().is_empty());
+    missing.then(|| SettingsIssue {
+        level,
+        path: pointer.trim_start_matches('/').replace('/', "."),
+        message: message.to_string(),
+    })
+}
+
+fn blank(v: &Option<String>) -> bool {
+    v.as_deref().is_none_or(|s| s.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(issues: &[SettingsIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.path.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_rejects_unusable_files() {
+        let issues = parse("{ not json").err().unwrap();
+        assert_eq!(paths(&issues), [""]);
+        assert!(issues[0].message.starts_with("invalid JSON"));
+
+        assert_eq!(paths(&parse("[]").err().unwrap()), [""]);
+    }
+
+    #[test]
+    fn wrong_typed_values_fall_back_to_their_defaults() {
+        let parsed = parse(
+            r#"{
+                "working_dir": "~/code",
+                "permissions": { "mode": "ask", "timeout_secs": "300" },
+                "tunnel": { "provider": "ngrok", "ngrok": { "auth_token": "t", "domain": 5 } },
+                "workspaces": { "rules": [ { "path": 1 }, { "channel": "telegram", "path": "~/a" }, { "path": [] } ] },
+                "channels": {
+                    "telegram": { "bot_token": "x", "verbose": { "show_thinking": "true", "show_tool_use": true } },
+                    "slack": { "allowed_senders": ["U1", {}, "U2"] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let errors: Vec<&str> =
+            parsed.issues.iter().filter(|i| i.level == IssueLevel::Error).map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            errors,
+            [
+                "channels.slack.allowed_senders[1]",
+                "channels.telegram.verbose.show_thinking",
+                "permissions.timeout_secs",
+                "tunnel.ngrok.domain",
+                "workspaces.rules[0].path",
+                "workspaces.rules[0]",
+                "workspaces.rules[2].path",
+                "workspaces.rules[2]",
+            ]
+        );
+
+        // Everything else still applies.
+        let s = &parsed.settings;
+        assert_eq!(s.working_dir.as_deref(), Some("~/code"));
+        assert_eq!(s.permissions.mode.as_deref(), Some("ask"));
+        assert_eq!(s.permissions.timeout_secs, None);
+        assert_eq!(s.tunnel.provider.as_deref(), Some("ngrok"));
+        assert_eq!(s.tunnel.ngrok.auth_token.as_deref(), Some("t"));
+        assert_eq!(s.tunnel.ngrok.domain, None);
+        assert_eq!(s.workspaces.rules.len(), 1);
+        assert_eq!(s.workspaces.rules[0].path, "~/a");
+        assert!(!s.channels["telegram"].verbose.show_thinking);
+        assert!(s.channels["telegram"].verbose.show_tool_use);
+        let ids: Vec<String> = s.channels["slack"].allowed_senders.iter().map(IdValue::as_string).collect();
+        assert_eq!(ids, ["U1", "U2"]);
+        // Plugins still get their channel config.
+        assert_eq!(parsed.root.pointer("/channels/telegram/bot_token"), Some(&Value::from("x")));
+    }
+
+    #[test]
+    fn parse_reads_typed_settings() {
+        let parsed = parse(
+            r#"{
+                "version": 1,
+                "default_agent": "claude",
+                "channels": { "telegram": { "allowed_senders": [123456789, " alice "], "bot_token": "x" } }
+            }"#,
+        )
+        .unwrap();
+        assert!(parsed.issues.is_empty(), "{:?}", parsed.issues);
+        assert_eq!(parsed.settings.default_agent.as_deref(), Some("claude"));
+        let ids: Vec<String> = parsed.settings.channels["telegram"].allowed_senders.iter().map(IdValue::as_string).collect();
+        assert_eq!(ids, ["123456789", "alice"]);
+        // Plugin keys stay in the raw JSON.
+        assert_eq!(parsed.root.pointer("/channels/telegram/bot_token"), Some(&Value::from("x")));
+    }
+
+    #[test]
+    fn migrate_moves_tunnel_preview_base_url() {
+        let mut root = serde_json::json!({ "tunnel": { "provider": "ngrok", "preview_base_url": "https://p.example" } });
+        let issues = migrate(&mut root);
+        assert_eq!(paths(&issues), ["tunnel.preview_base_url"]);
+        assert_eq!(issues[0].level, IssueLevel::Warning);
+        assert_eq!(root["preview_base_url"], "https://p.example");
+        assert!(root["tunnel"].get("preview_base_url").is_none());
+        assert_eq!(root["version"], SETTINGS_VERSION);
+    }
+
+    #[test]
+    fn migrate_keeps_an_existing_top_level_preview_base_url() {
+        let mut root = serde_json::json!({
+            "preview_base_url": "https://new.example",
+            "tunnel": { "preview_base_url": "https://old.example" },
+        });
+        let issues = migrate(&mut root);
+        assert_eq!(paths(&issues), ["tunnel.preview_base_url"]);
+        assert_eq!(root["preview_base_url"], "https://new.example");
+        assert!(root["tunnel"].get("preview_base_url").is_none());
+    }
+
+    #[test]
+    fn migrate_leaves_current_files_alone_and_warns_on_newer_ones() {
+        let mut root = serde_json::json!({ "version": 1, "tunnel": { "preview_base_url": "https://p.example" } });
+        assert!(migrate(&mut root).is_empty());
+        assert_eq!(root["tunnel"]["preview_base_url"], "https://p.example");
+
+        let mut root = serde_json::json!({ "version": SETTINGS_VERSION + 1 });
+        assert_eq!(paths(&migrate(&mut root)), ["version"]);
+        assert_eq!(root["version"], SETTINGS_VERSION + 1);
+    }
+
+    #[test]
+    fn unknown_keys_reports_typos_outside_channels() {
+        let root = serde_json::json!({
+            "workng_dir": "~/code",
+            "tunnel": { "provider": "ngrok", "ngrok": { "authtoken": "x" } },
+            "channels": { "feishu": { "app_id": "a", "anything": true } },
+        });
+        let issues = unknown_keys(&root);
+        assert_eq!(paths(&issues), ["workng_dir", "tunnel.ngrok.authtoken"]);
+        assert!(issues.iter().all(|i| i.level == IssueLevel::Warning));
+    }
+}
@@ -8,7 +8,7 @@ mod localtunnel;
 mod ngrok;
 
 /// Tunnel provider: localtunnel (default), ngrok, or cloudflare.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 #[allow(dead_code)] // Ngrok/Cloudflare for future use
 pub enum TunnelProvider {
     #[default]
@@ -28,10 +28,16 @@ impl TunnelProvider {
 
     /// Parse from config string (e.g. from settings.json "tunnel.provider").
     pub fn from_config(s: &str) -> Self {
+        Self::from_name(s).unwrap_or_default()
+    }
+
+    /// Strict parse; `None` for unknown providers.
+    pub fn from_name(s: &str) -> Option<Self> {
         match s.trim().to_lowercase().as_str() {
-            "ngrok" => TunnelProvider::Ngrok,
-            "cloudflare" => TunnelProvider::Cloudflare,
-            _ => TunnelProvider::Localtunnel,
+            "localtunnel" => Some(TunnelProvider::Localtunnel),
+            "ngrok" => Some(TunnelProvider::Ngrok),
+            "cloudflare" => Some(TunnelProvider::Cloudflare),
+            _ => None,
         }
     }
 
@@ -59,8 +65,9 @@ pub trait TunnelBackend: Send + Sync {
 }
 
 /// Guard that keeps the tunnel alive. Await `wait()` until the tunnel is done (e.g. process exit or SDK session closed).
+/// Dropping the guard (e.g. aborting the task that awaits it) shuts the tunnel down.
 pub enum TunnelGuard {
-    /// Tunnel is a child process (e.g. localtunnel, or ngrok CLI).
+    /// Tunnel is a child process (e.g. localtunnel, or ngrok CLI), spawned with kill_on_drop.
     Process(tokio::process::Child),
     /// Tunnel is held by an SDK task (e.g. ngrok Rust SDK).
     Sdk(tokio::task::JoinHandle<()>),
@@ -68,9 +75,9 @@ pub enum TunnelGuard {
 
 impl TunnelGuard {
     /// Wait until the tunnel exits. For Process, waits for the child; for Sdk, waits for the background task.
-    pub async fn wait(self) {
-        match self {
-            TunnelGuard::Process(mut child) => {
+    pub async fn wait(mut self) {
+        match &mut self {
+            TunnelGuard::Process(child) => {
                 let _ = child.wait().await;
             }
             TunnelGuard::Sdk(handle) => {
@@ -80,11 +87,19 @@ impl TunnelGuard {
     }
 }
 
+impl Drop for TunnelGuard {
+    fn drop(&mut self) {
+        if let TunnelGuard::Sdk(handle) = self {
+            handle.abort();
+        }
+    }
+}
+
 /// Start the web tunnel using the default provider (Localtunnel) and global config.
 /// Returns (guard, public URL). Caller must keep the guard and await `guard.wait()` to keep the tunnel alive.
 pub async fn start_web_tunnel() -> Result<(TunnelGuard, String), Box<dyn std::error::Error + Send + Sync>> {
     let config = crate::config::ensure_loaded();
-    start_web_tunnel_with_provider(TunnelProvider::default(), config).await
+    start_web_tunnel_with_provider(TunnelProvider::default(), &config).await
 }
 
 /// Start the web tunnel with the given provider and config (unified dispatch via TunnelBackend).
@@ -27,7 +27,8 @@ pub async fn start_web_tunnel(
     let mut cmd = Command::new("cloudflared");
     cmd.args(["tunnel", "run", "--token", token])
         .stdout(Stdio::null())
-        .stderr(Stdio::inherit());
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
 
     let child = cmd.spawn().map_err(|e| {
         format!("Failed to spawn cloudflared (is it installed? brew install cloudflared): {}", e)
@@ -43,7 +43,8 @@ pub async fn start(port: u16) -> Result<(super::TunnelGuard, String), Box<dyn st
     let mut cmd = Command::new("npx");
     cmd.args(["localtunnel", "--port", port.to_string().as_str()])
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
 
     let mut child = cmd.spawn().map_err(|e| {
         format!(
@@ -1,6 +1,6 @@
-//! Workspace utilities: directory checks and HTML detection.
+//! Workspace utilities: per-chat workspace resolution, directory checks and HTML detection.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// True if the directory exists and has no entries (or only . and ..).
 pub fn is_dir_empty(path: &Path) -> bool {
@@ -23,3 +23,69 @@ pub fn dir_has_html(path: &Path) -> bool {
             })
         })
 }
+
+/// Working directory for a chat's agents, in order of precedence:
+/// the chat's `/cd` binding, a matching `workspaces.rules` entry in settings.json,
+/// then a per-chat directory under `~/.vibearound/workspaces/chats/`.
+pub fn chat_workspace(channel_kind: &str, chat_id: &str, binding: Option<&Path>) -> PathBuf {
+    if let Some(dir) = binding {
+        return dir.to_path_buf();
+    }
+    if let Some(dir) = crate::config::ensure_loaded().workspace_rule_for(channel_kind, chat_id) {
+        return dir;
+    }
+    default_chat_workspace(channel_kind, chat_id)
+}
+
+/// Per-chat directory used when no binding or rule applies.
+pub fn default_chat_workspace(channel_kind: &str, chat_id: &str) -> PathBuf {
+    crate::config::data_dir()
+        .join("workspaces")
+        .join("chats")
+        .join(path_component(channel_kind))
+        .join(path_component(chat_id))
+}
+
+/// Expand a leading `~` and resolve relative paths against the configured `working_dir`.
+pub fn resolve_project_path(input: &str) -> PathBuf {
+    let path = crate::config::expand_home(input.trim());
+    if path.is_absolute() {
+        path
+    } else {
+        crate::config::ensure_loaded().working_dir.join(path)
+    }
+}
+
+/// Platform ids (e.g. Telegram "-100123", Feishu "oc_xxx") as a single safe path component.
+/// Ids that needed characters replaced get a short hash of the original appended, so that e.g.
+/// `a/b` and `a:b` do not share a directory.
+pub(crate) fn path_component(s: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let cleaned: String = s
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_') { c } else { '_' })
+        .collect();
+    if !cleaned.is_empty() && cleaned == s {
+        return cleaned;
+    }
+    let hash: String = Sha256::digest(s.as_bytes()).iter().take(4).map(|b| format!("{:02x}", b)).collect();
+    format!("{}_{}", cleaned, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_component_keeps_safe_ids_and_separates_rewritten_ones() {
+        assert_eq!(path_component("-100123"), "-100123");
+        assert_eq!(path_component("oc_xxx"), "oc_xxx");
+        let (slash, colon, plain) = (path_component("a/b"), path_component("a:b"), path_component("a_b"));
+        assert!(slash.starts_with("a_b_") && colon.starts_with("a_b_"));
+        assert_ne!(slash, colon);
+        assert_ne!(slash, plain);
+        assert_ne!(path_component(""), path_component("/"));
+        assert!(!path_component("../..").contains(['.', '/']));
+    }
+}
@@ -1,10 +1,23 @@
 import { useState, useEffect, useCallback, useRef } from "react";
+import { invoke } from "@tauri-apps/api/core";
 
 const API_BASE = "http://127.0.0.1:12358";
 const WS_URL = "ws://127.0.0.1:12358/ws/services";
 const POLL_INTERVAL = 5000;
 const WS_RECONNECT_DELAY = 3000;
 
+let tokenPromise: Promise<string> | null = null;
+
+/** Dashboard access token from the desktop backend (the server requires it on every route). */
+function dashboardToken(): Promise<string> {
+  if (!tokenPromise) tokenPromise = invoke<string>("dashboard_token");
+  return tokenPromise;
+}
+
+async function authHeaders(): Promise<HeadersInit> {
+  return { Authorization: `Bearer ${await dashboardToken()}` };
+}
+
 export interface ServiceInfo {
   id: string;
   category: string;
@@ -43,7 +56,7 @@ export function useServices() {
   // HTTP fallback fetch
   const fetchServices = useCallback(async () => {
     try {
-      const res = await fetch(`${API_BASE}/api/services`);
+      const res = await fetch(`${API_BASE}/api/services`, { headers: await authHeaders() });
       if (!res.ok) throw new Error(`HTTP ${res.status}`);
       const json: ServicesSnapshot = await res.json();
       setData(json);
@@ -71,10 +84,12 @@ export function useServices() {
   }, []);
 
   // WebSocket connection
-  const connectWs = useCallback(() => {
+  const connectWs = useCallback(async () => {
     if (wsRef.current?.readyState === WebSocket.OPEN) return;
 
-    const ws = new WebSocket(WS_URL);
+    // Browsers cannot set headers on WebSocket handshakes; the server accepts ?token= there.
+    const token = await dashboardToken();
+    const ws = new WebSocket(`${WS_URL}?token=${encodeURIComponent(token)}`);
     wsRef.current = ws;
 
     ws.onopen = () => {
@@ -124,7 +139,7 @@ export function useServices() {
       try {
         const res = await fetch(
           `${API_BASE}/api/services/${encodeURIComponent(category)}/${encodeURIComponent(id)}`,
-          { method: "DELETE" }
+          { method: "DELETE", headers: await authHeaders() }
         );
         if (!res.ok) throw new Error(`HTTP ${res.status}`);
         // If WS is connected, server will push the update.
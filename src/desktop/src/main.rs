@@ -19,6 +19,12 @@ pub struct AppServiceManager(pub Arc<common::service::ServiceStatusManager>);
 /// Whether the app is currently in onboarding mode (tray reads this).
 pub struct OnboardingActive(pub std::sync::atomic::AtomicBool);
 
+/// Dashboard access token for the desktop UI's API and WebSocket calls.
+#[tauri::command]
+fn dashboard_token() -> String {
+    config::dashboard_token().to_string()
+}
+
 fn main() {
     let _config = config::ensure_loaded();
 
@@ -55,6 +61,7 @@ fn main() {
             onboarding::get_settings,
             onboarding::save_settings,
             onboarding::finish_onboarding,
+            dashboard_token,
         ])
         .setup(move |app| {
             tray::setup(app)?;
@@ -79,9 +86,10 @@ fn main() {
                     gate.notified().await;
                     eprintln!("[VibeAround] Onboarding complete, starting daemon…");
 
-                    // Reload config after onboarding wrote new settings
-                    // (config is a OnceLock singleton, so we just proceed —
-                    //  the daemon will re-read settings.json via its own paths)
+                    // Onboarding wrote new settings; pick them up before the daemon starts.
+                    if let Err(e) = config::reload() {
+                        eprintln!("[VibeAround] Failed to reload settings after onboarding: {}", e);
+                    }
 
                     // Mark onboarding as done for tray
                     if let Some(state) = app_handle.try_state::<OnboardingActive>() {
@@ -52,7 +52,13 @@ pub fn setup<R: Runtime>(app: &App<R>) -> Result<(), Box<dyn std::error::Error>>
                 }
             }
             "open_local" => {
-                let _ = open::that(LOCAL_DASHBOARD_URL);
+                // Sign the browser in with the dashboard token on the way in.
+                let url = format!(
+                    "{}/login?token={}",
+                    LOCAL_DASHBOARD_URL,
+                    common::config::dashboard_token()
+                );
+                let _ = open::that(url);
             }
             "open_tunnel" => {
                 if let Some(state) = app.try_state::<AppServiceManager>() {
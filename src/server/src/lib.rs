@@ -6,9 +6,13 @@ pub use web_server::run_web_server;
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use common::agent_manager::AgentManager;
+use common::channel_manager::channels::http::{HttpChannelManager, CHANNEL_NAME as HTTP_CHANNEL};
+use common::channel_manager::channels::{discord, email, matrix, slack, telegram};
 use common::channel_manager::channels::web::WebChannelManager;
+use common::channel_manager::manifest;
 use common::channel_manager::ChannelManager;
 use common::config;
 use common::service::ServiceStatusManager;
@@ -44,6 +48,7 @@ impl ServerDaemon {
     /// 3. Hub event subscriber (syncs hub state → ServiceStatusManager → Dashboard)
     /// 4. Web server (Axum: HTTP API + WebSocket + SPA)
     /// 5. Tunnel (cloudflare / localtunnel / ngrok)
+    /// 6. settings.json watcher (hot reload)
     pub async fn start(&self, dist_path: PathBuf) -> Result<(), String> {
         // Check if another instance is already running on the same port
         if let Ok(_) = tokio::net::TcpStream::connect(("127.0.0.1", self.port)).await {
@@ -62,6 +67,7 @@ impl ServerDaemon {
         let session_hub = Arc::new(SessionHub::new());
         let agent_hub = Arc::new(AgentManager::new());
         let web_channel = WebChannelManager::new();
+        let http_channel = HttpChannelManager::new();
 
         // Wire up cross-references
         channel_hub.set_session_hub(Arc::clone(&session_hub));
@@ -78,37 +84,26 @@ impl ServerDaemon {
                 }
             });
         }
+        let (http_outbound_tx, mut http_outbound_rx) = http_channel.sender();
+        channel_hub.start_internal_plugin_with_capabilities(
+            HTTP_CHANNEL,
+            http_outbound_tx,
+            HttpChannelManager::capabilities(),
+        );
+        {
+            let http_channel = Arc::clone(&http_channel);
+            tokio::spawn(async move {
+                while let Some(notif) = http_outbound_rx.recv().await {
+                    http_channel.dispatch_notification(notif);
+                }
+            });
+        }
 
         // 2. Channel plugins — start plugins for each channel in settings.json
+        //    (subscribe first so no supervisor event is missed)
+        let mut channel_hub_rx = channel_hub.subscribe();
         for name in cfg.channel_names() {
-            let plugin_entry = "dist/main.js";
-            let candidates = [
-                config::data_dir().join("plugins").join(&name),
-                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                    .parent()
-                    .unwrap_or(std::path::Path::new("."))
-                    .join("plugins")
-                    .join(&name),
-                std::env::current_dir()
-                    .unwrap_or_default()
-                    .join("src")
-                    .join("plugins")
-                    .join(&name),
-            ];
-
-            let plugin_dir = match candidates.iter().find(|d| d.join(plugin_entry).exists()) {
-                Some(d) => d.clone(),
-                None => {
-                    eprintln!("[VibeAround][daemon] no plugin found for channel '{}', skipping", name);
-                    continue;
-                }
-            };
-
-            if let Some(abort_handle) =
-                channel_hub.start_plugin(plugin_dir, &name).await
-            {
-                services.register_channel(&name, abort_handle);
-            }
+            start_channel(&channel_hub, services, &name).await;
         }
 
         // 3. Subscribe to hub events → sync to ServiceStatusManager → Dashboard
@@ -129,39 +124,54 @@ impl ServerDaemon {
                 }
             }
         });
+        let plugin_services = Arc::clone(services);
+        tokio::spawn(async move {
+            loop {
+                let event = match channel_hub_rx.recv().await {
+                    Ok(event) => event,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                };
+                match event {
+                    HubEvent::OnPluginStarted { channel, restarts } => {
+                        plugin_services.set_channel_running(&channel, restarts);
+                    }
+                    HubEvent::OnPluginFailed { channel, error, restarts, retry_in_secs } => {
+                        eprintln!(
+                            "[daemon] channel {} failed: {} (retry in {}s)",
+                            channel, error, retry_in_secs
+                        );
+                        plugin_services.set_channel_failed(&channel, error, restarts);
+                    }
+                    _ => {}
+                }
+            }
+        });
 
         // 4. Web server (Axum)
         let web_services = Arc::clone(services);
         let web_channel_hub = Arc::clone(&channel_hub);
+        let web_agent_hub = Arc::clone(&agent_hub);
         let web_channel_manager = Arc::clone(&web_channel);
+        let http_channel_manager = Arc::clone(&http_channel);
         let web_handle = tokio::spawn(async move {
             run_web_server(
                 common::config::DEFAULT_PORT,
                 dist_path,
                 web_services,
                 web_channel_hub,
+                web_agent_hub,
                 web_channel_manager,
+                http_channel_manager,
             )
             .await
         });
 
         // 5. Tunnel
-        let tunnel_provider = cfg.tunnel_provider;
-        eprintln!("[VibeAround][daemon] Tunnel ({})", tunnel_provider.as_str());
-        let tunnel_services = Arc::clone(services);
-        let tunnel_handle = tokio::spawn(async move {
-            match tunnels::start_web_tunnel_with_provider(tunnel_provider, cfg).await {
-                Ok((guard, url)) => {
-                    eprintln!("[VibeAround][daemon] Tunnel URL: {}", url);
-                    tunnel_services.set_tunnel_url(tunnel_provider.as_str(), &url);
-                    guard.wait().await;
-                }
-                Err(e) => {
-                    eprintln!("[VibeAround][daemon] Tunnel failed: {}", e);
-                }
-            }
-        });
-        services.register_tunnel(tunnel_provider, tunnel_handle.abort_handle());
+        start_tunnel(services);
+
+        // 6. Watch settings.json and apply changes without a restart
+        spawn_settings_watcher(Arc::clone(&channel_hub), Arc::clone(services));
 
         // Wait for web server or ctrl_c
         tokio::select! {
@@ -180,3 +190,141 @@ impl ServerDaemon {
         Ok(())
     }
 }
+
+/// How often settings.json's mtime is checked.
+const SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Locate the plugin for a channel (`plugin.json` or `dist/main.js`): ~/.vibearound/plugins,
+/// then the source tree.
+fn find_plugin_dir(name: &str) -> Option<PathBuf> {
+    let candidates = [
+        config::data_dir().join("plugins").join(name),
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap_or(std::path::Path::new("."))
+            .join("plugins")
+            .join(name),
+        std::env::current_dir()
+            .unwrap_or_default()
+            .join("src")
+            .join("plugins")
+            .join(name),
+    ];
+    candidates.into_iter().find(|d| manifest::is_plugin_dir(d))
+}
+
+/// Start one channel plugin and register it with the dashboard.
+/// An installed plugin wins; otherwise a built-in transport is used when there is one
+/// (Telegram, Slack, Discord, email, Matrix).
+async fn start_channel(channel_hub: &Arc<ChannelManager>, services: &Arc<ServiceStatusManager>, name: &str) {
+    if name == HTTP_CHANNEL {
+        // Always registered; the webhook reads channels.http on every request.
+        return;
+    }
+    let Some(plugin_dir) = find_plugin_dir(name) else {
+        let builtin = match name {
+            "telegram" => telegram::start(channel_hub, name),
+            "slack" => slack::start(channel_hub, name),
+            "discord" => discord::start(channel_hub, name),
+            "email" => email::start(channel_hub, name),
+            "matrix" => matrix::start(channel_hub, name),
+            _ => {
+                eprintln!("[VibeAround][daemon] no plugin found for channel '{}', skipping", name);
+                return;
+            }
+        };
+        match builtin {
+            Ok(abort_handle) => services.register_channel(name, abort_handle),
+            Err(e) => eprintln!("[VibeAround][daemon] built-in {} channel not started: {}", name, e),
+        }
+        return;
+    };
+    if let Some(abort_handle) = channel_hub.start_plugin(plugin_dir, name).await {
+        services.register_channel(name, abort_handle);
+    }
+}
+
+fn stop_channel(channel_hub: &ChannelManager, services: &ServiceStatusManager, name: &str) {
+    channel_hub.stop_plugin(name);
+    services.unregister_channel(name);
+}
+
+/// Start the tunnel for the current config's provider and register it with the dashboard.
+fn start_tunnel(services: &Arc<ServiceStatusManager>) {
+    let cfg = config::ensure_loaded();
+    let tunnel_provider = cfg.tunnel_provider;
+    eprintln!("[VibeAround][daemon] Tunnel ({})", tunnel_provider.as_str());
+    let tunnel_services = Arc::clone(services);
+    let tunnel_handle = tokio::spawn(async move {
+        match tunnels::start_web_tunnel_with_provider(tunnel_provider, &cfg).await {
+            Ok((guard, url)) => {
+                eprintln!("[VibeAround][daemon] Tunnel URL: {}", url);
+                tunnel_services.set_tunnel_url(tunnel_provider.as_str(), &url);
+                guard.wait().await;
+            }
+            Err(e) => {
+                eprintln!("[VibeAround][daemon] Tunnel failed: {}", e);
+            }
+        }
+    });
+    services.register_tunnel(tunnel_provider, tunnel_handle.abort_handle());
+}
+
+/// Poll settings.json and reload it when it changes.
+fn spawn_settings_watcher(channel_hub: Arc<ChannelManager>, services: Arc<ServiceStatusManager>) {
+    let path = config::settings_path();
+    let mtime = |p: &std::path::Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    tokio::spawn(async move {
+        let mut last = mtime(&path);
+        let mut interval = tokio::time::interval(SETTINGS_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let current = mtime(&path);
+            if current == last {
+                continue;
+            }
+            last = current;
+            let _ = reload_settings(&channel_hub, &services).await;
+        }
+    });
+}
+
+/// Re-read settings.json and apply what changed: restart affected channel plugins and the tunnel.
+/// Agent settings (enabled_agents, default_agent, permissions) are read per use and apply to new sessions.
+pub(crate) async fn reload_settings(
+    channel_hub: &Arc<ChannelManager>,
+    services: &Arc<ServiceStatusManager>,
+) -> Result<(), String> {
+    let change = config::reload().map_err(|e| {
+        eprintln!("[VibeAround][daemon] settings reload failed, keeping current config: {}", e);
+        e
+    })?;
+
+    for name in change.channels_removed() {
+        eprintln!("[VibeAround][daemon] channel '{}' removed from settings — stopping", name);
+        stop_channel(channel_hub, services, &name);
+    }
+    for name in change.channels_changed() {
+        eprintln!("[VibeAround][daemon] channel '{}' config changed — restarting", name);
+        stop_channel(channel_hub, services, &name);
+        start_channel(channel_hub, services, &name).await;
+    }
+    for name in change.channels_added() {
+        eprintln!("[VibeAround][daemon] channel '{}' added — starting", name);
+        start_channel(channel_hub, services, &name).await;
+    }
+    if change.tunnel_changed() {
+        eprintln!("[VibeAround][daemon] tunnel settings changed — restarting tunnel");
+        services.stop_tunnels();
+        start_tunnel(services);
+    }
+    let restart = change.restart_required();
+    if !restart.is_empty() {
+        eprintln!(
+            "[VibeAround][daemon] changes to {} apply after a restart",
+            restart.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -1,8 +1,24 @@
 //! Standalone VibeAround server binary — starts the ServerDaemon from the command line.
+//!
+//! Usage:
+//!   vibearound-server                      run the daemon
+//!   vibearound-server check-config [path]  validate settings.json and exit (1 if it has errors)
 
 use std::path::PathBuf;
 
+use common::settings::{self, IssueLevel};
+
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => {}
+        Some("check-config") => std::process::exit(check_config(args.next().map(PathBuf::from))),
+        Some(other) => {
+            eprintln!("Unknown command: {}\nUsage: vibearound-server [check-config [path]]", other);
+            std::process::exit(2);
+        }
+    }
+
     let daemon = server::ServerDaemon::new(common::config::DEFAULT_PORT);
     let dist_path = PathBuf::from("web").join("dist");
 
@@ -15,3 +31,21 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     std::process::exit(0);
 }
+
+/// Print every issue in the settings file; returns the process exit code.
+fn check_config(path: Option<PathBuf>) -> i32 {
+    let path = path.unwrap_or_else(common::config::settings_path);
+    println!("Checking {}", path.display());
+    let issues = settings::check_file(&path);
+    for issue in &issues {
+        println!("  {}", issue);
+    }
+    let errors = issues.iter().filter(|i| i.level == IssueLevel::Error).count();
+    let warnings = issues.len() - errors;
+    if issues.is_empty() {
+        println!("OK");
+    } else {
+        println!("{} error(s), {} warning(s)", errors, warnings);
+    }
+    if errors > 0 { 1 } else { 0 }
+}
@@ -7,6 +7,7 @@
 //! - GET /api/agents
 //! - GET /api/services
 //! - DELETE /api/services/:category/:id
+//! - POST /api/config/reload
 
 use axum::{
     extract::{Path, State},
@@ -136,3 +137,11 @@ pub async fn delete_session_handler(
         (StatusCode::NOT_FOUND, format!("Session {} not found", session_id))
     }
 }
+
+/// POST /api/config/reload — re-read settings.json now and apply the changes.
+pub async fn reload_config_handler(State(state): State<AppState>) -> impl IntoResponse {
+    match crate::reload_settings(&state.channel_hub, &state.services).await {
+        Ok(()) => (StatusCode::OK, "Settings reloaded".to_string()),
+        Err(e) => (StatusCode::BAD_REQUEST, e),
+    }
+}
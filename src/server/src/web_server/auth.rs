@@ -0,0 +1,649 @@
+//! Authentication for the web dashboard, WebSockets and API.
+//!
+//! - GET /login — login page; `/login?token=<token>` signs in directly
+//! - POST /api/auth/login — `{ "token": ... }` or `{ "password": ... }` → signed session cookie
+//! - POST /api/auth/logout — clear the session cookie
+//!
+//! Sign-in attempts that fail are delayed and capped per client and minute (429 past the cap).
+//!
+//! Every other route goes through `require_auth`. Browsers authenticate with the session cookie;
+//! scripts and the desktop app send `Authorization: Bearer <dashboard token>` (or `?token=` on
+//! WebSocket upgrades, which cannot carry headers). `/mcp` only accepts the scoped MCP token that
+//! AgentManager writes into each agent's MCP config. The `http` channel webhook checks its own
+//! HMAC signature.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use common::config;
+
+use super::AppState;
+
+const SESSION_COOKIE: &str = "va_session";
+const SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Slows down credential guessing through the tunnel.
+const FAILED_LOGIN_DELAY: Duration = Duration::from_secs(1);
+/// Sign-in attempts per client and `LOGIN_ATTEMPT_WINDOW` that have not succeeded.
+const MAX_FAILED_LOGINS: usize = 10;
+const LOGIN_ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+/// Past this many clients, those without recent failures are forgotten.
+const MAX_TRACKED_LOGIN_CLIENTS: usize = 1024;
+
+/// Routes reachable without a session.
+const PUBLIC_PATHS: &[&str] = &["/login", "/api/auth/login", "/api/auth/logout"];
+/// Routes whose handlers verify an HMAC signature instead (the `http` channel webhook).
+const SIGNED_PATH_PREFIX: &str = "/api/channels/http/";
+
+/// How a request proved who it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuthVia {
+    Cookie,
+    Token,
+}
+
+/// Caps sign-in attempts that have not succeeded, per client, within a sliding window. The
+/// per-request delay alone does not slow down guesses sent in parallel. Keying by client keeps
+/// one client's failures from locking everyone else (the owner) out.
+struct LoginThrottle {
+    clients: Mutex<HashMap<String, VecDeque<(u64, Instant)>>>,
+    next_id: AtomicU64,
+}
+
+/// One attempt counted by `LoginThrottle::begin`.
+struct LoginAttempt {
+    client: String,
+    id: u64,
+}
+
+impl LoginThrottle {
+    fn new() -> Self {
+        Self { clients: Mutex::new(HashMap::new()), next_id: AtomicU64::new(0) }
+    }
+
+    /// Count an attempt from `client` before checking its credentials; `None` when the client has
+    /// reached the limit. Counting up front keeps parallel requests from all passing the check.
+    fn begin(&self, client: &str, now: Instant) -> Option<LoginAttempt> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = |t: &Instant| now.duration_since(*t) >= LOGIN_ATTEMPT_WINDOW;
+        if clients.len() >= MAX_TRACKED_LOGIN_CLIENTS {
+            clients.retain(|_, attempts| attempts.back().is_some_and(|(_, t)| !expired(t)));
+        }
+        let attempts = clients.entry(client.to_string()).or_default();
+        while attempts.front().is_some_and(|(_, t)| expired(t)) {
+            attempts.pop_front();
+        }
+        if attempts.len() >= MAX_FAILED_LOGINS {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        attempts.push_back((id, now));
+        Some(LoginAttempt { client: client.to_string(), id })
+    }
+
+    /// The attempt succeeded; it no longer counts against its client's limit.
+    fn succeeded(&self, attempt: LoginAttempt) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(attempts) = clients.get_mut(&attempt.client) {
+            attempts.retain(|(id, _)| *id != attempt.id);
+            if attempts.is_empty() {
+                clients.remove(&attempt.client);
+            }
+        }
+    }
+}
+
+/// The client a sign-in attempt comes from. The server only listens on localhost, so requests
+/// through a tunnel are keyed by the address the tunnel appended to `X-Forwarded-For` (earlier
+/// entries are supplied by the client and can be forged).
+fn client_key(headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .rfind(|v| !v.is_empty());
+    match (forwarded, peer) {
+        (Some(addr), Some(peer)) if peer.ip().is_loopback() => addr.to_string(),
+        (_, Some(peer)) => peer.ip().to_string(),
+        (Some(addr), None) => addr.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+/// Credentials and cookie-signing key, loaded once at server start.
+pub(crate) struct WebAuth {
+    token: String,
+    password: Option<String>,
+    mcp_token: String,
+    signing_key: [u8; 32],
+    login_throttle: LoginThrottle,
+}
+
+impl WebAuth {
+    pub(crate) fn load() -> Self {
+        let token = config::dashboard_token().to_string();
+        let password = config::ensure_loaded().web_auth.password.clone();
+        // Mixing the credentials into the key invalidates existing cookies when they change.
+        let mut hasher = Sha256::new();
+        hasher.update(load_secret());
+        hasher.update([0u8]);
+        hasher.update(token.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(password.as_deref().unwrap_or("").as_bytes());
+        Self {
+            token,
+            password,
+            mcp_token: config::mcp_token().to_string(),
+            signing_key: hasher.finalize().into(),
+            login_throttle: LoginThrottle::new(),
+        }
+    }
+
+    fn is_dashboard_token(&self, candidate: &str) -> bool {
+        secret_eq(candidate, &self.token)
+    }
+
+    fn accepts_login(&self, token: Option<&str>, password: Option<&str>) -> bool {
+        token.is_some_and(|t| self.is_dashboard_token(t))
+            || matches!((password, self.password.as_deref()), (Some(p), Some(expected)) if secret_eq(p, expected))
+    }
+
+    /// Cookie value: `<expiry unix secs>.<hex hmac>`.
+    fn issue_session(&self) -> String {
+        let expires = unix_now() + SESSION_TTL.as_secs();
+        format!("{}.{}", expires, hex(&hmac_sha256(&self.signing_key, expires.to_string().as_bytes())))
+    }
+
+    fn valid_session(&self, value: &str) -> bool {
+        let Some((expires, sig)) = value.split_once('.') else {
+            return false;
+        };
+        let Ok(expires_at) = expires.parse::<u64>() else {
+            return false;
+        };
+        expires_at > unix_now() && secret_eq(sig, &hex(&hmac_sha256(&self.signing_key, expires.as_bytes())))
+    }
+
+    fn authenticate(&self, req: &Request) -> Option<AuthVia> {
+        if cookie_value(req.headers(), SESSION_COOKIE).is_some_and(|v| self.valid_session(v)) {
+            return Some(AuthVia::Cookie);
+        }
+        if bearer_token(req.headers()).is_some_and(|t| self.is_dashboard_token(t)) {
+            return Some(AuthVia::Token);
+        }
+        if is_ws_path(req.uri().path())
+            && query_param(req, "token").is_some_and(|t| self.is_dashboard_token(&t))
+        {
+            return Some(AuthVia::Token);
+        }
+        None
+    }
+
+    fn authenticate_mcp(&self, req: &Request) -> bool {
+        bearer_token(req.headers()).is_some_and(|t| secret_eq(t, &self.mcp_token))
+            || query_param(req, "token").is_some_and(|t| secret_eq(&t, &self.mcp_token))
+    }
+}
+
+/// Middleware guarding every route.
+pub async fn require_auth(State(auth): State<Arc<WebAuth>>, req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+
+    if path == "/mcp" {
+        if auth.authenticate_mcp(&req) {
+            return next.run(req).await;
+        }
+        return (StatusCode::UNAUTHORIZED, "Invalid MCP token").into_response();
+    }
+    if PUBLIC_PATHS.contains(&path.as_str()) || path.starts_with(SIGNED_PATH_PREFIX) {
+        return next.run(req).await;
+    }
+
+    match auth.authenticate(&req) {
+        // Cookies ride along on cross-site WebSocket handshakes; only same-origin pages may use them.
+        Some(AuthVia::Cookie) if is_ws_path(&path) && !same_origin(req.headers()) => {
+            (StatusCode::FORBIDDEN, "Cross-origin WebSocket rejected").into_response()
+        }
+        Some(_) => next.run(req).await,
+        None if req.method() == Method::GET && !path.starts_with("/api/") && !is_ws_path(&path) => {
+            let target = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+            Redirect::to(&format!("/login?next={}", percent_encode(target))).into_response()
+        }
+        None => (StatusCode::UNAUTHORIZED, "Authentication required").into_response(),
+    }
+}
+
+/// Query params for GET /login.
+#[derive(serde::Deserialize)]
+pub struct LoginQuery {
+    token: Option<String>,
+    next: Option<String>,
+}
+
+/// GET /login — sign in with `?token=`, or show the login form.
+pub async fn login_page_handler(
+    State(state): State<AppState>,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    let next = safe_next(query.next.as_deref());
+    if let Some(token) = query.token.as_deref() {
+        let client = client_key(&headers, peer.map(|Extension(ConnectInfo(addr))| addr));
+        let Some(attempt) = state.auth.login_throttle.begin(&client, Instant::now()) else {
+            return too_many_logins();
+        };
+        if state.auth.is_dashboard_token(token) {
+            state.auth.login_throttle.succeeded(attempt);
+            let cookie = session_cookie(&state.auth.issue_session(), &headers);
+            return ([(header::SET_COOKIE, cookie)], Redirect::to(next)).into_response();
+        }
+        tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+    }
+    // JSON string literal, with `<` escaped so a crafted `next` cannot close the script tag.
+    let next_js = serde_json::to_string(next).unwrap_or_else(|_| "\"/\"".into()).replace('<', "\\u003c");
+    let html = LOGIN_PAGE.replace("{{NEXT}}", &next_js);
+    ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response()
+}
+
+/// Body for POST /api/auth/login.
+#[derive(serde::Deserialize)]
+pub struct LoginRequest {
+    token: Option<String>,
+    password: Option<String>,
+}
+
+/// POST /api/auth/login — exchange the token or password for a session cookie.
+pub async fn login_handler(
+    State(state): State<AppState>,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Json(body): Json<LoginRequest>,
+) -> Response {
+    let token = body.token.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let password = body.password.as_deref().filter(|s| !s.is_empty());
+    let client = client_key(&headers, peer.map(|Extension(ConnectInfo(addr))| addr));
+    let Some(attempt) = state.auth.login_throttle.begin(&client, Instant::now()) else {
+        return too_many_logins();
+    };
+    if !state.auth.accepts_login(token, password) {
+        tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+        return (StatusCode::UNAUTHORIZED, "Invalid token or password").into_response();
+    }
+    state.auth.login_throttle.succeeded(attempt);
+    let cookie = session_cookie(&state.auth.issue_session(), &headers);
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+/// POST /api/auth/logout — clear the session cookie.
+pub async fn logout_handler() -> Response {
+    let cookie = format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", SESSION_COOKIE);
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+fn too_many_logins() -> Response {
+    let retry_after = LOGIN_ATTEMPT_WINDOW.as_secs().to_string();
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after)],
+        "Too many failed sign-in attempts; try again later",
+    )
+        .into_response()
+}
+
+fn session_cookie(value: &str, headers: &HeaderMap) -> String {
+    // Tunnels terminate TLS and forward plain HTTP; mark the cookie Secure when they say so.
+    let secure = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|p| p.eq_ignore_ascii_case("https"));
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE,
+        value,
+        SESSION_TTL.as_secs(),
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// Per-install random secret for signing session cookies (~/.vibearound/web_auth.key).
+fn load_secret() -> Vec<u8> {
+    let path = config::data_dir().join("web_auth.key");
+    if let Some(secret) = std::fs::read_to_string(&path)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| s.len() >= 32)
+    {
+        return secret.into_bytes();
+    }
+    let secret = config::random_token();
+    if let Err(e) = config::write_private_file(&path, secret.as_bytes()) {
+        eprintln!("[VibeAround][auth] Failed to write {:?}: {} (sessions will not survive restarts)", path, e);
+    }
+    secret.into_bytes()
+}
+
+fn is_ws_path(path: &str) -> bool {
+    path == "/ws" || path.starts_with("/ws/")
+}
+
+/// True when the Origin header (if any) names the host the request was sent to.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    let host = headers
+        .get("x-forwarded-host")
+        .or_else(|| headers.get(header::HOST))
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let origin_host = origin.split_once("://").map(|(_, h)| h).unwrap_or(origin);
+    !host.is_empty() && origin_host.eq_ignore_ascii_case(host)
+}
+
+/// Only same-site relative paths, so `/login?next=` cannot redirect elsewhere.
+fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(n) if n.starts_with('/') && !n.starts_with("//") && !n.starts_with("/\\") => n,
+        _ => "/",
+    }
+}
+
+fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn query_param(req: &Request, name: &str) -> Option<String> {
+    Query::<HashMap<String, String>>::try_from_uri(req.uri())
+        .ok()
+        .and_then(|Query(mut params)| params.remove(name))
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Compare secrets without leaking where they differ (hashing first also hides the length).
+fn secret_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (Sha256::digest(a.as_bytes()), Sha256::digest(b.as_bytes()));
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn hmac_sha256(key: &[u8; 32], msg: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(msg);
+    mac.finalize().into_bytes().into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+const LOGIN_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width,initial-scale=1">
+<title>VibeAround — Sign in</title>
+<style>
+body{font-family:system-ui,sans-serif;background:#0b0b0c;color:#e5e5e5;display:flex;align-items:center;justify-content:center;height:100vh;margin:0}
+form{background:#17171a;padding:24px;border-radius:10px;width:300px;display:flex;flex-direction:column;gap:12px}
+input,button{padding:10px;border-radius:6px;border:1px solid #333;background:#0b0b0c;color:inherit;font-size:14px}
+button{background:#e5e5e5;color:#0b0b0c;cursor:pointer}
+#err{color:#f87171;font-size:13px;min-height:1em}
+</style></head>
+<body><form id="f">
+<strong>VibeAround</strong>
+<input id="secret" type="password" placeholder="Access token or password" autofocus autocomplete="current-password">
+<button type="submit">Sign in</button>
+<div id="err"></div>
+</form>
+<script>
+const next = {{NEXT}};
+document.getElementById("f").addEventListener("submit", async (e) => {
+  e.preventDefault();
+  const secret = document.getElementById("secret").value;
+  const res = await fetch("/api/auth/login", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ token: secret, password: secret }),
+  });
+  if (res.ok) location.replace(next);
+  else document.getElementById("err").textContent = "Invalid token or password";
+});
+</script></body></html>"#;
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::any;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn web_auth() -> WebAuth {
+        WebAuth {
+            token: "dashboard-token".into(),
+            password: Some("hunter2".into()),
+            mcp_token: "mcp-token".into(),
+            signing_key: [7; 32],
+            login_throttle: LoginThrottle::new(),
+        }
+    }
+
+    /// `require_auth` in front of a handler that always answers 200.
+    async fn status_of(auth: &Arc<WebAuth>, req: axum::http::Request<Body>) -> (StatusCode, HeaderMap) {
+        let app = Router::new()
+            .fallback(any(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(Arc::clone(auth), require_auth));
+        let resp = app.oneshot(req).await.unwrap();
+        (resp.status(), resp.headers().clone())
+    }
+
+    fn get(uri: &str) -> axum::http::request::Builder {
+        axum::http::Request::builder().method(Method::GET).uri(uri)
+    }
+
+    #[test]
+    fn sessions_expire_and_reject_tampering() {
+        let auth = web_auth();
+        let session = auth.issue_session();
+        assert!(auth.valid_session(&session));
+
+        let (expires, sig) = session.split_once('.').unwrap();
+        let flipped = if sig.ends_with('0') { "1" } else { "0" };
+        assert!(!auth.valid_session(&format!("{}.{}{}", expires, &sig[..sig.len() - 1], flipped)));
+        // Extending the expiry invalidates the signature.
+        assert!(!auth.valid_session(&format!("{}.{}", expires.parse::<u64>().unwrap() + 1, sig)));
+        assert!(!auth.valid_session(sig));
+        assert!(!auth.valid_session(""));
+
+        let past = (unix_now() - 1).to_string();
+        let expired = format!("{}.{}", past, hex(&hmac_sha256(&auth.signing_key, past.as_bytes())));
+        assert!(!auth.valid_session(&expired));
+
+        let other_key = WebAuth { signing_key: [8; 32], ..web_auth() };
+        assert!(!other_key.valid_session(&session));
+    }
+
+    #[test]
+    fn safe_next_only_allows_local_paths() {
+        assert_eq!(safe_next(Some("/chats?id=1")), "/chats?id=1");
+        assert_eq!(safe_next(None), "/");
+        for next in ["//evil.example", "/\\evil.example", "https://evil.example", "evil", ""] {
+            assert_eq!(safe_next(Some(next)), "/", "{}", next);
+        }
+    }
+
+    #[test]
+    fn same_origin_compares_origin_with_host() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut map = HeaderMap::new();
+            for (k, v) in pairs {
+                map.insert(*k, v.parse().unwrap());
+            }
+            map
+        };
+        assert!(same_origin(&headers(&[])));
+        assert!(same_origin(&headers(&[("origin", "http://127.0.0.1:5182"), ("host", "127.0.0.1:5182")])));
+        assert!(same_origin(&headers(&[
+            ("origin", "https://va.example.com"),
+            ("host", "127.0.0.1:5182"),
+            ("x-forwarded-host", "va.example.com"),
+        ])));
+        assert!(!same_origin(&headers(&[("origin", "https://evil.example"), ("host", "127.0.0.1:5182")])));
+        assert!(!same_origin(&headers(&[("origin", "http://127.0.0.1:5182")])));
+    }
+
+    #[tokio::test]
+    async fn mcp_only_accepts_the_mcp_token() {
+        let auth = Arc::new(web_auth());
+        let mcp = |token: &str| {
+            axum::http::Request::builder()
+                .method(Method::POST)
+                .uri("/mcp")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert_eq!(status_of(&auth, mcp("mcp-token")).await.0, StatusCode::OK);
+        assert_eq!(status_of(&auth, mcp("dashboard-token")).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(status_of(&auth, get("/mcp?token=mcp-token").body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+        // The MCP token does not open the rest of the API.
+        let api = get("/api/chats").header(header::AUTHORIZATION, "Bearer mcp-token").body(Body::empty()).unwrap();
+        assert_eq!(status_of(&auth, api).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn public_and_signed_paths_skip_the_session_check() {
+        let auth = Arc::new(web_auth());
+        for path in PUBLIC_PATHS {
+            assert_eq!(status_of(&auth, get(path).body(Body::empty()).unwrap()).await.0, StatusCode::OK, "{}", path);
+        }
+        let webhook = axum::http::Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}ci/messages", SIGNED_PATH_PREFIX))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_of(&auth, webhook).await.0, StatusCode::OK);
+        // Only the exact public paths are public.
+        let nested = get("/api/auth/login/x").body(Body::empty()).unwrap();
+        assert_eq!(status_of(&auth, nested).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn other_routes_need_a_session_or_the_dashboard_token() {
+        let auth = Arc::new(web_auth());
+        let cookie = format!("{}={}", SESSION_COOKIE, auth.issue_session());
+
+        assert_eq!(status_of(&auth, get("/api/chats").body(Body::empty()).unwrap()).await.0, StatusCode::UNAUTHORIZED);
+        let bearer = get("/api/chats").header(header::AUTHORIZATION, "Bearer dashboard-token");
+        assert_eq!(status_of(&auth, bearer.body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+        let with_cookie = get("/api/chats").header(header::COOKIE, &cookie);
+        assert_eq!(status_of(&auth, with_cookie.body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+        // `?token=` is only honoured on WebSocket upgrades.
+        let query = get("/api/chats?token=dashboard-token").body(Body::empty()).unwrap();
+        assert_eq!(status_of(&auth, query).await.0, StatusCode::UNAUTHORIZED);
+        let ws_query = get("/ws/chat?token=dashboard-token").body(Body::empty()).unwrap();
+        assert_eq!(status_of(&auth, ws_query).await.0, StatusCode::OK);
+
+        // Pages redirect to the login form instead.
+        let (status, headers) = status_of(&auth, get("/chats?id=1").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(headers[header::LOCATION], "/login?next=/chats%3Fid%3D1");
+
+        // Session cookies on WebSockets only count from the same origin.
+        let cross = get("/ws")
+            .header(header::COOKIE, &cookie)
+            .header(header::HOST, "127.0.0.1:5182")
+            .header(header::ORIGIN, "https://evil.example");
+        assert_eq!(status_of(&auth, cross.body(Body::empty()).unwrap()).await.0, StatusCode::FORBIDDEN);
+        let same = get("/ws")
+            .header(header::COOKIE, &cookie)
+            .header(header::HOST, "127.0.0.1:5182")
+            .header(header::ORIGIN, "http://127.0.0.1:5182");
+        assert_eq!(status_of(&auth, same.body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+    }
+
+    #[test]
+    fn login_throttle_caps_unsuccessful_attempts_per_client_and_window() {
+        let throttle = LoginThrottle::new();
+        let start = Instant::now();
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert!(throttle.begin("203.0.113.7", start).is_some());
+        }
+        assert!(throttle.begin("203.0.113.7", start).is_none());
+        assert!(throttle.begin("203.0.113.7", start + LOGIN_ATTEMPT_WINDOW - Duration::from_secs(1)).is_none());
+        // Another client (the owner) is not locked out.
+        assert!(throttle.begin("198.51.100.1", start).is_some());
+        // Old failures age out of the window.
+        assert!(throttle.begin("203.0.113.7", start + LOGIN_ATTEMPT_WINDOW).is_some());
+    }
+
+    #[test]
+    fn successful_logins_only_release_their_own_attempt() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+        for _ in 0..MAX_FAILED_LOGINS * 2 {
+            let attempt = throttle.begin("owner", now).unwrap();
+            throttle.succeeded(attempt);
+        }
+
+        // A success finishing after later failures removes itself, not the latest failure.
+        let pending = throttle.begin("owner", now).unwrap();
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            assert!(throttle.begin("owner", now).is_some());
+        }
+        assert!(throttle.begin("owner", now).is_none());
+        throttle.succeeded(pending);
+        assert!(throttle.begin("owner", now).is_some());
+        assert!(throttle.begin("owner", now).is_none());
+    }
+
+    #[test]
+    fn client_key_trusts_only_the_proxy_appended_address() {
+        let peer = |ip: [u8; 4]| Some(SocketAddr::from((ip, 40000)));
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_key(&headers, peer([127, 0, 0, 1])), "127.0.0.1");
+        headers.insert("x-forwarded-for", "10.9.9.9, 203.0.113.7".parse().unwrap());
+        assert_eq!(client_key(&headers, peer([127, 0, 0, 1])), "203.0.113.7");
+        // Only a local proxy is believed.
+        assert_eq!(client_key(&headers, peer([192, 168, 1, 5])), "192.168.1.5");
+    }
+}
@@ -0,0 +1,210 @@
+//! Past chat conversations (Manager sessions started from a channel), for the web UI.
+//!
+//! - GET /api/chats — list conversations, newest first (`?channel=web`, `?archived=true`,
+//!   `?limit=50&offset=0`)
+//! - GET /api/chats/{id} — one conversation with its events, for rendering
+//! - DELETE /api/chats/{id} — delete it
+//! - POST /api/chats/{id}/archive — close it and move it out of the list
+//! - POST /api/chats/{id}/resume — fork it into a new web chat; connect to
+//!   `/ws/chat?chatId=<chatId>` and the next message continues the same agent session.
+//!   Refused while the original chat's agent is running; otherwise the original is closed so
+//!   only the fork continues that session
+
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use common::session_store::{self, SessionMeta, SessionOrigin, SessionWriter};
+
+use super::AppState;
+
+const WEB_CHANNEL: &str = "web";
+/// Page size of GET /api/chats when `limit` is not given, and the largest one allowed.
+const DEFAULT_PAGE: usize = 50;
+const MAX_PAGE: usize = 200;
+
+#[derive(serde::Deserialize)]
+pub struct ListChatsQuery {
+    /// Only conversations started from this channel kind.
+    channel: Option<String>,
+    #[serde(default)]
+    archived: bool,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+fn sessions_dir(archived: bool) -> PathBuf {
+    let dir = session_store::manager_sessions_dir();
+    if archived {
+        dir.join(session_store::ARCHIVE_DIR)
+    } else {
+        dir
+    }
+}
+
+fn chat_json(meta: &SessionMeta) -> serde_json::Value {
+    let overview = session_store::session_overview(meta);
+    let origin = meta.header.origin.as_ref();
+    serde_json::json!({
+        "id": meta.id(),
+        "summary": overview.summary,
+        "agentKind": meta.header.kind,
+        "workspace": meta.header.workspace,
+        "channelKind": origin.map(|o| o.channel_kind.as_str()),
+        "chatId": origin.map(|o| o.chat_id.as_str()),
+        "profile": origin.map(|o| o.profile.as_str()),
+        "createdAt": meta.header.created_at,
+        "updatedAt": overview.updated_at,
+        "userMessages": overview.user_messages,
+        "closed": overview.closed,
+    })
+}
+
+/// GET /api/chats
+///
+/// Sessions are ordered by their header, so only the requested page has its events read. All of it
+/// is file I/O, done off the async runtime.
+pub async fn list_chats_handler(Query(query): Query<ListChatsQuery>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+    let listed = tokio::task::spawn_blocking(move || {
+        session_store::list_sessions(&sessions_dir(query.archived))
+            .iter()
+            .filter(|meta| {
+                meta.header.origin.as_ref().is_some_and(|o| {
+                    query.channel.as_deref().is_none_or(|channel| o.channel_kind == channel)
+                })
+            })
+            .skip(query.offset)
+            .take(limit)
+            .map(chat_json)
+            .collect::<Vec<_>>()
+    })
+    .await;
+    match listed {
+        Ok(chats) => Json(chats).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list chats: {}", e)).into_response(),
+    }
+}
+
+/// A live or archived conversation by id.
+fn find_chat(id: &str) -> Option<SessionMeta> {
+    session_store::find_session(&sessions_dir(false), id)
+        .or_else(|| session_store::find_session(&sessions_dir(true), id))
+        .filter(|meta| meta.header.origin.is_some())
+}
+
+/// GET /api/chats/{id}
+pub async fn get_chat_handler(Path(id): Path<String>) -> impl IntoResponse {
+    let Some(meta) = find_chat(&id) else {
+        return (StatusCode::NOT_FOUND, format!("Chat {} not found", id)).into_response();
+    };
+    let mut chat = chat_json(&meta);
+    chat["events"] = serde_json::json!(session_store::read_events(&meta.path));
+    Json(chat).into_response()
+}
+
+/// Stop the conversation's agent if it is still the chat's open session, so nothing keeps writing
+/// to (or resumes) a removed file.
+async fn close_if_open(state: &AppState, meta: &SessionMeta) {
+    if session_store::is_closed(&meta.path) {
+        return;
+    }
+    if let Some(origin) = &meta.header.origin {
+        state
+            .agent_hub
+            .close_chat_agents(&origin.channel_kind, &origin.chat_id, Some("removed from the web UI"))
+            .await;
+    }
+}
+
+/// DELETE /api/chats/{id}
+pub async fn delete_chat_handler(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(meta) = find_chat(&id) else {
+        return (StatusCode::NOT_FOUND, format!("Chat {} not found", id));
+    };
+    close_if_open(&state, &meta).await;
+    match std::fs::remove_file(&meta.path) {
+        Ok(()) => (StatusCode::OK, format!("Chat {} deleted", id)),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to delete chat {}: {}", id, e)),
+    }
+}
+
+/// POST /api/chats/{id}/archive
+pub async fn archive_chat_handler(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(meta) = session_store::find_session(&sessions_dir(false), &id).filter(|m| m.header.origin.is_some())
+    else {
+        return (StatusCode::NOT_FOUND, format!("Chat {} not found", id));
+    };
+    close_if_open(&state, &meta).await;
+    match session_store::archive_session(&meta) {
+        Ok(_) => (StatusCode::OK, format!("Chat {} archived", id)),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to archive chat {}: {}", id, e)),
+    }
+}
+
+/// POST /api/chats/{id}/resume
+pub async fn resume_chat_handler(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(meta) = find_chat(&id) else {
+        return (StatusCode::NOT_FOUND, format!("Chat {} not found", id)).into_response();
+    };
+    if session_store::get_cli_session_id(&meta.path).is_none() {
+        return (StatusCode::CONFLICT, "This conversation has no agent session to resume").into_response();
+    }
+    // Two chats must never drive the same CLI session: refuse while the original's agent is live,
+    // and close the original so its chat starts a new session instead of resuming this one later.
+    if state.agent_hub.is_transcript_open(&meta.path) {
+        return (
+            StatusCode::CONFLICT,
+            "This conversation is still running in its chat; stop or archive it there before resuming",
+        )
+            .into_response();
+    }
+    if !session_store::is_closed(&meta.path) {
+        if let Err(e) = SessionWriter::reopen(&meta.path).map(|mut w| w.mark_closed(Some("resumed in the web UI"))) {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to resume chat {}: {}", id, e)).into_response();
+        }
+    }
+
+    let chat_id = Uuid::new_v4().to_string();
+    let profile = meta
+        .header
+        .origin
+        .as_ref()
+        .map(|o| o.profile.clone())
+        .unwrap_or_else(|| "default".to_string());
+    let origin = SessionOrigin {
+        channel_kind: WEB_CHANNEL.to_string(),
+        chat_id: chat_id.clone(),
+        profile: profile.clone(),
+    };
+    let forked = match session_store::fork_for_chat(&meta, origin) {
+        Ok(forked) => forked,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to resume chat {}: {}", id, e)).into_response()
+        }
+    };
+    state
+        .channel_hub
+        .prepare_resumed_route(
+            WEB_CHANNEL,
+            &chat_id,
+            &meta.header.kind,
+            &profile,
+            PathBuf::from(&meta.header.workspace),
+        )
+        .await;
+
+    Json(serde_json::json!({
+        "id": forked.id(),
+        "chatId": chat_id,
+        "channelId": format!("{}:{}", WEB_CHANNEL, chat_id),
+    }))
+    .into_response()
+}
@@ -0,0 +1,172 @@
+//! Webhook adapter for the internal `http` channel.
+//!
+//! - POST /api/channels/http/{chat_id}/messages — `{ "text", "messageId"?, "sender"? }`, signed with
+//!   `X-VibeAround-Signature: sha256=<hex HMAC of "<timestamp>.<body>">` using `channels.http.secret`
+//!   and `X-VibeAround-Timestamp: <unix seconds>`; stale timestamps and reused signatures are rejected.
+//!   With `Accept: text/event-stream` the reply streams back as SSE; otherwise the request is
+//!   answered with 202 and the reply goes to `channels.http.callback_url`.
+//!
+//! Authenticated by the signature, not the dashboard session (see `auth::require_auth`).
+//!
+//! The signature proves the caller knows `channels.http.secret`, nothing more: `sender` is taken
+//! from the body as the caller states it. `channels.http.allowed_senders` / `admins` therefore only
+//! tell apart callers that already share the secret (e.g. CI vs. a cron job); anyone holding the
+//! secret can claim any sender.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures_util::stream;
+use uuid::Uuid;
+
+use common::channel_manager::channels::http::{self as http_channel, HttpChannelConfig};
+use common::session_hub::types::ChannelNotification;
+
+use super::AppState;
+
+#[derive(serde::Deserialize)]
+struct InboundBody {
+    text: String,
+    #[serde(default, rename = "messageId")]
+    message_id: Option<String>,
+    /// Caller identity checked against `channels.http.allowed_senders`. Self-asserted: it is not
+    /// covered by anything but the shared secret (see the module docs).
+    #[serde(default)]
+    sender: Option<String>,
+}
+
+/// POST /api/channels/http/{chat_id}/messages
+pub async fn post_message_handler(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(cfg) = HttpChannelConfig::load() else {
+        return (StatusCode::NOT_FOUND, "channels.http is not configured").into_response();
+    };
+    if cfg.secret.is_empty() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "channels.http.secret is not set").into_response();
+    }
+    let header_str = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let timestamp = header_str(http_channel::TIMESTAMP_HEADER);
+    let signature = header_str(http_channel::SIGNATURE_HEADER);
+    if let Err(e) = http_channel::verify(&cfg.secret, timestamp, &body, signature) {
+        return (StatusCode::UNAUTHORIZED, e).into_response();
+    }
+    if !state.http_channel.claim_signature(timestamp, signature) {
+        return (StatusCode::UNAUTHORIZED, "Signature already used").into_response();
+    }
+
+    let inbound: InboundBody = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid body: {}", e)).into_response(),
+    };
+    let text = inbound.text.trim();
+    if text.is_empty() || chat_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "text and chat_id must not be empty").into_response();
+    }
+    let message_id = inbound.message_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let wants_stream = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+    // Subscribe before delivering so the first events are not missed.
+    let rx = wants_stream.then(|| state.http_channel.subscribe_reply(&chat_id, &message_id));
+
+    let msg = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "on_message",
+        "params": {
+            "channelId": ChannelNotification::plugin_channel_id(http_channel::CHANNEL_NAME, &chat_id),
+            "messageId": message_id,
+            "text": text,
+            "sender": { "id": inbound.sender.unwrap_or_else(|| http_channel::CHANNEL_NAME.to_string()) }
+        }
+    });
+    state
+        .channel_hub
+        .handle_inbound_jsonrpc(http_channel::CHANNEL_NAME, msg)
+        .await;
+
+    let Some(rx) = rx else {
+        return (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "chatId": chat_id, "messageId": message_id })),
+        )
+            .into_response();
+    };
+
+    // Stream until this message's turn ends, or until a direct reply to it (command output, a
+    // rejected sender) when no turn starts.
+    let events = stream::unfold(
+        (rx, message_id, false, false),
+        |(mut rx, message_id, mut started, done)| async move {
+            if done {
+                return None;
+            }
+            let notif = rx.recv().await?;
+            let finished = match &notif {
+                ChannelNotification::AgentStart { message_id: id, .. } => {
+                    started |= *id == message_id;
+                    false
+                }
+                ChannelNotification::AgentEnd { .. } | ChannelNotification::AgentError { .. } => started,
+                ChannelNotification::SendText { reply_to, .. } => {
+                    !started && reply_to.as_deref() == Some(message_id.as_str())
+                }
+                _ => false,
+            };
+            let event = to_sse_event(notif);
+            Some((Ok::<_, Infallible>(event), (rx, message_id, started, finished)))
+        },
+    );
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+fn to_sse_event(notif: ChannelNotification) -> Event {
+    let (name, data) = match notif {
+        ChannelNotification::AgentStart { message_id, .. } => {
+            ("start", serde_json::json!({ "messageId": message_id }))
+        }
+        ChannelNotification::AgentThinking { text, .. } => ("thinking", serde_json::json!({ "text": text })),
+        ChannelNotification::AgentToken { delta, .. } => ("token", serde_json::json!({ "delta": delta })),
+        ChannelNotification::AgentToolUse { tool_id, tool, input, .. } => {
+            ("tool_use", serde_json::json!({ "toolId": tool_id, "tool": tool, "input": input }))
+        }
+        ChannelNotification::AgentToolResult { tool_id, tool, output, is_error, .. } => (
+            "tool_result",
+            serde_json::json!({ "toolId": tool_id, "tool": tool, "output": output, "isError": is_error }),
+        ),
+        ChannelNotification::AgentEnd { stop_reason, cost_usd, usage, .. } => (
+            "end",
+            serde_json::json!({ "stopReason": stop_reason.as_str(), "costUsd": cost_usd, "usage": usage }),
+        ),
+        ChannelNotification::AgentError { error, .. } => ("error", serde_json::json!({ "error": error })),
+        ChannelNotification::SendText { text, .. } => ("text", serde_json::json!({ "text": text })),
+        ChannelNotification::PermissionRequest { request_id, tool, input, timeout_secs, default_allow, .. } => (
+            "permission_request",
+            serde_json::json!({
+                "requestId": request_id,
+                "tool": tool,
+                "input": input,
+                "timeoutSecs": timeout_secs,
+                "defaultDecision": if default_allow { "allow" } else { "deny" },
+            }),
+        ),
+        ChannelNotification::MessageQueued { message_id, position, .. } => {
+            ("queued", serde_json::json!({ "messageId": message_id, "position": position }))
+        }
+    };
+    Event::default().event(name).data(data.to_string())
+}
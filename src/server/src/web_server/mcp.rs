@@ -4,7 +4,7 @@
 //! Methods: initialize, notifications/initialized, tools/list, tools/call.
 
 use axum::{
-    extract::State,
+    extract::{Query, State},
     response::IntoResponse,
     Json,
 };
@@ -21,6 +21,12 @@ pub struct JsonRpcRequest {
     params: Option<serde_json::Value>,
 }
 
+/// Query params for /mcp. `caller` is the agent key baked into the agent's MCP config.
+#[derive(serde::Deserialize)]
+pub struct McpQuery {
+    caller: Option<String>,
+}
+
 /// Build a JSON-RPC 2.0 success response.
 fn jsonrpc_ok(id: Option<serde_json::Value>, result: serde_json::Value) -> Json<serde_json::Value> {
     Json(serde_json::json!({
@@ -43,6 +49,7 @@ fn jsonrpc_err(id: Option<serde_json::Value>, code: i64, message: &str) -> Json<
 /// Handles JSON-RPC methods: initialize, notifications/initialized, tools/list, tools/call.
 pub async fn mcp_handler(
     State(state): State<AppState>,
+    Query(query): Query<McpQuery>,
     Json(req): Json<JsonRpcRequest>,
 ) -> impl IntoResponse {
     if req.jsonrpc != "2.0" {
@@ -56,7 +63,7 @@ pub async fn mcp_handler(
             jsonrpc_ok(req.id, serde_json::json!({}))
         }
         "tools/list" => mcp_tools_list(req.id),
-        "tools/call" => mcp_tools_call(req.id, req.params, &state).await,
+        "tools/call" => mcp_tools_call(req.id, req.params, query.caller.as_deref(), &state).await,
         _ => jsonrpc_err(req.id, -32601, &format!("Method not found: {}", req.method)),
     }
 }
@@ -108,7 +115,8 @@ fn mcp_tools_list(id: Option<serde_json::Value>) -> Json<serde_json::Value> {
 async fn mcp_tools_call(
     id: Option<serde_json::Value>,
     params: Option<serde_json::Value>,
-    _state: &AppState,
+    caller: Option<&str>,
+    state: &AppState,
 ) -> Json<serde_json::Value> {
     let params = match params {
         Some(p) => p,
@@ -126,7 +134,8 @@ async fn mcp_tools_call(
     };
 
     let workspace = match arguments.get("workspace").and_then(|v| v.as_str()) {
-        Some(w) => std::path::PathBuf::from(w),
+        // Agents follow the prompt literally and often pass "~/.vibearound/workspaces/...".
+        Some(w) => common::config::expand_home(w),
         None => return jsonrpc_err(id, -32602, "Missing required argument: workspace"),
     };
 
@@ -152,18 +161,24 @@ async fn mcp_tools_call(
 
     // Inject current date so the worker knows what "today" is
     let date_str = chrono::Local::now().format("%Y-%m-%d").to_string();
-    let _message_with_date = format!("[Current date: {}]\n\n{}", date_str, message);
-    let _kind = arguments
+    let message_with_date = format!("[Current date: {}]\n\n{}", date_str, message);
+    let kind = arguments
         .get("kind")
         .and_then(|v| v.as_str())
         .and_then(common::agent::AgentKind::from_str_loose);
 
-    // TODO: migrate to AgentManager
-    jsonrpc_ok(id, serde_json::json!({
-        "content": [{
-            "type": "text",
-            "text": "MCP dispatch_task is not yet available in the new hub architecture"
-        }],
-        "isError": true
-    }))
+    match state
+        .agent_hub
+        .dispatch_task(caller, workspace, &message_with_date, kind)
+        .await
+    {
+        Ok(text) => jsonrpc_ok(id, serde_json::json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": false
+        })),
+        Err(e) => jsonrpc_ok(id, serde_json::json!({
+            "content": [{ "type": "text", "text": format!("Worker failed: {}", e) }],
+            "isError": true
+        })),
+    }
 }
@@ -1,8 +1,12 @@
 //! Axum HTTP + WebSocket server: serves Web SPA (from given dist path), WS at /ws for xterm ↔ PTY,
-//! agent chat WS at /ws/chat, static preview (/preview/:project_id, /raw/:project_id/*),
-//! and MCP endpoint at /mcp.
+//! agent chat WS at /ws/chat, past chats at /api/chats, static preview (/preview/:project_id,
+//! /raw/:project_id/*), MCP endpoint at /mcp, and the signed webhook for the `http` channel. Every route is behind
+//! `auth::require_auth`.
 
 mod api;
+mod auth;
+mod chats;
+mod http_channel;
 mod mcp;
 mod preview;
 mod ws_chat;
@@ -21,6 +25,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tower_http::services::ServeDir;
 
+use common::agent_manager::AgentManager;
+use common::channel_manager::channels::http::HttpChannelManager;
 use common::channel_manager::channels::web::WebChannelManager;
 use common::channel_manager::ChannelManager;
 use common::config;
@@ -49,7 +55,10 @@ pub(crate) struct AppState {
     working_dir: PathBuf,
     services: Arc<common::service::ServiceStatusManager>,
     channel_hub: Arc<ChannelManager>,
+    agent_hub: Arc<AgentManager>,
     web_channel: Arc<WebChannelManager>,
+    http_channel: Arc<HttpChannelManager>,
+    auth: Arc<auth::WebAuth>,
 }
 
 /// Ensure web dist exists (build web first).
@@ -91,7 +100,9 @@ pub async fn run_web_server(
     dist_path: PathBuf,
     services: Arc<common::service::ServiceStatusManager>,
     channel_hub: Arc<ChannelManager>,
+    agent_hub: Arc<AgentManager>,
     web_channel: Arc<WebChannelManager>,
+    http_channel: Arc<HttpChannelManager>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     verify_web_dist(&dist_path)?;
     let web_dist = dist_path
@@ -111,14 +122,30 @@ pub async fn run_web_server(
         working_dir,
         services,
         channel_hub,
+        agent_hub,
         web_channel,
+        http_channel,
+        auth: Arc::new(auth::WebAuth::load()),
     };
+    if config::ensure_loaded().web_auth.token.is_none() {
+        eprintln!(
+            "[VibeAround] Dashboard access token: see {:?}",
+            config::data_dir().join("dashboard_token")
+        );
+    }
 
     let app = Router::new()
+        .route("/login", get(auth::login_page_handler))
+        .route("/api/auth/login", post(auth::login_handler))
+        .route("/api/auth/logout", post(auth::logout_handler))
         .route("/api/sessions", get(api::list_sessions_handler).post(api::create_session_handler))
         .route("/api/sessions/{session_id}", delete(api::delete_session_handler))
         .route("/api/tmux/sessions", get(api::list_tmux_sessions_handler))
         .route("/api/agents", get(api::list_agents_handler))
+        .route("/api/chats", get(chats::list_chats_handler))
+        .route("/api/chats/{id}", get(chats::get_chat_handler).delete(chats::delete_chat_handler))
+        .route("/api/chats/{id}/archive", post(chats::archive_chat_handler))
+        .route("/api/chats/{id}/resume", post(chats::resume_chat_handler))
         .route("/preview/{project_id}", get(preview::preview_page_handler))
         .route("/raw/{project_id}", get(preview::raw_root_handler))
         .route("/raw/{project_id}/{*path}", get(preview::raw_path_handler))
@@ -127,15 +154,19 @@ pub async fn run_web_server(
         .route("/ws/services", get(ws_services::ws_services_handler))
         .route("/api/services", get(api::list_services_handler))
         .route("/api/services/{category}/{id}", delete(api::kill_service_handler))
+        .route("/api/config/reload", post(api::reload_config_handler))
+        .route("/api/channels/http/{chat_id}/messages", post(http_channel::post_message_handler))
         .route("/mcp", post(mcp::mcp_handler))
         .nest_service("/assets", ServeDir::new(assets_dir))
         .fallback(any(spa_fallback_handler))
+        .layer(axum::middleware::from_fn_with_state(Arc::clone(&state.auth), auth::require_auth))
         .with_state(state)
+        // Cross-origin callers (the desktop app's webview) authenticate with a bearer token, never cookies.
         .layer(
             tower_http::cors::CorsLayer::new()
                 .allow_origin(tower_http::cors::Any)
                 .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any),
+                .allow_headers([axum::http::header::CONTENT_TYPE, axum::http::header::AUTHORIZATION]),
         );
 
     let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
@@ -148,6 +179,6 @@ pub async fn run_web_server(
         e
     })?;
     println!("[VibeAround] Web server listening on http://127.0.0.1:{}", port);
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
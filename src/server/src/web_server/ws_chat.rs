@@ -1,35 +1,119 @@
 //! WebSocket handler for web chat channel.
 //!
 //! - GET /ws/chat — websocket adapter for the internal `web` channel
+//!
+//! The chat id comes from `?chatId=`, else the `va_chat` cookie, else a new one is minted and set
+//! as that cookie. Reconnecting with the same id (page refresh, flaky network, another tab) rejoins
+//! the same session and replays the turn in progress.
+//!
+//! Outbound events follow the protocol picked with `?protocol=` (announced in the `config`
+//! message). Version 1 (the default) is the original untyped shape (`{"text"}`, `{"progress"}`,
+//! `{"done"}`). Version 2 gives every event a `type`:
+//!
+//! - `turn_start` { messageId } — a turn began (or is being replayed)
+//! - `text` { delta } / `thinking` { text }
+//! - `tool_call` { id, title, input, status: "running" }, then the same `id` with
+//!   { output, status: "completed" | "failed" }
+//! - `turn_end` { stopReason, usage: { costUsd, inputTokens, outputTokens, thoughtTokens,
+//!   cachedReadTokens, cachedWriteTokens } } — token counts only when the agent reports them
+//! - `error` { error }
+//! - `queue_position` { messageId, position } — the message waits behind `position` others
+//! - `system_text` { text, replyTo } / `permission_request` { requestId, tool, input, options, ... }
 
 use axum::extract::{
     ws::{Message, WebSocket, WebSocketUpgrade},
-    State,
+    Query, State,
 };
+use axum::http::{header, HeaderMap, HeaderValue};
 use axum::response::Response;
 use futures_util::{SinkExt, StreamExt};
 use uuid::Uuid;
 
-use common::config;
-use common::session_hub::types::ChannelNotification;
+use common::config::{self, ImVerboseConfig};
+use common::session_hub::types::{permission_callback_value, ChannelNotification};
 
 use super::AppState;
 
+const CHAT_COOKIE: &str = "va_chat";
+const CHAT_COOKIE_MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+/// The original untyped event shape; used when the client does not ask for another.
+const PROTOCOL_LEGACY: u32 = 1;
+const PROTOCOL_TYPED: u32 = 2;
+const SUPPORTED_PROTOCOLS: [u32; 2] = [PROTOCOL_LEGACY, PROTOCOL_TYPED];
+
+#[derive(serde::Deserialize)]
+pub struct ChatQuery {
+    #[serde(default, rename = "chatId")]
+    chat_id: Option<String>,
+    /// Event schema version; see the module docs.
+    #[serde(default)]
+    protocol: Option<u32>,
+}
+
 /// WebSocket upgrade handler for web chat.
-pub async fn ws_chat_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(move |socket| handle_chat_socket(socket, state))
+pub async fn ws_chat_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ChatQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let existing = query
+        .chat_id
+        .filter(|id| valid_chat_id(id))
+        .or_else(|| cookie_chat_id(&headers));
+    let minted = existing.is_none();
+    let protocol = query
+        .protocol
+        .filter(|v| SUPPORTED_PROTOCOLS.contains(v))
+        .unwrap_or(PROTOCOL_LEGACY);
+    let chat_id = existing.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    // Same Secure rule as the session cookie: tunnels forward plain HTTP behind TLS.
+    let secure = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|p| p.eq_ignore_ascii_case("https"));
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        CHAT_COOKIE,
+        chat_id,
+        CHAT_COOKIE_MAX_AGE_SECS,
+        if secure { "; Secure" } else { "" }
+    );
+    let mut response = ws.on_upgrade(move |socket| handle_chat_socket(socket, state, chat_id, protocol));
+    if minted {
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// Chat ids end up in paths and channel ids; keep them short and plain.
+fn valid_chat_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn cookie_chat_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, v)| *k == CHAT_COOKIE && valid_chat_id(v))
+        .map(|(_, v)| v.to_string())
 }
 
-async fn handle_chat_socket(socket: WebSocket, state: AppState) {
-    let chat_id = Uuid::new_v4().to_string();
+async fn handle_chat_socket(socket: WebSocket, state: AppState, chat_id: String, protocol: u32) {
     let channel_id = format!("web:{}", chat_id);
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<ChannelNotification>();
-    state.web_channel.register_connection(chat_id.clone(), tx);
 
     let (mut ws_tx, mut ws_rx) = socket.split();
 
     // Push config on connect so current UI can still render target info.
     let cfg = config::ensure_loaded();
+    let verbose = cfg.channel_verbose("web");
     let agents: Vec<serde_json::Value> = cfg
         .enabled_agents
         .iter()
@@ -43,14 +127,25 @@ async fn handle_chat_socket(socket: WebSocket, state: AppState) {
     let config_msg = serde_json::json!({
         "type": "config",
         "channelId": channel_id,
+        "chatId": chat_id,
+        "protocol": protocol,
+        "protocols": SUPPORTED_PROTOCOLS,
         "agents": agents,
         "default_agent": cfg.default_agent,
     });
     let _ = ws_tx.send(Message::Text(config_msg.to_string().into())).await;
+    // Registered after the config push so a replayed turn follows it.
+    let connection_id = state.web_channel.register_connection(chat_id.clone(), tx);
 
     let outbound_task = tokio::spawn(async move {
         while let Some(notif) = rx.recv().await {
-            let msg = notification_to_client_json(notif);
+            let msg = if protocol == PROTOCOL_TYPED {
+                notification_to_typed_json(notif)
+            } else if hidden_from_legacy(&notif, &verbose) {
+                continue;
+            } else {
+                notification_to_client_json(notif)
+            };
             if ws_tx.send(Message::Text(msg.to_string().into())).await.is_err() {
                 break;
             }
@@ -70,7 +165,7 @@ async fn handle_chat_socket(socket: WebSocket, state: AppState) {
     }
 
     outbound_task.abort();
-    state.web_channel.unregister_connection(&chat_id);
+    state.web_channel.unregister_connection(&chat_id, connection_id);
 }
 
 fn client_message_to_channel_json(chat_id: &str, text: &str) -> Option<serde_json::Value> {
@@ -82,7 +177,10 @@ fn client_message_to_channel_json(chat_id: &str, text: &str) -> Option<serde_jso
             match ty {
                 "message" => {
                     let text = v.get("text").and_then(|x| x.as_str()).unwrap_or("").trim();
-                    if text.is_empty() {
+                    // Web attachments carry their bytes inline: [{ fileName, mimeType, data: <base64> }].
+                    let attachments = v.get("attachments").cloned().unwrap_or_else(|| serde_json::json!([]));
+                    let has_attachments = attachments.as_array().is_some_and(|a| !a.is_empty());
+                    if text.is_empty() && !has_attachments {
                         return None;
                     }
                     let message_id = v
@@ -97,6 +195,22 @@ fn client_message_to_channel_json(chat_id: &str, text: &str) -> Option<serde_jso
                             "channelId": format!("web:{}", chat_id),
                             "messageId": message_id,
                             "text": text,
+                            "attachments": attachments,
+                            "sender": { "id": "web-user" }
+                        }
+                    }))
+                }
+                "callback" => {
+                    let value = v.get("value").and_then(|x| x.as_str()).unwrap_or("");
+                    if value.is_empty() {
+                        return None;
+                    }
+                    Some(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "on_callback",
+                        "params": {
+                            "channelId": format!("web:{}", chat_id),
+                            "data": { "value": value },
                             "sender": { "id": "web-user" }
                         }
                     }))
@@ -124,6 +238,18 @@ fn client_message_to_channel_json(chat_id: &str, text: &str) -> Option<serde_jso
     }
 }
 
+/// The web channel relays every tool call and thought; protocol 1 clients still only see them
+/// when `channels.web.verbose` asks for it, as before typed events existed.
+fn hidden_from_legacy(notif: &ChannelNotification, verbose: &ImVerboseConfig) -> bool {
+    match notif {
+        ChannelNotification::AgentThinking { .. } => !verbose.show_thinking,
+        ChannelNotification::AgentToolUse { .. } | ChannelNotification::AgentToolResult { .. } => {
+            !verbose.show_tool_use
+        }
+        _ => false,
+    }
+}
+
 fn notification_to_client_json(notif: ChannelNotification) -> serde_json::Value {
     match notif {
         ChannelNotification::AgentStart { .. } => serde_json::json!({ "type": "start" }),
@@ -133,10 +259,226 @@ fn notification_to_client_json(notif: ChannelNotification) -> serde_json::Value
             serde_json::json!({ "progress": format!("Using tool: {}...", tool) })
         }
         ChannelNotification::AgentToolResult { .. } => serde_json::json!({}),
-        ChannelNotification::AgentEnd { .. } => serde_json::json!({ "done": true }),
+        ChannelNotification::AgentEnd { stop_reason, .. } => {
+            serde_json::json!({ "done": true, "stop_reason": stop_reason.as_str() })
+        }
         ChannelNotification::AgentError { error, .. } => serde_json::json!({ "error": error }),
         ChannelNotification::SendText { text, .. } => {
             serde_json::json!({ "type": "system_text", "text": text, "done": true })
         }
+        ChannelNotification::PermissionRequest { request_id, tool, input, options, timeout_secs, default_allow, .. } => {
+            serde_json::json!({
+                "type": "permission_request",
+                "request_id": request_id,
+                "tool": tool,
+                "input": input,
+                "options": options.iter().map(|o| serde_json::json!({
+                    "id": o.id,
+                    "label": o.label,
+                    "kind": o.kind,
+                    "value": permission_callback_value(&request_id, &o.id),
+                })).collect::<Vec<_>>(),
+                "timeout_secs": timeout_secs,
+                "default_decision": if default_allow { "allow" } else { "deny" },
+            })
+        }
+        ChannelNotification::MessageQueued { position, .. } => {
+            serde_json::json!({ "progress": format!("Queued behind {} message(s)...", position) })
+        }
+    }
+}
+
+/// Protocol version 2: one typed event per notification.
+fn notification_to_typed_json(notif: ChannelNotification) -> serde_json::Value {
+    match notif {
+        ChannelNotification::AgentStart { message_id, .. } => {
+            serde_json::json!({ "type": "turn_start", "messageId": message_id })
+        }
+        ChannelNotification::AgentThinking { text, .. } => serde_json::json!({ "type": "thinking", "text": text }),
+        ChannelNotification::AgentToken { delta, .. } => serde_json::json!({ "type": "text", "delta": delta }),
+        ChannelNotification::AgentToolUse { tool_id, tool, input, .. } => serde_json::json!({
+            "type": "tool_call",
+            "id": tool_id,
+            "title": tool,
+            "input": input,
+            "status": "running",
+        }),
+        ChannelNotification::AgentToolResult { tool_id, tool, output, is_error, .. } => serde_json::json!({
+            "type": "tool_call",
+            "id": tool_id,
+            "title": tool,
+            "output": output,
+            "status": if is_error { "failed" } else { "completed" },
+        }),
+        ChannelNotification::AgentEnd { stop_reason, cost_usd, usage, .. } => {
+            let mut usage = usage
+                .and_then(|u| serde_json::to_value(u).ok())
+                .unwrap_or_else(|| serde_json::json!({}));
+            usage["costUsd"] = serde_json::json!(cost_usd);
+            serde_json::json!({ "type": "turn_end", "stopReason": stop_reason.as_str(), "usage": usage })
+        }
+        ChannelNotification::AgentError { error, .. } => serde_json::json!({ "type": "error", "error": error }),
+        ChannelNotification::SendText { text, reply_to, .. } => {
+            serde_json::json!({ "type": "system_text", "text": text, "replyTo": reply_to })
+        }
+        ChannelNotification::PermissionRequest { request_id, tool, input, options, timeout_secs, default_allow, .. } => {
+            serde_json::json!({
+                "type": "permission_request",
+                "requestId": request_id,
+                "tool": tool,
+                "input": input,
+                "options": options.iter().map(|o| serde_json::json!({
+                    "id": o.id,
+                    "label": o.label,
+                    "kind": o.kind,
+                    "value": permission_callback_value(&request_id, &o.id),
+                })).collect::<Vec<_>>(),
+                "timeoutSecs": timeout_secs,
+                "defaultDecision": if default_allow { "allow" } else { "deny" },
+            })
+        }
+        ChannelNotification::MessageQueued { message_id, position, .. } => {
+            serde_json::json!({ "type": "queue_position", "messageId": message_id, "position": position })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::agent::{StopReason, TokenUsage};
+    use serde_json::json;
+
+    use super::*;
+
+    fn web() -> (String, String) {
+        ("web".to_string(), "chat-1".to_string())
+    }
+
+    #[test]
+    fn typed_protocol_shapes() {
+        let (channel_kind, chat_id) = web();
+        let typed = notification_to_typed_json;
+
+        assert_eq!(
+            typed(ChannelNotification::AgentStart {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                message_id: "m1".into(),
+            }),
+            json!({ "type": "turn_start", "messageId": "m1" })
+        );
+        assert_eq!(
+            typed(ChannelNotification::AgentThinking {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                text: "hmm".into(),
+            }),
+            json!({ "type": "thinking", "text": "hmm" })
+        );
+        assert_eq!(
+            typed(ChannelNotification::AgentToken {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                delta: "Hi".into(),
+            }),
+            json!({ "type": "text", "delta": "Hi" })
+        );
+
+        // A tool call is one `id`, first running, then completed or failed.
+        assert_eq!(
+            typed(ChannelNotification::AgentToolUse {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                tool_id: "t1".into(),
+                tool: "Bash".into(),
+                input: "ls".into(),
+            }),
+            json!({ "type": "tool_call", "id": "t1", "title": "Bash", "input": "ls", "status": "running" })
+        );
+        assert_eq!(
+            typed(ChannelNotification::AgentToolResult {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                tool_id: "t1".into(),
+                tool: "Bash".into(),
+                output: "no such file".into(),
+                is_error: true,
+            }),
+            json!({ "type": "tool_call", "id": "t1", "title": "Bash", "output": "no such file", "status": "failed" })
+        );
+
+        assert_eq!(
+            typed(ChannelNotification::AgentEnd {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                stop_reason: StopReason::EndTurn,
+                cost_usd: Some(0.25),
+                usage: Some(TokenUsage {
+                    input_tokens: 1200,
+                    output_tokens: 300,
+                    thought_tokens: None,
+                    cached_read_tokens: Some(1000),
+                    cached_write_tokens: None,
+                }),
+            }),
+            json!({
+                "type": "turn_end",
+                "stopReason": "end_turn",
+                "usage": { "costUsd": 0.25, "inputTokens": 1200, "outputTokens": 300, "cachedReadTokens": 1000 },
+            })
+        );
+        // Agents that report nothing still get a `usage` object.
+        assert_eq!(
+            typed(ChannelNotification::AgentEnd {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                stop_reason: StopReason::Cancelled,
+                cost_usd: None,
+                usage: None,
+            }),
+            json!({ "type": "turn_end", "stopReason": "cancelled", "usage": { "costUsd": null } })
+        );
+
+        assert_eq!(
+            typed(ChannelNotification::MessageQueued {
+                channel_kind: channel_kind.clone(),
+                chat_id: chat_id.clone(),
+                message_id: "m2".into(),
+                position: 1,
+            }),
+            json!({ "type": "queue_position", "messageId": "m2", "position": 1 })
+        );
+        assert_eq!(
+            typed(ChannelNotification::AgentError { channel_kind, chat_id, error: "boom".into() }),
+            json!({ "type": "error", "error": "boom" })
+        );
+    }
+
+    #[test]
+    fn legacy_protocol_keeps_the_verbose_filter() {
+        let (channel_kind, chat_id) = web();
+        let thinking = ChannelNotification::AgentThinking {
+            channel_kind: channel_kind.clone(),
+            chat_id: chat_id.clone(),
+            text: "hmm".into(),
+        };
+        let tool = ChannelNotification::AgentToolUse {
+            channel_kind: channel_kind.clone(),
+            chat_id: chat_id.clone(),
+            tool_id: "t1".into(),
+            tool: "Bash".into(),
+            input: "ls".into(),
+        };
+        let token = ChannelNotification::AgentToken { channel_kind, chat_id, delta: "Hi".into() };
+
+        let quiet = ImVerboseConfig::default();
+        assert!(hidden_from_legacy(&thinking, &quiet));
+        assert!(hidden_from_legacy(&tool, &quiet));
+        assert!(!hidden_from_legacy(&token, &quiet));
+
+        let verbose = ImVerboseConfig { show_thinking: true, show_tool_use: true };
+        assert!(!hidden_from_legacy(&thinking, &verbose));
+        assert!(!hidden_from_legacy(&tool, &verbose));
+        assert_eq!(notification_to_client_json(tool), json!({ "progress": "Using tool: Bash..." }));
     }
 }
@@ -0,0 +1,81 @@
+/**
+ * Chats API: past chat conversations — list, open, delete, archive, resume.
+ */
+
+function getBaseUrl(): string {
+  if (typeof window === "undefined") return "http://127.0.0.1:12358";
+  return window.location.origin;
+}
+
+export interface ChatListItem {
+  id: string;
+  summary: string | null;
+  agentKind: string;
+  workspace: string;
+  channelKind: string | null;
+  chatId: string | null;
+  profile: string | null;
+  createdAt: string;
+  updatedAt: string;
+  userMessages: number;
+  closed: boolean;
+}
+
+export interface ChatEvent {
+  ts: string;
+  /** "user" | "assistant" | "system" */
+  role: string;
+  agent_id?: string;
+  /** text, thinking, tool_use, tool_result, turn_complete, error, progress, ... (absent for user messages) */
+  event?: string;
+  content?: string;
+  data?: unknown;
+}
+
+export interface ChatDetail extends ChatListItem {
+  events: ChatEvent[];
+}
+
+export interface ResumeChatResponse {
+  id: string;
+  /** Connect to `/ws/chat?chatId=<chatId>` to continue the conversation. */
+  chatId: string;
+  channelId: string;
+}
+
+/** One page of chats, newest first; the server returns 50 when `limit` is not set (at most 200). */
+export async function listChats(
+  options: { channel?: string; archived?: boolean; limit?: number; offset?: number } = {},
+): Promise<ChatListItem[]> {
+  const params = new URLSearchParams();
+  if (options.channel) params.set("channel", options.channel);
+  if (options.archived) params.set("archived", "true");
+  if (options.limit !== undefined) params.set("limit", String(options.limit));
+  if (options.offset) params.set("offset", String(options.offset));
+  const query = params.toString();
+  const res = await fetch(`${getBaseUrl()}/api/chats${query ? `?${query}` : ""}`);
+  if (!res.ok) throw new Error(`GET /api/chats: ${res.status}`);
+  return res.json();
+}
+
+export async function getChat(id: string): Promise<ChatDetail> {
+  const res = await fetch(`${getBaseUrl()}/api/chats/${encodeURIComponent(id)}`);
+  if (!res.ok) throw new Error(`GET /api/chats/${id}: ${res.status}`);
+  return res.json();
+}
+
+export async function deleteChat(id: string): Promise<void> {
+  const res = await fetch(`${getBaseUrl()}/api/chats/${encodeURIComponent(id)}`, { method: "DELETE" });
+  if (!res.ok) throw new Error(`DELETE /api/chats/${id}: ${res.status}`);
+}
+
+export async function archiveChat(id: string): Promise<void> {
+  const res = await fetch(`${getBaseUrl()}/api/chats/${encodeURIComponent(id)}/archive`, { method: "POST" });
+  if (!res.ok) throw new Error(`POST /api/chats/${id}/archive: ${res.status}`);
+}
+
+export async function resumeChat(id: string): Promise<ResumeChatResponse> {
+  const res = await fetch(`${getBaseUrl()}/api/chats/${encodeURIComponent(id)}/resume`, { method: "POST" });
+  if (!res.ok) throw new Error(`POST /api/chats/${id}/resume: ${res.status}`);
+  return res.json();
+}
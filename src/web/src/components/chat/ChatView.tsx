@@ -27,12 +27,37 @@ function capitalize(s: string): string {
   return s.charAt(0).toUpperCase() + s.slice(1);
 }
 
+/** A tool call inside an assistant reply (protocol 2 `tool_call` events, merged by id). */
+export type ToolCall = {
+  id: string;
+  title: string;
+  status: "running" | "completed" | "failed";
+};
+
+/** Token counts and cost of a finished reply (protocol 2 `turn_end.usage`). */
+export type TurnUsage = {
+  costUsd?: number | null;
+  inputTokens?: number;
+  outputTokens?: number;
+};
+
 export type ChatMessage = {
   role: "user" | "assistant" | "system";
   content: string;
   progress?: string;
+  tools?: ToolCall[];
+  usage?: TurnUsage;
 };
 
+/** "1,200 in · 300 out · $0.0250", from whatever the agent reported. */
+function formatUsage(usage: TurnUsage): string | null {
+  const parts: string[] = [];
+  if (typeof usage.inputTokens === "number") parts.push(`${usage.inputTokens.toLocaleString()} in`);
+  if (typeof usage.outputTokens === "number") parts.push(`${usage.outputTokens.toLocaleString()} out`);
+  if (typeof usage.costUsd === "number") parts.push(`$${usage.costUsd.toFixed(4)}`);
+  return parts.length ? parts.join(" · ") : null;
+}
+
 export function ChatView() {
   const [messages, setMessages] = useState<ChatMessage[]>([]);
   const [input, setInput] = useState("");
@@ -43,110 +68,166 @@ export function ChatView() {
   const [agents, setAgents] = useState<AgentInfo[]>([]);
   const [currentAgent, setCurrentAgent] = useState<string>("claude");
   const wsRef = useRef<WebSocket | null>(null);
+  // True from a sent message / "turn_start" until "turn_end" or "error"; survives reconnects.
+  const turnOpenRef = useRef(false);
 
   const toolType = agentIdToToolType(currentAgent);
   const agentLabel = capitalize(currentAgent);
 
-  // Connect on mount, close on unmount
+  // Connect on mount (reconnecting with backoff), close on unmount. The server keeps the chat id
+  // in a cookie, so a reconnect rejoins the same session and replays the turn in progress.
+  // Events use the typed protocol 2 (see server/src/web_server/ws_chat.rs).
   useEffect(() => {
-    const ws = new WebSocket(getWebSocketUrl("/ws/chat"));
-    wsRef.current = ws;
+    let unmounted = false;
+    let retryTimer: ReturnType<typeof setTimeout> | undefined;
+    let attempt = 0;
 
-    ws.onopen = () => setConnected(true);
-    ws.onclose = () => {
-      setConnected(false);
-      setStreaming(false);
-    };
-    ws.onerror = () => setConnected(false);
-
-    ws.onmessage = (event) => {
-      if (typeof event.data !== "string") return;
-      const s = event.data as string;
-
-      let j: Record<string, unknown>;
-      try {
-        j = JSON.parse(s);
-      } catch {
-        appendToAssistant(s);
-        return;
-      }
-
-      // {"type":"config","agents":[...],"default_agent":"claude"} — agent config push on connect
-      if (j.type === "config" && Array.isArray(j.agents)) {
-        setAgents(j.agents as AgentInfo[]);
-        if (typeof j.default_agent === "string") {
-          setCurrentAgent(j.default_agent as string);
-        }
-        return;
-      }
-
-      // {"type":"agent_switched","agent":"opencode"} — backend confirmed agent switch
-      if (j.type === "agent_switched" && typeof j.agent === "string") {
-        setCurrentAgent(j.agent as string);
-        return;
-      }
-
-      // {"type":"system_text","text":"..."} — standalone system message
-      if (j.type === "system_text" && typeof j.text === "string") {
-        setMessages((prev) => [...prev, { role: "system", content: j.text as string }]);
-        setStreaming(false);
-        return;
-      }
-
-      // {"done":true} — stream finished
-      if (j.done === true) {
-        setMessages((prev) => {
-          const last = prev[prev.length - 1];
-          if (last?.role === "assistant" && last.progress) {
-            const next = [...prev];
-            next[next.length - 1] = { ...last, progress: undefined };
-            return next;
-          }
-          return prev;
-        });
-        setStreaming(false);
-        return;
-      }
-
-      // {"error":"..."} — error
-      if (typeof j.error === "string") {
-        setMessages((prev) => {
-          const last = prev[prev.length - 1];
-          if (last?.role === "assistant") {
-            const next = [...prev];
-            next[next.length - 1] = {
-              ...last,
-              content: last.content + (last.content ? "\n\n" : "") + `Error: ${j.error}`,
-              progress: undefined,
-            };
-            return next;
-          }
-          return [...prev, { role: "assistant", content: `Error: ${j.error}` }];
-        });
+    function connect() {
+      const ws = new WebSocket(getWebSocketUrl("/ws/chat?protocol=2"));
+      wsRef.current = ws;
+
+      ws.onopen = () => {
+        attempt = 0;
+        setConnected(true);
+      };
+      ws.onclose = () => {
+        setConnected(false);
         setStreaming(false);
-        return;
-      }
-
-      // {"progress":"Thinking..."} — progress indicator
-      if (typeof j.progress === "string") {
-        setMessages((prev) => {
-          const last = prev[prev.length - 1];
-          if (last?.role === "assistant") {
-            const next = [...prev];
-            next[next.length - 1] = { ...last, progress: j.progress as string };
-            return next;
+        if (unmounted) return;
+        retryTimer = setTimeout(connect, Math.min(1000 * 2 ** attempt, 15000));
+        attempt += 1;
+      };
+      ws.onerror = () => setConnected(false);
+
+      ws.onmessage = (event) => {
+        if (typeof event.data !== "string") return;
+        const s = event.data as string;
+
+        let j: Record<string, unknown>;
+        try {
+          j = JSON.parse(s);
+        } catch {
+          appendToAssistant(s);
+          return;
+        }
+
+        // {"type":"config","agents":[...],"default_agent":"claude"} — agent config push on connect
+        if (j.type === "config" && Array.isArray(j.agents)) {
+          setAgents(j.agents as AgentInfo[]);
+          if (typeof j.default_agent === "string") {
+            setCurrentAgent(j.default_agent as string);
           }
-          return prev;
-        });
-        return;
-      }
-
-      // {"text":"..."} — text content to append
-      if (typeof j.text === "string") {
-        appendToAssistant(j.text as string);
-        return;
-      }
-    };
+          return;
+        }
+
+        // {"type":"turn_start","messageId":"..."} — a turn began (or is being replayed after a reconnect)
+        if (j.type === "turn_start") {
+          const replaying = turnOpenRef.current;
+          turnOpenRef.current = true;
+          setStreaming(true);
+          setMessages((prev) => {
+            const last = prev[prev.length - 1];
+            // The replay rebuilds the reply from its first token.
+            if (last?.role === "assistant" && (replaying || !last.content)) {
+              const next = [...prev];
+              next[next.length - 1] = { role: "assistant", content: "" };
+              return next;
+            }
+            return [...prev, { role: "assistant", content: "" }];
+          });
+          return;
+        }
+
+        // {"type":"agent_switched","agent":"opencode"} — backend confirmed agent switch
+        if (j.type === "agent_switched" && typeof j.agent === "string") {
+          setCurrentAgent(j.agent as string);
+          return;
+        }
+
+        // {"type":"system_text","text":"...","replyTo":...} — standalone system message
+        if (j.type === "system_text" && typeof j.text === "string") {
+          setMessages((prev) => [...prev, { role: "system", content: j.text as string }]);
+          setStreaming(false);
+          return;
+        }
+
+        // {"type":"turn_end","stopReason":"end_turn","usage":{...}} — stream finished
+        if (j.type === "turn_end") {
+          turnOpenRef.current = false;
+          const usage = (j.usage ?? undefined) as TurnUsage | undefined;
+          updateAssistant((last) => ({ ...last, progress: undefined, usage }));
+          setStreaming(false);
+          return;
+        }
+
+        // {"type":"error","error":"..."}
+        if (j.type === "error" && typeof j.error === "string") {
+          turnOpenRef.current = false;
+          const error = `Error: ${j.error}`;
+          setMessages((prev) => {
+            const last = prev[prev.length - 1];
+            if (last?.role === "assistant") {
+              const next = [...prev];
+              next[next.length - 1] = {
+                ...last,
+                content: last.content + (last.content ? "\n\n" : "") + error,
+                progress: undefined,
+              };
+              return next;
+            }
+            return [...prev, { role: "assistant", content: error }];
+          });
+          setStreaming(false);
+          return;
+        }
+
+        // {"type":"thinking","text":"..."} — shown as the progress line
+        if (j.type === "thinking" && typeof j.text === "string") {
+          updateAssistant((last) => ({ ...last, progress: j.text as string }));
+          return;
+        }
+
+        // {"type":"queue_position","position":1} — the message waits behind others
+        if (j.type === "queue_position" && typeof j.position === "number") {
+          updateAssistant((last) => ({ ...last, progress: `Queued behind ${j.position} message(s)…` }));
+          return;
+        }
+
+        // {"type":"tool_call","id":"...","title":"Bash","status":"running"|"completed"|"failed"}
+        if (j.type === "tool_call" && typeof j.id === "string") {
+          const call: ToolCall = {
+            id: j.id as string,
+            title: typeof j.title === "string" && j.title ? (j.title as string) : "tool",
+            status: j.status === "completed" || j.status === "failed" ? j.status : "running",
+          };
+          updateAssistant((last) => {
+            const tools = [...(last.tools ?? [])];
+            const i = tools.findIndex((t) => t.id === call.id);
+            if (i >= 0) tools[i] = { ...tools[i], status: call.status };
+            else tools.push(call);
+            return { ...last, tools };
+          });
+          return;
+        }
+
+        // {"type":"text","delta":"..."} — text content to append
+        if (j.type === "text" && typeof j.delta === "string") {
+          appendToAssistant(j.delta as string);
+          return;
+        }
+      };
+    }
+
+    /** Apply `update` to the reply being streamed; no-op when the last message is not the assistant's. */
+    function updateAssistant(update: (last: ChatMessage) => ChatMessage) {
+      setMessages((prev) => {
+        const last = prev[prev.length - 1];
+        if (last?.role !== "assistant") return prev;
+        const next = [...prev];
+        next[next.length - 1] = update(last);
+        return next;
+      });
+    }
 
     function appendToAssistant(text: string) {
       if (!text) return;
@@ -162,8 +243,11 @@ export function ChatView() {
       });
     }
 
+    connect();
     return () => {
-      ws.close();
+      unmounted = true;
+      clearTimeout(retryTimer);
+      wsRef.current?.close();
       wsRef.current = null;
     };
   }, []);
@@ -179,6 +263,7 @@ export function ChatView() {
       { role: "assistant", content: "" },
     ]);
     setStreaming(true);
+    turnOpenRef.current = true;
     wsRef.current.send(JSON.stringify({ type: "message", text }));
   }, [input]);
 
@@ -215,6 +300,15 @@ export function ChatView() {
                     <p className="whitespace-pre-wrap text-xs font-mono leading-5">{msg.content}</p>
                   ) : (
                     <>
+                      {msg.tools && msg.tools.length > 0 && (
+                        <ul className="mb-2 space-y-0.5 text-xs font-mono text-muted-foreground">
+                          {msg.tools.map((tool) => (
+                            <li key={tool.id}>
+                              {tool.status === "running" ? "…" : tool.status === "failed" ? "✗" : "✓"} {tool.title}
+                            </li>
+                          ))}
+                        </ul>
+                      )}
                       <MessageResponse
                         content={msg.content}
                         isStreaming={streaming && i === messages.length - 1}
@@ -224,6 +318,9 @@ export function ChatView() {
                           {msg.progress}
                         </span>
                       )}
+                      {msg.usage && formatUsage(msg.usage) && (
+                        <p className="mt-1 text-xs text-muted-foreground/60 font-mono">{formatUsage(msg.usage)}</p>
+                      )}
                     </>
                   )}
                 </MessageContent>